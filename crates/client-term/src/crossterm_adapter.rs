@@ -31,6 +31,7 @@ impl CrosstermAdapter {
         mut app: GameTui,
     ) -> Result<()> {
         loop {
+            app.tick();
             terminal.draw(|f| {
                 app.render(f);
 
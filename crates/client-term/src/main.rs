@@ -1,7 +1,11 @@
 #![allow(clippy::all)]
 
-use losig_client::adapter::Adapter;
+use losig_client::{
+    adapter::Adapter,
+    tui::{keybindings::KeyBindings, select_palette},
+};
 use losig_core::types::PlayerId;
+use std::time::Duration;
 
 use crate::crossterm_adapter::CrosstermAdapter;
 use crate::ws_client::WsClient;
@@ -26,12 +30,36 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         .parse()
         .map_err(|_| "Avatar ID must be a valid number")?;
 
+    if let Ok(palette) = std::env::var("LOSIG_THEME") {
+        select_palette(palette.parse()?);
+    }
+
+    let keybindings = match std::env::var("LOSIG_KEYS") {
+        Ok(path) => {
+            let content = std::fs::read_to_string(&path)
+                .map_err(|e| format!("Could not read keybindings {path}: {e}"))?;
+            KeyBindings::parse(&content).map_err(|e| format!("Invalid keybindings {path}: {e}"))?
+        }
+        Err(_) => KeyBindings::default(),
+    };
+
     let client = WsClient::new();
     let tui_adapter = CrosstermAdapter::new();
     Adapter {
         player_id,
         client,
         tui_adapter,
+        keybindings,
+        name: std::env::var("LOSIG_NAME").ok(),
+        debug: std::env::var("LOSIG_DEBUG").is_ok(),
+        record: std::env::var("LOSIG_RECORD").is_ok(),
+        warp: std::env::var("LOSIG_WARP")
+            .ok()
+            .and_then(|stage| stage.parse().ok()),
+        retry_after: std::env::var("LOSIG_RETRY")
+            .ok()
+            .and_then(|secs| secs.parse().ok())
+            .map(Duration::from_secs_f32),
     }
     .run();
     Ok(())
@@ -88,7 +88,9 @@ impl Client for WsClient {
                 }
 
                 if let Ok(server_message) = handle_read::<ServerMessage>(socket) {
-                    let _ = s_tx.send(server_message);
+                    for server_message in server_message.unbatch() {
+                        let _ = s_tx.send(server_message);
+                    }
                 }
 
                 for client_message in c_rx.try_iter() {
@@ -1,8 +1,8 @@
-use std::io;
+use std::{io, time::Duration};
 
 use crate::{ratzilla_adapter::RatzillaAdapter, ws::WsClient};
 use log::Level;
-use losig_client::adapter::Adapter;
+use losig_client::{adapter::Adapter, tui::select_palette};
 use losig_core::types::PlayerId;
 use wasm_bindgen::JsValue;
 use web_sys::{Url, UrlSearchParams, window};
@@ -16,24 +16,40 @@ fn main() -> io::Result<()> {
     let player_id = get_player_id().unwrap_or_else(generate_player_id);
     update_history(player_id);
 
+    if let Some(palette) = get_param("theme").and_then(|p| p.parse().ok()) {
+        select_palette(palette);
+    }
+
     let client = WsClient::new();
     let tui_adapter = RatzillaAdapter::new();
     Adapter {
         player_id,
         client,
         tui_adapter,
+        keybindings: Default::default(),
+        name: get_param("name"),
+        debug: get_param("debug").is_some(),
+        record: get_param("record").is_some(),
+        warp: get_param("warp").and_then(|stage| stage.parse().ok()),
+        retry_after: get_param("retry")
+            .and_then(|secs| secs.parse().ok())
+            .map(Duration::from_secs_f32),
     }
     .run();
     Ok(())
 }
 
 fn get_player_id() -> Option<PlayerId> {
+    get_param("id").and_then(|s| s.parse::<PlayerId>().ok())
+}
+
+fn get_param(name: &str) -> Option<String> {
     let window = window()?;
     let location = window.location();
     let params = location.search().ok()?;
     let params = UrlSearchParams::new_with_str(&params).ok()?;
 
-    params.get("id").and_then(|s| s.parse::<PlayerId>().ok())
+    params.get(name)
 }
 
 fn generate_player_id() -> PlayerId {
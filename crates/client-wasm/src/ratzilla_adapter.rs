@@ -29,8 +29,11 @@ impl RatzillaAdapter {
         });
 
         let event_app = Rc::clone(&app);
+        // Ticks are throttled by the app, drawing is only the most regular callback there is
         terminal.draw_web(move |frame| {
-            event_app.borrow_mut().render(frame);
+            let mut app = event_app.borrow_mut();
+            app.tick();
+            app.render(frame);
         });
 
         Ok(())
@@ -79,7 +79,9 @@ impl WsClient {
         let onmessage_callback = Closure::wrap(Box::new(move |e: MessageEvent| {
             let server_message = convert_response(e);
             if let Some(server_message) = server_message {
-                (ws.on_recv.borrow())(server_message);
+                for server_message in server_message.unbatch() {
+                    (ws.on_recv.borrow())(server_message);
+                }
             }
         }) as Box<dyn FnMut(MessageEvent)>);
         socket.set_onmessage(Some(onmessage_callback.as_ref().unchecked_ref()));
@@ -1,25 +1,54 @@
 //! Adapter to make the client cross platform between terminal and web
 
-use std::sync::{Arc, Mutex};
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 
-use log::debug;
+use log::{debug, warn};
 use losig_core::{
     leaderboard::Leaderboard,
-    network::{ClientMessage, ClientMessageContent, ServerMessage},
-    types::{GameOver, PlayerId},
+    network::{ClientMessage, ClientMessageContent, ErrorCode, LobbyMessage, ServerMessage},
+    types::{GameOver, PlayerId, StageId},
 };
 
-use crate::{tui::GameTui, world::WorldView};
+use crate::{
+    logs::ClientLog,
+    tui::{GameTui, keybindings::KeyBindings},
+    world::WorldView,
+};
 
 pub struct Adapter<C, T> {
     pub player_id: PlayerId,
     pub client: C,
     pub tui_adapter: T,
+    pub keybindings: KeyBindings,
+    /// Name given at startup, suggested when starting a game
+    pub name: Option<String>,
+    /// Keeps diagnostics about the client predictions
+    pub debug: bool,
+    /// Keeps every turn of the run for the replay page
+    pub record: bool,
+    /// Stage to warp to when starting a game, on servers allowing it
+    pub warp: Option<StageId>,
+    /// Asks for a resync when an action gets no response for this long
+    pub retry_after: Option<Duration>,
 }
 
 impl<C: Client, T: TuiAdapter> Adapter<C, T> {
     pub fn run(mut self) {
-        let shared_state = Arc::new(Mutex::new(SharedState::new(self.player_id)));
+        let mut state = SharedState::new(self.player_id);
+        if self.debug {
+            state.world.enable_corrections();
+        }
+        if self.record {
+            state.world.enable_recording();
+        }
+        if let Some(timeout) = self.retry_after {
+            state.world.enable_retry(timeout);
+        }
+        state.warp = self.warp;
+        let shared_state = Arc::new(Mutex::new(state));
 
         // Set up server message callback
         let callback: ServerMessageCallback;
@@ -35,7 +64,17 @@ impl<C: Client, T: TuiAdapter> Adapter<C, T> {
                         ServerMessage::Transition(_) => "Transition".to_string(),
                         ServerMessage::GameOver(_) => "Game over".to_string(),
                         ServerMessage::Limbo { .. } => "Limbo".to_string(),
+                        ServerMessage::FallingBehind(_) => "Falling behind".to_string(),
                         ServerMessage::Timeline(_, _, _, _) => "Timeline".to_string(),
+                        ServerMessage::Lobby(_) => "Lobby".to_string(),
+                        ServerMessage::Spectate(_) => "Spectate".to_string(),
+                        ServerMessage::Resync(_) => "Resync".to_string(),
+                        ServerMessage::ServerShutdown => "Server shutdown".to_string(),
+                        ServerMessage::Paused(_) => "Paused".to_string(),
+                        ServerMessage::StageSnapshot(_) => "Stage snapshot".to_string(),
+                        ServerMessage::MapSet(_) => "Map set".to_string(),
+                        ServerMessage::Batch(_) => "Batch".to_string(),
+                        ServerMessage::Error { .. } => "Error".to_string(),
                     }
                 );
                 match msg {
@@ -58,6 +97,13 @@ impl<C: Client, T: TuiAdapter> Adapter<C, T> {
                             state.world.update_on_averted(info);
                         }
                     }
+                    ServerMessage::FallingBehind(turns_left) => {
+                        let turn = state.world.turn;
+                        state
+                            .world
+                            .logs
+                            .add(turn, ClientLog::FallingBehind(turns_left));
+                    }
                     ServerMessage::Timeline(stage_id, _, timeline, senses_info) => {
                         state.world.update_timeline(stage_id, timeline);
                         if let Some(info) = senses_info {
@@ -66,6 +112,43 @@ impl<C: Client, T: TuiAdapter> Adapter<C, T> {
                     }
                     ServerMessage::Transition(transition_message) => {
                         state.world.transition(transition_message);
+                        state.stage_intro = true;
+                    }
+                    ServerMessage::Lobby(lobby) => {
+                        state.lobby = lobby;
+                    }
+                    ServerMessage::Spectate(spectate_message) => {
+                        state.world.spectate(spectate_message);
+                    }
+                    ServerMessage::Resync(resync_message) => {
+                        if resync_message.limbo {
+                            state.limbo = Some(false);
+                        }
+                        state.world.resync(resync_message);
+                    }
+                    ServerMessage::ServerShutdown => {
+                        state.server_shutdown = true;
+                    }
+                    ServerMessage::Paused(paused) => {
+                        let turn = state.world.turn;
+                        state.world.logs.add(turn, ClientLog::Paused(paused));
+                    }
+                    ServerMessage::StageSnapshot(_) | ServerMessage::MapSet(_) => {
+                        // Meant for tooling, the game asks for none
+                        warn!("Unexpected admin answer");
+                    }
+                    ServerMessage::Batch(_) => {
+                        // Clients unwrap batches before calling back
+                        warn!("Unexpected batch in callback");
+                    }
+                    ServerMessage::Error { code, message } => {
+                        warn!("Server error {code:?}: {message}");
+                        // What was predicted since may be wrong, catch up with the next action
+                        if code == ErrorCode::Refused {
+                            state.world.request_resync();
+                        }
+                        let turn = state.world.turn;
+                        state.world.logs.add(turn, ClientLog::ServerError(code));
                     }
                 }
             });
@@ -76,17 +159,26 @@ impl<C: Client, T: TuiAdapter> Adapter<C, T> {
 
         if let Ok(ref mut client) = shared_client.lock() {
             let client_connect = shared_client.clone();
+            let state_connect = shared_state.clone();
             client.set_on_connect(Box::new(move || {
                 let client = client_connect.lock().unwrap();
                 client.send(ClientMessage {
                     player_id: Some(self.player_id),
                     content: ClientMessageContent::Leaderboard,
                 });
+
+                // Reconnecting during a game, catch up with what happened meanwhile
+                if state_connect.lock().unwrap().world.turn > 1 {
+                    client.send(ClientMessage {
+                        player_id: Some(self.player_id),
+                        content: ClientMessageContent::Resync,
+                    });
+                }
             }));
             client.run();
         }
 
-        let game_tui = GameTui::new(shared_client, shared_state);
+        let game_tui = GameTui::new(shared_client, shared_state, self.keybindings, self.name);
         self.tui_adapter.run(game_tui);
     }
 }
@@ -110,8 +202,15 @@ pub struct SharedState {
     pub player_id: PlayerId,
     pub gameover: Option<GameOver>,
     pub limbo: Option<bool>,
+    /// A stage was just entered, its intro is shown until the next input
+    pub stage_intro: bool,
     pub leaderboard: Leaderboard,
+    pub lobby: LobbyMessage,
+    /// The server announced it is stopping, further disconnections are expected
+    pub server_shutdown: bool,
     pub world: WorldView,
+    /// Stage to warp to when starting a game, for testing
+    pub warp: Option<StageId>,
 }
 
 impl SharedState {
@@ -120,8 +219,12 @@ impl SharedState {
             player_id,
             gameover: None,
             limbo: None,
+            stage_intro: false,
             leaderboard: Leaderboard::new(),
+            lobby: LobbyMessage::default(),
+            server_shutdown: false,
             world: WorldView::new(),
+            warp: None,
         }
     }
 }
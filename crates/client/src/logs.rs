@@ -1,4 +1,8 @@
-use losig_core::{events::GEvent, types::Turn};
+use losig_core::{
+    events::GEvent,
+    network::ErrorCode,
+    types::{StageTurn, Turn},
+};
 
 #[derive(Default, Clone, Debug)]
 pub struct GameLogs {
@@ -21,6 +25,30 @@ pub enum LogEvent {
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum ClientLog {
     Help,
+    /// The server refused a move into a wall
+    Blocked,
+    /// Received tiles contradict the ones already known
+    Incoherent,
+    /// Sharing senses with allies was turned on or off
+    ShareSenses(bool),
+    /// The stage does not pool the sight of its players
+    NoTeamVision,
+    /// The last action was taken back
+    Undo,
+    /// The last action is too old, too costly or not known well enough to be taken back
+    CannotUndo,
+    /// The server froze or resumed the simulation
+    Paused(bool),
+    /// The explored map was written to a file, or could not be
+    MapExported(bool),
+    /// The player is close to being too far behind, with the turns left before it
+    FallingBehind(StageTurn),
+    /// The server could not handle a message
+    ServerError(ErrorCode),
+    /// The planned moves were dropped, something got in the way
+    PlanStopped,
+    /// The last action got no response in time, the server is asked where things stand
+    NoResponse,
 }
 
 impl GameLogs {
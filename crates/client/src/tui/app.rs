@@ -1,23 +1,33 @@
 use losig_core::{
+    leaderboard::sanitize_name,
     network::{ClientMessage, ClientMessageContent, CommandMessage},
     sense::Senses,
-    types::ClientAction,
+    types::{ClientAction, StageId},
 };
-use ratatui::Frame;
+use ratatui::{Frame, widgets::Widget};
 use std::sync::{Arc, Mutex, MutexGuard};
+use web_time::{Duration, Instant};
 
 use crate::{
     adapter::{Client, SharedState},
     tui::{
-        pages::{GamePage, MenuPage},
-        state::{GameOverState, GameState, LimboState, MenuState, PageSelection, TuiState},
+        keybindings::KeyBindings,
+        pages::{GamePage, MenuPage, ReplayPage, SpectatePage},
+        state::{
+            GameOverState, GameState, LimboState, MenuState, PageSelection, ReplayState, TuiState,
+        },
+        widgets::shutdown::ShutdownWidget,
     },
     tui_adapter::Event,
 };
 
+/// Time between two ticks, so that the actions sent don't depend on the frame rate
+const TICK_INTERVAL: Duration = Duration::from_millis(50);
+
 pub struct GameTui {
     state: TuiState,
     external: ExternalServices,
+    last_tick: Option<Instant>,
 }
 
 struct ExternalServices {
@@ -55,6 +65,12 @@ impl<'a> InputServices<'a> {
     pub fn act(&mut self, action: ClientAction, senses: Senses) {
         self.state.world.act(&action, &senses);
         let player_id = self.state.player_id;
+        if self.state.world.take_resync_needed() {
+            self.client.send(ClientMessage {
+                player_id: Some(player_id),
+                content: ClientMessageContent::Resync,
+            });
+        }
         self.client.send(ClientMessage {
             player_id: Some(player_id),
             content: ClientMessageContent::Command(CommandMessage {
@@ -66,6 +82,57 @@ impl<'a> InputServices<'a> {
         });
     }
 
+    /// Asks for a resync if the last action went unanswered for too long
+    pub fn reconcile(&mut self) {
+        if self.state.world.take_response_overdue() {
+            self.client.send(ClientMessage {
+                player_id: Some(self.state.player_id),
+                content: ClientMessageContent::Resync,
+            });
+        }
+    }
+
+    /// Rewinds the world and asks the server to take back the last turn
+    pub fn undo(&mut self, senses: Senses) {
+        if !self.state.world.undo() {
+            return;
+        }
+        let player_id = self.state.player_id;
+        self.client.send(ClientMessage {
+            player_id: Some(player_id),
+            content: ClientMessageContent::Command(CommandMessage {
+                player_id,
+                turn: self.state.world.turn,
+                action: ClientAction::Undo,
+                senses,
+            }),
+        });
+    }
+
+    /// Senses the last turn again, only the senses not used yet are paid for
+    pub fn resense(&mut self, senses: Senses) {
+        if !self.state.world.resense() {
+            return;
+        }
+        let player_id = self.state.player_id;
+        self.client.send(ClientMessage {
+            player_id: Some(player_id),
+            content: ClientMessageContent::Command(CommandMessage {
+                player_id,
+                turn: self.state.world.turn,
+                action: ClientAction::Sense,
+                senses,
+            }),
+        });
+    }
+
+    pub fn share_senses(&self, share: bool) {
+        self.client.send(ClientMessage {
+            player_id: Some(self.state.player_id),
+            content: ClientMessageContent::ShareSenses(share),
+        });
+    }
+
     pub fn submit_leaderboard(&self, name: String) {
         self.client.send(ClientMessage {
             player_id: Some(self.state.player_id),
@@ -73,11 +140,32 @@ impl<'a> InputServices<'a> {
         });
     }
 
-    pub fn new_game(&self, name: Option<String>) {
+    pub fn new_game(&mut self, name: Option<String>) {
+        self.state.world.new_run();
         self.client.send(ClientMessage {
             player_id: Some(self.state.player_id),
             content: ClientMessageContent::Start(self.state.player_id, name),
         });
+        if let Some(stage_id) = self.state.warp {
+            self.client.send(ClientMessage {
+                player_id: Some(self.state.player_id),
+                content: ClientMessageContent::WarpTo(stage_id),
+            });
+        }
+    }
+
+    pub fn spectate(&self, stage_id: StageId) {
+        self.client.send(ClientMessage {
+            player_id: Some(self.state.player_id),
+            content: ClientMessageContent::Spectate(stage_id),
+        });
+    }
+
+    pub fn stop_spectating(&self) {
+        self.client.send(ClientMessage {
+            player_id: Some(self.state.player_id),
+            content: ClientMessageContent::StopSpectating,
+        });
     }
 
     pub fn clear_gameover(&mut self) {
@@ -87,38 +175,84 @@ impl<'a> InputServices<'a> {
     pub fn clear_limbo(&mut self) {
         self.state.limbo = None;
     }
+
+    pub fn clear_stage_intro(&mut self) {
+        self.state.stage_intro = false;
+    }
 }
 
 impl GameTui {
-    pub fn new(client: Arc<Mutex<dyn Client>>, shared_state: Arc<Mutex<SharedState>>) -> Self {
+    pub fn new(
+        client: Arc<Mutex<dyn Client>>,
+        shared_state: Arc<Mutex<SharedState>>,
+        keybindings: KeyBindings,
+        name: Option<String>,
+    ) -> Self {
         Self {
             external: ExternalServices {
                 state: shared_state,
                 client,
             },
             state: TuiState {
-                menu: MenuState::default(),
-                game: GameState::default(),
+                menu: MenuState {
+                    default_name: name.as_deref().and_then(sanitize_name).unwrap_or_default(),
+                    ..Default::default()
+                },
+                game: GameState {
+                    keybindings,
+                    ..Default::default()
+                },
                 you_win: GameOverState::default(),
                 limbo: LimboState::default(),
+                replay: ReplayState::default(),
                 page: PageSelection::Menu,
                 should_exit: false,
             },
+            last_tick: None,
         }
     }
 }
 
 impl GameTui {
+    /// Advances what goes on without an input: the planned moves, the resync of unanswered
+    /// actions and the focus history. Adapters call it regularly, apart from rendering, and it
+    /// does nothing until TICK_INTERVAL has passed.
+    pub fn tick(&mut self) {
+        if self
+            .last_tick
+            .is_some_and(|tick| tick.elapsed() < TICK_INTERVAL)
+        {
+            return;
+        }
+        self.last_tick = Some(Instant::now());
+
+        if let PageSelection::Game = self.state.page {
+            self.external.input_services().reconcile();
+            GamePage::follow_path(&mut self.state.game, self.external.input_services());
+            GamePage::follow_queue(&mut self.state.game, self.external.input_services());
+            GamePage::record_focus(&mut self.state.game, self.external.render_services());
+        }
+    }
+
     pub fn render(&mut self, f: &mut Frame) {
         let area = f.area();
         let buf = f.buffer_mut();
 
         let services = self.external.render_services();
+        let server_shutdown = services.state.server_shutdown;
 
         match self.state.page {
             PageSelection::Menu => MenuPage {}.render(area, buf, &mut self.state.menu, services),
             PageSelection::Game => GamePage {}.render(area, buf, &mut self.state, services),
+            PageSelection::Spectate => SpectatePage {}.render(area, buf, services),
+            PageSelection::Replay => {
+                ReplayPage {}.render(area, buf, &mut self.state.replay, services)
+            }
         };
+
+        if server_shutdown {
+            ShutdownWidget.render(area, buf);
+        }
     }
 
     pub fn handle_events(&mut self, event: Event) -> bool {
@@ -126,6 +260,8 @@ impl GameTui {
         match self.state.page {
             PageSelection::Menu => MenuPage {}.on_event(&event, &mut self.state, services),
             PageSelection::Game => GamePage {}.on_event(&event, &mut self.state, services),
+            PageSelection::Spectate => SpectatePage {}.on_event(&event, &mut self.state, services),
+            PageSelection::Replay => ReplayPage {}.on_event(&event, &mut self.state, services),
         }
     }
 
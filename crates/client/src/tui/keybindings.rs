@@ -0,0 +1,451 @@
+//! Remappable game controls.
+//!
+//! Bindings are read from a small TOML subset, one action per line:
+//!
+//! ```toml
+//! # Comments are allowed
+//! move_up = ["Up", "8", "k"]
+//! sense_up = ["Shift+Up", "Shift+K"]
+//! wait = [".", "#", ","]
+//! ```
+//!
+//! Actions missing from the file keep their default keys.
+
+use std::fmt::Display;
+
+use anyhow::{Result, anyhow, bail};
+
+use crate::tui_adapter::{KeyCode, KeyEvent};
+
+/// Logical actions triggered by a key in the game page
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyAction {
+    MoveUp,
+    MoveDown,
+    MoveLeft,
+    MoveRight,
+    MoveUpLeft,
+    MoveUpRight,
+    MoveDownLeft,
+    MoveDownRight,
+    Wait,
+    Rest,
+    Disarm,
+    ShareSenses,
+    Undo,
+    Resense,
+    Help,
+    SenseUp,
+    SenseDown,
+    SenseIncr,
+    SenseDecr,
+    CyclePreset,
+    Minimap,
+    Look,
+    Examine,
+    ExportMap,
+    Redraw,
+    Corrections,
+    FocusGraph,
+    Plan,
+}
+
+impl KeyAction {
+    /// In matching order: sense actions use modifiers so they must be checked before moves
+    pub const ALL: &[KeyAction] = &[
+        KeyAction::SenseUp,
+        KeyAction::SenseDown,
+        KeyAction::SenseIncr,
+        KeyAction::SenseDecr,
+        KeyAction::CyclePreset,
+        KeyAction::MoveUp,
+        KeyAction::MoveDown,
+        KeyAction::MoveLeft,
+        KeyAction::MoveRight,
+        KeyAction::MoveUpLeft,
+        KeyAction::MoveUpRight,
+        KeyAction::MoveDownLeft,
+        KeyAction::MoveDownRight,
+        KeyAction::Wait,
+        KeyAction::Rest,
+        KeyAction::Disarm,
+        KeyAction::ShareSenses,
+        KeyAction::Undo,
+        KeyAction::Resense,
+        KeyAction::Help,
+        KeyAction::Minimap,
+        KeyAction::Look,
+        KeyAction::Examine,
+        KeyAction::ExportMap,
+        KeyAction::Redraw,
+        KeyAction::Corrections,
+        KeyAction::FocusGraph,
+        KeyAction::Plan,
+    ];
+
+    /// Name used in the bindings file
+    pub fn name(&self) -> &'static str {
+        match self {
+            KeyAction::MoveUp => "move_up",
+            KeyAction::MoveDown => "move_down",
+            KeyAction::MoveLeft => "move_left",
+            KeyAction::MoveRight => "move_right",
+            KeyAction::MoveUpLeft => "move_up_left",
+            KeyAction::MoveUpRight => "move_up_right",
+            KeyAction::MoveDownLeft => "move_down_left",
+            KeyAction::MoveDownRight => "move_down_right",
+            KeyAction::Wait => "wait",
+            KeyAction::Rest => "rest",
+            KeyAction::Disarm => "disarm",
+            KeyAction::ShareSenses => "share_senses",
+            KeyAction::Undo => "undo",
+            KeyAction::Resense => "resense",
+            KeyAction::Help => "help",
+            KeyAction::SenseUp => "sense_up",
+            KeyAction::SenseDown => "sense_down",
+            KeyAction::SenseIncr => "sense_incr",
+            KeyAction::SenseDecr => "sense_decr",
+            KeyAction::CyclePreset => "cycle_preset",
+            KeyAction::Minimap => "minimap",
+            KeyAction::Look => "look",
+            KeyAction::Examine => "examine",
+            KeyAction::ExportMap => "export_map",
+            KeyAction::Redraw => "redraw",
+            KeyAction::Corrections => "corrections",
+            KeyAction::FocusGraph => "focus_graph",
+            KeyAction::Plan => "plan",
+        }
+    }
+
+    /// Human readable description, for the help screen
+    pub fn label(&self) -> &'static str {
+        match self {
+            KeyAction::MoveUp => "Move up",
+            KeyAction::MoveDown => "Move down",
+            KeyAction::MoveLeft => "Move left",
+            KeyAction::MoveRight => "Move right",
+            KeyAction::MoveUpLeft => "Move up-left",
+            KeyAction::MoveUpRight => "Move up-right",
+            KeyAction::MoveDownLeft => "Move down-left",
+            KeyAction::MoveDownRight => "Move down-right",
+            KeyAction::Wait => "Wait",
+            KeyAction::Rest => "Rest until disturbed",
+            KeyAction::Disarm => "Disarm a trap, then pick a direction",
+            KeyAction::ShareSenses => "Share senses with allies",
+            KeyAction::Undo => "Take back the last move",
+            KeyAction::Resense => "Sense the last turn again",
+            KeyAction::Help => "Help",
+            KeyAction::SenseUp => "Select previous sense",
+            KeyAction::SenseDown => "Select next sense",
+            KeyAction::SenseIncr => "Strengthen sense",
+            KeyAction::SenseDecr => "Weaken sense",
+            KeyAction::CyclePreset => "Cycle presets",
+            KeyAction::Minimap => "Toggle map",
+            KeyAction::Look => "Look around, moves pan the view",
+            KeyAction::Examine => "Examine, moves pick what to describe",
+            KeyAction::ExportMap => "Write the map to a text file",
+            KeyAction::Redraw => "Forget the map",
+            KeyAction::Corrections => "Toggle prediction corrections",
+            KeyAction::FocusGraph => "Toggle focus graph",
+            KeyAction::Plan => "Plan moves, again to play them",
+        }
+    }
+
+    /// Debug actions, left out of the help screen
+    pub fn hidden(&self) -> bool {
+        matches!(self, KeyAction::Corrections)
+    }
+
+    fn from_name(name: &str) -> Option<KeyAction> {
+        KeyAction::ALL.iter().copied().find(|a| a.name() == name)
+    }
+}
+
+/// A key and the modifiers that must be held with it
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyBinding {
+    pub code: KeyCode,
+    pub shift: bool,
+    pub control: bool,
+    pub alt: bool,
+}
+
+impl KeyBinding {
+    pub fn new(code: KeyCode) -> Self {
+        Self {
+            code,
+            shift: false,
+            control: false,
+            alt: false,
+        }
+    }
+
+    pub fn shift(code: KeyCode) -> Self {
+        Self {
+            shift: true,
+            ..Self::new(code)
+        }
+    }
+
+    /// Extra modifiers held by the player are ignored
+    pub fn matches(&self, key: &KeyEvent) -> bool {
+        self.code == key.code
+            && (!self.shift || key.modifiers.shift)
+            && (!self.control || key.modifiers.control)
+            && (!self.alt || key.modifiers.alt)
+    }
+
+    /// Parses a key like `k`, `Space`, `Shift+Up` or `Ctrl+Alt+x`
+    pub fn parse(s: &str) -> Result<Self> {
+        let mut parts: Vec<&str> = s.split('+').collect();
+        // `+` alone or as the last key
+        if s.ends_with("++") || s == "+" {
+            parts.truncate(parts.len() - 2);
+            parts.push("+");
+        }
+        let key = parts.pop().ok_or_else(|| anyhow!("Empty key"))?;
+
+        let code = match key {
+            "Up" => KeyCode::Up,
+            "Down" => KeyCode::Down,
+            "Left" => KeyCode::Left,
+            "Right" => KeyCode::Right,
+            "Tab" => KeyCode::Tab,
+            "Enter" => KeyCode::Enter,
+            "Esc" => KeyCode::Esc,
+            "Backspace" => KeyCode::Backspace,
+            "Space" => KeyCode::Char(' '),
+            _ => {
+                let mut chars = key.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => KeyCode::Char(c),
+                    _ => bail!("Unknown key '{key}'"),
+                }
+            }
+        };
+
+        let mut binding = Self::new(code);
+        for modifier in parts {
+            match modifier {
+                "Shift" => binding.shift = true,
+                "Ctrl" => binding.control = true,
+                "Alt" => binding.alt = true,
+                _ => bail!("Unknown modifier '{modifier}'"),
+            }
+        }
+        Ok(binding)
+    }
+}
+
+impl Display for KeyBinding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.control {
+            f.write_str("Ctrl+")?;
+        }
+        if self.alt {
+            f.write_str("Alt+")?;
+        }
+        if self.shift {
+            f.write_str("Shift+")?;
+        }
+        match &self.code {
+            KeyCode::Char(' ') => f.write_str("Space"),
+            KeyCode::Char(c) => write!(f, "{c}"),
+            code => write!(f, "{code:?}"),
+        }
+    }
+}
+
+/// Keys bound to each action
+#[derive(Debug, Clone)]
+pub struct KeyBindings {
+    bindings: Vec<(KeyAction, Vec<KeyBinding>)>,
+}
+
+impl KeyBindings {
+    /// Finds the action bound to this key, if any
+    pub fn action(&self, key: &KeyEvent) -> Option<KeyAction> {
+        KeyAction::ALL
+            .iter()
+            .copied()
+            .find(|action| self.keys(*action).iter().any(|b| b.matches(key)))
+    }
+
+    pub fn keys(&self, action: KeyAction) -> &[KeyBinding] {
+        self.bindings
+            .iter()
+            .find(|(a, _)| *a == action)
+            .map(|(_, keys)| keys.as_slice())
+            .unwrap_or_default()
+    }
+
+    fn set(&mut self, action: KeyAction, keys: Vec<KeyBinding>) {
+        match self.bindings.iter_mut().find(|(a, _)| *a == action) {
+            Some((_, old)) => *old = keys,
+            None => self.bindings.push((action, keys)),
+        }
+    }
+
+    /// Reads bindings from a file content, on top of the default ones
+    pub fn parse(content: &str) -> Result<Self> {
+        let mut bindings = Self::default();
+
+        for (i, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let parse_line = || -> Result<(KeyAction, Vec<KeyBinding>)> {
+                let (name, keys) = line
+                    .split_once('=')
+                    .ok_or_else(|| anyhow!("Expected 'action = [keys]'"))?;
+                let name = name.trim();
+                let action =
+                    KeyAction::from_name(name).ok_or_else(|| anyhow!("Unknown action '{name}'"))?;
+                Ok((action, parse_keys(keys)?))
+            };
+
+            let (action, keys) = parse_line().map_err(|e| anyhow!("Line {}: {e}", i + 1))?;
+            bindings.set(action, keys);
+        }
+
+        Ok(bindings)
+    }
+}
+
+/// Parses a list of quoted keys, maybe followed by a comment. Keys are read before looking for
+/// commas and comments so that `"#"` and `","` can be bound, `\"` and `\\` are escapes.
+fn parse_keys(s: &str) -> Result<Vec<KeyBinding>> {
+    let mut chars = s.trim_start().chars();
+    if chars.next() != Some('[') {
+        bail!("Keys must be a list");
+    }
+
+    let mut keys = vec![];
+    loop {
+        match chars.find(|c| !c.is_whitespace()) {
+            Some(']') => break,
+            Some('"') => {}
+            _ => bail!("Keys must be quoted"),
+        }
+        let mut key = String::new();
+        loop {
+            match chars.next() {
+                Some('"') => break,
+                Some('\\') => key.extend(chars.next()),
+                Some(c) => key.push(c),
+                None => bail!("Unterminated key"),
+            }
+        }
+        keys.push(KeyBinding::parse(&key)?);
+
+        match chars.find(|c| !c.is_whitespace()) {
+            Some(',') => {}
+            Some(']') => break,
+            _ => bail!("Keys must be separated by commas"),
+        }
+    }
+
+    let rest = chars.as_str().trim();
+    if !rest.is_empty() && !rest.starts_with('#') {
+        bail!("Unexpected '{rest}' after the keys");
+    }
+    Ok(keys)
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        use KeyCode::*;
+        let keys = |codes: &[KeyCode]| codes.iter().cloned().map(KeyBinding::new).collect();
+        let shifted = |codes: &[KeyCode]| codes.iter().cloned().map(KeyBinding::shift).collect();
+
+        Self {
+            bindings: vec![
+                (KeyAction::MoveUp, keys(&[Up, Char('8'), Char('k')])),
+                (KeyAction::MoveDown, keys(&[Down, Char('2'), Char('j')])),
+                (KeyAction::MoveLeft, keys(&[Left, Char('4'), Char('h')])),
+                (KeyAction::MoveRight, keys(&[Right, Char('6'), Char('l')])),
+                (KeyAction::MoveUpLeft, keys(&[Char('7'), Char('y')])),
+                (KeyAction::MoveUpRight, keys(&[Char('9'), Char('u')])),
+                (KeyAction::MoveDownLeft, keys(&[Char('1'), Char('b')])),
+                (KeyAction::MoveDownRight, keys(&[Char('3'), Char('n')])),
+                (KeyAction::Wait, keys(&[Char('5'), Char(' ')])),
+                (KeyAction::Rest, keys(&[Char('r')])),
+                (KeyAction::Disarm, keys(&[Char('x')])),
+                (KeyAction::ShareSenses, keys(&[Char('v')])),
+                (KeyAction::Undo, keys(&[Char('z'), Backspace])),
+                (KeyAction::Resense, keys(&[Char('e')])),
+                (KeyAction::Help, keys(&[Char('?')])),
+                (KeyAction::SenseUp, shifted(&[Up, Char('8'), Char('K')])),
+                (KeyAction::SenseDown, shifted(&[Down, Char('2'), Char('J')])),
+                (
+                    KeyAction::SenseIncr,
+                    shifted(&[Right, Char('6'), Char('L')]),
+                ),
+                (KeyAction::SenseDecr, shifted(&[Left, Char('4'), Char('H')])),
+                (KeyAction::CyclePreset, keys(&[Tab])),
+                (KeyAction::Minimap, keys(&[Char('m')])),
+                (KeyAction::Look, keys(&[Char(';')])),
+                (KeyAction::Examine, keys(&[Char('X')])),
+                (KeyAction::ExportMap, keys(&[Char('M')])),
+                (KeyAction::Redraw, keys(&[Char('R')])),
+                (KeyAction::Corrections, keys(&[Char('D')])),
+                (KeyAction::FocusGraph, keys(&[Char('F')])),
+                (KeyAction::Plan, keys(&[Char('p')])),
+            ],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_test() {
+        let bindings = KeyBindings::parse(
+            r#"
+            # Left handed
+            move_up = ["w", "Up"]
+            sense_incr = ["Shift+d"] # trailing comment
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            bindings.keys(KeyAction::MoveUp),
+            &[
+                KeyBinding::new(KeyCode::Char('w')),
+                KeyBinding::new(KeyCode::Up)
+            ]
+        );
+        assert_eq!(
+            bindings.keys(KeyAction::SenseIncr),
+            &[KeyBinding::shift(KeyCode::Char('d'))]
+        );
+        // Untouched actions keep their defaults
+        assert_eq!(
+            bindings.keys(KeyAction::Wait),
+            KeyBindings::default().keys(KeyAction::Wait)
+        );
+
+        // Keys that are also separators, once quoted
+        let bindings =
+            KeyBindings::parse(r##"wait = ["#", ",", "\"", "Shift+,"] # comment"##).unwrap();
+        assert_eq!(
+            bindings.keys(KeyAction::Wait),
+            &[
+                KeyBinding::new(KeyCode::Char('#')),
+                KeyBinding::new(KeyCode::Char(',')),
+                KeyBinding::new(KeyCode::Char('"')),
+                KeyBinding::shift(KeyCode::Char(','))
+            ]
+        );
+
+        assert!(KeyBindings::parse("jump = [\"x\"]").is_err());
+        assert!(KeyBindings::parse("wait = [\"x\" \"y\"]").is_err());
+        assert!(KeyBindings::parse("wait = [\"x\"] y").is_err());
+        assert!(KeyBindings::parse("wait = [\"Super+x\"]").is_err());
+    }
+}
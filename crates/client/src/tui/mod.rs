@@ -1,4 +1,5 @@
 pub mod app;
+pub mod keybindings;
 pub mod pages;
 pub mod state;
 pub mod theme;
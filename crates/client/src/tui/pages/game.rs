@@ -1,35 +1,65 @@
+use std::collections::VecDeque;
+
 use itertools::Itertools;
-use log::info;
 use losig_core::{
+    leaderboard::NAME_MAX_LEN,
     sense::SightedAllyStatus,
-    types::{ClientAction, Direction, FoeType, GameOver, GameOverStatus, Offset, Tile},
+    types::{ClientAction, Direction, FoeType, GameOver, GameOverStatus, Offset, REST_MAX, Tile},
 };
 use ratatui::{
     buffer::Buffer,
-    layout::{Constraint, Layout, Rect},
+    layout::{Constraint, Layout, Position, Rect},
     style::{Color, Style, Stylize},
-    text::Line,
+    text::{Line, Span},
     widgets::{Block, Borders, Clear, Widget},
 };
 
 use crate::{
+    logs::ClientLog,
     tui::{
         GameOverState, InputServices, RenderServices, THEME, ally_color,
-        state::{LimboState, TuiState},
-        utils::center,
+        keybindings::KeyAction,
+        state::{GameState, LimboState, PRESET_SLOTS, TuiState},
+        theme::FoeTypeRender,
+        utils::{centered_line, describe, popup},
         widgets::{
-            block_wrap::BlockWrap, help::HelpWidget, logs::LogsWidget, senses::SensesWidget,
+            block_wrap::BlockWrap,
+            corrections::CorrectionsWidget,
+            focus::FocusWidget,
+            help::HelpWidget,
+            logs::LogsWidget,
+            minimap::MinimapWidget,
+            senses::{SensesWidget, senses_height},
+            stage_intro::StageIntroWidget,
             timeline::TimelineWidget,
+            too_small::TooSmallWidget,
         },
     },
-    tui_adapter::{Event, KeyCode},
-    world::WorldView,
+    tui_adapter::{Event, KeyCode, MouseButton, MouseEvent, MouseEventKind},
+    world::{FOE_MEMORY_TURNS, WorldView},
 };
 
+/// Below this width, the senses are stacked under the map and the log is hidden
+const NARROW_WIDTH: u16 = 70;
+const MIN_WIDTH: u16 = 30;
+/// Rows kept for the map when the senses are stacked under it
+const STACKED_WORLD_HEIGHT: u16 = 9;
+/// Rows of the senses panel besides the senses: its border, the presets and the latency
+const SENSES_PANEL_EXTRA: u16 = 3;
+
 pub struct GamePage {}
 
 impl GamePage {
-    fn layout(area: Rect) -> [Rect; 3] {
+    fn layout(area: Rect, panel_height: u16) -> [Rect; 3] {
+        if area.width < NARROW_WIDTH {
+            let [world, senses] = Layout::vertical([
+                Constraint::Min(STACKED_WORLD_HEIGHT),
+                Constraint::Length(panel_height),
+            ])
+            .areas(area);
+            return [world, Rect::default(), senses];
+        }
+
         let [main, senses] =
             Layout::horizontal([Constraint::Percentage(70), Constraint::Percentage(30)])
                 .areas(area);
@@ -47,27 +77,122 @@ impl GamePage {
         state: &mut TuiState,
         services: RenderServices,
     ) {
-        let [world_a, log_a, _senses_a] = Self::layout(area);
         let world = &services.state.world;
-
-        let world_widget = WorldViewWidget { world };
+        let flash = world.attack_flash();
+        let panel_height = senses_height(&world.stage_info.senses) + SENSES_PANEL_EXTRA;
+        let min_height = if area.width < NARROW_WIDTH {
+            panel_height + STACKED_WORLD_HEIGHT
+        } else {
+            panel_height
+        };
+        if area.width < MIN_WIDTH || area.height < min_height {
+            TooSmallWidget {
+                width: MIN_WIDTH,
+                height: min_height,
+            }
+            .render(area, buf);
+            return;
+        }
+        let [world_a, log_a, _senses_a] = Self::layout(area, panel_height);
+
+        let world_widget = WorldViewWidget {
+            world,
+            view: state.game.look.unwrap_or_default(),
+            cursor: state.game.examine,
+            flash,
+            planned: state
+                .game
+                .planned_positions(world.current_state.position)
+                .into_iter()
+                .map(|position| position - world.current_state.position)
+                .collect(),
+        };
         let timeline = TimelineWidget::new(world);
 
-        Block::default()
-            .borders(Borders::ALL)
-            .title(timeline)
-            .wrap(world_widget)
-            .render(world_a, buf);
+        let mut world_block = Block::default().borders(Borders::ALL).title(timeline);
+        if world.is_incoherent() {
+            let redraw = state.game.keybindings.keys(KeyAction::Redraw).first();
+            let hint = redraw
+                .map(|k| format!(" ({k} to redraw)"))
+                .unwrap_or_default();
+            world_block = world_block.title_bottom(
+                Line::from(format!(" Incoherent map{hint} ")).fg(THEME.palette.log_paradox),
+            );
+        }
+        if state.game.disarming {
+            world_block = world_block.title_bottom(
+                Line::from(" Disarm in which direction? ").fg(THEME.palette.important),
+            );
+        }
+        if state.game.planning {
+            let plan = state.game.keybindings.keys(KeyAction::Plan).first();
+            let hint = plan.map(|k| format!(", {k} to go")).unwrap_or_default();
+            world_block = world_block.title_bottom(
+                Line::from(format!(" Planning moves{hint}, Esc to cancel "))
+                    .fg(THEME.palette.important),
+            );
+        }
+        if state.game.look.is_some() {
+            world_block = world_block.title_bottom(
+                Line::from(" Looking around (Esc to recenter) ").fg(THEME.palette.important),
+            );
+        }
+        state.game.world_area = world_block.inner(world_a);
+        world_block.wrap(world_widget).render(world_a, buf);
+
+        if state.game.show_minimap {
+            let world_area = state.game.world_area;
+            let width = (world_area.width / 3).min(34);
+            let height = (world_area.height / 3).min(18);
+            let minimap_a = Rect::new(
+                world_area.right().saturating_sub(width),
+                world_area.y,
+                width,
+                height,
+            );
+            MinimapWidget {
+                state: world.current_state(),
+            }
+            .render(minimap_a, buf);
+        }
+
+        if state.game.show_corrections
+            && let Some(corrections) = world.corrections()
+        {
+            let world_area = state.game.world_area;
+            let width = (world_area.width / 2).min(48);
+            let height = (world_area.height / 2).min(14);
+            let corrections_a = Rect::new(
+                world_area.x,
+                world_area.bottom().saturating_sub(height),
+                width,
+                height,
+            );
+            CorrectionsWidget { corrections }.render(corrections_a, buf);
+        }
+
+        if state.game.show_focus {
+            let world_area = state.game.world_area;
+            let width = (world_area.width / 2).min(40);
+            let height = (world_area.height / 3).clamp(3, 8).min(world_area.height);
+            let focus_a = Rect::new(world_area.x, world_area.y, width, height);
+            FocusWidget {
+                history: state.game.focus_history.values(),
+                cost: state.game.senses.cost(&world.stage_info.costs),
+            }
+            .render(focus_a, buf);
+        }
 
         let logs_widget = LogsWidget {
             logs: world.logs.logs(),
             current_turn: world.turn,
         };
-        Block::default()
-            .borders(Borders::ALL)
-            .title("Game Log")
-            .wrap(logs_widget)
-            .render(log_a, buf);
+        let mut log_block = Block::default().borders(Borders::ALL).title("Game Log");
+        if let Some(cursor) = state.game.examine {
+            let description = format!(" {} ", describe(world, cursor));
+            log_block = log_block.title_bottom(Line::from(description).fg(THEME.palette.important));
+        }
+        log_block.wrap(logs_widget).render(log_a, buf);
 
         let game_state = &mut state.game;
         let available_senses = &world.stage_info.senses;
@@ -78,16 +203,18 @@ impl GamePage {
             selection: game_state.sense_selection,
             max_sense: available_senses.len().saturating_sub(1),
             available_senses,
+            hearing_diagonal: world.stage_info.hearing_diagonal,
+            costs: &world.stage_info.costs,
         };
 
-        let cost = game_state.senses.cost();
+        let cost = game_state.senses.cost(&world.stage_info.costs);
         let focus = world
             .last_info()
             .and_then(|info| info.selfi.as_ref())
             .map(|selfi| selfi.focus);
 
+        let tired_style = Style::default().fg(Color::White).bg(Color::Red);
         let (cost_style, title) = if world.last_info().is_none() {
-            let tired_style = Style::default().fg(Color::White).bg(Color::Red);
             let tired_title = Line::from(" TIRED ").alignment(ratatui::layout::Alignment::Center);
             (tired_style, tired_title)
         } else {
@@ -110,9 +237,35 @@ impl GamePage {
             .wrap(senses_widget);
         senses_wigdet.render(_senses_a, buf);
 
+        // Display sense presets right above the latency, flagging the ones we can't afford
+        let mut preset_spans = vec![Span::from("Presets:")];
+        for (i, preset) in game_state.presets.iter().enumerate() {
+            let span = match preset {
+                Some(senses) => {
+                    let cost = senses.cost(&world.stage_info.costs);
+                    let style = if focus.is_some_and(|f| f < cost) {
+                        tired_style
+                    } else if game_state.active_preset == Some(i) {
+                        Style::default().fg(THEME.palette.ui_selected)
+                    } else {
+                        Style::default()
+                    };
+                    Span::from(format!(" {}:{}", i + 1, cost)).style(style)
+                }
+                None => Span::from(format!(" {}:-", i + 1)).style(THEME.palette.ui_disabled),
+            };
+            preset_spans.push(span);
+        }
+        let presets_y = _senses_a.y + _senses_a.height.saturating_sub(2);
+        let presets_area = Rect::new(_senses_a.x, presets_y, _senses_a.width, 1);
+        Line::from(preset_spans).render(presets_area, buf);
+
         // Display latency at the bottom of the senses panel
-        if let Some(latency) = world.last_latency {
-            let latency_text = format!("Latency: {}ms", latency.as_millis());
+        if let Some(latency) = world.avg_latency() {
+            let mut latency_text = format!("Latency: {}ms", latency.as_millis());
+            if let Some((min, max)) = world.latency_range() {
+                latency_text += &format!(" ({}-{}ms)", min.as_millis(), max.as_millis());
+            }
             let latency_style = Style::default().fg(Color::Black).bg(Color::White);
             let bottom_y = _senses_a.y + _senses_a.height.saturating_sub(1);
             let latency_area = Rect::new(_senses_a.x, bottom_y, _senses_a.width, 1);
@@ -133,7 +286,12 @@ impl GamePage {
         } else if state.limbo.open {
             LimboWidget {}.render(area, buf, state.limbo.averted, &mut state.limbo);
         } else if state.game.help.open {
-            HelpWidget.render(area, buf, &state.game.help);
+            HelpWidget.render(area, buf, &state.game.help, &state.game.keybindings);
+        } else if services.state.stage_intro {
+            StageIntroWidget {
+                info: &world.stage_info,
+            }
+            .render(area, buf);
         }
     }
 
@@ -155,7 +313,26 @@ impl GamePage {
 
         // If help is visible, let HelpWidget handle the event
         if state.game.help.open {
-            return HelpWidget.on_event(event, &mut state.game.help);
+            return HelpWidget.on_event(event, &mut state.game.help, &state.game.keybindings);
+        }
+
+        // The first key or click dismisses the stage intro
+        if services.state.stage_intro
+            && matches!(
+                event,
+                Event::Key(_)
+                    | Event::Mouse(MouseEvent {
+                        kind: MouseEventKind::Down(_),
+                        ..
+                    })
+            )
+        {
+            services.clear_stage_intro();
+            return true;
+        }
+
+        if let Event::Mouse(mouse) = event {
+            return self.on_mouse(mouse, state, services);
         }
 
         let Event::Key(key) = event else {
@@ -163,86 +340,375 @@ impl GamePage {
         };
 
         let game_state = &mut state.game;
-        if key.modifiers.shift {
-            let mut consumed = true;
-            match key.code {
-                KeyCode::Up | KeyCode::Char('8') | KeyCode::Char('K') => {
-                    game_state.sense_selection = game_state.sense_selection.saturating_sub(1);
-                }
-                KeyCode::Down | KeyCode::Char('2') | KeyCode::Char('J') => {
-                    let max_sense_idx = services.state.world.stage_info.senses.len().saturating_sub(1);
-                    if game_state.sense_selection < max_sense_idx {
-                        game_state.sense_selection += 1;
-                    }
-                }
-                KeyCode::Right | KeyCode::Char('6') | KeyCode::Char('L') => {
-                    game_state.incr_sense(&services.state.world.stage_info.senses);
-                }
-                KeyCode::Left | KeyCode::Char('4') | KeyCode::Char('H') => {
-                    game_state.decr_sense(&services.state.world.stage_info.senses);
-                }
-                _ => {
-                    consumed = false;
-                }
+        // Any key takes back control from click-to-move and planned moves
+        game_state.path.clear();
+        if !game_state.planning {
+            game_state.queue.clear();
+        }
+
+        // Sense presets: Alt + digit loads a slot, Ctrl + digit saves into it
+        if (key.modifiers.alt || key.modifiers.control)
+            && let KeyCode::Char(c) = key.code
+            && let Some(slot) = c.to_digit(10).map(|d| d as usize)
+            && (1..=PRESET_SLOTS).contains(&slot)
+        {
+            if key.modifiers.control {
+                game_state.save_preset(slot - 1);
+            } else {
+                game_state.load_preset(slot - 1);
             }
-            if consumed {
+            return true;
+        }
+
+        if key.code == KeyCode::Esc {
+            if std::mem::take(&mut game_state.planning) {
+                game_state.queue.clear();
+                return true;
+            }
+            if game_state.examine.take().is_some() {
+                return true;
+            }
+            if game_state.look.take().is_some() {
                 return true;
             }
         }
 
-        let action = match key.code {
-            KeyCode::Up | KeyCode::Char('8') | KeyCode::Char('k') => {
-                Some(ClientAction::MoveOrAttack(Direction::Up))
+        let Some(key_action) = game_state.keybindings.action(key) else {
+            game_state.disarming = false;
+            return false;
+        };
+
+        // The direction following a disarm key targets the trap
+        let disarming = std::mem::take(&mut game_state.disarming);
+        let action = match key_action {
+            KeyAction::CyclePreset => {
+                game_state.cycle_preset();
+                return true;
             }
-            KeyCode::Down | KeyCode::Char('2') | KeyCode::Char('j') => {
-                Some(ClientAction::MoveOrAttack(Direction::Down))
+            KeyAction::SenseUp => {
+                game_state.sense_selection = game_state.sense_selection.saturating_sub(1);
+                return true;
             }
-            KeyCode::Left | KeyCode::Char('4') | KeyCode::Char('h') => {
-                Some(ClientAction::MoveOrAttack(Direction::Left))
+            KeyAction::SenseDown => {
+                let max_sense_idx = services.state.world.stage_info.senses.len().saturating_sub(1);
+                if game_state.sense_selection < max_sense_idx {
+                    game_state.sense_selection += 1;
+                }
+                return true;
             }
-            KeyCode::Right | KeyCode::Char('6') | KeyCode::Char('l') => {
-                Some(ClientAction::MoveOrAttack(Direction::Right))
+            KeyAction::SenseIncr => {
+                game_state.incr_sense(&services.state.world.stage_info);
+                return true;
             }
-            KeyCode::Char('7') | KeyCode::Char('y') => {
-                Some(ClientAction::MoveOrAttack(Direction::UpLeft))
+            KeyAction::SenseDecr => {
+                game_state.decr_sense(&services.state.world.stage_info.senses);
+                return true;
             }
-            KeyCode::Char('9') | KeyCode::Char('u') => {
-                Some(ClientAction::MoveOrAttack(Direction::UpRight))
+            KeyAction::Help => {
+                game_state.help.open = true;
+                return true;
             }
-            KeyCode::Char('1') | KeyCode::Char('b') => {
-                Some(ClientAction::MoveOrAttack(Direction::DownLeft))
+            KeyAction::Minimap => {
+                game_state.show_minimap = !game_state.show_minimap;
+                return true;
             }
-            KeyCode::Char('3') | KeyCode::Char('n') => {
-                Some(ClientAction::MoveOrAttack(Direction::DownRight))
+            KeyAction::Plan => {
+                game_state.planning = !game_state.planning;
+                game_state.queue_expected = None;
+                return true;
             }
-            KeyCode::Char('5') | KeyCode::Char(' ') => Some(ClientAction::Wait),
-            KeyCode::Char('?') => {
-                game_state.help.open = true;
+            KeyAction::FocusGraph => {
+                game_state.show_focus = !game_state.show_focus;
                 return true;
             }
-            _ => None,
-        };
-        if let Some(action) = action {
-            // Check for wall collision before moving
-            if let ClientAction::MoveOrAttack(dir) = &action {
-                let new_pos = services.state.world.current_state.position + dir.offset();
-                let tile = services.state.world.current_state.tile_at(new_pos);
-                if !tile.can_travel() {
-                    // Cancel move into wall
+            KeyAction::Examine => {
+                game_state.examine = match game_state.examine {
+                    Some(_) => None,
+                    None => Some(game_state.look.unwrap_or_default()),
+                };
+                return true;
+            }
+            KeyAction::Look => {
+                game_state.look = match game_state.look {
+                    Some(_) => None,
+                    None => Some(Offset::default()),
+                };
+                return true;
+            }
+            KeyAction::ExportMap => {
+                let world = &mut services.state.world;
+                let exported = export_map(world);
+                world.logs.add(world.turn, ClientLog::MapExported(exported));
+                return true;
+            }
+            KeyAction::Redraw => {
+                services.state.world.redraw();
+                return true;
+            }
+            KeyAction::Corrections => {
+                if services.state.world.corrections().is_some() {
+                    game_state.show_corrections = !game_state.show_corrections;
+                }
+                return true;
+            }
+            KeyAction::Disarm => {
+                game_state.disarming = true;
+                return true;
+            }
+            KeyAction::ShareSenses => {
+                let world = &mut services.state.world;
+                if !world.stage_info.team_vision {
+                    world.logs.add(world.turn, ClientLog::NoTeamVision);
                     return true;
                 }
+                game_state.share_senses = !game_state.share_senses;
+                world
+                    .logs
+                    .add(world.turn, ClientLog::ShareSenses(game_state.share_senses));
+                services.share_senses(game_state.share_senses);
+                return true;
+            }
+            KeyAction::Undo => {
+                if game_state.planning {
+                    game_state.queue.pop_back();
+                } else {
+                    services.undo(game_state.senses.clone());
+                }
+                return true;
+            }
+            KeyAction::Resense => {
+                services.resense(game_state.senses.clone());
+                return true;
+            }
+            KeyAction::MoveUp => ClientAction::MoveOrAttack(Direction::Up),
+            KeyAction::MoveDown => ClientAction::MoveOrAttack(Direction::Down),
+            KeyAction::MoveLeft => ClientAction::MoveOrAttack(Direction::Left),
+            KeyAction::MoveRight => ClientAction::MoveOrAttack(Direction::Right),
+            KeyAction::MoveUpLeft => ClientAction::MoveOrAttack(Direction::UpLeft),
+            KeyAction::MoveUpRight => ClientAction::MoveOrAttack(Direction::UpRight),
+            KeyAction::MoveDownLeft => ClientAction::MoveOrAttack(Direction::DownLeft),
+            KeyAction::MoveDownRight => ClientAction::MoveOrAttack(Direction::DownRight),
+            KeyAction::Wait => ClientAction::Wait,
+            KeyAction::Rest => ClientAction::Rest(REST_MAX),
+        };
+
+        // While examining, moves pick the cell within the view and nothing takes a turn
+        if let Some(cursor) = game_state.examine {
+            if let ClientAction::MoveOrAttack(dir) = action {
+                let area = game_state.world_area;
+                let target = cursor + dir.offset() - game_state.look.unwrap_or_default();
+                let in_view = target.x.abs() <= area.width as isize / 2
+                    && target.y.abs() <= area.height as isize / 2;
+                if in_view {
+                    game_state.examine = Some(cursor + dir.offset());
+                }
             }
+            return true;
+        }
 
-            services.act(action, game_state.senses.clone());
+        // While looking around, moves pan the view and nothing takes a turn
+        if let Some(look) = game_state.look {
+            if let ClientAction::MoveOrAttack(dir) = action {
+                let state = &services.state.world.current_state;
+                let target = state.position + look + dir.offset();
+                let explored = state.explored().is_some_and(|(min, max)| {
+                    (min.x..=max.x).contains(&target.x) && (min.y..=max.y).contains(&target.y)
+                });
+                if explored {
+                    game_state.look = Some(look + dir.offset());
+                }
+            }
+            return true;
+        }
+
+        let action = match action {
+            ClientAction::MoveOrAttack(dir) if disarming => ClientAction::Disarm(dir),
+            action => action,
+        };
+
+        if game_state.planning {
+            let world = &services.state.world;
+            game_state.plan(
+                action,
+                &world.current_state,
+                world.stage_info.allow_diagonal,
+            );
             return true;
         }
-        false
+
+        // Check for wall collision before moving
+        if let ClientAction::MoveOrAttack(dir) = &action {
+            if dir.is_diagonal() && !services.state.world.stage_info.allow_diagonal {
+                // Diagonals are disabled on this stage
+                return true;
+            }
+            let new_pos = services.state.world.current_state.position + dir.offset();
+            let tile = services.state.world.current_state.tile_at(new_pos);
+            if !tile.can_travel() {
+                // Cancel move into wall
+                return true;
+            }
+        }
+
+        services.act(action, game_state.senses.clone());
+        true
+    }
+
+    fn on_mouse(self, mouse: &MouseEvent, state: &mut TuiState, services: InputServices) -> bool {
+        let game_state = &mut state.game;
+        if !game_state.planning {
+            game_state.queue.clear();
+        }
+        match mouse.kind {
+            MouseEventKind::Down(MouseButton::Left) => {
+                let area = game_state.world_area;
+                if !area.contains(Position::new(mouse.column, mouse.row)) {
+                    return false;
+                }
+
+                // Same centering as WorldViewWidget
+                let offset = Offset {
+                    x: (mouse.column - area.x) as isize - area.width as isize / 2,
+                    y: (mouse.row - area.y) as isize - area.height as isize / 2,
+                } + game_state.look.unwrap_or_default();
+                let world = &services.state.world;
+                let position = world.current_state.position;
+                let target = position + offset;
+
+                game_state.path = if target.dist(&position) == 1 {
+                    // Allows attacking, which a path through known tiles would not
+                    VecDeque::from([target])
+                } else {
+                    let diagonal = world.stage_info.allow_diagonal;
+                    world
+                        .current_state
+                        .path_to(target, diagonal)
+                        .unwrap_or_default()
+                        .into()
+                };
+                Self::follow_path(game_state, services);
+                true
+            }
+            MouseEventKind::Down(MouseButton::Right) => {
+                game_state.path.clear();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Keeps the focus of the last info for the focus panel
+    pub fn record_focus(state: &mut GameState, services: RenderServices) {
+        let world = &services.state.world;
+        if let Some(selfi) = world.last_info().and_then(|info| info.selfi.as_ref()) {
+            state.focus_history.record(world.turn, selfi.focus);
+        }
+    }
+
+    /// Takes the next step of a click-to-move path once the previous one got its response
+    pub fn follow_path(state: &mut GameState, mut services: InputServices) {
+        if state.path.is_empty() || services.state.world.awaiting_response() {
+            return;
+        }
+        if services.state.gameover.is_some() || services.state.limbo.is_some() {
+            state.path.clear();
+            return;
+        }
+
+        let position = services.state.world.current_state.position;
+        let Some(next) = state.path.pop_front() else {
+            return;
+        };
+
+        match Direction::from_offset(next - position) {
+            // Stop if we didn't end up where expected, e.g. after an attack
+            Some(dir) if next.dist(&position) == 1 => {
+                if dir.is_diagonal() && !services.state.world.stage_info.allow_diagonal {
+                    state.path.clear();
+                    return;
+                }
+                services.act(ClientAction::MoveOrAttack(dir), state.senses.clone());
+            }
+            _ => state.path.clear(),
+        }
+    }
+
+    /// Plays the next planned action once the previous one got its response. The plan is dropped
+    /// when a foe is in sight, the avatar got hurt or did not end up where planned.
+    pub fn follow_queue(state: &mut GameState, mut services: InputServices) {
+        if state.planning || state.queue.is_empty() || services.state.world.awaiting_response() {
+            return;
+        }
+        if services.state.gameover.is_some() || services.state.limbo.is_some() {
+            state.queue.clear();
+            return;
+        }
+
+        let world = &mut services.state.world;
+        let position = world.current_state.position;
+        let info = world.last_info();
+        let hp = info
+            .and_then(|info| info.selfi.as_ref())
+            .map(|selfi| selfi.hp);
+        let foe_in_sight = info
+            .and_then(|info| info.sight.as_ref())
+            .is_some_and(|sight| sight.foes.iter().any(|foe| foe.alive));
+        let off_plan = state
+            .queue_expected
+            .is_some_and(|(expected, last_hp)| expected != position || hp < last_hp);
+        let next = state.queue.pop_front();
+        let blocked = match next {
+            Some(ClientAction::MoveOrAttack(dir)) => !world
+                .current_state
+                .tile_at(position + dir.offset())
+                .can_travel(),
+            _ => false,
+        };
+        let Some(action) = next.filter(|_| !foe_in_sight && !off_plan && !blocked) else {
+            state.queue.clear();
+            world.logs.add(world.turn, ClientLog::PlanStopped);
+            return;
+        };
+
+        let expected = match action {
+            ClientAction::MoveOrAttack(dir) => position + dir.offset(),
+            _ => position,
+        };
+        state.queue_expected = Some((expected, hp));
+        services.act(action, state.senses.clone());
     }
 }
 
 // Game tile styles are now inline to use THEME palette
 const DEFAULT_STYLE: &Style = &Style::new();
 
+/// File the explored map is written to, in the working directory
+pub const MAP_EXPORT_FILE: &str = "losig_map.txt";
+
+/// Writes the explored map with the same glyphs as the game view, marking the avatar and the orb
+/// last seen
+fn export_map(world: &WorldView) -> bool {
+    let state = world.current_state();
+    let mut marks = vec![];
+    if let Some(orb) = world
+        .last_info()
+        .and_then(|info| info.sight.as_ref())
+        .and_then(|sight| sight.orb)
+    {
+        marks.push((state.position + orb, 'o'));
+    }
+    marks.push((state.position, '@'));
+
+    let text = state.to_text(|tile| render_tile(tile).0, &marks);
+    match std::fs::write(MAP_EXPORT_FILE, text) {
+        Ok(()) => true,
+        Err(e) => {
+            log::warn!("Could not write the map to {MAP_EXPORT_FILE}: {e}");
+            false
+        }
+    }
+}
+
 fn render_tile(tile: Tile) -> (char, Style) {
     match tile {
         Tile::Spawn => ('_', Style::new().fg(THEME.palette.important)),
@@ -255,8 +721,27 @@ fn render_tile(tile: Tile) -> (char, Style) {
     }
 }
 
-struct WorldViewWidget<'a> {
-    world: &'a WorldView,
+pub struct WorldViewWidget<'a> {
+    pub world: &'a WorldView,
+    /// Offset of the rendered center from the avatar
+    pub view: Offset,
+    /// Cell highlighted while examining, from the avatar
+    pub cursor: Option<Offset>,
+    /// Cell the avatar just attacked, from the avatar
+    pub flash: Option<Offset>,
+    /// Cells the planned moves go through, from the avatar
+    pub planned: Vec<Offset>,
+}
+
+impl<'a> WorldViewWidget<'a> {
+    /// Cell showing what is at this offset from the avatar, if it is in the area
+    fn cell(&self, area: Rect, offset: Offset) -> Option<(u16, u16)> {
+        let x = area.width as isize / 2 + offset.x - self.view.x;
+        let y = area.height as isize / 2 + offset.y - self.view.y;
+        let in_area =
+            (0..area.width as isize).contains(&x) && (0..area.height as isize).contains(&y);
+        in_area.then(|| (area.x + x as u16, area.y + y as u16))
+    }
 }
 
 impl<'a> Widget for WorldViewWidget<'a> {
@@ -271,7 +756,7 @@ impl<'a> Widget for WorldViewWidget<'a> {
                 let offset = Offset {
                     x: x as isize - center_x,
                     y: y as isize - center_y,
-                };
+                } + self.view;
 
                 let tile = w.current_state().tile_from_viewer(offset);
 
@@ -296,24 +781,43 @@ impl<'a> Widget for WorldViewWidget<'a> {
             }
         }
 
+        // Foes out of sight where they were last seen, fading as the sighting gets older
+        for (offset, foe) in w.current_state().stale_foes() {
+            if let Some((x, y)) = self.cell(area, offset) {
+                let style = if foe.age <= FOE_MEMORY_TURNS / 2 {
+                    Style::default().fg(THEME.palette.foe).dim()
+                } else {
+                    Style::default().fg(THEME.palette.tile_unseen)
+                };
+                buf.set_string(x, y, foe.foe_type.grapheme(), style);
+            }
+        }
+
+        for offset in &self.planned {
+            if let Some((x, y)) = self.cell(area, *offset) {
+                buf.set_string(x, y, "+", Style::default().fg(THEME.palette.ui_disabled));
+            }
+        }
+
         if let Some(sight) = last_info.and_then(|i| i.sight.as_ref()) {
-            // Show the orb
-            if let Some(ref offset) = sight.orb {
-                let x = center_x + offset.x;
-                let y = center_y + offset.y;
+            for offset in sight.shards.iter() {
+                if let Some((x, y)) = self.cell(area, *offset) {
+                    buf.set_string(x, y, "*", THEME.palette.important);
+                }
+            }
 
-                buf.set_string(
-                    area.x + x as u16,
-                    area.y + y as u16,
-                    "o",
-                    THEME.palette.important,
-                );
+            // Show the orb
+            if let Some(offset) = sight.orb
+                && let Some((x, y)) = self.cell(area, offset)
+            {
+                buf.set_string(x, y, "o", THEME.palette.important);
             }
 
             // Show the foes
             for foe in sight.foes.iter().sorted_by_key(|f| f.alive) {
-                let x = center_x + foe.offset.x;
-                let y = center_y + foe.offset.y;
+                let Some((x, y)) = self.cell(area, foe.offset) else {
+                    continue;
+                };
 
                 let char = match foe.foe_type {
                     FoeType::Dummy => "d",
@@ -327,27 +831,26 @@ impl<'a> Widget for WorldViewWidget<'a> {
                     Style::default().fg(THEME.palette.ui_disabled)
                 };
 
-                buf.set_string(area.x + x as u16, area.y + y as u16, char, style);
+                buf.set_string(x, y, char, style);
             }
 
             // Show the allies
             for ally in &sight.allies {
-                let x = center_x + ally.offset.x;
-                let y = center_y + ally.offset.y;
+                if let Some((x, y)) = self.cell(area, ally.offset) {
+                    let color = match ally.status {
+                        SightedAllyStatus::Controlled { turn, .. } => {
+                            ally_color(turn, w.stage_turn)
+                        }
+                        SightedAllyStatus::Discarded => THEME.palette.ally_discarded,
+                    };
+                    buf.set_string(x, y, "@", color);
+                }
 
-                let color = match ally.status {
-                    SightedAllyStatus::Controlled { turn, .. } => ally_color(turn, w.stage_turn),
-                    SightedAllyStatus::Discarded => THEME.palette.ally_discarded,
-                };
-                buf.set_string(area.x + x as u16, area.y + y as u16, "@", color);
-
-                if let Some(offset) = ally.next_move {
-                    info!(
-                        "{}; {}; {}| {}, {}, {}",
-                        area.x, center_x, offset.x, area.y, center_y, offset.y
-                    );
-                    let x = area.x + (center_x + offset.x) as u16;
-                    let y = area.y + (center_y + offset.y) as u16;
+                // Offsets of sighted things are relative to the viewer, except the next move
+                // which is relative to the ally
+                if let Some(step) = ally.next_move
+                    && let Some((x, y)) = self.cell(area, ally.offset + step)
+                {
                     let area = Rect::new(x, y, 1, 1);
                     buf.set_style(area, Style::default().bg(THEME.palette.ally_next_move));
                 }
@@ -360,27 +863,29 @@ impl<'a> Widget for WorldViewWidget<'a> {
         // Render touched foes as "?" when sight is inactive
         if !has_sight && let Some(touch) = touch_info {
             for offset in &touch.foes {
-                let x = center_x + offset.x;
-                let y = center_y + offset.y;
-                buf.set_string(area.x + x as u16, area.y + y as u16, "?", THEME.palette.foe);
+                if let Some((x, y)) = self.cell(area, *offset) {
+                    buf.set_string(x, y, "?", THEME.palette.foe);
+                }
             }
         }
 
-        let neigboring_traps = touch_info.map(|it| it.traps).unwrap_or_default();
-        if !has_sight && neigboring_traps > 0 {
-            buf.set_string(
-                area.x + center_x as u16,
-                area.y + center_y as u16,
-                neigboring_traps.to_string(),
-                THEME.palette.trap,
-            );
-        } else {
-            buf.set_string(
-                area.x + center_x as u16,
-                area.y + center_y as u16,
-                "@",
-                THEME.palette.avatar,
-            );
+        if let Some((x, y)) = self.cell(area, Offset::default()) {
+            let neigboring_traps = touch_info.map(|it| it.traps).unwrap_or_default();
+            if !has_sight && neigboring_traps > 0 {
+                buf.set_string(x, y, neigboring_traps.to_string(), THEME.palette.trap);
+            } else {
+                buf.set_string(x, y, "@", THEME.palette.avatar);
+            }
+        }
+
+        if let Some((x, y)) = self.flash.and_then(|flash| self.cell(area, flash)) {
+            let area = Rect::new(x, y, 1, 1);
+            buf.set_style(area, Style::default().bg(THEME.palette.foe));
+        }
+
+        if let Some((x, y)) = self.cursor.and_then(|cursor| self.cell(area, cursor)) {
+            let area = Rect::new(x, y, 1, 1);
+            buf.set_style(area, Style::default().bg(THEME.palette.ui_selected));
         }
     }
 }
@@ -395,14 +900,7 @@ impl GameOverWidget {
         gameover: &GameOver,
         state: &mut GameOverState,
     ) {
-        let popup_width = 50;
-        let popup_height = 10;
-
-        let popup_area = center(
-            area,
-            Constraint::Length(popup_width),
-            Constraint::Length(popup_height),
-        );
+        let popup_area = popup(area, 50, 12);
 
         // Clear the popup area to reset style
         Clear.render(popup_area, buf);
@@ -430,25 +928,33 @@ impl GameOverWidget {
 
             for (i, line) in lines.iter().enumerate() {
                 let y = inner.y + 2 + i as u16;
-                let x = inner.x + (inner.width.saturating_sub(line.len() as u16)) / 2;
                 let style = if i == 0 {
                     Style::default().fg(THEME.palette.important)
                 } else {
                     Style::default().fg(THEME.palette.ui_disabled)
                 };
-                buf.set_string(x, y, line, style);
+                centered_line(buf, inner, y, line, style);
             }
         } else {
             // Show game stats and name input form
-            let stage_line = format!("Stage: {}", gameover.stage);
+            let stage_line = match gameover.seed {
+                Some(seed) => format!("Stage: {} (seed {seed})", gameover.stage),
+                None => format!("Stage: {}", gameover.stage),
+            };
             let turns_line = format!("Turns: {}", gameover.turns);
             let score_line = format!("Score: {}", gameover.score);
+            let stealth_line = format!(
+                "Stealth: {} ({:.1} focus per turn)",
+                gameover.stealth(),
+                gameover.avg_sense_cost
+            );
             let name_line = format!("> {}_", state.name);
 
             let stats_lines = [
                 stage_line.as_str(),
                 turns_line.as_str(),
                 score_line.as_str(),
+                stealth_line.as_str(),
                 "",
                 "Enter your name for the leaderboard:",
                 "",
@@ -459,15 +965,14 @@ impl GameOverWidget {
 
             for (i, line) in stats_lines.iter().enumerate() {
                 let y = inner.y + i as u16;
-                let x = inner.x + (inner.width.saturating_sub(line.len() as u16)) / 2;
                 let style = match i {
-                    0..=2 => Style::default().fg(THEME.palette.important), // Stats
-                    4 => Style::default().fg(THEME.palette.ui_text),       // Prompt
-                    6 => Style::default().fg(THEME.palette.ui_text),       // Name input
-                    8 => Style::default().fg(THEME.palette.ui_text),       // Instructions
+                    0..=3 => Style::default().fg(THEME.palette.important), // Stats
+                    5 => Style::default().fg(THEME.palette.ui_text),       // Prompt
+                    7 => Style::default().fg(THEME.palette.ui_text),       // Name input
+                    9 => Style::default().fg(THEME.palette.ui_text),       // Instructions
                     _ => Style::default().fg(THEME.palette.ui_text),
                 };
-                buf.set_string(x, y, line, style);
+                centered_line(buf, inner, y, line, style);
             }
         }
     }
@@ -492,16 +997,14 @@ impl GameOverWidget {
         };
 
         match event.code {
-            KeyCode::Enter => {
-                if !you_win.name.is_empty() {
-                    services.submit_leaderboard(you_win.name.clone());
-                    you_win.sent = true;
-                }
+            KeyCode::Enter if !you_win.name.is_empty() => {
+                services.submit_leaderboard(you_win.name.clone());
+                you_win.sent = true;
             }
             KeyCode::Backspace => {
                 you_win.name.pop();
             }
-            KeyCode::Char(c) if you_win.name.len() < 8 => {
+            KeyCode::Char(c) if you_win.name.chars().count() < NAME_MAX_LEN => {
                 you_win.name.push(c);
             }
             KeyCode::Esc => {
@@ -518,14 +1021,7 @@ struct LimboWidget {}
 
 impl LimboWidget {
     pub fn render(self, area: Rect, buf: &mut Buffer, averted: bool, _state: &mut LimboState) {
-        let popup_width = 50;
-        let popup_height = 10;
-
-        let popup_area = center(
-            area,
-            Constraint::Length(popup_width),
-            Constraint::Length(popup_height),
-        );
+        let popup_area = popup(area, 50, 10);
 
         // Clear the popup area to reset style
         Clear.render(popup_area, buf);
@@ -565,13 +1061,12 @@ impl LimboWidget {
 
         for (i, line) in lines.iter().enumerate() {
             let y = inner.y + 1 + i as u16;
-            let x = inner.x + (inner.width.saturating_sub(line.len() as u16)) / 2;
             let style = if i == 0 {
                 Style::default().fg(THEME.palette.important)
             } else {
                 Style::default().fg(THEME.palette.ui_text)
             };
-            buf.set_string(x, y, line, style);
+            centered_line(buf, inner, y, line, style);
         }
     }
 
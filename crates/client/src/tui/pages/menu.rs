@@ -1,5 +1,5 @@
 use losig_core::{
-    leaderboard::Leaderboard,
+    leaderboard::{LEADERBOARD_TOP, Leaderboard, NAME_MAX_LEN, sanitize_name},
     types::{ClientAction, GameOverStatus},
 };
 use ratatui::{
@@ -14,7 +14,7 @@ use crate::{
     tui::{
         InputServices, MenuState, RenderServices, THEME,
         state::{PageSelection, TuiState},
-        utils::center,
+        utils::{center, centered_line, popup},
     },
     tui_adapter::{Event, KeyCode},
 };
@@ -23,6 +23,8 @@ use crate::{
 pub enum MenuOption {
     Start,
     Continue,
+    Spectate,
+    Replay,
 }
 
 impl Display for MenuOption {
@@ -30,12 +32,19 @@ impl Display for MenuOption {
         let string = match self {
             MenuOption::Start => "Start Game",
             MenuOption::Continue => "Continue Game",
+            MenuOption::Spectate => "Spectate",
+            MenuOption::Replay => "Replay Last Run",
         };
         f.write_str(string)
     }
 }
 
-const MENU_OPTIONS: &[MenuOption] = &[MenuOption::Start, MenuOption::Continue];
+const MENU_OPTIONS: &[MenuOption] = &[
+    MenuOption::Start,
+    MenuOption::Continue,
+    MenuOption::Spectate,
+    MenuOption::Replay,
+];
 
 pub struct MenuPage {}
 
@@ -63,12 +72,23 @@ impl MenuPage {
                     match selection {
                         MenuOption::Start => {
                             state.menu.entering_name = true;
-                            state.menu.name.clear();
+                            state.menu.name = state.menu.default_name.clone();
                         }
                         MenuOption::Continue => {
                             services.act(ClientAction::Wait, Default::default());
                             state.page = PageSelection::Game;
                         }
+                        MenuOption::Spectate => {
+                            // Start where someone plays
+                            let lobby = &services.state.lobby;
+                            let stage_id = lobby.per_stage.first().map_or(0, |(stage, _)| *stage);
+                            services.spectate(stage_id);
+                            state.page = PageSelection::Spectate;
+                        }
+                        MenuOption::Replay => {
+                            state.replay.index = 0;
+                            state.page = PageSelection::Replay;
+                        }
                     }
                 }
             }
@@ -84,11 +104,11 @@ impl MenuPage {
         self,
         key: &crate::tui_adapter::KeyEvent,
         state: &mut TuiState,
-        services: InputServices,
+        mut services: InputServices,
     ) -> bool {
         match key.code {
             KeyCode::Char(c) => {
-                if state.menu.name.len() < 8 {
+                if state.menu.name.chars().count() < NAME_MAX_LEN {
                     state.menu.name.push(c);
                 }
             }
@@ -96,12 +116,10 @@ impl MenuPage {
                 state.menu.name.pop();
             }
             KeyCode::Enter => {
-                let name = if state.menu.name.is_empty() {
-                    None
-                } else {
-                    Some(state.menu.name.clone())
-                };
-                services.new_game(name);
+                services.new_game(sanitize_name(&state.menu.name));
+                state.game.focus_history = Default::default();
+                state.game.queue.clear();
+                state.game.planning = false;
                 state.menu.entering_name = false;
                 state.page = PageSelection::Game;
             }
@@ -166,6 +184,30 @@ impl MenuPage {
             &mut state.list_state,
         );
 
+        // Online players below the menu
+        let lobby = &services.state.lobby;
+        let mut lobby_lines = vec![format!("{} players online", lobby.online)];
+        lobby_lines.extend(
+            lobby
+                .per_stage
+                .iter()
+                .map(|(stage, count)| format!("stage {}: {}", stage, count)),
+        );
+        let lobby_area = Rect {
+            y: menu_center.bottom() + 1,
+            height: lobby_lines.len() as u16,
+            ..menu_center
+        }
+        .intersection(chunks[0]);
+        for (y, line) in (lobby_area.y..lobby_area.bottom()).zip(lobby_lines) {
+            buf.set_string(
+                lobby_area.x,
+                y,
+                line,
+                Style::default().fg(THEME.palette.ui_disabled),
+            );
+        }
+
         // Leaderboard on the right
         let leaderboard_widget = LeaderboardWidget::new(&services.state.leaderboard);
         leaderboard_widget.render(chunks[1], buf);
@@ -174,14 +216,7 @@ impl MenuPage {
     fn render_name_input(self, area: Rect, buf: &mut Buffer, state: &MenuState) {
         use ratatui::widgets::Clear;
 
-        let popup_width = 50;
-        let popup_height = 8;
-
-        let popup_area = center(
-            area,
-            Constraint::Length(popup_width),
-            Constraint::Length(popup_height),
-        );
+        let popup_area = popup(area, 50, 8);
 
         // Clear the popup area to reset style
         Clear.render(popup_area, buf);
@@ -206,14 +241,13 @@ impl MenuPage {
 
         for (i, line) in lines.iter().enumerate() {
             let y = inner.y + i as u16;
-            let x = inner.x + (inner.width.saturating_sub(line.len() as u16)) / 2;
             let style = match i {
                 0 => Style::default().fg(THEME.palette.ui_text).bold(),
                 2 => Style::default().fg(THEME.palette.ui_highlight),
                 4 | 6 => Style::default().fg(THEME.palette.ui_disabled),
                 _ => Style::default().fg(THEME.palette.ui_text),
             };
-            buf.set_string(x, y, line, style);
+            centered_line(buf, inner, y, line, style);
         }
     }
 }
@@ -227,14 +261,14 @@ impl<'a> LeaderboardWidget<'a> {
     fn new(leaderboard: &'a Leaderboard) -> Self {
         Self {
             leaderboard,
-            max_entries: 10,
+            max_entries: LEADERBOARD_TOP,
         }
     }
 }
 
 impl<'a> Widget for LeaderboardWidget<'a> {
     fn render(self, area: Rect, buf: &mut Buffer) {
-        let area = center(area, Constraint::Length(36), Constraint::Length(12));
+        let area = center(area, Constraint::Length(52), Constraint::Length(12));
         let top_entries = self.leaderboard.top_entries(self.max_entries);
 
         let header = Row::new(vec![
@@ -243,6 +277,8 @@ impl<'a> Widget for LeaderboardWidget<'a> {
             Cell::from("Stage").style(Style::default().bold()),
             Cell::from("Turns").style(Style::default().bold()),
             Cell::from("Score").style(Style::default().bold()),
+            Cell::from("Stealth").style(Style::default().bold()),
+            Cell::from("Seed").style(Style::default().bold()),
         ]);
 
         let mut rows = Vec::new();
@@ -261,6 +297,13 @@ impl<'a> Widget for LeaderboardWidget<'a> {
                     }),
                     Cell::from(entry.gameover.turns.to_string()),
                     Cell::from(entry.gameover.score.to_string()),
+                    Cell::from(entry.gameover.stealth()),
+                    Cell::from(
+                        entry
+                            .gameover
+                            .seed
+                            .map_or_else(|| "-".to_owned(), |seed| seed.to_string()),
+                    ),
                 ]);
                 rows.push(row);
             } else {
@@ -270,6 +313,8 @@ impl<'a> Widget for LeaderboardWidget<'a> {
                     Cell::from("-"),
                     Cell::from("-"),
                     Cell::from("-"),
+                    Cell::from("-"),
+                    Cell::from("-"),
                 ])
                 .style(THEME.palette.ui_disabled);
                 rows.push(row);
@@ -284,6 +329,8 @@ impl<'a> Widget for LeaderboardWidget<'a> {
                 Constraint::Length(6), // Deaths
                 Constraint::Length(6), // Turns
                 Constraint::Length(6), // Score
+                Constraint::Length(7), // Stealth
+                Constraint::Length(8), // Seed
             ],
         )
         .header(header)
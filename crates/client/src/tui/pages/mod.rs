@@ -1,5 +1,9 @@
 mod game;
 mod menu;
+mod replay;
+mod spectate;
 
 pub use game::*;
 pub use menu::*;
+pub use replay::*;
+pub use spectate::*;
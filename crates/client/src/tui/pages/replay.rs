@@ -0,0 +1,92 @@
+use losig_core::types::Offset;
+use ratatui::{
+    buffer::Buffer,
+    layout::{Constraint, Rect},
+    widgets::{Block, Borders, Paragraph, Widget},
+};
+
+use crate::{
+    tui::{
+        InputServices, RenderServices,
+        pages::WorldViewWidget,
+        state::{PageSelection, ReplayState, TuiState},
+        utils::center,
+        widgets::block_wrap::BlockWrap,
+    },
+    tui_adapter::{Event, KeyCode},
+};
+
+/// Turns skipped by a page up or down
+const REPLAY_JUMP: usize = 10;
+
+/// Steps through the turns of the last run, as the player knew them
+pub struct ReplayPage {}
+
+impl ReplayPage {
+    pub fn render(
+        self,
+        area: Rect,
+        buf: &mut Buffer,
+        state: &mut ReplayState,
+        services: RenderServices,
+    ) {
+        let world = &services.state.world;
+        let len = world.replay_len();
+        state.index = state.index.min(len.saturating_sub(1));
+
+        let block = Block::default().borders(Borders::ALL).title_bottom(
+            " h/l: step, PageUp/PageDown: jump, Home/End: first/last turn, Esc: back ",
+        );
+        let Some(frame) = world.replay_frame(state.index) else {
+            let inner = block.inner(area);
+            block.render(area, buf);
+            let text = "Nothing to replay yet.";
+            let text_area = center(
+                inner,
+                Constraint::Length(text.len() as u16),
+                Constraint::Length(1),
+            );
+            Paragraph::new(text).render(text_area, buf);
+            return;
+        };
+
+        let world_widget = WorldViewWidget {
+            world: &frame,
+            view: Offset::default(),
+            cursor: None,
+            flash: None,
+            planned: vec![],
+        };
+        block
+            .title(format!(
+                " Replay of {} - turn {}/{len} ",
+                frame.stage_info.name,
+                state.index + 1
+            ))
+            .wrap(world_widget)
+            .render(area, buf);
+    }
+
+    pub fn on_event(self, event: &Event, state: &mut TuiState, services: InputServices) -> bool {
+        let Event::Key(key) = event else {
+            return false;
+        };
+
+        let last = services.state.world.replay_len().saturating_sub(1);
+        let index = &mut state.replay.index;
+        match key.code {
+            KeyCode::Char('h') | KeyCode::Left => *index = index.saturating_sub(1),
+            KeyCode::Char('l') | KeyCode::Right => *index = (*index + 1).min(last),
+            KeyCode::PageUp => *index = index.saturating_sub(REPLAY_JUMP),
+            KeyCode::PageDown => *index = (*index + REPLAY_JUMP).min(last),
+            KeyCode::Home => *index = 0,
+            KeyCode::End => *index = last,
+            KeyCode::Esc => state.page = PageSelection::Menu,
+            _ => {
+                return false;
+            }
+        }
+
+        true
+    }
+}
@@ -0,0 +1,72 @@
+use losig_core::types::Offset;
+use ratatui::{
+    buffer::Buffer,
+    layout::Rect,
+    widgets::{Block, Borders, Widget},
+};
+
+use crate::{
+    tui::{
+        InputServices, RenderServices,
+        pages::WorldViewWidget,
+        state::{PageSelection, TuiState},
+        widgets::{block_wrap::BlockWrap, timeline::TimelineWidget},
+    },
+    tui_adapter::{Event, KeyCode},
+};
+
+/// Read-only view of a whole stage
+pub struct SpectatePage {}
+
+impl SpectatePage {
+    pub fn render(self, area: Rect, buf: &mut Buffer, services: RenderServices) {
+        let world = &services.state.world;
+
+        let world_widget = WorldViewWidget {
+            world,
+            view: Offset::default(),
+            cursor: None,
+            flash: None,
+            planned: vec![],
+        };
+        let timeline = TimelineWidget::new(world);
+
+        Block::default()
+            .borders(Borders::ALL)
+            .title(timeline)
+            .title_bottom(format!(
+                " Spectating {} - h/l: change stage, Esc: back ",
+                world.stage_info.name
+            ))
+            .wrap(world_widget)
+            .render(area, buf);
+    }
+
+    pub fn on_event(self, event: &Event, state: &mut TuiState, services: InputServices) -> bool {
+        let Event::Key(key) = event else {
+            return false;
+        };
+
+        let stage_id = services.state.world.stage_id;
+        let stages = services.state.lobby.stages;
+        match key.code {
+            KeyCode::Char('h') | KeyCode::Left => {
+                services.spectate(stage_id.saturating_sub(1));
+            }
+            KeyCode::Char('l') | KeyCode::Right => {
+                if stage_id + 1 < stages {
+                    services.spectate(stage_id + 1);
+                }
+            }
+            KeyCode::Esc => {
+                services.stop_spectating();
+                state.page = PageSelection::Menu;
+            }
+            _ => {
+                return false;
+            }
+        }
+
+        true
+    }
+}
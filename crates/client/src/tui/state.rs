@@ -1,13 +1,23 @@
-use losig_core::sense::{SenseStrength, Senses};
-use ratatui::widgets::ListState;
+use std::collections::VecDeque;
 
-use crate::tui::widgets::help::HelpState;
+use losig_core::{
+    network::StageInfo,
+    sense::{SenseStrength, Senses},
+    types::{ClientAction, Offset, Position, Turn},
+};
+use ratatui::{layout::Rect, widgets::ListState};
+
+use crate::{
+    tui::{keybindings::KeyBindings, widgets::help::HelpState},
+    world::WorldState,
+};
 
 pub struct TuiState {
     pub menu: MenuState,
     pub game: GameState,
     pub you_win: GameOverState,
     pub limbo: LimboState,
+    pub replay: ReplayState,
     pub page: PageSelection,
     pub should_exit: bool,
 }
@@ -16,6 +26,8 @@ pub struct TuiState {
 pub enum PageSelection {
     Menu,
     Game,
+    Spectate,
+    Replay,
 }
 
 #[derive(Debug)]
@@ -23,6 +35,8 @@ pub struct MenuState {
     pub list_state: ListState,
     pub entering_name: bool,
     pub name: String,
+    /// Name given at startup, the input starts with it
+    pub default_name: String,
 }
 
 impl Default for MenuState {
@@ -33,26 +47,153 @@ impl Default for MenuState {
             list_state,
             entering_name: false,
             name: String::new(),
+            default_name: String::new(),
         }
     }
 }
 
+/// Number of sense presets a player can save
+pub const PRESET_SLOTS: usize = 4;
+/// Turns of focus kept for the focus graph
+pub const FOCUS_HISTORY: usize = 64;
+/// Actions that can be planned ahead at most
+pub const PLAN_MAX: usize = 20;
+
 #[derive(Debug, Default)]
 pub struct GameState {
     pub senses: Senses,
     pub sense_selection: usize,
     pub help: HelpState,
+    /// Senses saved by the player for the session
+    pub presets: [Option<Senses>; PRESET_SLOTS],
+    /// Last loaded preset, cleared when the senses are tweaked by hand
+    pub active_preset: Option<usize>,
+    pub keybindings: KeyBindings,
+    /// Where the world was last rendered, to map clicks to tiles
+    pub world_area: Rect,
+    /// Tiles left to walk through after a click
+    pub path: VecDeque<Position>,
+    pub show_minimap: bool,
+    /// Waiting for the direction of the trap to disarm
+    pub disarming: bool,
+    /// Allies may see through our eyes on team stages
+    pub share_senses: bool,
+    /// Debug panel of the prediction corrections
+    pub show_corrections: bool,
+    /// Where the view is moved from the avatar while looking around the map
+    pub look: Option<Offset>,
+    /// Cell described to the player, from the avatar
+    pub examine: Option<Offset>,
+    pub show_focus: bool,
+    pub focus_history: FocusHistory,
+    /// Moves are queued instead of played until planning ends
+    pub planning: bool,
+    /// Planned actions left to play, one per server response
+    pub queue: VecDeque<ClientAction>,
+    /// Where the last queued action should leave the avatar, with its health before it
+    pub queue_expected: Option<(Position, Option<u8>)>,
+}
+
+/// Focus of the last turns, oldest first
+#[derive(Debug, Default)]
+pub struct FocusHistory {
+    last_turn: Option<Turn>,
+    values: VecDeque<u8>,
+}
+
+impl FocusHistory {
+    /// Keeps the focus once per turn, the latest value replacing the previous one of the turn
+    pub fn record(&mut self, turn: Turn, focus: u8) {
+        if self.last_turn == Some(turn) {
+            self.values.pop_back();
+        } else if self.values.len() == FOCUS_HISTORY {
+            self.values.pop_front();
+        }
+        self.last_turn = Some(turn);
+        self.values.push_back(focus);
+    }
+
+    pub fn values(&self) -> &VecDeque<u8> {
+        &self.values
+    }
 }
 
 impl GameState {
+    /// Queues a move or a wait if it can follow the ones already planned
+    pub fn plan(&mut self, action: ClientAction, world: &WorldState, diagonal: bool) -> bool {
+        if self.queue.len() >= PLAN_MAX {
+            return false;
+        }
+        match action {
+            ClientAction::MoveOrAttack(dir) => {
+                let from = self
+                    .planned_positions(world.position)
+                    .last()
+                    .copied()
+                    .unwrap_or(world.position);
+                let blocked = !world.tile_at(from + dir.offset()).can_travel();
+                if blocked || (dir.is_diagonal() && !diagonal) {
+                    return false;
+                }
+            }
+            ClientAction::Wait => {}
+            _ => return false,
+        }
+        self.queue.push_back(action);
+        true
+    }
+
+    /// Cells the queued moves go through, starting from the avatar position
+    pub fn planned_positions(&self, start: Position) -> Vec<Position> {
+        let mut position = start;
+        self.queue
+            .iter()
+            .filter_map(|action| match action {
+                ClientAction::MoveOrAttack(dir) => {
+                    position = position + dir.offset();
+                    Some(position)
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    pub fn save_preset(&mut self, slot: usize) {
+        if let Some(preset) = self.presets.get_mut(slot) {
+            *preset = Some(self.senses.clone());
+            self.active_preset = Some(slot);
+        }
+    }
+
+    pub fn load_preset(&mut self, slot: usize) -> bool {
+        let Some(Some(preset)) = self.presets.get(slot) else {
+            return false;
+        };
+        self.senses = preset.clone();
+        self.active_preset = Some(slot);
+        true
+    }
+
+    /// Load the next saved preset after the active one
+    pub fn cycle_preset(&mut self) -> bool {
+        let start = self.active_preset.map(|i| i + 1).unwrap_or(0);
+        (0..PRESET_SLOTS)
+            .map(|i| (start + i) % PRESET_SLOTS)
+            .any(|slot| self.load_preset(slot))
+    }
+
     fn get_ordered_senses(available_senses: &[losig_core::sense::SenseType]) -> Vec<losig_core::sense::SenseType> {
         use losig_core::sense::SenseType;
 
         // Define fixed order for senses (must match the widget order)
-        const SENSE_ORDER: [SenseType; 4] = [
+        const SENSE_ORDER: [SenseType; 8] = [
             SenseType::SelfSense,
             SenseType::Touch,
             SenseType::Hearing,
+            SenseType::Smell,
+            SenseType::Danger,
+            SenseType::OrbSense,
+            SenseType::Chronosense,
             SenseType::Sight,
         ];
 
@@ -71,30 +212,41 @@ impl GameState {
             return;
         };
 
+        self.active_preset = None;
         let senses = &mut self.senses;
         match sense_type {
             SenseType::SelfSense => senses.selfs = senses.selfs.decr(),
             SenseType::Touch => senses.touch = senses.touch.decr(),
             SenseType::Hearing => senses.hearing = senses.hearing.decr(),
+            SenseType::Smell => senses.smell = senses.smell.decr(),
+            SenseType::Danger => senses.danger = senses.danger.decr(),
+            SenseType::OrbSense => senses.orb = senses.orb.decr(),
+            SenseType::Chronosense => senses.chrono = senses.chrono.decr(),
             SenseType::Sight => senses.sight = senses.sight.decr(),
         }
     }
 
-    pub fn incr_sense(&mut self, available_senses: &[losig_core::sense::SenseType]) {
+    pub fn incr_sense(&mut self, stage_info: &StageInfo) {
         use losig_core::sense::SenseType;
 
-        let ordered = Self::get_ordered_senses(available_senses);
+        let ordered = Self::get_ordered_senses(&stage_info.senses);
         let Some(&sense_type) = ordered.get(self.sense_selection) else {
             return;
         };
 
+        self.active_preset = None;
         let senses = &mut self.senses;
         match sense_type {
             SenseType::SelfSense => senses.selfs = senses.selfs.incr(),
             SenseType::Touch => senses.touch = senses.touch.incr(),
             SenseType::Hearing => senses.hearing = senses.hearing.incr(),
+            SenseType::Smell => senses.smell = senses.smell.incr(),
+            SenseType::Danger => senses.danger = senses.danger.incr(),
+            SenseType::OrbSense => senses.orb = senses.orb.incr(),
+            SenseType::Chronosense => senses.chrono = senses.chrono.incr(),
             SenseType::Sight => senses.sight = senses.sight.incr(),
         }
+        senses.cap(stage_info.max_sight, stage_info.max_hearing);
     }
 }
 
@@ -110,3 +262,9 @@ pub struct LimboState {
     pub open: bool,
     pub averted: bool,
 }
+
+#[derive(Debug, Default)]
+pub struct ReplayState {
+    /// Turn of the run shown, from its first one
+    pub index: usize,
+}
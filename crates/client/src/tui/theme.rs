@@ -1,10 +1,17 @@
-use std::{cmp::Ordering, sync::LazyLock};
+use std::{
+    cmp::Ordering,
+    str::FromStr,
+    sync::{LazyLock, OnceLock},
+};
+
+use anyhow::{Result, bail};
 
 use losig_core::types::{FoeType, MAX_WITHOUT_PLAY, StageTurn};
 use palette::Hsl;
 use ratatui::style::Color;
 
 pub struct Theme {
+    pub palette_name: PaletteName,
     pub palette: ThemePalette,
 }
 
@@ -49,49 +56,150 @@ pub struct ThemePalette {
     pub ui_focus: Color,
 }
 
-pub static THEME: LazyLock<Theme> = LazyLock::new(|| Theme {
-    palette: ThemePalette {
-        foe: Color::from_hsl(Hsl::new(0.0, 1.0, 0.5)),
-        trap: Color::from_hsl(Hsl::new(0.0, 0.8, 0.3)),
-        ally_leading: Color::from_hsl(Hsl::new(40.0, 1.0, 0.5)),
-        ally_trailing: Color::from_hsl(Hsl::new(180.0, 1.0, 0.5)),
-        ally_sync: Color::from_hsl(Hsl::new(75.0, 0.5, 1.0)),
-        ally_discarded: Color::from_hsl(Hsl::new(40.0, 0.2, 0.2)),
-        ally_next_move: Color::from_hsl(Hsl::new(40.0, 0.7, 0.2)),
-
-        tile_wall: Color::from_hsl(Hsl::new(270.0, 1.0, 0.5)),
-        tile_floor: Color::from_hsl(Hsl::new(270.0, 0.2, 0.5)),
-        tile_unseen: Color::from_hsl(Hsl::new(270.0, 0.0, 0.1)),
-        tile_stair: Color::from_hsl(Hsl::new(55.0, 1.0, 0.55)),
-
-        ui: Color::White,
-        ui_disabled: Color::from_hsl(Hsl::new(0.0, 0.0, 0.5)),
-        ui_text: Color::from_hsl(Hsl::new(0.0, 0.0, 0.8)),
-        ui_highlight: Color::White,
-        ui_selected: Color::Rgb(0, 255, 0),
-        ui_hp: Color::from_hsl(Hsl::new(115.0, 0.7, 0.3)),
-        ui_bar_empty: Color::from_hsl(Hsl::new(115.0, 0.0, 0.5)),
-        ui_focus: Color::from_hsl(Hsl::new(220.0, 1.0, 0.5)),
-
-        avatar: Color::from_hsl(Hsl::new(220.0, 1.0, 0.5)),
-
-        important: Color::from_hsl(Hsl::new(40.0, 1.0, 0.5)),
-
-        log_minor: Color::from_hsl(Hsl::new(0.0, 0.0, 0.8)),
-        log_info: Color::White,
-        log_warn: Color::from_hsl(Hsl::new(40.0, 1.0, 0.5)),
-        log_grave: Color::from_hsl(Hsl::new(0.0, 1.0, 0.5)),
-        log_averted: Color::Rgb(139, 69, 19), // Dark brown
-        log_paradox: Color::from_hsl(Hsl::new(330.0, 1.0, 0.5)), // Bright pink
-        log_revision_bg: Color::Cyan,
-        log_revision_fg: Color::Black,
-
-        timeline_tail: Hsl::new(180.0, 1.0, 0.5),
-        timeline_head: Hsl::new(40.0, 1.0, 0.5),
-        page_info: Color::Gray,
-    },
+/// Palettes the player can pick at startup
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PaletteName {
+    #[default]
+    Default,
+    /// Avoids red/green distinctions
+    Deuteranopia,
+    HighContrast,
+}
+
+impl FromStr for PaletteName {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "default" => Ok(PaletteName::Default),
+            "deuteranopia" => Ok(PaletteName::Deuteranopia),
+            "high-contrast" => Ok(PaletteName::HighContrast),
+            _ => bail!("Unknown palette '{s}', expected default, deuteranopia or high-contrast"),
+        }
+    }
+}
+
+static PALETTE_NAME: OnceLock<PaletteName> = OnceLock::new();
+
+/// Chooses the palette used by THEME. Must be called before anything is rendered, returns false
+/// if the theme was already in use.
+pub fn select_palette(name: PaletteName) -> bool {
+    PALETTE_NAME.set(name).is_ok()
+}
+
+pub static THEME: LazyLock<Theme> = LazyLock::new(|| {
+    let palette_name = *PALETTE_NAME.get_or_init(PaletteName::default);
+    Theme {
+        palette_name,
+        palette: ThemePalette::new(palette_name),
+    }
 });
 
+impl ThemePalette {
+    pub fn new(name: PaletteName) -> Self {
+        match name {
+            PaletteName::Default => Self::default_palette(),
+            PaletteName::Deuteranopia => Self::deuteranopia(),
+            PaletteName::HighContrast => Self::high_contrast(),
+        }
+    }
+
+    fn default_palette() -> Self {
+        ThemePalette {
+            foe: Color::from_hsl(Hsl::new(0.0, 1.0, 0.5)),
+            trap: Color::from_hsl(Hsl::new(0.0, 0.8, 0.3)),
+            ally_leading: Color::from_hsl(Hsl::new(40.0, 1.0, 0.5)),
+            ally_trailing: Color::from_hsl(Hsl::new(180.0, 1.0, 0.5)),
+            ally_sync: Color::from_hsl(Hsl::new(75.0, 0.5, 1.0)),
+            ally_discarded: Color::from_hsl(Hsl::new(40.0, 0.2, 0.2)),
+            ally_next_move: Color::from_hsl(Hsl::new(40.0, 0.7, 0.2)),
+
+            tile_wall: Color::from_hsl(Hsl::new(270.0, 1.0, 0.5)),
+            tile_floor: Color::from_hsl(Hsl::new(270.0, 0.2, 0.5)),
+            tile_unseen: Color::from_hsl(Hsl::new(270.0, 0.0, 0.1)),
+            tile_stair: Color::from_hsl(Hsl::new(55.0, 1.0, 0.55)),
+
+            ui: Color::White,
+            ui_disabled: Color::from_hsl(Hsl::new(0.0, 0.0, 0.5)),
+            ui_text: Color::from_hsl(Hsl::new(0.0, 0.0, 0.8)),
+            ui_highlight: Color::White,
+            ui_selected: Color::Rgb(0, 255, 0),
+            ui_hp: Color::from_hsl(Hsl::new(115.0, 0.7, 0.3)),
+            ui_bar_empty: Color::from_hsl(Hsl::new(115.0, 0.0, 0.5)),
+            ui_focus: Color::from_hsl(Hsl::new(220.0, 1.0, 0.5)),
+
+            avatar: Color::from_hsl(Hsl::new(220.0, 1.0, 0.5)),
+
+            important: Color::from_hsl(Hsl::new(40.0, 1.0, 0.5)),
+
+            log_minor: Color::from_hsl(Hsl::new(0.0, 0.0, 0.8)),
+            log_info: Color::White,
+            log_warn: Color::from_hsl(Hsl::new(40.0, 1.0, 0.5)),
+            log_grave: Color::from_hsl(Hsl::new(0.0, 1.0, 0.5)),
+            log_averted: Color::Rgb(139, 69, 19), // Dark brown
+            log_paradox: Color::from_hsl(Hsl::new(330.0, 1.0, 0.5)), // Bright pink
+            log_revision_bg: Color::Cyan,
+            log_revision_fg: Color::Black,
+
+            timeline_tail: Hsl::new(180.0, 1.0, 0.5),
+            timeline_head: Hsl::new(40.0, 1.0, 0.5),
+            page_info: Color::Gray,
+        }
+    }
+
+    /// Foes and allies are told apart by the blue/orange axis and by lightness
+    fn deuteranopia() -> Self {
+        let vermillion = Color::Rgb(213, 94, 0);
+        let orange = Color::Rgb(230, 159, 0);
+        let sky_blue = Color::Rgb(86, 180, 233);
+        let blue = Color::Rgb(0, 114, 178);
+        let yellow = Color::Rgb(240, 228, 66);
+
+        ThemePalette {
+            foe: vermillion,
+            trap: Color::Rgb(140, 60, 0),
+            ally_leading: yellow,
+            ally_trailing: sky_blue,
+            ally_next_move: Color::Rgb(90, 85, 20),
+            ui_selected: sky_blue,
+            ui_hp: yellow,
+            ui_focus: blue,
+            avatar: blue,
+            important: orange,
+            log_warn: orange,
+            log_grave: vermillion,
+            timeline_tail: Hsl::new(202.0, 0.8, 0.63),
+            timeline_head: Hsl::new(56.0, 0.85, 0.6),
+            ..Self::default_palette()
+        }
+    }
+
+    /// Saturated colors on a darker map
+    fn high_contrast() -> Self {
+        ThemePalette {
+            foe: Color::Rgb(255, 0, 0),
+            trap: Color::Rgb(255, 0, 255),
+            ally_leading: Color::Rgb(255, 255, 0),
+            ally_trailing: Color::Rgb(0, 255, 255),
+            ally_sync: Color::White,
+            ally_next_move: Color::Rgb(0, 0, 160),
+            tile_wall: Color::White,
+            tile_floor: Color::from_hsl(Hsl::new(0.0, 0.0, 0.45)),
+            tile_unseen: Color::from_hsl(Hsl::new(0.0, 0.0, 0.2)),
+            ui_text: Color::White,
+            ui_disabled: Color::from_hsl(Hsl::new(0.0, 0.0, 0.6)),
+            ui_hp: Color::Rgb(0, 255, 0),
+            ui_bar_empty: Color::from_hsl(Hsl::new(0.0, 0.0, 0.3)),
+            ui_focus: Color::Rgb(0, 128, 255),
+            avatar: Color::White,
+            log_minor: Color::White,
+            timeline_tail: Hsl::new(180.0, 1.0, 0.5),
+            timeline_head: Hsl::new(60.0, 1.0, 0.5),
+            ..Self::default_palette()
+        }
+    }
+}
+
 pub trait FoeTypeRender {
     fn grapheme(&self) -> &'static str;
     fn label(&self) -> &'static str;
@@ -118,8 +226,8 @@ impl FoeTypeRender for FoeType {
 pub fn ally_color(ally: StageTurn, player: StageTurn) -> Color {
     match ally.cmp(&player) {
         Ordering::Less => Color::from_hsl(Hsl::new(
-            180.0,
-            1.0,
+            THEME.palette.timeline_tail.hue,
+            THEME.palette.timeline_tail.saturation,
             0.5 * (1.0 - ally.abs_diff(player) as f32 / (MAX_WITHOUT_PLAY as f32 + 1.0)),
         )),
         Ordering::Equal => THEME.palette.ally_sync,
@@ -0,0 +1,70 @@
+use losig_core::{
+    sense::SightedAllyStatus,
+    types::{Offset, Tile},
+};
+
+use crate::{tui::theme::FoeTypeRender, world::WorldView};
+
+/// Describes what the player knows about the cell at this offset from the avatar. Entities are
+/// known in the last sight, living foes and tiles are remembered.
+pub fn describe(world: &WorldView, offset: Offset) -> String {
+    if offset == Offset::default() {
+        return "You.".to_string();
+    }
+
+    let sight = world.last_info().and_then(|info| info.sight.as_ref());
+    if let Some(sight) = sight {
+        if let Some(ally) = sight.allies.iter().find(|ally| ally.offset == offset) {
+            let name = ally.name.as_deref().unwrap_or("someone");
+            return match (&ally.status, ally.alive) {
+                (_, false) => format!("The body of {name}."),
+                (SightedAllyStatus::Controlled { .. }, true) => format!("{name}, an ally."),
+                (SightedAllyStatus::Discarded, true) => {
+                    format!("{name}, an echo left behind in time.")
+                }
+            };
+        }
+
+        // Living foes stand over the dead ones
+        let foe = sight
+            .foes
+            .iter()
+            .filter(|foe| foe.offset == offset)
+            .max_by_key(|foe| foe.alive);
+        if let Some(foe) = foe {
+            let state = if foe.alive { "A" } else { "A dead" };
+            return format!("{state} {}.", foe.foe_type.label());
+        }
+
+        if sight.orb == Some(offset) {
+            return "The orb, the way out of the stage.".to_string();
+        }
+        if sight.shards.contains(&offset) {
+            return "A shard, it sharpens your attacks.".to_string();
+        }
+    }
+
+    let remembered = world
+        .current_state()
+        .stale_foes()
+        .find(|(foe_offset, _)| *foe_offset == offset);
+    if let Some((_, foe)) = remembered {
+        let turns = if foe.age == 1 { "turn" } else { "turns" };
+        return format!(
+            "A {}, last seen {} {turns} ago.",
+            foe.foe_type.label(),
+            foe.age
+        );
+    }
+
+    let tile = match world.current_state().tile_from_viewer(offset) {
+        Tile::Unknown => "Unexplored.",
+        Tile::Empty => "Floor.",
+        Tile::Wall => "A wall.",
+        Tile::Spawn => "A spawn point.",
+        Tile::Pylon => "A pylon, standing next to it restores focus.",
+        Tile::StairUp => "Stairs going up.",
+        Tile::StairDown => "Stairs going down.",
+    };
+    tile.to_string()
+}
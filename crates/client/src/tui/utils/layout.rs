@@ -1,4 +1,8 @@
-use ratatui::layout::{Constraint, Flex, Layout, Rect};
+use ratatui::{
+    buffer::Buffer,
+    layout::{Constraint, Flex, Layout, Rect},
+    style::Style,
+};
 
 pub fn center(area: Rect, horizontal: Constraint, vertical: Constraint) -> Rect {
     let [area] = Layout::horizontal([horizontal])
@@ -7,3 +11,22 @@ pub fn center(area: Rect, horizontal: Constraint, vertical: Constraint) -> Rect
     let [area] = Layout::vertical([vertical]).flex(Flex::Center).areas(area);
     area
 }
+
+/// Centered popup of this size, shrunk to fit small terminals
+pub fn popup(area: Rect, width: u16, height: u16) -> Rect {
+    center(
+        area,
+        Constraint::Length(width.min(area.width)),
+        Constraint::Length(height.min(area.height)),
+    )
+}
+
+/// Writes a line centered in the area, cut to its width and dropped past its bottom
+pub fn centered_line(buf: &mut Buffer, area: Rect, y: u16, line: &str, style: Style) {
+    if y < area.y || y >= area.bottom() {
+        return;
+    }
+    let width = line.chars().count() as u16;
+    let x = area.x + area.width.saturating_sub(width) / 2;
+    buf.set_stringn(x, y, line, area.width as usize, style);
+}
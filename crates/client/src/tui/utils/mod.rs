@@ -1,3 +1,5 @@
+mod examine;
 mod layout;
 
+pub use examine::*;
 pub use layout::*;
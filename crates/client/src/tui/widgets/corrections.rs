@@ -0,0 +1,46 @@
+use std::collections::VecDeque;
+
+use ratatui::{
+    buffer::Buffer,
+    layout::Rect,
+    style::{Style, Stylize},
+    text::Line,
+    widgets::{Block, Borders, Clear, Paragraph, Widget},
+};
+
+use crate::{tui::THEME, world::Correction};
+
+/// Debug panel listing the server responses that contradicted the predictions, newest first
+pub struct CorrectionsWidget<'a> {
+    pub corrections: &'a VecDeque<Correction>,
+}
+
+impl<'a> Widget for CorrectionsWidget<'a> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        Clear.render(area, buf);
+
+        let lines: Vec<Line> = if self.corrections.is_empty() {
+            vec![Line::from("No corrections yet").fg(THEME.palette.log_minor)]
+        } else {
+            self.corrections
+                .iter()
+                .rev()
+                .map(|c| {
+                    Line::from(format!(
+                        "T{}: {:?} -> {:?} ({:+}, {:+})",
+                        c.turn, c.predicted, c.actual, c.offset.x, c.offset.y
+                    ))
+                })
+                .collect()
+        };
+
+        Paragraph::new(lines)
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title("Corrections")
+                    .border_style(Style::default().fg(THEME.palette.ui)),
+            )
+            .render(area, buf);
+    }
+}
@@ -0,0 +1,48 @@
+use std::collections::VecDeque;
+
+use losig_core::types::FOCUS_MAX;
+use ratatui::{
+    buffer::Buffer,
+    layout::Rect,
+    style::Style,
+    widgets::{Block, Borders, Clear, Sparkline, Widget},
+};
+
+use crate::tui::THEME;
+
+/// Focus of the last turns, oldest first, with the cost of the current senses
+pub struct FocusWidget<'a> {
+    pub history: &'a VecDeque<u8>,
+    pub cost: u8,
+}
+
+impl<'a> Widget for FocusWidget<'a> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        Clear.render(area, buf);
+
+        let title = match self.history.back() {
+            Some(focus) => format!("Focus {focus} - cost {}", self.cost),
+            None => format!("Focus ?? - cost {}", self.cost),
+        };
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .title(title)
+            .border_style(Style::default().fg(THEME.palette.ui));
+
+        // Only the most recent turns fit
+        let width = block.inner(area).width as usize;
+        let data: Vec<u64> = self
+            .history
+            .iter()
+            .skip(self.history.len().saturating_sub(width))
+            .map(|focus| *focus as u64)
+            .collect();
+
+        Sparkline::default()
+            .block(block)
+            .data(&data)
+            .max(FOCUS_MAX as u64)
+            .style(Style::default().fg(THEME.palette.important))
+            .render(area, buf);
+    }
+}
@@ -1,3 +1,4 @@
+use losig_core::{sense::Senses, types::DISARM_COST};
 use ratatui::{
     buffer::Buffer,
     layout::{Constraint, Rect},
@@ -7,7 +8,10 @@ use ratatui::{
 };
 
 use crate::{
-    tui::utils::center,
+    tui::{
+        keybindings::{KeyAction, KeyBindings},
+        utils::center,
+    },
     tui_adapter::{Event, KeyCode},
 };
 
@@ -22,7 +26,7 @@ pub struct HelpState {
 }
 
 impl HelpWidget {
-    pub fn on_event(&self, event: &Event, state: &mut HelpState) -> bool {
+    pub fn on_event(&self, event: &Event, state: &mut HelpState, keys: &KeyBindings) -> bool {
         if !state.open {
             return false;
         }
@@ -31,8 +35,13 @@ impl HelpWidget {
             return true; // Consume all non-key events when help is visible
         };
 
+        if keys.action(key) == Some(KeyAction::Help) {
+            state.open = false;
+            return true;
+        }
+
         match key.code {
-            KeyCode::Char('?') | KeyCode::Esc => {
+            KeyCode::Esc => {
                 state.open = false;
             }
             KeyCode::Left => {
@@ -47,13 +56,20 @@ impl HelpWidget {
         true
     }
 
-    pub fn render(&self, area: Rect, buf: &mut Buffer, state: &HelpState) {
+    pub fn render(&self, area: Rect, buf: &mut Buffer, state: &HelpState, keys: &KeyBindings) {
         let popup_area = center(area, Constraint::Percentage(60), Constraint::Percentage(60));
 
         // Clear the popup area to reset style
         Clear.render(popup_area, buf);
 
-        let title = "Help - Press '?' or 'ESC' to close";
+        let help_keys = keys
+            .keys(KeyAction::Help)
+            .iter()
+            .map(|k| format!("'{k}' or "));
+        let title = format!(
+            "Help - Press {}'ESC' to close",
+            help_keys.collect::<String>()
+        );
         let page_info = format!("< page {} of {} >", state.selection + 1, state.max + 1);
 
         let block = Block::default()
@@ -65,8 +81,8 @@ impl HelpWidget {
         block.render(popup_area, buf);
 
         let help_text = match state.selection {
-            0 => self.page_1(),
-            _ => self.page_1(),
+            0 => self.page_1(keys),
+            _ => self.page_1(keys),
         }
         .wrap(Wrap { trim: true });
 
@@ -80,29 +96,53 @@ impl HelpWidget {
         buf.set_string(page_x, page_y, page_info, Style::default().fg(Color::Gray));
     }
 
-    fn page_1(&self) -> Paragraph<'_> {
-        Paragraph::new(vec![
-            Line::from(""),
-            Line::from("CONTROLS"),
-            Line::from("Movement: Arrow Keys, Vi keys (hjklyubn), or Numpad"),
-            Line::from("Wait: 5 or Space | Help: ?"),
-            Line::from(""),
-            Line::from("SENSE CONTROLS"),
-            Line::from("Sense selection: Shift + Up/Down"),
-            Line::from("Weaken/Strengthen sense: Shift + Left/Right"),
+    fn page_1(&self, keys: &KeyBindings) -> Paragraph<'_> {
+        let mut lines = vec![Line::from(""), Line::from("CONTROLS")];
+        for action in KeyAction::ALL.iter().filter(|a| !a.hidden()) {
+            let bound = keys.keys(*action).iter().map(|k| k.to_string());
+            lines.push(Line::from(format!(
+                "{}: {}",
+                action.label(),
+                bound.collect::<Vec<_>>().join(", ")
+            )));
+        }
+        lines.extend(vec![
+            Line::from("Save/Load sense preset: Ctrl/Alt + 1-4"),
+            Line::from("Move to a tile: Left click | Stop moving: Right click"),
             Line::from(""),
             Line::from("SELF SENSE - cost: 1"),
-            Line::from("Shows your current hp and focus level."),
+            Line::from("Shows your current hp, focus level and attack."),
             Line::from(""),
-            Line::from("TOUCH SENSE - cost: 1"),
-            Line::from("Shows the adjacent terrain/traps/enemies."),
+            Line::from("TOUCH SENSE - cost: STRENGTH"),
+            Line::from("Shows the terrain/traps/enemies in a STRENGTH radius."),
+            Line::from(format!(
+                "Disarming a trap next to you costs {DISARM_COST} focus."
+            )),
             Line::from(""),
             Line::from("HEARING SENSE - cost: STRENGTH"),
             Line::from("Shows the sound sources and their approximate distance in tiles."),
             Line::from("The higher the STRENGTH the higher the range."),
+            Line::from("From STRENGTH 4, also shows the direction of the orb."),
+            Line::from(""),
+            Line::from("SMELL SENSE - cost: STRENGTH"),
+            Line::from("Shows how many enemies are nearby and where most of them are."),
+            Line::from("The higher the STRENGTH the higher the range."),
+            Line::from(""),
+            Line::from("DANGER SENSE - cost: STRENGTH"),
+            Line::from("Shows how many threats, traps included, are close, but not where."),
+            Line::from("The higher the STRENGTH the higher the range."),
+            Line::from(""),
+            Line::from("GOAL SENSE - cost: STRENGTH"),
+            Line::from("Shows how close the nearest orb is, however many walls stand between."),
+            Line::from("The higher the STRENGTH the higher the range."),
+            Line::from(""),
+            Line::from(format!("CHRONO SENSE - cost: {}", Senses::CHRONO_COST)),
+            Line::from("Shows how far ahead or behind in time every ally of the stage is."),
             Line::from(""),
             Line::from("SIGHT SENSE - cost: 2 + STRENGTH"),
-            Line::from("Shows enemies, players and terrain in a STRENGTH radius."),
-        ])
+            Line::from("Shows enemies, players, shards (*) and terrain in a STRENGTH radius."),
+            Line::from("On team stages, allies sharing their senses extend your sight."),
+        ]);
+        Paragraph::new(lines)
     }
 }
@@ -1,5 +1,7 @@
 use losig_core::{
     events::{GameEvent, Target},
+    network::ErrorCode,
+    sense::SenseType,
     types::FoeType,
 };
 use ratatui::{
@@ -12,7 +14,7 @@ use ratatui::{
 
 use crate::{
     logs::{ClientLog, GameLog, LogEvent},
-    tui::{THEME, theme::FoeTypeRender},
+    tui::{THEME, pages::MAP_EXPORT_FILE, theme::FoeTypeRender},
 };
 
 pub struct LogsWidget<'a> {
@@ -50,11 +52,67 @@ fn format_log(log: &LogEvent) -> Line<'_> {
 fn format_client_log(log: &ClientLog) -> Line<'_> {
     match log {
         ClientLog::Help => Line::from("Press '?' for help"),
+        ClientLog::Blocked => Line::from("You bump into a wall.").fg(THEME.palette.log_minor),
+        ClientLog::ShareSenses(true) => Line::from("Your allies can now see what you see."),
+        ClientLog::ShareSenses(false) => Line::from("You keep your senses to yourself."),
+        ClientLog::NoTeamVision => {
+            Line::from("Allies can't share their senses here.").fg(THEME.palette.log_minor)
+        }
+        ClientLog::Undo => Line::from("You take back your last move."),
+        ClientLog::CannotUndo => {
+            Line::from("This moment is out of reach.").fg(THEME.palette.log_minor)
+        }
+        ClientLog::Paused(true) => {
+            Line::from("Time stands still, the signal is frozen.").fg(THEME.palette.log_minor)
+        }
+        ClientLog::Paused(false) => Line::from("Time flows again."),
+        ClientLog::FallingBehind(turns_left) => Line::from(format!(
+            "You're falling dangerously far behind, {turns_left} turns before being lost."
+        ))
+        .fg(THEME.palette.log_grave),
+        ClientLog::MapExported(true) => {
+            Line::from(format!("You sketch the map in {MAP_EXPORT_FILE}."))
+        }
+        ClientLog::MapExported(false) => {
+            Line::from("You fail to sketch the map.").fg(THEME.palette.log_minor)
+        }
+        ClientLog::Incoherent => Line::from("Your memories of this place contradict each other.")
+            .fg(THEME.palette.log_paradox),
+        ClientLog::ServerError(ErrorCode::UnknownPlayer) => {
+            Line::from("The signal lost track of you, start a new game.")
+                .fg(THEME.palette.log_grave)
+        }
+        ClientLog::ServerError(ErrorCode::NotInStage) => {
+            Line::from("You are no longer anywhere.").fg(THEME.palette.log_grave)
+        }
+        ClientLog::ServerError(ErrorCode::Refused) => {
+            Line::from("The signal garbles your last action.").fg(THEME.palette.log_paradox)
+        }
+        ClientLog::ServerError(ErrorCode::PermissionDenied) => {
+            Line::from("The signal ignores your request.").fg(THEME.palette.log_minor)
+        }
+        ClientLog::PlanStopped => {
+            Line::from("You stop to take stock of the situation.").fg(THEME.palette.log_warn)
+        }
+        ClientLog::NoResponse => {
+            Line::from("The signal goes quiet, you reach out for it.").fg(THEME.palette.log_warn)
+        }
     }
 }
 
 fn format_game_event(event: &GameEvent) -> Line<'_> {
     let (line, style) = match event {
+        GameEvent::Attack {
+            subject,
+            source: Target::You,
+        } => (format!("You hit {}.", format_target(subject)), None),
+        GameEvent::Kill {
+            subject,
+            source: Target::You,
+        } => (
+            format!("You killed {}.", format_target(subject)),
+            Some(THEME.palette.important),
+        ),
         GameEvent::Attack { subject, source } => (
             format!(
                 "{} attacked {}.",
@@ -95,6 +153,31 @@ fn format_game_event(event: &GameEvent) -> Line<'_> {
             format!("{} lays his hands on the orb.", format_target(other)),
             Some(THEME.palette.important),
         ),
+        GameEvent::ShardTaken(Target::You) => (
+            "The shard dissolves in your hands, your blows feel heavier.".to_string(),
+            Some(THEME.palette.important),
+        ),
+        GameEvent::ShardTaken(other) => {
+            (format!("{} picks up a shard.", format_target(other)), None)
+        }
+        GameEvent::TrapDisarmed(Target::You) => (
+            "You carefully take the trap apart.".to_string(),
+            Some(THEME.palette.log_averted),
+        ),
+        GameEvent::TrapDisarmed(other) => {
+            (format!("{} disarms a trap.", format_target(other)), None)
+        }
+        GameEvent::Wounded {
+            subject: Target::You,
+            ..
+        } => (
+            "You are badly wounded.".to_string(),
+            Some(THEME.palette.log_grave),
+        ),
+        GameEvent::Wounded { subject, .. } => (
+            format!("{} is badly wounded.", format_target(subject)),
+            None,
+        ),
         GameEvent::AvatarFadedOut(target) => (
             format!(
                 "{} fades out as you move forward in time.",
@@ -102,6 +185,25 @@ fn format_game_event(event: &GameEvent) -> Line<'_> {
             ),
             Some(Color::from_hsl(THEME.palette.timeline_tail)),
         ),
+        GameEvent::Exhausted => (
+            "You were too drained to sense anything.".to_string(),
+            Some(THEME.palette.log_warn),
+        ),
+        GameEvent::SenseCooldown(sense) => (
+            format!("Your {} needs rest, it stays dim.", sense_name(*sense)),
+            Some(THEME.palette.log_minor),
+        ),
+        GameEvent::WindUp(target) => (
+            format!(
+                "{} raises its arms, ready to strike.",
+                format_target(target)
+            ),
+            Some(THEME.palette.log_warn),
+        ),
+        GameEvent::Summon(target) => (
+            format!("{} calls for help.", format_target(target)),
+            Some(THEME.palette.log_warn),
+        ),
     };
 
     let mut result = Line::from(capitalize_first(&line));
@@ -114,6 +216,19 @@ fn format_game_event(event: &GameEvent) -> Line<'_> {
     result
 }
 
+fn sense_name(sense: SenseType) -> &'static str {
+    match sense {
+        SenseType::SelfSense => "self sense",
+        SenseType::Sight => "sight",
+        SenseType::Touch => "touch",
+        SenseType::Hearing => "hearing",
+        SenseType::Smell => "smell",
+        SenseType::Danger => "danger sense",
+        SenseType::Chronosense => "chronosense",
+        SenseType::OrbSense => "goal sense",
+    }
+}
+
 fn capitalize_first(s: &str) -> String {
     let mut chars = s.chars();
     match chars.next() {
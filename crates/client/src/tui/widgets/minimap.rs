@@ -0,0 +1,114 @@
+use losig_core::types::{Position, Tile};
+use ratatui::{
+    buffer::Buffer,
+    layout::Rect,
+    style::Style,
+    widgets::{Block, Borders, Clear, Widget},
+};
+
+use crate::{
+    tui::{THEME, widgets::block_wrap::BlockWrap},
+    world::WorldState,
+};
+
+/// Downscaled view of the explored tiles, several tiles per cell
+pub struct MinimapWidget<'a> {
+    pub state: &'a WorldState,
+}
+
+impl<'a> MinimapWidget<'a> {
+    /// Walls are shown over floors so that corridors stay readable
+    fn cell(&self, from: Position, scale: usize) -> Tile {
+        let mut cell = Tile::Unknown;
+        for x in from.x..from.x + scale {
+            for y in from.y..from.y + scale {
+                match self.state.tile_at(Position { x, y }) {
+                    Tile::Wall => return Tile::Wall,
+                    Tile::Unknown => {}
+                    tile => cell = tile,
+                }
+            }
+        }
+        cell
+    }
+}
+
+impl<'a> Widget for MinimapWidget<'a> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        Clear.render(area, buf);
+
+        // Contradictory tiles from time travel make the map unreliable
+        let border = if self.state.incoherent {
+            THEME.palette.log_paradox
+        } else {
+            THEME.palette.ui
+        };
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .title("Map")
+            .border_style(Style::default().fg(border));
+        block.wrap(MinimapCells { map: self }).render(area, buf);
+    }
+}
+
+struct MinimapCells<'a> {
+    map: MinimapWidget<'a>,
+}
+
+impl<'a> Widget for MinimapCells<'a> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let Some((min, max)) = self.map.state.explored() else {
+            return;
+        };
+        if area.width == 0 || area.height == 0 {
+            return;
+        }
+
+        let width = max.x - min.x + 1;
+        let height = max.y - min.y + 1;
+        let scale = width
+            .div_ceil(area.width as usize)
+            .max(height.div_ceil(area.height as usize))
+            .max(1);
+
+        for cx in 0..area.width as usize {
+            for cy in 0..area.height as usize {
+                let from = Position {
+                    x: min.x + cx * scale,
+                    y: min.y + cy * scale,
+                };
+                if from.x > max.x || from.y > max.y {
+                    continue;
+                }
+
+                let (ch, color) = match self.map.cell(from, scale) {
+                    Tile::Unknown => continue,
+                    Tile::Wall => ('█', THEME.palette.tile_wall),
+                    Tile::StairUp | Tile::StairDown => ('>', THEME.palette.tile_stair),
+                    Tile::Spawn | Tile::Pylon => ('.', THEME.palette.important),
+                    Tile::Empty => ('.', THEME.palette.tile_floor),
+                };
+                buf.set_string(
+                    area.x + cx as u16,
+                    area.y + cy as u16,
+                    ch.to_string(),
+                    Style::default().fg(color),
+                );
+            }
+        }
+
+        let avatar = self.map.state.position;
+        if avatar.x >= min.x && avatar.y >= min.y {
+            let cx = (avatar.x - min.x) / scale;
+            let cy = (avatar.y - min.y) / scale;
+            if cx < area.width as usize && cy < area.height as usize {
+                buf.set_string(
+                    area.x + cx as u16,
+                    area.y + cy as u16,
+                    "@",
+                    Style::default().fg(THEME.palette.avatar),
+                );
+            }
+        }
+    }
+}
@@ -1,5 +1,11 @@
 pub mod block_wrap;
+pub mod corrections;
+pub mod focus;
 pub mod help;
 pub mod logs;
+pub mod minimap;
 pub mod senses;
+pub mod shutdown;
+pub mod stage_intro;
 pub mod timeline;
+pub mod too_small;
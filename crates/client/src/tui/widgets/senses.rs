@@ -2,20 +2,72 @@ use std::cmp::Ordering;
 
 use bounded_integer::BoundedU8;
 use itertools::Itertools;
-use losig_core::sense::{SenseStrength, Senses, SensesInfo, SightInfo, SightedAllyStatus};
-use losig_core::types::{FOCUS_MAX, FoeType, HP_MAX, StageTurn};
+use losig_core::sense::{
+    ChronoInfo, CostTable, DangerInfo, OrbInfo, SenseLevel, SenseStrength, SenseType, Senses,
+    SensesInfo, SightInfo, SightedAllyStatus,
+};
+use losig_core::types::{Direction, FOCUS_MAX, FoeType, HP_MAX, StageTurn};
 use ratatui::layout::Spacing;
 use ratatui::widgets::Paragraph;
 use ratatui::{
     buffer::Buffer,
     layout::{Constraint, Layout, Rect},
-    style::{Color, Style},
+    style::{Color, Style, Stylize},
     text::{Line, Span},
     widgets::Widget,
 };
 
 use crate::tui::{FoeTypeRender, THEME, ally_color};
 
+/// Rows used by the widget of a sense
+fn sense_height(sense_type: SenseType) -> u16 {
+    match sense_type {
+        SenseType::SelfSense => 2,
+        SenseType::Touch => 4,
+        SenseType::Hearing => 2,
+        SenseType::Smell => 2,
+        SenseType::Danger => 2,
+        SenseType::OrbSense => 2,
+        SenseType::Chronosense => 3,
+        SenseType::Sight => 2,
+    }
+}
+
+/// Rows needed to show the widgets of these senses
+pub fn senses_height(available_senses: &[SenseType]) -> u16 {
+    available_senses
+        .iter()
+        .map(|sense| sense_height(*sense))
+        .sum()
+}
+
+/// Sense to lower for the cost to fit in the focus, with the strength to lower it to. The one
+/// losing the fewest levels is picked, None if the senses are affordable or if no single sense
+/// is enough.
+fn cheapest_cut(
+    senses: &Senses,
+    costs: &CostTable,
+    focus: u8,
+    candidates: &[SenseType],
+) -> Option<(SenseType, u8)> {
+    if senses.cost(costs) <= focus {
+        return None;
+    }
+    candidates
+        .iter()
+        .filter_map(|&sense| {
+            (0..senses.strength(sense))
+                .rev()
+                .find(|&strength| {
+                    let mut lowered = senses.clone();
+                    lowered.lower(sense, strength);
+                    lowered.cost(costs) <= focus
+                })
+                .map(|strength| (sense, strength))
+        })
+        .min_by_key(|(sense, strength)| senses.strength(*sense) - strength)
+}
+
 /// Renders the common header line for a sense widget (label, indicator, selection styling)
 fn render_sense_header(
     area: Rect,
@@ -54,16 +106,25 @@ impl<'a> Widget for SelfSenseWidget<'a> {
         let indicator = if self.sense { "(+)" } else { "(-)" };
         render_sense_header(first, buf, "Self", indicator, self.selected, self.sense);
 
-        // Render content (HP gauge + Focus gauge)
+        // Render content (HP gauge + Focus gauge + attack)
         if let Some(info) = self.info {
-            // Split the second line into two equal halves
-            let halves =
-                Layout::horizontal([Constraint::Percentage(50), Constraint::Percentage(50)])
-                    .spacing(Spacing::Space(2))
-                    .split(second);
-            let [hp_area, fp_area] = halves.as_ref() else {
+            // Split the second line into two equal halves, leaving room for the attack
+            let halves = Layout::horizontal([
+                Constraint::Fill(1),
+                Constraint::Fill(1),
+                Constraint::Length(5),
+            ])
+            .spacing(Spacing::Space(2))
+            .split(second);
+            let [hp_area, fp_area, atk_area] = halves.as_ref() else {
                 return;
             };
+            buf.set_string(
+                atk_area.x,
+                atk_area.y,
+                format!("ATK:{}", info.attack),
+                Style::default(),
+            );
 
             // Render HP gauge in left half
             let hp = info.hp.min(HP_MAX);
@@ -121,7 +182,7 @@ impl<'a> Widget for SelfSenseWidget<'a> {
 }
 
 pub struct TouchSenseWidget<'a> {
-    pub sense: bool,
+    pub sense: BoundedU8<0, 3>,
     pub info: Option<&'a losig_core::sense::TouchInfo>,
     pub selected: bool,
 }
@@ -132,8 +193,14 @@ impl<'a> Widget for TouchSenseWidget<'a> {
         let [header, content] = layout.areas(area);
 
         // Render header
-        let indicator = if self.sense { "(+)" } else { "(-)" };
-        render_sense_header(header, buf, "Touch", indicator, self.selected, self.sense);
+        render_sense_header(
+            header,
+            buf,
+            "Touch",
+            &format!("({})", self.sense),
+            self.selected,
+            !self.sense.is_min(),
+        );
 
         let lines: Vec<Line> = match self.info {
             Some(info) => {
@@ -189,6 +256,8 @@ pub struct HearingSenseWidget<'a> {
     pub sense: BoundedU8<0, 5>,
     pub info: Option<&'a losig_core::sense::HearingInfo>,
     pub selected: bool,
+    /// Map diagonal the distances are scaled to, if they are
+    pub diagonal: Option<u16>,
 }
 
 impl<'a> Widget for HearingSenseWidget<'a> {
@@ -222,9 +291,19 @@ impl<'a> Widget for HearingSenseWidget<'a> {
                     ])
                     .render(left, buf);
 
-                    // Right part: "< {dist}"
-                    if let Some(dist) = losig_core::sense::HearingInfo::dist(range.get()) {
-                        Line::from(format!("< {}", dist))
+                    // Right part: "{bearing} < {dist}"
+                    let dist = match self.diagonal {
+                        Some(diagonal) => {
+                            losig_core::sense::HearingInfo::dist_for(range.get(), diagonal)
+                        }
+                        None => losig_core::sense::HearingInfo::dist(range.get()).map(u16::from),
+                    };
+                    if let Some(dist) = dist {
+                        let text = match info.bearing {
+                            Some(bearing) => format!("{} < {}", direction_arrow(bearing), dist),
+                            None => format!("< {}", dist),
+                        };
+                        Line::from(text)
                             .style(THEME.palette.important)
                             .right_aligned()
                             .render(right, buf);
@@ -243,6 +322,219 @@ impl<'a> Widget for HearingSenseWidget<'a> {
     }
 }
 
+pub struct SmellSenseWidget<'a> {
+    pub sense: BoundedU8<0, 5>,
+    pub info: Option<&'a losig_core::sense::SmellInfo>,
+    pub selected: bool,
+}
+
+impl<'a> Widget for SmellSenseWidget<'a> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let layout = Layout::vertical([Constraint::Length(1), Constraint::Length(1)]);
+        let [first, second] = layout.areas(area);
+
+        // Render header
+        render_sense_header(
+            first,
+            buf,
+            "Smell",
+            &format!("({})", self.sense),
+            self.selected,
+            !self.sense.is_min(),
+        );
+
+        // Render content
+        match self.info {
+            Some(info) if info.foe_count > 0 => {
+                let content_layout = Layout::horizontal([Constraint::Min(0), Constraint::Min(0)]);
+                let [left, right] = content_layout.areas(second);
+
+                Line::from(vec![
+                    Span::from("?").style(THEME.palette.foe),
+                    Span::from(format!(
+                        ": {} foe{}",
+                        info.foe_count,
+                        if info.foe_count == 1 { "" } else { "s" }
+                    )),
+                ])
+                .render(left, buf);
+
+                if let Some(bearing) = info.strongest_bearing {
+                    Line::from(direction_arrow(bearing))
+                        .style(THEME.palette.foe)
+                        .right_aligned()
+                        .render(right, buf);
+                }
+            }
+            Some(_) => {
+                Line::from("Nothing").render(second, buf);
+            }
+            None => {
+                Line::from("-")
+                    .style(THEME.palette.ui_disabled)
+                    .right_aligned()
+                    .render(second, buf);
+            }
+        }
+    }
+}
+
+pub struct DangerSenseWidget<'a> {
+    pub sense: BoundedU8<0, 5>,
+    pub info: Option<&'a DangerInfo>,
+    pub selected: bool,
+}
+
+impl<'a> Widget for DangerSenseWidget<'a> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let layout = Layout::vertical([Constraint::Length(1), Constraint::Length(1)]);
+        let [first, second] = layout.areas(area);
+
+        // Render header
+        render_sense_header(
+            first,
+            buf,
+            "Danger",
+            &format!("({})", self.sense),
+            self.selected,
+            !self.sense.is_min(),
+        );
+
+        // Render content
+        match self.info {
+            Some(info) if info.count > 0 => {
+                let text = if info.count == 1 {
+                    ": There's something nearby.".to_string()
+                } else {
+                    format!(": {} dangers nearby.", info.count)
+                };
+                Line::from(vec![
+                    Span::from("!").style(THEME.palette.foe),
+                    Span::from(text),
+                ])
+                .render(second, buf);
+            }
+            Some(_) => {
+                Line::from("Nothing").render(second, buf);
+            }
+            None => {
+                Line::from("-")
+                    .style(THEME.palette.ui_disabled)
+                    .right_aligned()
+                    .render(second, buf);
+            }
+        }
+    }
+}
+
+pub struct OrbSenseWidget<'a> {
+    pub sense: BoundedU8<0, 3>,
+    pub info: Option<&'a OrbInfo>,
+    pub selected: bool,
+}
+
+impl<'a> Widget for OrbSenseWidget<'a> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let layout = Layout::vertical([Constraint::Length(1), Constraint::Length(1)]);
+        let [first, second] = layout.areas(area);
+
+        // Render header
+        render_sense_header(
+            first,
+            buf,
+            "Goal",
+            &format!("({})", self.sense),
+            self.selected,
+            !self.sense.is_min(),
+        );
+
+        // Render content
+        match self.info {
+            Some(info) if info.level > SenseLevel::Minimum => {
+                let content_layout = Layout::horizontal([Constraint::Min(0), Constraint::Min(0)]);
+                let [left, right] = content_layout.areas(second);
+
+                Line::from(vec![
+                    Span::from("o").style(THEME.palette.important),
+                    Span::from(": The orb"),
+                ])
+                .render(left, buf);
+
+                let text = match info.level {
+                    SenseLevel::Maximum => "Very close",
+                    SenseLevel::High => "Close",
+                    SenseLevel::Medium => "Near",
+                    _ => "Far",
+                };
+                Line::from(text)
+                    .style(THEME.palette.important)
+                    .right_aligned()
+                    .render(right, buf);
+            }
+            Some(_) => {
+                Line::from("Nothing").render(second, buf);
+            }
+            None => {
+                Line::from("-")
+                    .style(THEME.palette.ui_disabled)
+                    .right_aligned()
+                    .render(second, buf);
+            }
+        }
+    }
+}
+
+pub struct ChronoSenseWidget<'a> {
+    pub sense: bool,
+    pub stage_turn: StageTurn,
+    pub info: Option<&'a ChronoInfo>,
+    pub selected: bool,
+}
+
+impl<'a> Widget for ChronoSenseWidget<'a> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let layout = Layout::vertical([Constraint::Length(1), Constraint::Min(0)]);
+        let [header, content] = layout.areas(area);
+
+        // Render header
+        let indicator = if self.sense { "(+)" } else { "(-)" };
+        render_sense_header(header, buf, "Chrono", indicator, self.selected, self.sense);
+
+        // Render content
+        let lines: Vec<Line> = match self.info {
+            Some(info) if !info.allies.is_empty() => info
+                .allies
+                .iter()
+                .map(|ally| {
+                    let turn = self.stage_turn.saturating_add_signed(ally.turn_offset);
+                    ally_line(turn, self.stage_turn, &ally.name)
+                })
+                .collect(),
+            Some(_) => vec![Line::from("Alone in time")],
+            None => vec![
+                Line::from("-")
+                    .style(THEME.palette.ui_disabled)
+                    .right_aligned(),
+            ],
+        };
+
+        Paragraph::new(lines).render(content, buf);
+    }
+}
+
+fn direction_arrow(dir: Direction) -> &'static str {
+    match dir {
+        Direction::Up => "↑",
+        Direction::UpRight => "↗",
+        Direction::Right => "→",
+        Direction::DownRight => "↘",
+        Direction::Down => "↓",
+        Direction::DownLeft => "↙",
+        Direction::Left => "←",
+        Direction::UpLeft => "↖",
+    }
+}
+
 pub struct SightSenseWidget<'a> {
     pub sense: BoundedU8<0, 10>,
     pub stage_turn: StageTurn,
@@ -294,23 +586,26 @@ fn render_sight_line<'a>(l: SightWidgetLine, stage_turn: u64) -> Line<'a> {
             Span::from(foe_type.grapheme()).style(THEME.palette.foe),
             Span::from(format!(": {} {}", count, foe_type.label())),
         ]),
-        SightWidgetLine::Ally(turn, name) => {
-            let diff = turn.abs_diff(stage_turn);
-            let label = match turn.cmp(&stage_turn) {
-                Ordering::Greater => format!("{diff} turns ahead"),
-                Ordering::Equal => "on the same turn".to_owned(),
-                Ordering::Less => format!("{diff} turns behind"),
-            };
-            let color = ally_color(turn, stage_turn);
-
-            Line::from(vec![
-                Span::from("@").style(color),
-                Span::from(format!(": {name} ({label})")),
-            ])
-        }
+        SightWidgetLine::Ally(turn, name) => ally_line(turn, stage_turn, &name),
     }
 }
 
+/// An ally and how far it is in time from the player
+fn ally_line<'a>(turn: StageTurn, stage_turn: StageTurn, name: &str) -> Line<'a> {
+    let diff = turn.abs_diff(stage_turn);
+    let label = match turn.cmp(&stage_turn) {
+        Ordering::Greater => format!("{diff} turns ahead"),
+        Ordering::Equal => "on the same turn".to_owned(),
+        Ordering::Less => format!("{diff} turns behind"),
+    };
+    let color = ally_color(turn, stage_turn);
+
+    Line::from(vec![
+        Span::from("@").style(color),
+        Span::from(format!(": {name} ({label})")),
+    ])
+}
+
 fn to_widget_lines(info: &SightInfo, max_lines: usize) -> Vec<SightWidgetLine> {
     let mut lines = vec![];
 
@@ -371,6 +666,8 @@ pub struct SensesWidget<'a> {
     pub selection: usize,
     pub max_sense: usize,
     pub available_senses: &'a [losig_core::sense::SenseType],
+    pub hearing_diagonal: Option<u16>,
+    pub costs: &'a CostTable,
 }
 
 impl<'a> Widget for SensesWidget<'a> {
@@ -378,13 +675,15 @@ impl<'a> Widget for SensesWidget<'a> {
     where
         Self: Sized,
     {
-        use losig_core::sense::SenseType;
-
         // Define fixed order for senses
-        const SENSE_ORDER: [SenseType; 4] = [
+        const SENSE_ORDER: [SenseType; 8] = [
             SenseType::SelfSense,
             SenseType::Touch,
             SenseType::Hearing,
+            SenseType::Smell,
+            SenseType::Danger,
+            SenseType::OrbSense,
+            SenseType::Chronosense,
             SenseType::Sight,
         ];
 
@@ -396,16 +695,10 @@ impl<'a> Widget for SensesWidget<'a> {
             .collect();
 
         // Build constraints dynamically based on available senses
-        let mut constraints = vec![];
-        for sense_type in &ordered_senses {
-            let height = match sense_type {
-                SenseType::SelfSense => 2,
-                SenseType::Touch => 4,
-                SenseType::Hearing => 2,
-                SenseType::Sight => 2,
-            };
-            constraints.push(Constraint::Length(height));
-        }
+        let mut constraints: Vec<Constraint> = ordered_senses
+            .iter()
+            .map(|sense_type| Constraint::Length(sense_height(*sense_type)))
+            .collect();
         // Ensure last constraint takes remaining space for Sight
         if let Some(last) = constraints.last_mut()
             && matches!(ordered_senses.last(), Some(SenseType::Sight))
@@ -415,6 +708,13 @@ impl<'a> Widget for SensesWidget<'a> {
 
         let rows = Layout::vertical(constraints).split(area);
 
+        let focus = self
+            .info
+            .and_then(|i| i.selfi.as_ref())
+            .map(|selfi| selfi.focus);
+        let cut =
+            focus.and_then(|focus| cheapest_cut(&self.senses, self.costs, focus, &ordered_senses));
+
         for (idx, sense_type) in ordered_senses.iter().enumerate() {
             let selected = self.selection == idx;
             match sense_type {
@@ -439,6 +739,40 @@ impl<'a> Widget for SensesWidget<'a> {
                         sense: self.senses.hearing,
                         info: self.info.and_then(|i| i.hearing.as_ref()),
                         selected,
+                        diagonal: self.hearing_diagonal,
+                    }
+                    .render(rows[idx], buf);
+                }
+                SenseType::Smell => {
+                    SmellSenseWidget {
+                        sense: self.senses.smell,
+                        info: self.info.and_then(|i| i.smell.as_ref()),
+                        selected,
+                    }
+                    .render(rows[idx], buf);
+                }
+                SenseType::Danger => {
+                    DangerSenseWidget {
+                        sense: self.senses.danger,
+                        info: self.info.and_then(|i| i.danger.as_ref()),
+                        selected,
+                    }
+                    .render(rows[idx], buf);
+                }
+                SenseType::OrbSense => {
+                    OrbSenseWidget {
+                        sense: self.senses.orb,
+                        info: self.info.and_then(|i| i.orb.as_ref()),
+                        selected,
+                    }
+                    .render(rows[idx], buf);
+                }
+                SenseType::Chronosense => {
+                    ChronoSenseWidget {
+                        sense: self.senses.chrono,
+                        stage_turn: self.stage_turn,
+                        info: self.info.and_then(|i| i.chrono.as_ref()),
+                        selected,
                     }
                     .render(rows[idx], buf);
                 }
@@ -452,6 +786,29 @@ impl<'a> Widget for SensesWidget<'a> {
                     .render(rows[idx], buf);
                 }
             }
+
+            // Annotate the header with what the sense costs, and how to get under the focus
+            let cost = self
+                .costs
+                .get(*sense_type)
+                .cost(self.senses.strength(*sense_type));
+            let header = Rect {
+                height: 1,
+                ..rows[idx]
+            };
+            match cut {
+                Some((sense, strength)) if sense == *sense_type => {
+                    let lost = self.senses.strength(sense) - strength;
+                    Line::from(format!(" {cost}fp -{lost} "))
+                        .style(Style::default().bold().bg(THEME.palette.foe))
+                        .centered()
+                        .render(header, buf);
+                }
+                _ if cost > 0 => Line::from(format!(" {cost}fp "))
+                    .centered()
+                    .render(header, buf),
+                _ => {}
+            }
         }
     }
 }
@@ -0,0 +1,31 @@
+use ratatui::{
+    buffer::Buffer,
+    layout::{Constraint, Rect},
+    style::{Style, Stylize},
+    widgets::{Block, Borders, Clear, Paragraph, Widget},
+};
+
+use crate::tui::{THEME, utils::center};
+
+/// Shown when the server announced it is stopping
+pub struct ShutdownWidget;
+
+impl Widget for ShutdownWidget {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let popup_area = center(area, Constraint::Length(44), Constraint::Length(5));
+        Clear.render(popup_area, buf);
+
+        let block = Block::default()
+            .title("Server stopped")
+            .borders(Borders::ALL)
+            .style(Style::default().fg(THEME.palette.log_warn));
+
+        Paragraph::new(vec![
+            "The server has shut down.".bold().into(),
+            "Come back later!".into(),
+        ])
+        .centered()
+        .block(block)
+        .render(popup_area, buf);
+    }
+}
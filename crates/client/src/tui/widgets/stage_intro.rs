@@ -0,0 +1,60 @@
+use losig_core::network::{DIFFICULTY_MAX, StageInfo};
+use ratatui::{
+    buffer::Buffer,
+    layout::Rect,
+    style::{Style, Stylize},
+    text::Line,
+    widgets::{Block, Borders, Clear, Paragraph, Widget, Wrap},
+};
+
+use crate::tui::{THEME, utils::popup};
+
+/// Presents the stage just entered, until the next input
+pub struct StageIntroWidget<'a> {
+    pub info: &'a StageInfo,
+}
+
+impl<'a> Widget for StageIntroWidget<'a> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let mut lines: Vec<Line> = vec![];
+        if !self.info.author.is_empty() {
+            lines.push(format!("by {}", self.info.author).into());
+        }
+        let difficulty = match self.info.difficulty {
+            Some(difficulty) => {
+                let difficulty = difficulty.min(DIFFICULTY_MAX) as usize;
+                format!(
+                    "{}{}",
+                    "*".repeat(difficulty),
+                    ".".repeat(DIFFICULTY_MAX as usize - difficulty)
+                )
+            }
+            None => "unknown".to_string(),
+        };
+        lines.push(Line::from(format!("Difficulty: {difficulty}")).fg(THEME.palette.important));
+        if !self.info.description.is_empty() {
+            lines.push("".into());
+            lines.push(self.info.description.clone().italic().into());
+        }
+        lines.push("".into());
+        lines.push(Line::from("Press any key").fg(THEME.palette.ui_disabled));
+
+        let width = 50;
+        let text_width = width - 2;
+        let description_rows = self.info.description.chars().count().div_ceil(text_width) as u16;
+        let height = lines.len() as u16 + description_rows.saturating_sub(1) + 2;
+        let popup_area = popup(area, width as u16, height);
+        Clear.render(popup_area, buf);
+
+        let block = Block::default()
+            .title(Line::from(format!(" {} ", self.info.name)).bold())
+            .borders(Borders::ALL)
+            .style(Style::default().fg(THEME.palette.ui));
+
+        Paragraph::new(lines)
+            .centered()
+            .wrap(Wrap { trim: true })
+            .block(block)
+            .render(popup_area, buf);
+    }
+}
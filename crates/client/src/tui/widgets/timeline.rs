@@ -8,11 +8,18 @@ use ratatui::{
 
 use crate::{tui::THEME, world::WorldView};
 
+/// Turns drawn by a single cell of the bar
+const TURNS_PER_CELL: StageTurn = 5;
+
 pub struct TimelineWidget {
     timeline: Timeline,
     current: StageTurn,
     stage_name: String,
     timeline_type: TimelineType,
+    /// Avatars this far behind the head are lost
+    timeline_length: StageTurn,
+    /// Turns of the allies, known through the chronosense
+    allies: Vec<StageTurn>,
 }
 
 impl TimelineWidget {
@@ -22,6 +29,27 @@ impl TimelineWidget {
             current: world.stage_turn,
             stage_name: world.stage_info.name.clone(),
             timeline_type: world.stage_info.timeline_type,
+            timeline_length: world.stage_info.timeline_length as StageTurn,
+            allies: world
+                .last_info()
+                .and_then(|info| info.chrono.as_ref())
+                .map(|chrono| {
+                    chrono
+                        .allies
+                        .iter()
+                        .map(|ally| world.stage_turn.saturating_add_signed(ally.turn_offset))
+                        .collect()
+                })
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Cell of the bar, ticked when an ally is at one of its turns
+    fn cell(&self, turns: impl Fn(StageTurn) -> bool) -> Span<'static> {
+        if self.allies.iter().any(|turn| turns(*turn)) {
+            Span::from("|").fg(Color::Black)
+        } else {
+            Span::from(" ")
         }
     }
 
@@ -37,16 +65,42 @@ impl TimelineWidget {
         let turn_span = Span::from(format!(" - Turn {}: ", self.current));
 
         let mut timelines_spans: Vec<Span> = vec![stage_span, turn_span];
-        let chars_before = self.current.saturating_sub(self.timeline.tail).div_ceil(5);
+        let current = self.current;
+        let chars_before = current
+            .saturating_sub(self.timeline.tail)
+            .div_ceil(TURNS_PER_CELL);
+
+        // The last fifth of the timeline before being lost is a danger zone
+        let lost_before = self.timeline.head.saturating_sub(self.timeline_length);
+        let danger_before = lost_before + self.timeline_length / 5;
 
         for i in (0..chars_before).rev() {
-            timelines_spans.push(Span::from(" ").bg(tail_color(i)));
+            let oldest = current.saturating_sub((i + 1) * TURNS_PER_CELL);
+            let color = if oldest < danger_before {
+                THEME.palette.log_grave
+            } else {
+                tail_color(i)
+            };
+            let cell =
+                self.cell(|turn| turn < current && (current - turn - 1) / TURNS_PER_CELL == i);
+            timelines_spans.push(cell.bg(color));
         }
 
-        timelines_spans.push(Span::from("@").fg(Color::Black).bg(Color::White));
-        let chars_after = self.timeline.head.saturating_sub(self.current).div_ceil(5);
+        let avatar_color = if current < danger_before {
+            THEME.palette.log_grave
+        } else {
+            Color::White
+        };
+        timelines_spans.push(Span::from("@").fg(Color::Black).bg(avatar_color));
+        let chars_after = self
+            .timeline
+            .head
+            .saturating_sub(current)
+            .div_ceil(TURNS_PER_CELL);
         for i in 0..chars_after {
-            timelines_spans.push(Span::from(" ").bg(head_color(i)));
+            let cell =
+                self.cell(|turn| turn > current && (turn - current - 1) / TURNS_PER_CELL == i);
+            timelines_spans.push(cell.bg(head_color(i)));
         }
 
         Line::from(timelines_spans)
@@ -0,0 +1,34 @@
+use ratatui::{
+    buffer::Buffer,
+    layout::Rect,
+    style::{Style, Stylize},
+    widgets::{Paragraph, Widget, Wrap},
+};
+
+use crate::tui::THEME;
+
+/// Shown instead of the page when the terminal can't fit it
+pub struct TooSmallWidget {
+    pub width: u16,
+    pub height: u16,
+}
+
+impl Widget for TooSmallWidget {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let y = area.y + area.height.saturating_sub(2) / 2;
+        let area = Rect {
+            y,
+            height: 2,
+            ..area
+        }
+        .intersection(area);
+        Paragraph::new(vec![
+            "Terminal too small".bold().into(),
+            format!("{}x{} needed", self.width, self.height).into(),
+        ])
+        .centered()
+        .wrap(Wrap { trim: true })
+        .style(Style::default().fg(THEME.palette.log_warn))
+        .render(area, buf);
+    }
+}
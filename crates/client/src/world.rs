@@ -1,11 +1,13 @@
+use std::collections::{HashMap, VecDeque};
+
 use log::{debug, warn};
 use losig_core::{
     fov,
-    network::{StageInfo, TransitionMessage, TurnMessage},
-    sense::{Senses, SensesInfo, SightInfo},
+    network::{ResyncMessage, SpectateMessage, StageInfo, TransitionMessage, TurnMessage},
+    sense::{Senses, SensesInfo, SightInfo, TouchInfo},
     types::{
-        ClientAction, Offset, Position, ServerAction, StageId, StageTurn, Tile, Tiles, Timeline,
-        Turn,
+        ClientAction, FoeId, FoeType, Offset, Position, ServerAction, StageId, StageTurn, Tile,
+        Tiles, Timeline, TimelineType, Turn, UNDO_COST,
     },
 };
 use web_time::{Duration, Instant};
@@ -13,6 +15,16 @@ use web_time::{Duration, Instant};
 use crate::logs::{ClientLog, GameLogs};
 
 const VIEW_SIZE: usize = 256;
+/// Number of latency samples kept for the averages
+const LATENCY_SAMPLES: usize = 20;
+/// Turns a foe out of sight is remembered for
+pub const FOE_MEMORY_TURNS: u32 = 20;
+/// Number of foes remembered at most, the oldest sightings are forgotten first
+const FOE_MEMORY_SIZE: usize = 32;
+/// Number of prediction corrections kept for debugging
+const CORRECTIONS_SIZE: usize = 50;
+/// How long the cell just attacked is flashed
+const ATTACK_FLASH: Duration = Duration::from_millis(150);
 const START_POS: Position = Position {
     x: VIEW_SIZE / 2,
     y: VIEW_SIZE / 2,
@@ -31,8 +43,33 @@ pub struct WorldView {
     pub stage_turn: StageTurn,
     pub timeline: Timeline,
     pub last_latency: Option<Duration>,
+    latencies: VecDeque<Duration>,
     action_sent_at: Option<Instant>,
     pub stage_info: StageInfo,
+    /// Stage turn and tiles of the last sight received, sight deltas apply to it
+    sight_base: Option<(StageTurn, Tiles)>,
+    /// A sight delta could not be applied, the server must send everything again
+    resync_needed: bool,
+    /// Responses that contradicted the predictions, only tracked in debug mode
+    corrections: Option<VecDeque<Correction>>,
+    /// Keeps the whole history of the run instead of the recent turns, for replays
+    record: bool,
+    /// History of the stages already left in this run, only kept when recording
+    recorded: Vec<(StageInfo, Vec<WorldDiff>)>,
+    /// Cell the avatar just attacked and when, flashed for ATTACK_FLASH
+    attack_flash: Option<(Position, Instant)>,
+    /// How long to wait for the response of an action before asking for a resync
+    retry_after: Option<Duration>,
+}
+
+/// A server response that did not go the way the client predicted
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Correction {
+    pub turn: Turn,
+    pub predicted: ClientAction,
+    pub actual: ServerAction,
+    /// How far the avatar jumped when the response was applied
+    pub offset: Offset,
 }
 
 impl WorldView {
@@ -52,8 +89,70 @@ impl WorldView {
             logs,
             timeline: Timeline { head: 1, tail: 1 },
             last_latency: None,
+            latencies: VecDeque::with_capacity(LATENCY_SAMPLES),
             action_sent_at: None,
+            sight_base: None,
+            resync_needed: false,
+            corrections: None,
+            record: false,
+            recorded: vec![],
+            attack_flash: None,
+            retry_after: None,
+        }
+    }
+
+    /// Starts keeping track of the prediction corrections
+    pub fn enable_corrections(&mut self) {
+        self.corrections.get_or_insert_with(VecDeque::new);
+    }
+
+    /// Most recent corrections last, None if not in debug mode
+    pub fn corrections(&self) -> Option<&VecDeque<Correction>> {
+        self.corrections.as_ref()
+    }
+
+    /// Gives up on the responses that take longer than the timeout, see `take_response_overdue`
+    pub fn enable_retry(&mut self, timeout: Duration) {
+        self.retry_after = Some(timeout);
+    }
+
+    /// Starts keeping every turn of the run, so it can be replayed in full
+    pub fn enable_recording(&mut self) {
+        self.record = true;
+    }
+
+    /// Forgets the world and the recorded turns, a new run is starting
+    pub fn new_run(&mut self) {
+        self.clear();
+        self.recorded.clear();
+    }
+
+    /// Number of turns that can be replayed, the whole run when recording
+    pub fn replay_len(&self) -> usize {
+        let recorded: usize = self.recorded.iter().map(|(_, history)| history.len()).sum();
+        recorded + self.history.len()
+    }
+
+    /// The world as it was known at this turn of the replay
+    pub fn replay_frame(&self, index: usize) -> Option<WorldView> {
+        let stages = self
+            .recorded
+            .iter()
+            .map(|(stage_info, history)| (stage_info, history.as_slice()))
+            .chain(std::iter::once((&self.stage_info, self.history.as_slice())));
+
+        let mut index = index;
+        for (stage_info, history) in stages {
+            if index < history.len() {
+                let mut frame = WorldView::new();
+                frame.stage_info = stage_info.clone();
+                frame.history = history[..=index].to_vec();
+                frame.rebuild_current_state();
+                return Some(frame);
+            }
+            index -= history.len();
         }
+        None
     }
 
     pub fn act(&mut self, action: &ClientAction, senses: &Senses) {
@@ -78,14 +177,50 @@ impl WorldView {
         self.history.push(history);
 
         // Don't maintain old history entries
-        let to_remove = self.history.len().saturating_sub(10);
-        for history in self.history.drain(0..to_remove) {
-            self.past_state.update(&history);
+        if !self.record {
+            let to_remove = self.history.len().saturating_sub(10);
+            for history in self.history.drain(0..to_remove) {
+                self.past_state.update(&history);
+            }
         }
 
         self.turn += 1;
     }
 
+    /// Takes back the last action, as the server will once the undo is sent. Its response is the
+    /// one of the turn before, so it lands on the history entry that is now the last one.
+    pub fn undo(&mut self) -> bool {
+        let focus = self
+            .last_info()
+            .and_then(|info| info.selfi.as_ref())
+            .map(|selfi| selfi.focus);
+        let allowed = !self.awaiting_response()
+            && self.history.len() > 1
+            && self.stage_turn > self.timeline.tail
+            && self.stage_info.timeline_type != TimelineType::Immediate
+            && focus.is_none_or(|focus| focus >= UNDO_COST);
+        if !allowed {
+            self.logs.add(self.turn, ClientLog::CannotUndo);
+            return false;
+        }
+
+        self.action_sent_at = Some(Instant::now());
+        self.history.pop();
+        self.turn -= 1;
+        self.logs.add(self.turn, ClientLog::Undo);
+        self.rebuild_current_state();
+        true
+    }
+
+    /// Asks to sense the last turn again, its response lands on the last history entry
+    pub fn resense(&mut self) -> bool {
+        if self.awaiting_response() || self.history.is_empty() {
+            return false;
+        }
+        self.action_sent_at = Some(Instant::now());
+        true
+    }
+
     pub fn update(
         &mut self,
         TurnMessage {
@@ -99,13 +234,19 @@ impl WorldView {
             timeline,
         }: TurnMessage,
     ) {
+        let info = self.expand_sight(stage_turn, info);
         let diff = turn.abs_diff(self.turn);
 
         // Calculate latency if this is a response to our action
         if diff == 0
             && let Some(sent_at) = self.action_sent_at.take()
         {
-            self.last_latency = Some(sent_at.elapsed());
+            let latency = sent_at.elapsed();
+            self.last_latency = Some(latency);
+            if self.latencies.len() == LATENCY_SAMPLES {
+                self.latencies.pop_front();
+            }
+            self.latencies.push_back(latency);
         }
 
         // Update global info
@@ -117,14 +258,32 @@ impl WorldView {
         }
 
         self.logs.add_server_events(turn, events);
+        if action == ServerAction::Blocked {
+            self.logs.add(turn, ClientLog::Blocked);
+        }
 
         match diff {
             i if self.history.len() > i as usize => {
                 let index = self.history.len() - i as usize - 1;
+                let predicted = self.history[index].action;
+                let position = self.current_state.position;
                 self.history[index].info = info;
                 self.history[index].server_action = Some(action);
                 self.history[index].update_received = true;
                 self.rebuild_current_state();
+                let offset = self.current_state.position - position;
+                self.record_correction(turn, predicted, action, offset);
+
+                if diff == 0
+                    && let ClientAction::MoveOrAttack(dir) = predicted
+                    && matches!(
+                        action,
+                        ServerAction::Attack(_) | ServerAction::AttackAvatar(_)
+                    )
+                {
+                    let target = self.current_state.position + dir.offset();
+                    self.attack_flash = Some((target, Instant::now()));
+                }
             }
             _ => {
                 // Event too old, drop it.
@@ -140,7 +299,7 @@ impl WorldView {
         &mut self,
         TransitionMessage {
             player_id: _,
-            turn: _,
+            turn,
             stage_turn,
             stage_info,
             info,
@@ -150,6 +309,51 @@ impl WorldView {
     ) {
         self.clear();
 
+        self.turn = turn;
+        self.stage_id = stage_id;
+        self.stage_info = stage_info;
+        self.stage_turn = stage_turn;
+        self.history.push(WorldDiff {
+            action: ClientAction::Wait,
+            server_action: Some(ServerAction::Wait),
+            info,
+            update_received: true,
+        });
+        self.timeline = timeline;
+        self.rebuild_current_state();
+    }
+
+    /// Catches up with the server after a reconnection. Unlike a transition, the explored map is
+    /// kept if still in the same stage. The history starts over from the turn of the server.
+    pub fn resync(
+        &mut self,
+        ResyncMessage {
+            turn,
+            stage_id,
+            stage_info,
+            stage_turn,
+            info,
+            timeline,
+            limbo: _,
+        }: ResyncMessage,
+    ) {
+        if self.stage_id != stage_id {
+            self.clear();
+        }
+
+        // Responses to actions sent before the disconnection are lost, the turns played so far
+        // only matter for the explored map
+        self.action_sent_at = None;
+        self.sight_base = None;
+        let history = std::mem::take(&mut self.history);
+        for history in history.iter() {
+            self.past_state.update(history);
+        }
+        if self.record && !history.is_empty() {
+            self.recorded.push((self.stage_info.clone(), history));
+        }
+        self.turn = turn;
+
         self.stage_id = stage_id;
         self.stage_info = stage_info;
         self.stage_turn = stage_turn;
@@ -163,17 +367,134 @@ impl WorldView {
         self.rebuild_current_state();
     }
 
+    /// Replaces the world with the full view of a spectated stage
+    pub fn spectate(
+        &mut self,
+        SpectateMessage {
+            stage_id,
+            stage_info,
+            stage_turn,
+            timeline,
+            sight,
+        }: SpectateMessage,
+    ) {
+        self.clear();
+
+        self.stage_id = stage_id;
+        self.stage_info = stage_info;
+        self.stage_turn = stage_turn;
+        self.history.push(WorldDiff {
+            action: ClientAction::Wait,
+            server_action: Some(ServerAction::Wait),
+            info: Some(SensesInfo {
+                sight: Some(sight),
+                ..Default::default()
+            }),
+            update_received: true,
+        });
+        self.timeline = timeline;
+        self.rebuild_current_state();
+    }
+
     /// Resets the world. Mostly after a respawn or a goal reached.
     pub fn clear(&mut self) {
-        self.history = vec![];
+        let history = std::mem::take(&mut self.history);
+        if self.record && !history.is_empty() {
+            self.recorded.push((self.stage_info.clone(), history));
+        }
         self.past_state = WorldState::new();
         self.current_state = WorldState::new();
+        self.sight_base = None;
+        self.attack_flash = None;
+    }
+
+    /// Rebuilds the sight tiles sent as a delta, and keeps them for the next one
+    fn expand_sight(
+        &mut self,
+        stage_turn: StageTurn,
+        mut info: Option<SensesInfo>,
+    ) -> Option<SensesInfo> {
+        let Some(sight) = info.as_mut().and_then(|info| info.sight.as_mut()) else {
+            return info;
+        };
+
+        if let Some(delta) = sight.delta.take() {
+            match &self.sight_base {
+                Some((turn, base)) if *turn == delta.base_turn => sight.tiles = delta.apply(base),
+                _ => {
+                    warn!("Dropping sight delta without its base");
+                    self.resync_needed = true;
+                    self.sight_base = None;
+                    if let Some(info) = info.as_mut() {
+                        info.sight = None;
+                    }
+                    return info;
+                }
+            }
+        }
+
+        self.sight_base = Some((stage_turn, sight.tiles.clone()));
+        info
+    }
+
+    /// Asks the server for a resync along the next action
+    pub fn request_resync(&mut self) {
+        self.resync_needed = true;
+    }
+
+    /// Whether the server should be asked for a resync, only true once
+    pub fn take_resync_needed(&mut self) -> bool {
+        std::mem::take(&mut self.resync_needed)
     }
 
     pub fn current_state(&self) -> &WorldState {
         &self.current_state
     }
 
+    /// Mean of the recent latencies
+    pub fn avg_latency(&self) -> Option<Duration> {
+        if self.latencies.is_empty() {
+            return None;
+        }
+        let total: Duration = self.latencies.iter().sum();
+        Some(total / self.latencies.len() as u32)
+    }
+
+    /// 95th percentile of the recent latencies
+    pub fn p95_latency(&self) -> Option<Duration> {
+        let mut sorted: Vec<Duration> = self.latencies.iter().copied().collect();
+        sorted.sort();
+        let index = (sorted.len() * 95).div_ceil(100).checked_sub(1)?;
+        sorted.get(index).copied()
+    }
+
+    /// Min and max of the recent latencies
+    pub fn latency_range(&self) -> Option<(Duration, Duration)> {
+        let min = self.latencies.iter().min()?;
+        let max = self.latencies.iter().max()?;
+        Some((*min, *max))
+    }
+
+    /// The response of the last action is too late, the server likely refused or lost it. The
+    /// action is no longer awaited and the server should be asked for a resync, only true once.
+    pub fn take_response_overdue(&mut self) -> bool {
+        let overdue = match (self.retry_after, self.action_sent_at) {
+            (Some(timeout), Some(sent_at)) => sent_at.elapsed() >= timeout,
+            _ => false,
+        };
+        if overdue {
+            warn!("No response to the action of turn {}, resyncing", self.turn);
+            self.action_sent_at = None;
+            self.logs.add(self.turn, ClientLog::NoResponse);
+        }
+        overdue
+    }
+
+    /// An action was sent and its response is not there yet
+    pub fn awaiting_response(&self) -> bool {
+        self.action_sent_at.is_some()
+    }
+
     pub fn last_info(&self) -> Option<&SensesInfo> {
         self.history.last().and_then(|h| h.info.as_ref())
     }
@@ -201,16 +522,79 @@ impl WorldView {
         self.rebuild_current_state();
     }
 
+    /// Offset from the avatar of the cell it just attacked, for ATTACK_FLASH after the response
+    pub fn attack_flash(&self) -> Option<Offset> {
+        self.attack_flash
+            .filter(|(_, at)| at.elapsed() < ATTACK_FLASH)
+            .map(|(position, _)| position - self.current_state.position)
+    }
+
+    /// Known tiles contradict each other, often after a time travel
+    pub fn is_incoherent(&self) -> bool {
+        self.current_state.incoherent
+    }
+
+    /// Forgets the explored map, keeping only what was sensed in the recent history
+    pub fn redraw(&mut self) {
+        self.past_state = WorldState {
+            position: self.past_state.position,
+            ..WorldState::new()
+        };
+        self.rebuild_current_state();
+    }
+
     fn rebuild_current_state(&mut self) {
         let mut state = self.past_state.clone();
         for history in self.history.iter() {
             state.update(history);
         }
 
+        if state.incoherent && !self.current_state.incoherent {
+            warn!("Incoherent tiles received");
+            self.logs.add(self.turn, ClientLog::Incoherent);
+        }
+
         debug!("Rebuilding state up to turn {}", self.turn);
         self.current_state = state;
     }
 
+    fn record_correction(
+        &mut self,
+        turn: Turn,
+        predicted: ClientAction,
+        actual: ServerAction,
+        offset: Offset,
+    ) {
+        let Some(corrections) = self.corrections.as_mut() else {
+            return;
+        };
+
+        let expected = matches!(
+            (predicted, actual),
+            (
+                ClientAction::MoveOrAttack(_),
+                ServerAction::Move(_) | ServerAction::Attack(_) | ServerAction::AttackAvatar(_)
+            ) | (
+                ClientAction::Wait | ClientAction::Rest(_),
+                ServerAction::Wait
+            ) | (ClientAction::Disarm(_), ServerAction::Disarm(_))
+        );
+        if expected && offset == Offset::default() {
+            return;
+        }
+
+        debug!("Prediction corrected at turn {turn}: {predicted:?} became {actual:?}");
+        if corrections.len() == CORRECTIONS_SIZE {
+            corrections.pop_front();
+        }
+        corrections.push_back(Correction {
+            turn,
+            predicted,
+            actual,
+            offset,
+        });
+    }
+
     pub fn update_timeline(&mut self, stage: StageId, timeline: Timeline) {
         if self.stage_id == stage {
             self.timeline = timeline;
@@ -237,17 +621,41 @@ pub struct WorldState {
     pub tiles: [Tile; VIEW_SIZE * VIEW_SIZE],
     pub position: Position,
     pub incoherent: bool,
+    /// Living foes last seen, oldest sightings first
+    pub remembered_foes: Vec<RememberedFoe>,
+}
+
+/// Where a foe was when last seen
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RememberedFoe {
+    pub id: FoeId,
+    pub foe_type: FoeType,
+    pub position: Position,
+    /// Turns since it was seen, 0 if it is in the last sight
+    pub age: u32,
 }
 
 impl WorldState {
+    /// Width and height of the tracked world
+    pub const SIZE: usize = VIEW_SIZE;
+
     pub fn new() -> Self {
         Self {
             tiles: [Tile::Unknown; VIEW_SIZE * VIEW_SIZE],
             position: START_POS,
             incoherent: false,
+            remembered_foes: vec![],
         }
     }
 
+    /// Foes remembered out of sight, with their offset from the viewer
+    pub fn stale_foes(&self) -> impl Iterator<Item = (Offset, &RememberedFoe)> {
+        self.remembered_foes
+            .iter()
+            .filter(|foe| foe.age > 0)
+            .map(|foe| (foe.position - self.position, foe))
+    }
+
     pub fn tile_from_viewer(&self, offset: Offset) -> Tile {
         let position = self.position + offset;
         self.tile_at(position)
@@ -261,8 +669,96 @@ impl WorldState {
         self.tiles[pos.x + VIEW_SIZE * pos.y]
     }
 
+    /// Bounds of the explored tiles, as (min, max) inclusive
+    pub fn explored(&self) -> Option<(Position, Position)> {
+        let mut bounds: Option<(Position, Position)> = None;
+        for (i, tile) in self.tiles.iter().enumerate() {
+            if *tile == Tile::Unknown {
+                continue;
+            }
+            let pos = Position::from_index(i, Self::SIZE);
+            bounds = Some(match bounds {
+                None => (pos, pos),
+                Some((min, max)) => (
+                    Position {
+                        x: min.x.min(pos.x),
+                        y: min.y.min(pos.y),
+                    },
+                    Position {
+                        x: max.x.max(pos.x),
+                        y: max.y.max(pos.y),
+                    },
+                ),
+            });
+        }
+        bounds
+    }
+
+    /// Explored tiles as text cropped to their bounds, the marks being drawn over the tiles
+    pub fn to_text(&self, glyph: impl Fn(Tile) -> char, marks: &[(Position, char)]) -> String {
+        let Some((min, max)) = self.explored() else {
+            return String::new();
+        };
+
+        let mut text = String::new();
+        for y in min.y..=max.y {
+            let line: String = (min.x..=max.x)
+                .map(|x| {
+                    let pos = Position { x, y };
+                    marks
+                        .iter()
+                        .rev()
+                        .find(|(mark, _)| *mark == pos)
+                        .map_or_else(|| glyph(self.tile_at(pos)), |(_, c)| *c)
+                })
+                .collect();
+            text.push_str(line.trim_end());
+            text.push('\n');
+        }
+        text
+    }
+
+    /// Shortest path to the target through explored tiles, excluding the current position
+    pub fn path_to(&self, target: Position, diagonal: bool) -> Option<Vec<Position>> {
+        let mut parents = HashMap::from([(self.position, self.position)]);
+        let mut queue = VecDeque::from([self.position]);
+
+        while let Some(pos) = queue.pop_front() {
+            if pos == target {
+                let mut path = vec![];
+                let mut current = pos;
+                while current != self.position {
+                    path.push(current);
+                    current = parents[&current];
+                }
+                path.reverse();
+                return Some(path);
+            }
+
+            for x in -1..2 {
+                for y in -1..2 {
+                    if !diagonal && x != 0 && y != 0 {
+                        continue;
+                    }
+                    let next = pos + Offset { x, y };
+                    if next.x >= VIEW_SIZE || next.y >= VIEW_SIZE || parents.contains_key(&next) {
+                        continue;
+                    }
+                    let tile = self.tile_at(next);
+                    if tile != Tile::Unknown && tile.can_travel() {
+                        parents.insert(next, pos);
+                        queue.push_back(next);
+                    }
+                }
+            }
+        }
+        None
+    }
+
     fn update(&mut self, history: &WorldDiff) {
         self.update_action(&history.action, history.server_action.as_ref());
+        let sight = history.info.as_ref().and_then(|info| info.sight.as_ref());
+        self.update_foe_memory(sight);
 
         if let Some(ref info) = history.info {
             if let Some(ref info) = info.sight {
@@ -275,6 +771,34 @@ impl WorldState {
         }
     }
 
+    /// Foes in sight are remembered where they stand, the ones missing from where they were
+    /// seen are forgotten
+    fn update_foe_memory(&mut self, sight: Option<&SightInfo>) {
+        for foe in self.remembered_foes.iter_mut() {
+            foe.age += 1;
+        }
+        if let Some(sight) = sight {
+            let viewer = self.position;
+            self.remembered_foes.retain(|foe| {
+                let in_sight =
+                    sight.tiles.at_offset_from_center(foe.position - viewer) != Tile::Unknown;
+                !in_sight && sight.foes.iter().all(|seen| seen.id != foe.id)
+            });
+            for foe in sight.foes.iter().filter(|foe| foe.alive) {
+                self.remembered_foes.push(RememberedFoe {
+                    id: foe.id,
+                    foe_type: foe.foe_type,
+                    position: viewer + foe.offset,
+                    age: 0,
+                });
+            }
+        }
+        self.remembered_foes
+            .retain(|foe| foe.age <= FOE_MEMORY_TURNS);
+        let excess = self.remembered_foes.len().saturating_sub(FOE_MEMORY_SIZE);
+        self.remembered_foes.drain(0..excess);
+    }
+
     fn update_action(&mut self, action: &ClientAction, server_action: Option<&ServerAction>) {
         match action {
             ClientAction::MoveOrAttack(dir) => {
@@ -286,7 +810,11 @@ impl WorldState {
                     }
                 }
             }
-            ClientAction::Wait => {}
+            ClientAction::Wait
+            | ClientAction::Rest(_)
+            | ClientAction::Disarm(_)
+            | ClientAction::Undo
+            | ClientAction::Sense => {}
         }
     }
 
@@ -381,8 +909,9 @@ impl WorldState {
                 };
                 let fov_tiles = fov::fov(center_pos, sight_radius, &tiles_for_fov);
 
-                // Copy foes, orb, and allies from previous sight, adjusting offsets for player movement
-                let (foes, orb, allies) = if let Some(prev_sight) = previous_sight {
+                // Copy foes, orb, allies and shards from previous sight, adjusting offsets for
+                // player movement
+                let (foes, orb, allies, shards) = if let Some(prev_sight) = previous_sight {
                     let adjusted_foes = prev_sight
                         .foes
                         .iter()
@@ -400,18 +929,28 @@ impl WorldState {
                         .iter()
                         .map(|ally| {
                             let mut adjusted = ally.clone();
+                            // The next move is relative to the ally, it doesn't shift
                             adjusted.offset = adjusted.offset - player_movement;
-                            // Also adjust the offset in Leading status if present
-                            if let Some(offset) = adjusted.next_move.as_mut() {
-                                *offset = *offset - player_movement;
-                            }
                             adjusted
                         })
                         .collect();
 
-                    (adjusted_foes, adjusted_orb, adjusted_allies)
+                    // The shard the player steps on is picked
+                    let adjusted_shards = prev_sight
+                        .shards
+                        .iter()
+                        .map(|offset| *offset - player_movement)
+                        .filter(|offset| offset.x != 0 || offset.y != 0)
+                        .collect();
+
+                    (
+                        adjusted_foes,
+                        adjusted_orb,
+                        adjusted_allies,
+                        adjusted_shards,
+                    )
                 } else {
-                    (vec![], None, vec![])
+                    (vec![], None, vec![], vec![])
                 };
 
                 Some(SightInfo {
@@ -419,6 +958,8 @@ impl WorldState {
                     foes,
                     orb,
                     allies,
+                    shards,
+                    delta: None,
                 })
             } else {
                 None
@@ -427,18 +968,65 @@ impl WorldState {
             None
         };
 
+        // Generate touch info on the local grid, keeping the last touched entities
+        let touch = if senses.touch.get() > 0 {
+            previous_info
+                .and_then(|info| info.touch.as_ref())
+                .map(|prev_touch| {
+                    let touch_radius = senses.touch.get() as usize;
+                    let tiles_for_fov = predicted_state.tiles_for_fov(touch_radius);
+                    let center_pos = Position {
+                        x: touch_radius,
+                        y: touch_radius,
+                    };
+
+                    let foes = prev_touch
+                        .foes
+                        .iter()
+                        .map(|offset| *offset - player_movement)
+                        .filter(|offset| {
+                            offset.x.unsigned_abs().max(offset.y.unsigned_abs()) <= touch_radius
+                        })
+                        .collect();
+
+                    TouchInfo {
+                        tiles: fov::fov(center_pos, touch_radius, &tiles_for_fov),
+                        foes,
+                        traps: prev_touch.traps,
+                        orb: prev_touch.orb,
+                    }
+                })
+        } else {
+            None
+        };
+
         // Copy other sense infos from previous state
         let selfi = previous_info.and_then(|info| info.selfi.clone());
-        let touch = None;
         let hearing = previous_info.and_then(|info| info.hearing.clone());
+        let smell = previous_info.and_then(|info| info.smell.clone());
+        let chrono = previous_info.and_then(|info| info.chrono.clone());
+        let orb = previous_info.and_then(|info| info.orb.clone());
+        let danger = previous_info.and_then(|info| info.danger.clone());
 
         // Return intermediate info if at least one sense is present
-        if sight.is_some() || selfi.is_some() || touch.is_some() || hearing.is_some() {
+        if sight.is_some()
+            || selfi.is_some()
+            || touch.is_some()
+            || hearing.is_some()
+            || smell.is_some()
+            || chrono.is_some()
+            || orb.is_some()
+            || danger.is_some()
+        {
             Some(SensesInfo {
                 selfi,
                 touch,
                 sight,
                 hearing,
+                smell,
+                chrono,
+                orb,
+                danger,
             })
         } else {
             None
@@ -451,3 +1039,99 @@ impl Default for WorldState {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The prediction computes the sight over the explored tiles around the avatar, it must match
+    /// what the server computes over the whole map
+    #[test]
+    fn predicted_fov_parity_test() {
+        let map = [
+            "##########",
+            "#....#...#",
+            "#.##...#.#",
+            "#..#.|...#",
+            "#.....##.#",
+            "##########",
+        ];
+        let mut tiles = Tiles::new(map[0].len(), map.len());
+        for (y, row) in map.iter().enumerate() {
+            for (x, c) in row.chars().enumerate() {
+                tiles.grid[(x, y)] = match c {
+                    '#' => Tile::Wall,
+                    '|' => Tile::Pylon,
+                    _ => Tile::Empty,
+                };
+            }
+        }
+
+        let origin = Offset { x: 100, y: 100 };
+        let mut state = WorldState::new();
+        // Tiles are placed around the viewer, so the map starts at the origin
+        state.update_tiles(tiles.center() + origin, &tiles);
+
+        for x in 0..tiles.width() {
+            for y in 0..tiles.height() {
+                let viewer = Position { x, y };
+                if tiles.get(viewer).opaque() {
+                    continue;
+                }
+                for radius in 1..5 {
+                    state.position = viewer + origin;
+                    let local = state.tiles_for_fov(radius);
+                    let predicted = fov::fov(
+                        Position {
+                            x: radius,
+                            y: radius,
+                        },
+                        radius,
+                        &local,
+                    );
+                    assert_eq!(
+                        predicted,
+                        fov::fov(viewer, radius, &tiles),
+                        "{viewer} with radius {radius}"
+                    );
+                }
+            }
+        }
+    }
+
+    /// Actions the server never got are forgotten, the next response lands on the next action
+    #[test]
+    fn resync_test() {
+        let mut world = WorldView::new();
+        world.act(&ClientAction::Wait, &Senses::default());
+        world.act(&ClientAction::Wait, &Senses::default());
+        assert_eq!(world.turn, 3);
+
+        world.resync(ResyncMessage {
+            turn: 2,
+            stage_id: 0,
+            stage_info: StageInfo::default(),
+            stage_turn: 5,
+            info: None,
+            timeline: Timeline { head: 5, tail: 1 },
+            limbo: false,
+        });
+        assert_eq!(world.turn, 2);
+        assert!(!world.awaiting_response());
+
+        world.act(&ClientAction::Wait, &Senses::default());
+        world.update(TurnMessage {
+            player_id: 1,
+            turn: 3,
+            stage_turn: 6,
+            stage: 0,
+            info: None,
+            action: ServerAction::Wait,
+            events: vec![],
+            timeline: Timeline { head: 6, tail: 1 },
+        });
+        assert_eq!(world.history.len(), 2);
+        assert!(world.history.iter().all(|history| history.update_received));
+        assert_eq!(world.replay_len(), 2);
+    }
+}
@@ -0,0 +1,40 @@
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use losig_core::{
+    fov::{self, VisibilitySet},
+    types::{Position, Tile, Tiles},
+};
+
+/// Open map with scattered pillars, so that the scanners split
+fn pillars(size: usize) -> Tiles {
+    let mut tiles = Tiles::new(size, size);
+    for y in 0..size {
+        for x in 0..size {
+            let wall =
+                x == 0 || y == 0 || x == size - 1 || y == size - 1 || (x * 7 + y * 13) % 11 == 0;
+            tiles.grid[(x, y)] = if wall { Tile::Wall } else { Tile::Empty };
+        }
+    }
+    tiles
+}
+
+/// Copying the tiles out on every call against computing into a reused set
+fn fov_bench(c: &mut Criterion) {
+    let tiles = pillars(32);
+    let viewer = Position { x: 16, y: 16 };
+    let mut group = c.benchmark_group("fov");
+    for radius in [3, 6, 10] {
+        group.bench_with_input(BenchmarkId::new("tiles", radius), &radius, |b, &radius| {
+            b.iter(|| fov::fov(viewer, radius, &tiles))
+        });
+        let mut visible = VisibilitySet::default();
+        group.bench_with_input(
+            BenchmarkId::new("reused_set", radius),
+            &radius,
+            |b, &radius| b.iter(|| fov::fov_into(viewer, radius, &tiles, &mut visible)),
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, fov_bench);
+criterion_main!(benches);
@@ -44,7 +44,24 @@ pub enum GameEvent {
     ParadoxTeleport(FoeType),
     OrbSeen,
     OrbTaken(Target),
+    /// An avatar picked a shard, raising its attack
+    ShardTaken(Target),
+    /// An avatar disarmed a trap next to it
+    TrapDisarmed(Target),
     AvatarFadedOut(Target),
+    /// When the player runs out of focus and loses its senses
+    Exhausted,
+    /// A sense used too strongly lately was weakened
+    SenseCooldown(SenseType),
+    /// A foe gathers its strength, it strikes hard next turn
+    WindUp(Target),
+    /// A foe called another one to its side
+    Summon(Target),
+    /// When an avatar's hp drops below the low hp threshold
+    Wounded {
+        subject: Target,
+        hp: u8,
+    },
 }
 impl GameEvent {
     pub fn has_player(&self, pid: PlayerId) -> bool {
@@ -55,6 +72,9 @@ impl GameEvent {
             GameEvent::Fumble(target) => target.is_player(pid),
             GameEvent::Kill { subject, source } => subject.is_player(pid) || source.is_player(pid),
             GameEvent::OrbTaken(target) => target.is_player(pid),
+            GameEvent::ShardTaken(target) => target.is_player(pid),
+            GameEvent::TrapDisarmed(target) => target.is_player(pid),
+            GameEvent::Wounded { subject, .. } => subject.is_player(pid),
             _ => false,
         }
     }
@@ -1,4 +1,4 @@
-use crate::types::{Offset, Position, Tile, Tiles};
+use crate::types::{Offset, Position, Tiles};
 
 type F = fraction::Fraction;
 
@@ -66,14 +66,81 @@ impl Scanner {
     }
 }
 
-/// This does a clone instead of a bit mask for now
+/// Set of visible offsets around a viewer, backed by a bitset. It can be reused between fov
+/// computations to avoid allocating.
+#[derive(Debug, Clone, Default)]
+pub struct VisibilitySet {
+    radius: usize,
+    bits: Vec<u64>,
+}
+
+impl VisibilitySet {
+    pub fn new(radius: usize) -> Self {
+        let mut new = Self::default();
+        new.reset(radius);
+        new
+    }
+
+    /// Clears the set and resizes it for the given radius, keeping the allocation
+    pub fn reset(&mut self, radius: usize) {
+        let side = 2 * radius + 1;
+        self.radius = radius;
+        self.bits.clear();
+        self.bits.resize((side * side).div_ceil(64), 0);
+    }
+
+    pub fn radius(&self) -> usize {
+        self.radius
+    }
+
+    fn index(&self, offset: Offset) -> Option<usize> {
+        let radius = self.radius as isize;
+        if offset.x.abs() > radius || offset.y.abs() > radius {
+            return None;
+        }
+        let side = 2 * radius + 1;
+        Some(((offset.y + radius) * side + offset.x + radius) as usize)
+    }
+
+    pub fn insert(&mut self, offset: Offset) {
+        if let Some(i) = self.index(offset) {
+            self.bits[i / 64] |= 1 << (i % 64);
+        }
+    }
+
+    pub fn contains(&self, offset: Offset) -> bool {
+        self.index(offset)
+            .is_some_and(|i| self.bits[i / 64] & (1 << (i % 64)) != 0)
+    }
+
+    /// Copies the visible tiles, centered on the viewer
+    pub fn to_tiles(&self, viewer: Position, tiles: &Tiles) -> Tiles {
+        let radius = self.radius as isize;
+        let mut result = Tiles::new(2 * self.radius + 1, 2 * self.radius + 1);
+        let center_view = result.center();
+
+        for y in -radius..=radius {
+            for x in -radius..=radius {
+                let offset = Offset { x, y };
+                if self.contains(offset) {
+                    result.grid[(center_view + offset).into()] = tiles.get(viewer + offset);
+                }
+            }
+        }
+
+        result
+    }
+}
+
 pub fn fov(viewer: Position, radius: usize, tiles: &Tiles) -> Tiles {
-    let mut result = Tiles::new(2 * radius + 1, 2 * radius + 1);
+    let mut visible = VisibilitySet::new(radius);
+    fov_into(viewer, radius, tiles, &mut visible);
+    visible.to_tiles(viewer, tiles)
+}
 
-    let center_view = Position {
-        x: radius,
-        y: radius,
-    };
+/// Computes the offsets visible from the viewer into the given set, which is reset beforehand
+pub fn fov_into(viewer: Position, radius: usize, tiles: &Tiles, visible: &mut VisibilitySet) {
+    visible.reset(radius);
 
     for q in [
         Quadrant::East,
@@ -92,17 +159,11 @@ pub fn fov(viewer: Position, radius: usize, tiles: &Tiles) -> Tiles {
 
             for offset in scanner.offsets() {
                 let world_offset = q.transform(&offset);
-                let position = viewer + world_offset;
-                let tile = tiles
-                    .grid
-                    .get(position.x, position.y)
-                    .copied()
-                    .unwrap_or_default();
+                let tile = tiles.get(viewer + world_offset);
 
                 // 1. check if we show the tile
                 if tile.opaque() || scanner.see_center(&offset) {
-                    let result_pos = center_view + world_offset;
-                    result.grid[result_pos.into()] = tile;
+                    visible.insert(world_offset);
                 }
                 // 2. Start a new scanner if we go from (nothing | opaque) -> see through
                 if next_scan.is_none() && !tile.opaque() {
@@ -134,19 +195,86 @@ pub fn fov(viewer: Position, radius: usize, tiles: &Tiles) -> Tiles {
             }
         }
     }
-
-    result
 }
 
-/// viewer and viewed are interchangeable as the algorithm is symmetric
-pub fn can_see(tiles: &Tiles, viewer: Position, viewed: Position, max_dist: u8) -> bool {
+/// viewer and viewed are interchangeable as the algorithm is symmetric: floor tiles are only
+/// revealed when their center is within the scanned slopes. The set is used as a buffer.
+pub fn can_see(
+    tiles: &Tiles,
+    viewer: Position,
+    viewed: Position,
+    max_dist: u8,
+    visible: &mut VisibilitySet,
+) -> bool {
     let radius = viewer.dist(&viewed);
     if radius > max_dist as usize {
         return false;
     }
 
-    let visible_tiles = fov(viewer, radius, tiles);
-    let position = visible_tiles.center() + (viewer - viewed);
+    fov_into(viewer, radius, tiles, visible);
+    visible.contains(viewed - viewer)
+}
 
-    visible_tiles.grid[position.into()] != Tile::Unknown
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Tile;
+
+    fn parse(map: &[&str]) -> Tiles {
+        let mut tiles = Tiles::new(map[0].len(), map.len());
+        for (y, row) in map.iter().enumerate() {
+            for (x, c) in row.chars().enumerate() {
+                tiles.grid[(x, y)] = if c == '#' { Tile::Wall } else { Tile::Empty };
+            }
+        }
+        tiles
+    }
+
+    #[test]
+    fn can_see_symmetry_test() {
+        let maps = [
+            vec![
+                "#########",
+                "#...#...#",
+                "#.#...#.#",
+                "#...#...#",
+                "#########",
+            ],
+            vec![
+                "##########",
+                "#........#",
+                "#..##....#",
+                "#..#..#..#",
+                "#.....#..#",
+                "##########",
+            ],
+            vec![
+                "#########",
+                "#.#.#.#.#",
+                "#.......#",
+                "#.#.#.#.#",
+                "#.......#",
+                "#########",
+            ],
+        ];
+
+        let mut visible = VisibilitySet::default();
+        for map in maps {
+            let tiles = parse(&map);
+            let floors: Vec<Position> = (0..tiles.width())
+                .flat_map(|x| (0..tiles.height()).map(move |y| Position { x, y }))
+                .filter(|p| !tiles.get(*p).opaque())
+                .collect();
+
+            for a in &floors {
+                for b in &floors {
+                    assert_eq!(
+                        can_see(&tiles, *a, *b, 10, &mut visible),
+                        can_see(&tiles, *b, *a, 10, &mut visible),
+                        "{a} / {b} in {map:?}"
+                    );
+                }
+            }
+        }
+    }
 }
@@ -2,6 +2,19 @@ use serde::{Deserialize, Serialize};
 
 use crate::types::GameOver;
 
+/// Longest player name, in characters
+pub const NAME_MAX_LEN: usize = 8;
+/// Entries shown to the players
+pub const LEADERBOARD_TOP: usize = 10;
+
+/// Trims the name and keeps its first characters, None if nothing is left
+pub fn sanitize_name(name: &str) -> Option<String> {
+    let name: String = name.chars().filter(|c| !c.is_control()).collect();
+    let name: String = name.trim().chars().take(NAME_MAX_LEN).collect();
+    let name = name.trim_end();
+    (!name.is_empty()).then(|| name.to_owned())
+}
+
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct Leaderboard {
     entries: Vec<LeaderboardEntry>,
@@ -12,9 +25,17 @@ impl Leaderboard {
         Self { entries: vec![] }
     }
 
-    pub fn add(&mut self, entry: LeaderboardEntry) {
+    /// Returns whether the entry made it to the LEADERBOARD_TOP ones
+    pub fn add(&mut self, entry: LeaderboardEntry) -> bool {
+        // Sorting is stable, so the entry ranks above the older ones with the same score
+        let score = entry.gameover.score;
         self.entries.push(entry);
         self.entries.sort_by_key(|e| e.gameover.score);
+        self.entries
+            .iter()
+            .filter(|e| e.gameover.score > score)
+            .count()
+            < LEADERBOARD_TOP
     }
 
     pub fn top_entries(&self, n: usize) -> &[LeaderboardEntry] {
@@ -36,10 +57,9 @@ pub struct LeaderboardEntry {
 }
 
 impl LeaderboardEntry {
-    pub fn new(mut name: String, gameover: &GameOver) -> Self {
-        name.truncate(8);
+    pub fn new(name: String, gameover: &GameOver) -> Self {
         LeaderboardEntry {
-            name,
+            name: sanitize_name(&name).unwrap_or_default(),
             gameover: gameover.clone(),
         }
     }
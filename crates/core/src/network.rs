@@ -3,10 +3,10 @@ use serde::{Deserialize, Serialize};
 use crate::{
     events::GEvent,
     leaderboard::Leaderboard,
-    sense::{SenseType, Senses, SensesInfo},
+    sense::{CostTable, SenseType, Senses, SensesInfo, SightInfo},
     types::{
-        ClientAction, GameOver, PlayerId, ServerAction, StageId, StageTurn, Timeline,
-        TimelineType, Turn,
+        ClientAction, FoeId, FoeType, GameOver, PlayerId, Position, ServerAction, StageId,
+        StageTurn, Tiles, Timeline, TimelineType, Turn,
     },
 };
 
@@ -21,7 +21,7 @@ pub struct CommandMessage {
     pub senses: Senses,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct TurnMessage {
     pub player_id: PlayerId,
     /// The avatar's turn. Used to Keep track of which response corresponds to which command
@@ -36,7 +36,7 @@ pub struct TurnMessage {
 }
 
 /// Received when changing stages
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct TransitionMessage {
     pub player_id: PlayerId,
     /// The avatar's turn. Used to Keep track of which response corresponds to which command
@@ -51,12 +51,66 @@ pub struct TransitionMessage {
 }
 
 /// Static information about the stage. Namely its id, name and senses
-#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct StageInfo {
     pub name: String,
     pub timeline_length: u32,
     pub timeline_type: TimelineType,
     pub senses: Vec<SenseType>,
+    pub allow_diagonal: bool,
+    /// Whether allies can share their senses
+    #[serde(default)]
+    pub team_vision: bool,
+    #[serde(default = "default_max_sight")]
+    pub max_sight: u8,
+    #[serde(default = "default_max_hearing")]
+    pub max_hearing: u8,
+    /// Focus costs of the senses, the same the server charges
+    #[serde(default)]
+    pub costs: CostTable,
+    /// Who made the map, empty if unknown
+    #[serde(default)]
+    pub author: String,
+    /// From 1 to DIFFICULTY_MAX, None if unknown
+    #[serde(default)]
+    pub difficulty: Option<u8>,
+    /// One line presenting the stage, empty if none
+    #[serde(default)]
+    pub description: String,
+    /// Map diagonal the hearing distances are scaled to, None if they are not
+    #[serde(default)]
+    pub hearing_diagonal: Option<u16>,
+}
+
+/// Highest difficulty a stage can be given
+pub const DIFFICULTY_MAX: u8 = 5;
+
+fn default_max_sight() -> u8 {
+    Senses::MAX_SIGHT
+}
+
+fn default_max_hearing() -> u8 {
+    Senses::MAX_HEARING
+}
+
+impl Default for StageInfo {
+    fn default() -> Self {
+        Self {
+            name: Default::default(),
+            timeline_length: Default::default(),
+            timeline_type: Default::default(),
+            senses: Default::default(),
+            allow_diagonal: true,
+            team_vision: false,
+            max_sight: Senses::MAX_SIGHT,
+            max_hearing: Senses::MAX_HEARING,
+            costs: CostTable::default(),
+            author: Default::default(),
+            difficulty: None,
+            description: Default::default(),
+            hearing_diagonal: None,
+        }
+    }
 }
 
 pub struct LimboMessage {}
@@ -75,10 +129,34 @@ pub enum ClientMessageContent {
     Leaderboard,
     LeaderboardSubmit(PlayerId, String),
     Command(CommandMessage),
+    /// Watch a stage without playing in it
+    Spectate(StageId),
+    /// Stops watching the stage, its updates are no longer sent
+    StopSpectating,
+    /// Asks for the current state of the player, after a reconnection
+    Resync,
+    /// Pools the sight with allies who share theirs, on stages allowing it
+    ShareSenses(bool),
+    /// Asks for the whole state of a stage, for tooling. Refused without the admin token.
+    InspectStage { stage_id: StageId, token: String },
+    /// The socket of the player was closed cleanly, its avatar is retired
+    Disconnect,
+    /// Moves the player to another stage, for testing. Refused unless the server allows warps.
+    WarpTo(StageId),
+    /// Asks for the maps played on each stage. Refused without the admin token.
+    ListMaps {
+        token: String,
+    },
+    /// Replaces the maps of the stages, one per stage, sending their players back to the first.
+    /// Refused without the admin token.
+    SwapMaps {
+        maps: Vec<String>,
+        token: String,
+    },
 }
 
 #[allow(clippy::large_enum_variant)]
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum ServerMessage {
     Leaderboard(Leaderboard),
     Turn(TurnMessage),
@@ -88,9 +166,129 @@ pub enum ServerMessage {
         averted: bool,
         senses_info: Option<SensesInfo>,
     },
+    /// The player is close to being too far behind the head, with the turns left before it
+    FallingBehind(StageTurn),
 
     /// Sent when someone plays, it updates where the head and tail of the stage is
     Timeline(StageId, StageTurn, Timeline, Option<SensesInfo>),
+
+    /// Sent on connection and when the number of players changes
+    Lobby(LobbyMessage),
+
+    /// Sent to spectators each time the stage changes
+    Spectate(SpectateMessage),
+
+    /// Answer to a resync request
+    Resync(ResyncMessage),
+
+    /// The server is about to stop, connections will be closed
+    ServerShutdown,
+
+    /// The server froze or resumed the simulation, commands sent while frozen are refused
+    Paused(bool),
+
+    /// Answer to an admin inspecting a stage
+    StageSnapshot(StageSnapshot),
+
+    /// Answer to an admin listing or swapping the maps, by stage id
+    MapSet(Vec<String>),
+
+    /// Several messages sent in a single frame, to be handled in order
+    Batch(Vec<ServerMessage>),
+
+    /// A message of the player could not be handled
+    Error {
+        code: ErrorCode,
+        message: String,
+    },
+}
+
+/// Why a message of the player failed, for clients to react to it
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCode {
+    /// The server does not know the player, a new game must be started
+    UnknownPlayer,
+    /// The player is not in a stage anymore
+    NotInStage,
+    /// The message was refused, the client should resync
+    Refused,
+    /// The server doesn't allow this message, or the admin token is wrong
+    PermissionDenied,
+}
+
+impl ServerMessage {
+    /// Messages contained in this one, in order
+    pub fn unbatch(self) -> Vec<ServerMessage> {
+        match self {
+            ServerMessage::Batch(messages) => messages,
+            message => vec![message],
+        }
+    }
+}
+
+/// Current state of a player, to catch up after a disconnection
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ResyncMessage {
+    /// The avatar's turn as counted by the server, the client starts over from it
+    pub turn: Turn,
+    pub stage_id: StageId,
+    pub stage_info: StageInfo,
+    pub stage_turn: StageTurn,
+    pub info: Option<SensesInfo>,
+    pub timeline: Timeline,
+    /// The player is waiting to know if it is dead
+    pub limbo: bool,
+}
+
+/// Full view of a stage, without fog
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SpectateMessage {
+    pub stage_id: StageId,
+    pub stage_info: StageInfo,
+    pub stage_turn: StageTurn,
+    pub timeline: Timeline,
+    /// The whole stage, as seen from its center
+    pub sight: SightInfo,
+}
+
+/// Unfogged state of the head of a stage, for tooling rather than gameplay
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct StageSnapshot {
+    pub stage_id: StageId,
+    pub stage_turn: StageTurn,
+    pub tiles: Tiles,
+    pub foes: Vec<FoeSnapshot>,
+    pub orbs: Vec<Position>,
+    pub shards: Vec<Position>,
+    pub avatars: Vec<AvatarSnapshot>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct FoeSnapshot {
+    pub id: FoeId,
+    pub foe_type: FoeType,
+    pub position: Position,
+    pub hp: u8,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct AvatarSnapshot {
+    pub player_id: PlayerId,
+    pub position: Position,
+    pub hp: u8,
+    /// Turn the player is at, the head of the stage or behind it
+    pub turn: StageTurn,
+}
+
+/// How many players are currently in game
+#[derive(Serialize, Deserialize, Default, Debug, Clone, PartialEq, Eq)]
+pub struct LobbyMessage {
+    pub online: u32,
+    /// Players by stage, only for stages with players
+    pub per_stage: Vec<(StageId, u32)>,
+    /// How many stages the server runs, spectators can't go past the last one
+    #[serde(default)]
+    pub stages: usize,
 }
 
 #[derive(Serialize, Deserialize, Default, Debug)]
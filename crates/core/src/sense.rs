@@ -3,7 +3,7 @@ use std::str::FromStr;
 use bounded_integer::BoundedU8;
 use serde::{Deserialize, Serialize};
 
-use crate::types::{FoeId, FoeType, Offset, StageTurn, Tiles, Turn};
+use crate::types::{Direction, FoeId, FoeType, Offset, Position, StageTurn, Tile, Tiles, Turn};
 
 #[derive(Debug, Eq, PartialEq, Serialize, Deserialize, Clone, Copy)]
 pub enum SenseType {
@@ -11,6 +11,23 @@ pub enum SenseType {
     Sight,
     Touch,
     Hearing,
+    Smell,
+    Chronosense,
+    OrbSense,
+    Danger,
+}
+
+impl SenseType {
+    pub const ALL: [SenseType; 8] = [
+        SenseType::SelfSense,
+        SenseType::Sight,
+        SenseType::Touch,
+        SenseType::Hearing,
+        SenseType::Smell,
+        SenseType::Chronosense,
+        SenseType::OrbSense,
+        SenseType::Danger,
+    ];
 }
 
 impl FromStr for SenseType {
@@ -22,6 +39,10 @@ impl FromStr for SenseType {
             "Sight" => Ok(SenseType::Sight),
             "Touch" => Ok(SenseType::Touch),
             "Hearing" => Ok(SenseType::Hearing),
+            "Smell" => Ok(SenseType::Smell),
+            "Chrono" | "Chronosense" => Ok(SenseType::Chronosense),
+            "Orb" | "OrbSense" | "Goal" => Ok(SenseType::OrbSense),
+            "Danger" => Ok(SenseType::Danger),
             _ => Err(format!("Unknown sense type: {}", s)),
         }
     }
@@ -31,54 +52,104 @@ impl FromStr for SenseType {
 #[derive(Debug, Eq, PartialEq, Serialize, Deserialize, Clone)]
 pub struct Senses {
     pub selfs: bool,
-    pub touch: bool,
+    pub touch: BoundedU8<0, 3>,
     pub sight: BoundedU8<0, 10>,
     pub hearing: BoundedU8<0, 5>,
+    #[serde(default)]
+    pub smell: BoundedU8<0, 5>,
+    #[serde(default)]
+    pub chrono: bool,
+    #[serde(default)]
+    pub orb: BoundedU8<0, 3>,
+    #[serde(default)]
+    pub danger: BoundedU8<0, 5>,
 }
 
 impl Default for Senses {
     fn default() -> Self {
         Self {
             selfs: true,
-            touch: false,
+            touch: BoundedU8::const_new::<0>(),
             sight: BoundedU8::const_new::<5>(),
             hearing: BoundedU8::const_new::<0>(),
+            smell: BoundedU8::const_new::<0>(),
+            chrono: false,
+            orb: BoundedU8::const_new::<0>(),
+            danger: BoundedU8::const_new::<0>(),
         }
     }
 }
 
 impl Senses {
-    pub fn cost(&self) -> u8 {
-        let mut result = 0;
-        if self.selfs {
-            result += 1;
-        }
-        if self.touch {
-            result += 1;
-        }
-        if self.sight > 0 {
-            result += 2;
-            result += self.sight;
-        }
-        result += self.hearing;
-
-        result
+    /// Focus spent by the chronosense, whatever the number of allies
+    pub const CHRONO_COST: u8 = 3;
+    /// Strongest sight, unless the stage caps it
+    pub const MAX_SIGHT: u8 = 10;
+    /// Strongest hearing, unless the stage caps it
+    pub const MAX_HEARING: u8 = 5;
+
+    /// Focus spent every turn by these senses under the costs of the stage
+    pub fn cost(&self, table: &CostTable) -> u8 {
+        SenseType::ALL
+            .iter()
+            .map(|sense| table.get(*sense).cost(self.strength(*sense)))
+            .fold(0, u8::saturating_add)
     }
 
     pub fn merge(mut self, senses: Senses) -> Senses {
-        self.touch = bool::merge(senses.touch, self.touch);
+        self.touch = BoundedU8::merge(senses.touch, self.touch);
         self.selfs = bool::merge(senses.selfs, self.selfs);
         self.sight = BoundedU8::merge(senses.sight, self.sight);
         self.hearing = BoundedU8::merge(senses.hearing, self.hearing);
+        self.smell = BoundedU8::merge(senses.smell, self.smell);
+        self.chrono = bool::merge(senses.chrono, self.chrono);
+        self.orb = BoundedU8::merge(senses.orb, self.orb);
+        self.danger = BoundedU8::merge(senses.danger, self.danger);
         self
     }
 
+    /// Strength of the sense, toggled senses being 0 or 1
+    pub fn strength(&self, sense: SenseType) -> u8 {
+        match sense {
+            SenseType::SelfSense => self.selfs.into(),
+            SenseType::Sight => self.sight.get(),
+            SenseType::Touch => self.touch.get(),
+            SenseType::Hearing => self.hearing.get(),
+            SenseType::Smell => self.smell.get(),
+            SenseType::Chronosense => self.chrono.into(),
+            SenseType::OrbSense => self.orb.get(),
+            SenseType::Danger => self.danger.get(),
+        }
+    }
+
+    /// Lowers the sense to the given strength at most
+    pub fn lower(&mut self, sense: SenseType, strength: u8) {
+        match sense {
+            SenseType::SelfSense => self.selfs &= strength > 0,
+            SenseType::Sight => self.sight = self.sight.min(BoundedU8::new_saturating(strength)),
+            SenseType::Touch => self.touch = self.touch.min(BoundedU8::new_saturating(strength)),
+            SenseType::Hearing => {
+                self.hearing = self.hearing.min(BoundedU8::new_saturating(strength))
+            }
+            SenseType::Smell => self.smell = self.smell.min(BoundedU8::new_saturating(strength)),
+            SenseType::Chronosense => self.chrono &= strength > 0,
+            SenseType::OrbSense => self.orb = self.orb.min(BoundedU8::new_saturating(strength)),
+            SenseType::Danger => self.danger = self.danger.min(BoundedU8::new_saturating(strength)),
+        }
+    }
+
+    /// Lowers the sight and hearing to the caps of the stage
+    pub fn cap(&mut self, max_sight: u8, max_hearing: u8) {
+        self.sight = self.sight.min(BoundedU8::new_saturating(max_sight));
+        self.hearing = self.hearing.min(BoundedU8::new_saturating(max_hearing));
+    }
+
     pub fn restrict_to(&mut self, available_senses: &[SenseType]) {
         if !available_senses.contains(&SenseType::SelfSense) {
             self.selfs = false;
         }
         if !available_senses.contains(&SenseType::Touch) {
-            self.touch = false;
+            self.touch = BoundedU8::const_new::<0>();
         }
         if !available_senses.contains(&SenseType::Hearing) {
             self.hearing = BoundedU8::const_new::<0>();
@@ -86,6 +157,95 @@ impl Senses {
         if !available_senses.contains(&SenseType::Sight) {
             self.sight = BoundedU8::const_new::<0>();
         }
+        if !available_senses.contains(&SenseType::Smell) {
+            self.smell = BoundedU8::const_new::<0>();
+        }
+        if !available_senses.contains(&SenseType::Chronosense) {
+            self.chrono = false;
+        }
+        if !available_senses.contains(&SenseType::OrbSense) {
+            self.orb = BoundedU8::const_new::<0>();
+        }
+        if !available_senses.contains(&SenseType::Danger) {
+            self.danger = BoundedU8::const_new::<0>();
+        }
+    }
+}
+
+/// Focus spent by a sense once active, then for each level of strength
+#[derive(Debug, Eq, PartialEq, Serialize, Deserialize, Clone, Copy, Default)]
+pub struct SenseCost {
+    pub base: u8,
+    pub per_level: u8,
+}
+
+impl SenseCost {
+    pub const fn new(base: u8, per_level: u8) -> Self {
+        Self { base, per_level }
+    }
+
+    pub fn cost(&self, strength: u8) -> u8 {
+        if strength == 0 {
+            return 0;
+        }
+        self.base
+            .saturating_add(self.per_level.saturating_mul(strength))
+    }
+}
+
+/// Focus costs of every sense, stages can rebalance them
+#[derive(Debug, Eq, PartialEq, Serialize, Deserialize, Clone)]
+pub struct CostTable {
+    pub selfs: SenseCost,
+    pub touch: SenseCost,
+    pub sight: SenseCost,
+    pub hearing: SenseCost,
+    pub smell: SenseCost,
+    pub chrono: SenseCost,
+    pub orb: SenseCost,
+    pub danger: SenseCost,
+}
+
+impl Default for CostTable {
+    fn default() -> Self {
+        Self {
+            selfs: SenseCost::new(1, 0),
+            touch: SenseCost::new(0, 1),
+            sight: SenseCost::new(2, 1),
+            hearing: SenseCost::new(0, 1),
+            smell: SenseCost::new(0, 1),
+            chrono: SenseCost::new(Senses::CHRONO_COST, 0),
+            orb: SenseCost::new(0, 1),
+            danger: SenseCost::new(0, 1),
+        }
+    }
+}
+
+impl CostTable {
+    pub fn get(&self, sense: SenseType) -> SenseCost {
+        match sense {
+            SenseType::SelfSense => self.selfs,
+            SenseType::Sight => self.sight,
+            SenseType::Touch => self.touch,
+            SenseType::Hearing => self.hearing,
+            SenseType::Smell => self.smell,
+            SenseType::Chronosense => self.chrono,
+            SenseType::OrbSense => self.orb,
+            SenseType::Danger => self.danger,
+        }
+    }
+
+    pub fn get_mut(&mut self, sense: SenseType) -> &mut SenseCost {
+        match sense {
+            SenseType::SelfSense => &mut self.selfs,
+            SenseType::Sight => &mut self.sight,
+            SenseType::Touch => &mut self.touch,
+            SenseType::Hearing => &mut self.hearing,
+            SenseType::Smell => &mut self.smell,
+            SenseType::Chronosense => &mut self.chrono,
+            SenseType::OrbSense => &mut self.orb,
+            SenseType::Danger => &mut self.danger,
+        }
     }
 }
 
@@ -95,6 +255,14 @@ pub struct SensesInfo {
     pub touch: Option<TouchInfo>,
     pub sight: Option<SightInfo>,
     pub hearing: Option<HearingInfo>,
+    #[serde(default)]
+    pub smell: Option<SmellInfo>,
+    #[serde(default)]
+    pub chrono: Option<ChronoInfo>,
+    #[serde(default)]
+    pub orb: Option<OrbInfo>,
+    #[serde(default)]
+    pub danger: Option<DangerInfo>,
 }
 
 #[derive(Debug, Eq, PartialEq, Serialize, Deserialize, Clone, Default)]
@@ -102,6 +270,8 @@ pub struct SelfInfo {
     pub hp: u8,
     pub hp_max: u8,
     pub focus: u8,
+    #[serde(default)]
+    pub attack: u8,
     pub turn: Turn,
 }
 
@@ -127,6 +297,7 @@ pub struct SightedAlly {
     pub offset: Offset,
     pub alive: bool,
     pub status: SightedAllyStatus,
+    /// Where the ally steps next, relative to the ally itself and not to the viewer
     pub next_move: Option<Offset>,
 }
 
@@ -144,15 +315,99 @@ pub struct SightInfo {
     pub foes: Vec<SightedFoe>,
     pub orb: Option<Offset>,
     pub allies: Vec<SightedAlly>,
+    #[serde(default)]
+    pub shards: Vec<Offset>,
+    /// Sent instead of the tiles when the avatar still sees most of what it saw last turn
+    #[serde(default)]
+    pub delta: Option<SightDelta>,
+}
+
+/// Sight tiles that changed since a previous sight, once shifted by the avatar movement
+#[derive(Debug, Eq, PartialEq, Serialize, Deserialize, Clone)]
+pub struct SightDelta {
+    /// Stage turn of the sight the delta applies to
+    pub base_turn: StageTurn,
+    /// How much the avatar moved since the base sight
+    pub shift: Offset,
+    pub width: u16,
+    pub height: u16,
+    /// Index in the resulting tiles, column by column, and its new value
+    pub changes: Vec<(u16, Tile)>,
+}
+
+impl SightDelta {
+    /// Returns None when the full tiles are cheaper to send
+    pub fn between(
+        base_turn: StageTurn,
+        base: &Tiles,
+        shift: Offset,
+        tiles: &Tiles,
+    ) -> Option<Self> {
+        let width = u16::try_from(tiles.width()).ok()?;
+        let height = u16::try_from(tiles.height()).ok()?;
+        let shifted = Self::shift(base, shift, tiles.width(), tiles.height());
+
+        let changes: Vec<_> = tiles
+            .grid
+            .indexed_iter()
+            .filter(|(pos, tile)| shifted.get(*pos) != **tile)
+            .map(|((x, y), tile)| ((x * tiles.height() + y) as u16, *tile))
+            .collect();
+
+        // A change weighs a bit more than a tile
+        if changes.len() * 2 > tiles.width() * tiles.height() {
+            return None;
+        }
+
+        Some(Self {
+            base_turn,
+            shift,
+            width,
+            height,
+            changes,
+        })
+    }
+
+    pub fn apply(&self, base: &Tiles) -> Tiles {
+        let height = self.height as usize;
+        let mut tiles = Self::shift(base, self.shift, self.width as usize, height);
+        for &(index, tile) in &self.changes {
+            let index = index as usize;
+            if let Some(t) = tiles.grid.get_mut(index / height, index % height) {
+                *t = tile;
+            }
+        }
+        tiles
+    }
+
+    /// The base tiles as seen from the new avatar position
+    fn shift(base: &Tiles, shift: Offset, width: usize, height: usize) -> Tiles {
+        let mut tiles = Tiles::new(width, height);
+        let center = tiles.center();
+        let base_center = base.center() + shift;
+        for ((x, y), tile) in tiles.grid.indexed_iter_mut() {
+            let offset = Position { x, y } - center;
+            *tile = base.get(base_center + offset);
+        }
+        tiles
+    }
 }
 
 #[derive(Debug, Eq, PartialEq, Serialize, Deserialize, Clone, Default)]
 pub struct HearingInfo {
     /// We don't want to give the player the exact distance
     pub range: Option<BoundedU8<1, 5>>,
+    /// Rough direction of the orb, only given at high strengths
+    #[serde(default)]
+    pub bearing: Option<Direction>,
 }
 
 impl HearingInfo {
+    /// Minimum strength at which the bearing is revealed
+    pub const BEARING_STRENGTH: u8 = 4;
+    /// Diagonal of the largest map the default distances are made for
+    pub const REFERENCE_DIAGONAL: u16 = 32;
+
     pub fn dist(strength: u8) -> Option<u8> {
         match strength {
             1 => Some(3),
@@ -163,6 +418,112 @@ impl HearingInfo {
             _ => None,
         }
     }
+
+    /// Distances stretched on maps with a diagonal longer than the reference, so that the orb is
+    /// not always out of earshot. Smaller maps keep the default distances.
+    pub fn dist_for(strength: u8, map_diagonal: u16) -> Option<u16> {
+        let dist = Self::dist(strength)? as u32;
+        let diagonal = map_diagonal.max(Self::REFERENCE_DIAGONAL) as u32;
+        Some((dist * diagonal).div_ceil(Self::REFERENCE_DIAGONAL as u32) as u16)
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Serialize, Deserialize, Clone, Default)]
+pub struct SmellInfo {
+    /// Number of living foes in range
+    pub foe_count: u8,
+    /// Rough direction of the densest group of foes
+    pub strongest_bearing: Option<Direction>,
+}
+
+impl SmellInfo {
+    pub fn radius(strength: u8) -> Option<u8> {
+        match strength {
+            1 => Some(3),
+            2 => Some(5),
+            3 => Some(7),
+            4 => Some(9),
+            5 => Some(12),
+            _ => None,
+        }
+    }
+}
+
+/// How many threats are close, traps included, without telling where they are
+#[derive(Debug, Eq, PartialEq, Serialize, Deserialize, Clone, Default)]
+pub struct DangerInfo {
+    /// Living foes in range
+    pub count: u8,
+}
+
+impl DangerInfo {
+    pub fn radius(strength: u8) -> Option<u8> {
+        match strength {
+            1 => Some(2),
+            2 => Some(3),
+            3 => Some(4),
+            4 => Some(6),
+            5 => Some(8),
+            _ => None,
+        }
+    }
+}
+
+/// Where the allies of the stage are in time, wherever they are in space
+#[derive(Debug, Eq, PartialEq, Serialize, Deserialize, Clone, Default)]
+pub struct ChronoInfo {
+    pub allies: Vec<ChronoAlly>,
+}
+
+#[derive(Debug, Eq, PartialEq, Serialize, Deserialize, Clone)]
+pub struct ChronoAlly {
+    pub name: String,
+    /// Turns the ally is ahead of the viewer, negative when behind
+    pub turn_offset: i64,
+}
+
+/// How close the nearest orb is, walls or not. Unlike hearing, the closer levels stay just as
+/// precise when the range grows.
+#[derive(Debug, Eq, PartialEq, Serialize, Deserialize, Clone, Default)]
+pub struct OrbInfo {
+    pub level: SenseLevel,
+}
+
+impl OrbInfo {
+    /// Distance past which the orb is out of reach
+    pub fn range(strength: u8) -> Option<u8> {
+        match strength {
+            1 => Some(12),
+            2 => Some(24),
+            3 => Some(48),
+            _ => None,
+        }
+    }
+
+    /// Proximity of an orb at this distance, by quarters of the range
+    pub fn level(strength: u8, dist: usize) -> SenseLevel {
+        let Some(range) = Self::range(strength).map(usize::from) else {
+            return SenseLevel::Minimum;
+        };
+        match dist * 4 {
+            d if d <= range => SenseLevel::Maximum,
+            d if d <= range * 2 => SenseLevel::High,
+            d if d <= range * 3 => SenseLevel::Medium,
+            d if d <= range * 4 => SenseLevel::Low,
+            _ => SenseLevel::Minimum,
+        }
+    }
+}
+
+/// Qualitative level reported by the graded senses, from nothing sensed to the strongest signal
+#[derive(Debug, Eq, PartialEq, Ord, PartialOrd, Serialize, Deserialize, Clone, Copy, Default)]
+pub enum SenseLevel {
+    #[default]
+    Minimum,
+    Low,
+    Medium,
+    High,
+    Maximum,
 }
 
 pub trait SenseStrength: Eq + Sized {
@@ -224,3 +585,64 @@ impl<const MIN: u8, const MAX: u8> SenseStrength for BoundedU8<MIN, MAX> {
         left.max(right)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cost_test() {
+        let senses = Senses {
+            selfs: true,
+            sight: BoundedU8::const_new::<3>(),
+            hearing: BoundedU8::const_new::<2>(),
+            chrono: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            senses.cost(&CostTable::default()),
+            1 + 5 + 2 + Senses::CHRONO_COST
+        );
+
+        let costs = CostTable {
+            selfs: SenseCost::new(0, 0),
+            sight: SenseCost::new(1, 2),
+            chrono: SenseCost::new(1, 0),
+            ..Default::default()
+        };
+        assert_eq!(senses.cost(&costs), 7 + 2 + 1);
+
+        // Inactive senses cost nothing, whatever their base
+        let costs = CostTable {
+            touch: SenseCost::new(4, 1),
+            ..costs
+        };
+        assert_eq!(senses.cost(&costs), 10);
+        assert_eq!(
+            Senses {
+                touch: BoundedU8::const_new::<2>(),
+                ..senses
+            }
+            .cost(&costs),
+            16
+        );
+    }
+
+    #[test]
+    fn hearing_dist_test() {
+        // Medium maps keep the default distances
+        for strength in 1..=5 {
+            assert_eq!(
+                HearingInfo::dist_for(strength, 20),
+                HearingInfo::dist(strength).map(u16::from)
+            );
+        }
+        assert_eq!(HearingInfo::dist_for(6, 20), None);
+
+        // A map twice the reference hears twice as far
+        let diagonal = HearingInfo::REFERENCE_DIAGONAL * 2;
+        assert_eq!(HearingInfo::dist_for(1, diagonal), Some(6));
+        assert_eq!(HearingInfo::dist_for(5, diagonal), Some(42));
+        assert_eq!(HearingInfo::dist_for(3, 80), Some(25));
+    }
+}
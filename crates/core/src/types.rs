@@ -5,7 +5,15 @@ use grid::Grid;
 use serde::{Deserialize, Serialize};
 
 pub const HP_MAX: u8 = 10;
+/// Avatars are warned when their hp drops below this
+pub const HP_LOW: u8 = 4;
+/// Damage dealt by an avatar before picking any shard
+pub const AVATAR_ATTACK: u8 = 1;
 pub const FOCUS_MAX: u8 = 100;
+/// Focus spent to disarm a trap
+pub const DISARM_COST: u8 = 10;
+/// Focus spent to take back the last turn
+pub const UNDO_COST: u8 = 15;
 pub const TURN_FOR_HP_REGEN: u64 = 10;
 
 /**
@@ -16,6 +24,14 @@ pub const TURN_FOR_HP_REGEN: u64 = 10;
 pub enum ClientAction {
     MoveOrAttack(Direction),
     Wait,
+    /// Wait for up to this many turns, until something happens
+    Rest(u16),
+    /// Disarm a trap next to the avatar
+    Disarm(Direction),
+    /// Take back the last turn played, the response is the turn before it
+    Undo,
+    /// Sense the last turn played again, adding the given senses to the ones already used
+    Sense,
 }
 
 /**
@@ -26,8 +42,14 @@ pub enum ServerAction {
     Wait,
     Move(Position),
     /// foe id, should stay server side though
-    Attack(usize),
+    Attack(FoeId),
     Enter,
+    /// A move into a tile that can't be traveled, the turn is spent waiting
+    Blocked,
+    /// foe id of the trap to disarm
+    Disarm(FoeId),
+    /// player id of the avatar attacked, only on pvp stages
+    AttackAvatar(PlayerId),
 }
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Deserialize, Serialize)]
@@ -76,7 +98,7 @@ impl Neg for Offset {
     }
 }
 
-#[derive(Debug, Clone, Copy, Eq, PartialEq, Default, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Default, Serialize, Deserialize)]
 pub struct Position {
     pub x: usize,
     pub y: usize,
@@ -174,6 +196,40 @@ impl Direction {
         };
         Offset { x, y }
     }
+
+    pub fn is_diagonal(&self) -> bool {
+        matches!(
+            self,
+            Direction::UpRight | Direction::UpLeft | Direction::DownRight | Direction::DownLeft
+        )
+    }
+
+    /// Rough 8-way direction of an offset. None if the offset is null.
+    pub fn from_offset(offset: Offset) -> Option<Direction> {
+        let Offset { x, y } = offset;
+        if x == 0 && y == 0 {
+            return None;
+        }
+
+        // An axis is ignored when the other one is more than twice as long
+        let horizontal = x.abs() > 2 * y.abs();
+        let vertical = y.abs() > 2 * x.abs();
+        let x = if vertical { 0 } else { x.signum() };
+        let y = if horizontal { 0 } else { y.signum() };
+
+        let dir = match (x, y) {
+            (0, -1) => Direction::Up,
+            (1, -1) => Direction::UpRight,
+            (-1, -1) => Direction::UpLeft,
+            (-1, 0) => Direction::Left,
+            (1, 0) => Direction::Right,
+            (1, 1) => Direction::DownRight,
+            (0, 1) => Direction::Down,
+            (-1, 1) => Direction::DownLeft,
+            _ => unreachable!(),
+        };
+        Some(dir)
+    }
 }
 
 #[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
@@ -208,6 +264,7 @@ pub type StageId = usize;
 pub type FoeId = usize;
 
 pub const MAX_WITHOUT_PLAY: Turn = 5;
+pub const REST_MAX: u16 = 20;
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Deserialize, Serialize)]
 #[derive(Default)]
@@ -240,6 +297,8 @@ pub struct Avatar {
     pub player_id: PlayerId,
     pub position: Position,
     pub hp: u8,
+    /// Damage dealt to foes, raised by shards
+    pub attack: u8,
     /// TODO: put in player instead of avatar
     pub turns: Turn,
 
@@ -263,6 +322,7 @@ impl Avatar {
             player_id,
             position: Position { x: 1, y: 1 },
             hp: HP_MAX,
+            attack: AVATAR_ATTACK,
             turns: 1,
             turns_not_played: 0,
         }
@@ -287,6 +347,10 @@ pub struct Foe {
     pub position: Position,
     pub hp: u8,
     pub attack: u8,
+    /// Last avatar the foe had in sight, and when
+    pub last_seen: Option<(AvatarId, StageTurn)>,
+    /// Whether the foe already called for help, it only does it once
+    pub summoned: bool,
 }
 
 impl Foe {
@@ -310,8 +374,20 @@ impl Foe {
 #[derive(Clone, Debug, Default)]
 pub struct Orb {
     pub position: Position,
-    /// If excited, it will change position next turn
+    /// If excited, it will change position once the countdown is over
     pub excited: bool,
+    /// Turns left before changing position, once excited
+    pub countdown: u32,
+}
+
+impl Orb {
+    /// Starts the countdown, unless it is already running
+    pub fn excite(&mut self, delay: u32) {
+        if !self.excited {
+            self.excited = true;
+            self.countdown = delay;
+        }
+    }
 }
 
 #[derive(Default, PartialEq, Eq, Debug, Clone, Deserialize, Serialize)]
@@ -360,6 +436,14 @@ pub struct GameOver {
     pub stage: u8,
     pub turns: Turn,
     pub score: u64,
+    /// Focus spent on senses during the whole game
+    #[serde(default)]
+    pub focus_spent: u64,
+    #[serde(default)]
+    pub avg_sense_cost: f32,
+    /// Seed shared by every stage of the game, when the server fixed one
+    #[serde(default)]
+    pub seed: Option<u64>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
@@ -382,6 +466,36 @@ impl GameOver {
             stage: stage as u8,
             turns: avatar.turns,
             score,
+            focus_spent: 0,
+            avg_sense_cost: 0.0,
+            seed: None,
+        }
+    }
+
+    /// Records the seed of the stages, so that runs on the same seed can be compared
+    pub fn with_seed(mut self, seed: Option<u64>) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Adds how much the player relied on its senses over the turns it played
+    pub fn with_focus(mut self, focus_spent: u64, turns: Turn) -> Self {
+        self.focus_spent = focus_spent;
+        self.avg_sense_cost = if turns == 0 {
+            0.0
+        } else {
+            focus_spent as f32 / turns as f32
+        };
+        self
+    }
+
+    /// Rates how little the player sensed, stages usually regenerate 4 focus per turn
+    pub fn stealth(&self) -> &'static str {
+        match self.avg_sense_cost {
+            c if c <= 2.0 => "Ghost",
+            c if c <= 4.0 => "Shadow",
+            c if c <= 6.0 => "Quiet",
+            _ => "Loud",
         }
     }
 }
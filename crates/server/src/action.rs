@@ -1,6 +1,6 @@
 use losig_core::{
     events::{GameEvent, Target},
-    types::{Avatar, ClientAction, Direction, PlayerId, Position, ServerAction},
+    types::{Avatar, ClientAction, Direction, FoeId, PlayerId, Position, ServerAction},
 };
 
 use crate::{
@@ -9,24 +9,42 @@ use crate::{
 };
 
 /// Execute an action for an avatar
-pub fn act(action: &ServerAction, avatar: &mut Avatar, state: &mut StageState, _stage: &Stage) {
+pub fn act(action: &ServerAction, avatar: &mut Avatar, state: &mut StageState, stage: &Stage) {
     match action {
-        ServerAction::Move(position) => act_move(avatar, *position),
-        ServerAction::Attack(target_index) => act_attack(avatar, *target_index, state),
-        ServerAction::Wait | ServerAction::Enter => {}
+        ServerAction::Move(position) => act_move(avatar, *position, state),
+        ServerAction::Attack(target_index) => act_attack(avatar, *target_index, state, stage),
+        ServerAction::Disarm(target_index) => act_disarm(avatar, *target_index, state, stage),
+        ServerAction::AttackAvatar(target_id) => {
+            act_attack_avatar(avatar, *target_id, state, stage)
+        }
+        ServerAction::Wait | ServerAction::Enter | ServerAction::Blocked => {}
     }
 }
 
-fn act_move(avatar: &mut Avatar, position: Position) {
+fn act_move(avatar: &mut Avatar, position: Position, state: &mut StageState) {
     avatar.position = position;
+
+    if let Some(i) = state.shards.iter().position(|shard| *shard == position) {
+        state.shards.remove(i);
+        avatar.attack = avatar.attack.saturating_add(1);
+        state.events.add(GameEventSource {
+            senses: EventSenses::All,
+            source: EventSource::Position(position),
+            event: GameEvent::ShardTaken(Target::Avatar(avatar.player_id)),
+        });
+    }
 }
 
-fn act_attack(avatar: &mut Avatar, target_index: usize, state: &mut StageState) {
-    if let Some(foe) = state.foes.get_mut(target_index)
+fn act_attack(avatar: &mut Avatar, foe_id: FoeId, state: &mut StageState, stage: &Stage) {
+    if let Some(foe) = state.foes.iter_mut().find(|foe| foe.id == foe_id)
         && foe.can_be_attacked()
         && foe.position.dist(&avatar.position) <= 1
     {
-        foe.hp = foe.hp.saturating_sub(1);
+        foe.hp = foe.hp.saturating_sub(avatar.attack);
+        // Someone saw it alive later in the timeline, it can't die now
+        if !foe.alive() && stage.bounds.witnessed_alive(foe.id, state.turn) {
+            foe.hp = 1;
+        }
 
         let event = if foe.alive() {
             GameEvent::Attack {
@@ -54,15 +72,100 @@ fn act_attack(avatar: &mut Avatar, target_index: usize, state: &mut StageState)
     }
 }
 
+fn act_attack_avatar(avatar: &Avatar, target_id: PlayerId, state: &mut StageState, stage: &Stage) {
+    if let Some(target) = state.avatars.get_mut(&target_id)
+        && !target.is_dead()
+        && target.position.dist(&avatar.position) <= 1
+    {
+        // The target can't lose the health someone saw it with later in the timeline
+        let witnessed = stage.bounds.witnessed_hp(target_id, state.turn);
+        target.hp = target
+            .hp
+            .saturating_sub(avatar.attack)
+            .max(witnessed.min(target.hp));
+
+        let event = if target.is_dead() {
+            GameEvent::Kill {
+                subject: Target::Avatar(target_id),
+                source: Target::Avatar(avatar.player_id),
+            }
+        } else {
+            GameEvent::Attack {
+                subject: Target::Avatar(target_id),
+                source: Target::Avatar(avatar.player_id),
+            }
+        };
+
+        state.events.add(GameEventSource {
+            senses: EventSenses::All,
+            source: EventSource::Position(target.position),
+            event,
+        });
+    } else {
+        state.events.add(GameEventSource {
+            senses: EventSenses::All,
+            source: EventSource::Position(avatar.position),
+            event: GameEvent::Fumble(Target::Avatar(avatar.player_id)),
+        });
+    }
+}
+
+fn act_disarm(avatar: &mut Avatar, foe_id: FoeId, state: &mut StageState, stage: &Stage) {
+    if let Some(foe) = state.foes.iter_mut().find(|foe| foe.id == foe_id)
+        && foe.alive()
+        && foe.is_trap()
+        && foe.position.dist(&avatar.position) <= 1
+        // Someone saw it armed later in the timeline
+        && !stage.bounds.witnessed_alive(foe.id, state.turn)
+    {
+        foe.hp = 0;
+        state.events.add(GameEventSource {
+            senses: EventSenses::All,
+            source: EventSource::Position(foe.position),
+            event: GameEvent::TrapDisarmed(Target::Avatar(avatar.player_id)),
+        });
+    } else {
+        state.events.add(GameEventSource {
+            senses: EventSenses::All,
+            source: EventSource::Position(avatar.position),
+            event: GameEvent::Fumble(Target::Avatar(avatar.player_id)),
+        });
+    }
+}
+
 pub fn convert_client(action: ClientAction, stage: &mut Stage, pid: PlayerId) -> ServerAction {
     match action {
+        ClientAction::MoveOrAttack(direction)
+            if direction.is_diagonal() && !stage.template.allow_diagonal =>
+        {
+            ServerAction::Wait
+        }
         ClientAction::MoveOrAttack(direction) => {
             convert_move_or_attack_action(direction, stage, pid).unwrap_or(ServerAction::Wait)
         }
-        ClientAction::Wait => ServerAction::Wait,
+        ClientAction::Disarm(direction) => {
+            convert_disarm_action(direction, stage, pid).unwrap_or(ServerAction::Wait)
+        }
+        // Undo and Sense are handled by the stage, they are never a turn on their own
+        ClientAction::Wait | ClientAction::Rest(_) | ClientAction::Undo | ClientAction::Sense => {
+            ServerAction::Wait
+        }
     }
 }
 
+/// Without a trap in that direction, the turn is spent waiting
+fn convert_disarm_action(dir: Direction, stage: &Stage, aid: u32) -> Option<ServerAction> {
+    let state = stage.state_for(aid)?;
+    let avatar = state.avatars.get(&aid)?;
+
+    let next_pos = avatar.position + dir.offset();
+    state
+        .foes
+        .iter()
+        .find(|foe| foe.alive() && foe.is_trap() && foe.position == next_pos)
+        .map(|foe| ServerAction::Disarm(foe.id))
+}
+
 fn convert_move_or_attack_action(dir: Direction, stage: &Stage, aid: u32) -> Option<ServerAction> {
     let state = stage.state_for(aid)?;
     let avatar = state.avatars.get(&aid)?;
@@ -75,10 +178,19 @@ fn convert_move_or_attack_action(dir: Direction, stage: &Stage, aid: u32) -> Opt
         return Some(ServerAction::Attack(id));
     }
 
+    if stage.template.pvp
+        && let Some(target) = state
+            .avatars
+            .values()
+            .find(|other| other.player_id != aid && !other.is_dead() && other.position == next_pos)
+    {
+        return Some(ServerAction::AttackAvatar(target.player_id));
+    }
+
     let tile = stage.template.tiles.get(next_pos);
     if tile.can_travel() {
         Some(ServerAction::Move(next_pos))
     } else {
-        None
+        Some(ServerAction::Blocked)
     }
 }
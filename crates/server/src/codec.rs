@@ -0,0 +1,128 @@
+//! Encodings of the messages exchanged with the clients. The real clients use bincode, JSON
+//! is picked with the `losig-json` websocket subprotocol for scripts and debugging.
+
+use anyhow::{Result, bail};
+use losig_core::network::{ClientMessage, ServerMessage};
+use tungstenite::{Bytes, Message};
+
+/// Subprotocol asking for JSON messages
+pub const JSON_PROTOCOL: &str = "losig-json";
+
+pub trait Codec: Send {
+    fn encode(&self, msg: &ServerMessage) -> Result<Message>;
+    fn decode(&self, msg: Message) -> Result<ClientMessage>;
+
+    /// Subprotocol confirmed to the client during the handshake
+    fn protocol(&self) -> Option<&'static str> {
+        None
+    }
+}
+
+/// Picks the codec from the subprotocols requested by the client, bincode by default
+pub fn negotiate(protocols: Option<&str>) -> Box<dyn Codec> {
+    let json = protocols
+        .into_iter()
+        .flat_map(|p| p.split(','))
+        .any(|p| p.trim() == JSON_PROTOCOL);
+    if json {
+        Box::new(JsonCodec)
+    } else {
+        Box::new(BincodeCodec)
+    }
+}
+
+pub struct BincodeCodec;
+
+impl Codec for BincodeCodec {
+    fn encode(&self, msg: &ServerMessage) -> Result<Message> {
+        let msg = bincode::serialize(msg)?;
+        Ok(Message::Binary(Bytes::from_owner(msg)))
+    }
+
+    fn decode(&self, msg: Message) -> Result<ClientMessage> {
+        let Message::Binary(msg) = msg else {
+            bail!("Not a binary");
+        };
+        Ok(bincode::deserialize::<ClientMessage>(&msg)?)
+    }
+}
+
+pub struct JsonCodec;
+
+impl Codec for JsonCodec {
+    fn encode(&self, msg: &ServerMessage) -> Result<Message> {
+        Ok(Message::text(serde_json::to_string(msg)?))
+    }
+
+    fn decode(&self, msg: Message) -> Result<ClientMessage> {
+        let Message::Text(msg) = msg else {
+            bail!("Not a text");
+        };
+        Ok(serde_json::from_str::<ClientMessage>(msg.as_str())?)
+    }
+
+    fn protocol(&self) -> Option<&'static str> {
+        Some(JSON_PROTOCOL)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use losig_core::{
+        network::TurnMessage,
+        sense::{SelfInfo, SensesInfo, SightInfo, SightedFoe},
+        types::{FoeType, Offset, ServerAction, Tile, Tiles, Timeline},
+    };
+
+    use super::*;
+
+    fn encode(msg: &ServerMessage) -> String {
+        let Message::Text(json) = JsonCodec.encode(msg).unwrap() else {
+            panic!("Expected a text");
+        };
+        json.as_str().to_string()
+    }
+
+    #[test]
+    fn turn_roundtrip_test() {
+        let mut tiles = Tiles::new(3, 3);
+        tiles.grid[(1, 1)] = Tile::Empty;
+        tiles.grid[(0, 1)] = Tile::Wall;
+        let info = SensesInfo {
+            selfi: Some(SelfInfo {
+                hp: 5,
+                ..Default::default()
+            }),
+            sight: Some(SightInfo {
+                tiles,
+                foes: vec![SightedFoe {
+                    id: 2,
+                    offset: Offset { x: -1, y: 1 },
+                    foe_type: FoeType::Dummy,
+                    alive: true,
+                }],
+                orb: Some(Offset { x: 1, y: 0 }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let msg = ServerMessage::Turn(TurnMessage {
+            player_id: 7,
+            turn: 3,
+            stage_turn: 12,
+            stage: 0,
+            info: Some(info.clone()),
+            action: ServerAction::Move((4, 2).into()),
+            events: vec![],
+            timeline: Timeline { head: 12, tail: 2 },
+        });
+
+        let json = encode(&msg);
+        assert!(json.starts_with(r#"{"Turn":{"player_id":7,"turn":3"#));
+        let ServerMessage::Turn(turn) = serde_json::from_str(&json).unwrap() else {
+            panic!("Expected a turn");
+        };
+        assert_eq!(turn.info, Some(info));
+        assert_eq!(encode(&ServerMessage::Turn(turn)), json);
+    }
+}
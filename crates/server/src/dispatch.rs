@@ -1,53 +1,204 @@
-use std::{sync::mpsc::Receiver, thread::spawn, time::Instant};
+use std::{
+    sync::mpsc::{Receiver, RecvTimeoutError, Sender, channel},
+    thread::spawn,
+    time::{Duration, Instant},
+};
 
 use log::{debug, error};
-use losig_core::{
-    leaderboard::LeaderboardEntry,
-    network::{ClientMessage, ClientMessageContent, ServerMessage},
-};
+use losig_core::network::{ClientMessage, ClientMessageContent, CommandMessage, ServerMessage};
 
 use crate::{
-    game::Game,
+    game::{Game, LOBBY_THROTTLE},
     services::Services,
     ws_server::{Recipient, ServerMessageWithRecipient},
 };
 
+/// Players who don't play for this long are retired
+pub const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(15 * 60);
+/// Delay between two checks for idle players
+const IDLE_SWEEP_INTERVAL: Duration = Duration::from_secs(10);
+/// Delay between two checks for players to make wait
+const AUTO_WAIT_INTERVAL: Duration = Duration::from_secs(1);
+
 pub struct Dispatch {
     services: Services,
     cm_rx: Receiver<ClientMessage>,
+    idle_timeout: Duration,
 }
 
 impl Dispatch {
-    pub fn new(services: Services, cm_rx: Receiver<ClientMessage>) -> Self {
-        Self { services, cm_rx }
+    pub fn new(services: Services, cm_rx: Receiver<ClientMessage>, idle_timeout: Duration) -> Self {
+        Self {
+            services,
+            cm_rx,
+            idle_timeout,
+        }
     }
 
     pub fn run(self) {
         spawn(move || {
             let mut game = Game::new(self.services.clone());
+            let stage_count = self.services.world.stages.len();
+            let workers: Vec<Sender<CommandMessage>> = (0..stage_count)
+                .map(|_| self.spawn_worker(game.clone()))
+                .collect();
+            let mut last_sweep = Instant::now();
+            let mut last_auto_wait = Instant::now();
+
+            loop {
+                // Nobody can play while paused, so nobody is idle
+                if last_sweep.elapsed() >= IDLE_SWEEP_INTERVAL && !self.services.is_paused() {
+                    game.retire_idle(self.idle_timeout);
+                    last_sweep = Instant::now();
+                }
+                if last_auto_wait.elapsed() >= AUTO_WAIT_INTERVAL && !self.services.is_paused() {
+                    game.auto_wait();
+                    last_auto_wait = Instant::now();
+                }
+
+                // Wake up regularly to send throttled lobby and leaderboard updates
+                let msg = match self.cm_rx.recv_timeout(LOBBY_THROTTLE) {
+                    Ok(msg) => msg,
+                    Err(RecvTimeoutError::Timeout) => {
+                        game.update_lobby();
+                        game.update_leaderboard();
+                        continue;
+                    }
+                    Err(RecvTimeoutError::Disconnected) => break,
+                };
 
-            while let Ok(msg) = self.cm_rx.recv() {
                 match msg.content {
                     ClientMessageContent::Start(pid, name) => {
                         if let Err(e) = game.new_player(pid, name) {
                             error!("Error while creating new player: {e}");
+                            game.send_error(pid, &e);
                         }
                     }
-                    ClientMessageContent::Command(cmd) => {
-                        let player_id = cmd.player_id;
-                        let start = Instant::now();
-                        let result = game.player_command(cmd);
-                        let elapsed = start.elapsed();
-
-                        if elapsed.as_millis() > 100 {
-                            debug!(
-                                "player_command [player_id={}] took {:?}",
-                                player_id, elapsed
-                            );
+                    ClientMessageContent::Spectate(stage_id) => {
+                        if let Some(player_id) = msg.player_id
+                            && let Err(e) = game.spectate(player_id, stage_id)
+                        {
+                            error!("Error while spectating: {e}");
+                            game.send_error(player_id, &e);
                         }
-
-                        if let Err(e) = result {
-                            error!("Error while using command: {e}");
+                    }
+                    ClientMessageContent::StopSpectating => {
+                        if let Some(player_id) = msg.player_id {
+                            game.stop_spectating(player_id);
+                        }
+                    }
+                    ClientMessageContent::Resync => {
+                        if let Some(player_id) = msg.player_id
+                            && let Err(e) = game.resync(player_id)
+                        {
+                            error!("Error while resyncing: {e}");
+                            game.send_error(player_id, &e);
+                        }
+                    }
+                    ClientMessageContent::Disconnect => {
+                        if let Some(player_id) = msg.player_id {
+                            game.disconnect(player_id);
+                        }
+                    }
+                    ClientMessageContent::ShareSenses(share) => {
+                        if let Some(player_id) = msg.player_id
+                            && let Err(e) = self.services.world.share_senses(player_id, share)
+                        {
+                            error!("Error while sharing senses: {e}");
+                            game.send_error(player_id, &e);
+                        }
+                    }
+                    ClientMessageContent::WarpTo(stage_id) => {
+                        let Some(player_id) = msg.player_id else {
+                            continue;
+                        };
+                        if !self.services.allow_warp {
+                            error!("Player {player_id} tried to warp but warps are not allowed");
+                            game.send_denied(player_id, "Warps are not allowed");
+                            continue;
+                        }
+                        if let Err(e) = game.warp(player_id, stage_id) {
+                            error!("Error while warping: {e}");
+                            game.send_error(player_id, &e);
+                        }
+                    }
+                    ClientMessageContent::InspectStage { stage_id, token } => {
+                        let Some(player_id) = msg.player_id else {
+                            continue;
+                        };
+                        if !self.services.is_admin(&token) {
+                            error!("Player {player_id} tried to inspect a stage without the token");
+                            game.send_denied(player_id, "Wrong admin token");
+                            continue;
+                        }
+                        match self.services.world.inspect(stage_id) {
+                            Ok(snapshot) => {
+                                let message = ServerMessageWithRecipient {
+                                    recipient: Recipient::Single(player_id),
+                                    message: ServerMessage::StageSnapshot(snapshot),
+                                };
+                                if let Err(e) = self.services.sender.send(message) {
+                                    error!("Failed to send stage snapshot: {e}");
+                                }
+                            }
+                            Err(e) => {
+                                error!("Error while inspecting stage: {e}");
+                                game.send_error(player_id, &e);
+                            }
+                        }
+                    }
+                    ClientMessageContent::ListMaps { token } => {
+                        let Some(player_id) = msg.player_id else {
+                            continue;
+                        };
+                        if !self.services.is_admin(&token) {
+                            error!("Player {player_id} tried to list the maps without the token");
+                            game.send_denied(player_id, "Wrong admin token");
+                            continue;
+                        }
+                        game.send_map_set(player_id);
+                    }
+                    ClientMessageContent::SwapMaps { maps, token } => {
+                        let Some(player_id) = msg.player_id else {
+                            continue;
+                        };
+                        if !self.services.is_admin(&token) {
+                            error!("Player {player_id} tried to swap the maps without the token");
+                            game.send_denied(player_id, "Wrong admin token");
+                            continue;
+                        }
+                        match game.swap_maps(&maps) {
+                            Ok(()) => game.send_map_set(player_id),
+                            Err(e) => {
+                                error!("Error while swapping the maps: {e}");
+                                game.send_error(player_id, &e);
+                            }
+                        }
+                    }
+                    // Commands are bounced rather than queued, a burst of stale commands
+                    // on resume could be applied out of order. The client is resynced to undo
+                    // what it predicted.
+                    ClientMessageContent::Command(cmd) if self.services.is_paused() => {
+                        let message = ServerMessageWithRecipient {
+                            recipient: Recipient::Single(cmd.player_id),
+                            message: ServerMessage::Paused(true),
+                        };
+                        if let Err(e) = self.services.sender.send(message) {
+                            error!("Failed to send pause notice: {e}");
+                        }
+                        if let Err(e) = game.resync(cmd.player_id) {
+                            error!("Error while resyncing: {e}");
+                        }
+                    }
+                    ClientMessageContent::Command(cmd) => {
+                        // Players out of a stage only get an error, any worker will do
+                        let stage_id = self
+                            .services
+                            .world
+                            .player_stage(cmd.player_id)
+                            .unwrap_or_default();
+                        if workers[stage_id].send(cmd).is_err() {
+                            error!("Worker for stage {stage_id} is down");
                         }
                     }
                     ClientMessageContent::Leaderboard => {
@@ -62,31 +213,64 @@ impl Dispatch {
                             if let Err(e) = self.services.sender.send(message) {
                                 eprintln!("Failed to send leaderboard: {}", e);
                             }
+                            drop(leaderboard);
+
+                            // Called on connection, a good time to also send the lobby
+                            game.send_lobby(player_id);
+                            if self.services.is_paused() {
+                                let message = ServerMessageWithRecipient {
+                                    recipient: Recipient::Single(player_id),
+                                    message: ServerMessage::Paused(true),
+                                };
+                                if let Err(e) = self.services.sender.send(message) {
+                                    error!("Failed to send pause notice: {e}");
+                                }
+                            }
                         }
                     }
                     ClientMessageContent::LeaderboardSubmit(player_id, name) => {
-                        // Get avatar stats
-                        let mut world = self.services.world.lock().unwrap();
-                        if let Some(gameover) = world.retire_player(player_id) {
-                            let entry = LeaderboardEntry::new(name, &gameover);
-                            {
-                                let mut leaderboard = self.services.leaderboard.lock().unwrap();
-                                leaderboard.add(entry);
-                            }
+                        game.submit_leaderboard(player_id, name);
+                    }
+                }
 
-                            let leaderboard = self.services.leaderboard.lock().unwrap();
-                            let message = ServerMessageWithRecipient {
-                                recipient: Recipient::Broadcast,
-                                message: ServerMessage::Leaderboard((*leaderboard).clone()),
-                            };
+                game.update_lobby();
+                game.update_leaderboard();
+            }
+        });
+    }
 
-                            if let Err(e) = self.services.sender.send(message) {
-                                error!("Failed to broadcast leaderboard update: {}", e);
-                            }
-                        }
-                    }
+    /// Commands are handled by one thread per stage, so that a deep rollback in a stage doesn't
+    /// delay the players of other stages. Commands go to the stage of the player when they are
+    /// received, so the next one may reach another thread while a command changes the stage:
+    /// Game::player_command then waits for the first one to be handled.
+    fn spawn_worker(&self, mut game: Game) -> Sender<CommandMessage> {
+        let (tx, rx) = channel::<CommandMessage>();
+        #[cfg(feature = "metrics")]
+        let metrics = self.services.metrics.clone();
+
+        spawn(move || {
+            for cmd in rx {
+                let player_id = cmd.player_id;
+                let start = Instant::now();
+                let result = game.player_command(cmd);
+                let elapsed = start.elapsed();
+                #[cfg(feature = "metrics")]
+                metrics.record_command(elapsed);
+
+                if elapsed.as_millis() > 100 {
+                    debug!(
+                        "player_command [player_id={}] took {:?}",
+                        player_id, elapsed
+                    );
+                }
+
+                if let Err(e) = result {
+                    error!("Error while using command: {e}");
+                    game.send_error(player_id, &e);
                 }
             }
         });
+
+        tx
     }
 }
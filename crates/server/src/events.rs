@@ -1,11 +1,13 @@
 use losig_core::{
     events::{GEvent, GameEvent, Target},
-    fov,
     sense::{SenseStrength, SenseType, Senses},
     types::{PlayerId, Position, Tile, Tiles},
 };
 
-use crate::stage::{Stage, StageState};
+use crate::{
+    sense::FovCache,
+    stage::{Stage, StageState},
+};
 
 #[derive(Clone)]
 pub struct GameEventSource {
@@ -24,10 +26,13 @@ pub enum EventSource {
 #[derive(Clone)]
 pub enum EventSenses {
     All,
+    /// Only felt by the avatar the event happens to
+    SelfSense,
 }
 impl EventSenses {
     fn slice(&self) -> &[SenseType] {
         match self {
+            EventSenses::SelfSense => &[SenseType::SelfSense],
             EventSenses::All => &[
                 SenseType::Touch,
                 SenseType::Hearing,
@@ -43,16 +48,13 @@ pub fn gather_events(
     stage: &Stage,
     state: &StageState,
     pid: PlayerId,
+    fov_cache: &mut FovCache,
 ) -> Vec<GEvent> {
     let avatar = &state.avatars[&pid];
 
-    // Compute FOV once if sight is active
+    // Reuse the sight FOV if it was already computed for this command
     let sight_tiles = if senses.sight.get() > 0 {
-        Some(fov::fov(
-            avatar.position,
-            senses.sight.get().into(),
-            &stage.template.tiles,
-        ))
+        Some(fov_cache.sight(avatar.position, senses.sight.get().into(), stage, state))
     } else {
         None
     };
@@ -68,22 +70,26 @@ pub fn gather_events(
                 SenseType::Sight => {
                     senses.sight.is_active()
                         && (event.event.has_player(pid)
-                            || is_seen(
-                                avatar.position,
-                                &event.source,
-                                sight_tiles.as_ref().unwrap(),
-                            ))
+                            || is_seen(avatar.position, &event.source, sight_tiles.unwrap()))
                 }
                 SenseType::Touch => {
                     senses.touch.is_active()
                         && (event.event.has_player(pid)
-                            || is_touched(avatar.position, &event.source))
+                            || is_touched(avatar.position, &event.source, senses.touch.get()))
                 }
                 SenseType::Hearing => {
                     senses.hearing.is_active()
                         && (event.event.has_player(pid)
                             || is_heard(avatar.position, &event.source, senses.hearing.get()))
                 }
+                // Smell only reports foe density
+                SenseType::Smell => false,
+                // The danger sense only counts the threats around
+                SenseType::Danger => false,
+                // Chronosense only reports the turns of allies
+                SenseType::Chronosense => false,
+                // The orb sense only grades how close the orb is
+                SenseType::OrbSense => false,
             };
 
             if detected {
@@ -107,16 +113,16 @@ pub fn gather_events(
 fn is_seen(viewer: Position, source: &EventSource, sight_tiles: &Tiles) -> bool {
     match source {
         EventSource::Position(pos) => {
-            let offset = viewer - *pos;
+            let offset = *pos - viewer;
             let view_pos = sight_tiles.center() + offset;
             sight_tiles.get(view_pos) != Tile::Unknown
         }
     }
 }
 
-fn is_touched(avatar_pos: Position, source: &EventSource) -> bool {
+fn is_touched(avatar_pos: Position, source: &EventSource, touch_strength: u8) -> bool {
     match source {
-        EventSource::Position(pos) => avatar_pos.dist(pos) <= 1,
+        EventSource::Position(pos) => avatar_pos.dist(pos) <= touch_strength as usize,
     }
 }
 
@@ -172,8 +178,18 @@ fn transform_event_targets(
         },
         ParadoxDeath(foe_type) => ParadoxDeath(*foe_type),
         ParadoxTeleport(foe_type) => ParadoxTeleport(*foe_type),
+        Exhausted => Exhausted,
+        SenseCooldown(sense) => SenseCooldown(*sense),
         OrbSeen => OrbSeen,
         OrbTaken(target) => OrbTaken(transform_target(target)),
+        ShardTaken(target) => ShardTaken(transform_target(target)),
+        TrapDisarmed(target) => TrapDisarmed(transform_target(target)),
+        Wounded { subject, hp } => Wounded {
+            subject: transform_target(subject),
+            hp: *hp,
+        },
         AvatarFadedOut(target) => AvatarFadedOut(transform_target(target)),
+        WindUp(target) => WindUp(transform_target(target)),
+        Summon(target) => Summon(transform_target(target)),
     }
 }
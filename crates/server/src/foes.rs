@@ -1,6 +1,7 @@
 use grid::Grid;
 use losig_core::{
     events::{GameEvent, Target},
+    fov::{self, VisibilitySet},
     types::{Avatar, AvatarId, Direction, Foe, FoeType, Position, StageTurn},
 };
 
@@ -10,17 +11,51 @@ use crate::{
     stage::{Stage, StageState},
 };
 
+/// How far foes can see avatars
+const FOE_SIGHT: u8 = 4;
+/// Turns a foe keeps chasing an avatar it lost sight of
+const FOE_MEMORY: StageTurn = 3;
+/// How far kings of dummies can see avatars
+const KING_SIGHT: u8 = 7;
+/// Kings of dummies only strike on one turn out of this many, winding up the turn before
+const KING_STRIKE_CADENCE: StageTurn = 3;
+
+const DIRECTIONS: [Direction; 8] = [
+    Direction::Up,
+    Direction::UpRight,
+    Direction::Right,
+    Direction::DownRight,
+    Direction::Down,
+    Direction::DownLeft,
+    Direction::Left,
+    Direction::UpLeft,
+];
+
 pub fn act(
     foe: &Foe,
     stage: &Stage,
     state: &mut StageState,
     bindings: &SenseBounds,
+    visible: &mut VisibilitySet,
 ) -> Box<dyn FnOnce(&mut Foe)> {
     if !foe.alive() {
         return Box::new(|_| {});
     }
 
-    let action = foe_ai(foe, stage, state, bindings);
+    let visible_avatars = find_visible_avatars(foe, stage, state, visible);
+    let last_seen = visible_avatars
+        .iter()
+        .filter_map(|aid| state.avatars.get(aid))
+        .min_by_key(|avatar| avatar.position.dist(&foe.position))
+        .map(|avatar| (avatar.player_id, state.turn))
+        .or(foe.last_seen);
+    let targets = find_targets(foe, state, visible_avatars);
+    let summoned = match foe.foe_type {
+        FoeType::KingDummy => king_turn(foe, stage, state, &targets),
+        _ => foe.summoned,
+    };
+
+    let action = foe_ai(foe, stage, state, bindings, &targets);
 
     match action {
         FoeAction::Attack(aid) => {
@@ -46,14 +81,81 @@ pub fn act(
         }
         FoeAction::Wait => {}
         FoeAction::Move(position) => {
-            return Box::new(move |f| f.position = position);
+            return Box::new(move |f| {
+                f.position = position;
+                f.last_seen = last_seen;
+                f.summoned = summoned;
+            });
         }
     }
 
-    Box::new(|_| {})
+    Box::new(move |f| {
+        f.last_seen = last_seen;
+        f.summoned = summoned;
+    })
+}
+
+fn king_strikes(turn: StageTurn) -> bool {
+    turn.is_multiple_of(KING_STRIKE_CADENCE)
 }
 
-fn foe_ai(foe: &Foe, stage: &Stage, state: &mut StageState, bindings: &SenseBounds) -> FoeAction {
+/// Warns the targets of the king before it strikes and, once it lost half its HP, calls a dummy
+/// to its side. Returns whether the king has summoned.
+fn king_turn(foe: &Foe, stage: &Stage, state: &mut StageState, targets: &[AvatarId]) -> bool {
+    if !targets.is_empty() && king_strikes(state.turn + 1) {
+        state.events.add(GameEventSource {
+            senses: EventSenses::All,
+            source: EventSource::Position(foe.position),
+            event: GameEvent::WindUp(Target::Foe(foe.foe_type)),
+        });
+    }
+
+    let initial_hp = stage
+        .template
+        .initial_foe(foe.id)
+        .map_or(foe.hp, |initial| initial.hp);
+    if foe.summoned || foe.hp as u16 * 2 > initial_hp as u16 {
+        return foe.summoned;
+    }
+
+    // Replays summon on the same turn and in the same order, so the summoned foe keeps its id
+    let free = DIRECTIONS
+        .iter()
+        .map(|dir| foe.position.move_once(*dir))
+        .find(|pos| {
+            stage.template.tiles.get(*pos).can_travel()
+                && !state.avatars.values().any(|a| a.position == *pos)
+                && !matches!(state.find_foe(*pos), Some(f) if f.1.alive())
+        });
+    let Some(position) = free else {
+        // Surrounded, it will try again next turn
+        return false;
+    };
+    state.add_foe(Foe {
+        id: 0,
+        foe_type: FoeType::Dummy,
+        position,
+        // Same stats as the dummies placed on the maps
+        hp: 3,
+        attack: 2,
+        last_seen: foe.last_seen,
+        summoned: false,
+    });
+    state.events.add(GameEventSource {
+        senses: EventSenses::All,
+        source: EventSource::Position(foe.position),
+        event: GameEvent::Summon(Target::Foe(foe.foe_type)),
+    });
+    true
+}
+
+fn foe_ai(
+    foe: &Foe,
+    stage: &Stage,
+    state: &mut StageState,
+    bindings: &SenseBounds,
+    targets: &[AvatarId],
+) -> FoeAction {
     // 1. List all possible actions
     let actions: Vec<FoeAction> = compute_possible_actions(foe, stage, state, bindings);
     if actions.len() == 1 {
@@ -67,16 +169,7 @@ fn foe_ai(foe: &Foe, stage: &Stage, state: &mut StageState, bindings: &SenseBoun
         Some(bound) => filter_actions_by_bounds(foe, actions, bound),
     };
 
-    let visible_avatars = find_targets(foe, state);
-
-    select_best_action(
-        &actions,
-        &visible_avatars,
-        foe,
-        state,
-        position_bound,
-        bindings,
-    )
+    select_best_action(&actions, targets, foe, state, position_bound, bindings)
 }
 
 /// Compute all possible actions for a foe in the current situation
@@ -103,18 +196,9 @@ fn compute_possible_actions(
             }
         }
         FoeType::Dummy | FoeType::KingDummy => {
-            // Dummy and KingDummy foes can attack adjacent avatars and move normally
-            const DIRECTIONS: [Direction; 8] = [
-                Direction::Up,
-                Direction::UpRight,
-                Direction::Right,
-                Direction::DownRight,
-                Direction::Down,
-                Direction::DownLeft,
-                Direction::Left,
-                Direction::UpLeft,
-            ];
-
+            // Dummy and KingDummy foes can attack adjacent avatars and move normally, kings only
+            // strike on their cadence
+            let can_strike = foe.foe_type != FoeType::KingDummy || king_strikes(state.turn);
             for dir in DIRECTIONS {
                 let new_pos = foe.position.move_once(dir);
 
@@ -123,7 +207,7 @@ fn compute_possible_actions(
                     state.avatars.iter().find(|(_, a)| a.position == new_pos)
                 {
                     // Check if attack would be pointless due to MaxHpBound
-                    if can_damage_avatar(avatar, foe.attack, state.turn, bindings) {
+                    if can_strike && can_damage_avatar(avatar, foe.attack, state.turn, bindings) {
                         actions.push(FoeAction::Attack(*avatar_id));
                     }
                     continue;
@@ -148,18 +232,9 @@ fn can_damage_avatar(
     current_turn: StageTurn,
     bindings: &SenseBounds,
 ) -> bool {
-    // Check if there's a MaxHpBound for this avatar at or after the current turn
-    if let Some(hp_bound) = bindings.avatars.get(&avatar.player_id)
-        && hp_bound.turn >= current_turn
-    {
-        // If the bound would prevent damage, don't generate this attack
-        let hp_after_attack = avatar.hp.saturating_sub(attack_damage);
-        if hp_after_attack < hp_bound.value {
-            // The enforce step would restore HP at the bound turn, making this attack pointless
-            return false;
-        }
-    }
-    true
+    // The enforce step would restore HP at the bound turn, making this attack pointless
+    let hp_after_attack = avatar.hp.saturating_sub(attack_damage);
+    hp_after_attack >= bindings.witnessed_hp(avatar.player_id, current_turn)
 }
 
 /// Compute position bounds from the bindings as grids for evaluation
@@ -189,8 +264,8 @@ fn compute_position_bounds(
     // Calculate turn difference once
     let turn_diff = (1 + bound.turn - state.turn) as u8;
 
-    // Initialize result grid with zeros
-    let mut result_grid: Grid<u8> = Grid::new(height, width);
+    // Initialize result grid with zeros, indexed by (x, y)
+    let mut result_grid: Grid<u8> = Grid::new(width, height);
 
     let Position { x, y } = bound.value;
     result_grid[(x, y)] = turn_diff;
@@ -207,17 +282,6 @@ fn compute_position_bounds(
         let next_value = value - 1;
 
         // Check all 8 directions
-        const DIRECTIONS: [Direction; 8] = [
-            Direction::Up,
-            Direction::UpRight,
-            Direction::Right,
-            Direction::DownRight,
-            Direction::Down,
-            Direction::DownLeft,
-            Direction::Left,
-            Direction::UpLeft,
-        ];
-
         for dir in DIRECTIONS {
             let next_pos = pos.move_once(dir);
             let Position { x: nx, y: ny } = next_pos;
@@ -267,16 +331,57 @@ fn filter_actions_by_bounds(
     }
 }
 
-/// Find any avatar within a certain distance threshold
-fn find_targets(foe: &Foe, state: &StageState) -> Vec<AvatarId> {
-    state
+/// Find the avatars in the line of sight of the foe. Its field of view is computed once into the
+/// set, and only if an avatar is in range.
+fn find_visible_avatars(
+    foe: &Foe,
+    stage: &Stage,
+    state: &StageState,
+    visible: &mut VisibilitySet,
+) -> Vec<AvatarId> {
+    let radius = sight(foe) as usize;
+    let in_range: Vec<(AvatarId, Position)> = state
         .avatars
         .iter()
-        .filter(|(_, a)| a.position.dist(&foe.position) < 5)
-        .map(|(id, _)| *id)
+        .filter(|(_, a)| a.position.dist(&foe.position) <= radius)
+        .map(|(id, a)| (*id, a.position))
+        .collect();
+    if in_range.is_empty() {
+        return vec![];
+    }
+
+    fov::fov_into(foe.position, radius, &stage.template.tiles, visible);
+    in_range
+        .into_iter()
+        .filter(|(_, position)| visible.contains(*position - foe.position))
+        .map(|(id, _)| id)
         .collect()
 }
 
+fn sight(foe: &Foe) -> u8 {
+    match foe.foe_type {
+        FoeType::KingDummy => KING_SIGHT,
+        FoeType::Dummy | FoeType::Trap => FOE_SIGHT,
+    }
+}
+
+/// Targets are the visible avatars, or the last one seen if it was lost recently
+fn find_targets(foe: &Foe, state: &StageState, visible_avatars: Vec<AvatarId>) -> Vec<AvatarId> {
+    if !visible_avatars.is_empty() {
+        return visible_avatars;
+    }
+
+    match foe.last_seen {
+        Some((aid, turn))
+            if state.turn.saturating_sub(turn) <= FOE_MEMORY
+                && state.avatars.contains_key(&aid) =>
+        {
+            vec![aid]
+        }
+        _ => vec![],
+    }
+}
+
 /// Select the best action, prioritizing: attacking > moving toward enemy > respecting position bounds
 fn select_best_action(
     actions: &[FoeAction],
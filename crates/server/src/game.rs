@@ -1,34 +1,291 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
 use anyhow::Result;
+use log::{error, info};
 use losig_core::{
-    network::{CommandMessage, ServerMessage, TransitionMessage, TurnMessage},
-    types::{Avatar, GameOver, GameOverStatus, PlayerId},
+    leaderboard::LeaderboardEntry,
+    network::{
+        CommandMessage, ErrorCode, LobbyMessage, ServerMessage, TransitionMessage, TurnMessage,
+    },
+    types::{Avatar, ClientAction, GameOver, GameOverStatus, PlayerId, StageId, Timeline},
 };
 
 use crate::{
+    game_log::GameLogEntry,
     services::Services,
-    world::{CommandResult, CommandResultOutcome, Limbo},
+    tiled,
+    world::{CommandResult, CommandResultOutcome, Limbo, ResyncOutcome},
     ws_server::{Recipient, ServerMessageWithRecipient},
 };
 
-/// More like GameAPI
+/// Minimum delay between two lobby broadcasts
+pub const LOBBY_THROTTLE: Duration = Duration::from_secs(1);
+/// Minimum delay between two leaderboard broadcasts, submissions in between are sent together
+const LEADERBOARD_THROTTLE: Duration = Duration::from_secs(2);
+
+/// More like GameAPI. Clones share their state so they can be used from several threads.
+#[derive(Clone)]
 pub struct Game {
     services: Services,
+    /// Last lobby broadcasted and when
+    last_lobby: Arc<Mutex<Option<(LobbyMessage, Instant)>>>,
+    /// When the leaderboard was last broadcasted
+    last_leaderboard: Arc<Mutex<Option<Instant>>>,
+    /// A submission changed the top entries since the last broadcast
+    leaderboard_changed: Arc<AtomicBool>,
+    /// Clients watching a stage. They are not players so they don't impact the stage.
+    spectators: Arc<Mutex<HashMap<PlayerId, StageId>>>,
+    /// Held while a command of the player is handled, so that its commands are handled one at a
+    /// time even by different threads
+    commanding: Arc<Mutex<HashMap<PlayerId, Arc<Mutex<()>>>>>,
 }
 
 impl Game {
     pub fn new(services: Services) -> Self {
-        Game { services }
+        Game {
+            services,
+            last_lobby: Default::default(),
+            last_leaderboard: Default::default(),
+            leaderboard_changed: Default::default(),
+            spectators: Default::default(),
+            commanding: Default::default(),
+        }
     }
 
-    pub fn new_player(&mut self, pid: PlayerId, name: Option<String>) -> Result<()> {
-        let result = {
-            let mut world = self.services.world.lock().unwrap();
-            world.new_player(pid, name)?
+    pub fn spectate(&mut self, pid: PlayerId, stage_id: StageId) -> Result<()> {
+        let msg = self.services.world.spectate(stage_id)?;
+        self.spectators.lock().unwrap().insert(pid, stage_id);
+        let msg = ServerMessageWithRecipient {
+            recipient: Recipient::Single(pid),
+            message: ServerMessage::Spectate(msg),
+        };
+        self.services.sender.send(msg).unwrap();
+        Ok(())
+    }
+
+    pub fn stop_spectating(&mut self, pid: PlayerId) {
+        self.spectators.lock().unwrap().remove(&pid);
+    }
+
+    pub fn resync(&mut self, pid: PlayerId) -> Result<()> {
+        self.spectators.lock().unwrap().remove(&pid);
+        let resync = self.services.world.resync(pid)?;
+        let message = match resync {
+            ResyncOutcome::Playing(resync) => ServerMessage::Resync(resync),
+            ResyncOutcome::Gameover(gameover) => ServerMessage::GameOver(gameover),
+        };
+        let msg = ServerMessageWithRecipient {
+            recipient: Recipient::Single(pid),
+            message,
+        };
+        self.services.sender.send(msg).unwrap();
+        Ok(())
+    }
+
+    fn update_spectators(&self, stage_id: StageId) -> Result<()> {
+        let pids: Vec<PlayerId> = self
+            .spectators
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, sid)| **sid == stage_id)
+            .map(|(pid, _)| *pid)
+            .collect();
+        if pids.is_empty() {
+            return Ok(());
+        }
+
+        let msg = self.services.world.spectate(stage_id)?;
+        let msg = ServerMessageWithRecipient {
+            recipient: Recipient::Multi(pids),
+            message: ServerMessage::Spectate(msg),
+        };
+        self.services.sender.send(msg).unwrap();
+        Ok(())
+    }
+
+    pub fn send_lobby(&self, player_id: PlayerId) {
+        let lobby = self.services.world.lobby();
+        let msg = ServerMessageWithRecipient {
+            recipient: Recipient::Single(player_id),
+            message: ServerMessage::Lobby(lobby),
+        };
+        self.services.sender.send(msg).unwrap();
+    }
+
+    /// Tells an admin which maps are played
+    pub fn send_map_set(&self, player_id: PlayerId) {
+        let msg = ServerMessageWithRecipient {
+            recipient: Recipient::Single(player_id),
+            message: ServerMessage::MapSet(self.services.world.map_set()),
+        };
+        if let Err(e) = self.services.sender.send(msg) {
+            error!("Failed to send map set: {e}");
+        }
+    }
+
+    /// Broadcasts the lobby if it changed, at most once per LOBBY_THROTTLE. Should be called
+    /// regularly so that throttled changes are eventually sent.
+    pub fn update_lobby(&mut self) {
+        let mut last_lobby = self.last_lobby.lock().unwrap();
+        if let Some((_, sent_at)) = &*last_lobby
+            && sent_at.elapsed() < LOBBY_THROTTLE
+        {
+            return;
+        }
+
+        let lobby = self.services.world.lobby();
+        if last_lobby.as_ref().is_some_and(|(last, _)| *last == lobby) {
+            return;
+        }
+
+        let msg = ServerMessageWithRecipient {
+            recipient: Recipient::Broadcast,
+            message: ServerMessage::Lobby(lobby.clone()),
+        };
+        self.services.sender.send(msg).unwrap();
+        *last_lobby = Some((lobby, Instant::now()));
+    }
+
+    /// Retires the player and adds its score to the leaderboard under this name
+    pub fn submit_leaderboard(&mut self, player_id: PlayerId, name: String) {
+        if let Some(gameover) = self.services.world.retire_player(player_id) {
+            let entry = LeaderboardEntry::new(name, &gameover);
+            if self.services.leaderboard.lock().unwrap().add(entry) {
+                self.leaderboard_changed.store(true, Ordering::Release);
+            }
+        }
+    }
+
+    /// Broadcasts the leaderboard if its top changed, at most once per LEADERBOARD_THROTTLE.
+    /// Should be called regularly so that throttled changes are eventually sent.
+    pub fn update_leaderboard(&mut self) {
+        let mut last_leaderboard = self.last_leaderboard.lock().unwrap();
+        if !self.leaderboard_changed.load(Ordering::Acquire)
+            || last_leaderboard.is_some_and(|sent_at| sent_at.elapsed() < LEADERBOARD_THROTTLE)
+        {
+            return;
+        }
+
+        let leaderboard = self.services.leaderboard.lock().unwrap().clone();
+        let msg = ServerMessageWithRecipient {
+            recipient: Recipient::Broadcast,
+            message: ServerMessage::Leaderboard(leaderboard),
+        };
+        if let Err(e) = self.services.sender.send(msg) {
+            error!("Failed to broadcast leaderboard update: {e}");
+        }
+        self.leaderboard_changed.store(false, Ordering::Release);
+        *last_leaderboard = Some(Instant::now());
+    }
+
+    /// Retires idle players and tells them their game is over
+    pub fn retire_idle(&mut self, timeout: Duration) {
+        for (pid, gameover) in self.services.world.retire_idle(timeout) {
+            self.spectators.lock().unwrap().remove(&pid);
+            self.services
+                .game_log
+                .record(pid, GameLogEntry::GameOver(gameover.clone()));
+            let msg = ServerMessageWithRecipient {
+                recipient: Recipient::Single(pid),
+                message: ServerMessage::GameOver(gameover),
+            };
+            self.services.sender.send(msg).unwrap();
+        }
+    }
+
+    /// Retires a player whose socket was closed, there is nobody left to tell
+    pub fn disconnect(&mut self, pid: PlayerId) {
+        self.spectators.lock().unwrap().remove(&pid);
+        self.commanding.lock().unwrap().remove(&pid);
+        if let Some(gameover) = self.services.world.retire_player(pid) {
+            info!("Player #{pid} disconnected.");
+            self.services
+                .game_log
+                .record(pid, GameLogEntry::GameOver(gameover));
+        }
+    }
+
+    /// Tells the player its message failed, the code depending on where it stands
+    pub fn send_error(&self, pid: PlayerId, e: &anyhow::Error) {
+        let code = match self.services.world.players().get(&pid) {
+            None => ErrorCode::UnknownPlayer,
+            Some(player) if player.stage.is_none() => ErrorCode::NotInStage,
+            Some(_) => ErrorCode::Refused,
+        };
+        self.send_error_code(pid, code, e.to_string());
+    }
+
+    /// Tells the player it is not allowed to send its message
+    pub fn send_denied(&self, pid: PlayerId, message: &str) {
+        self.send_error_code(pid, ErrorCode::PermissionDenied, message.to_string());
+    }
+
+    fn send_error_code(&self, pid: PlayerId, code: ErrorCode, message: String) {
+        let msg = ServerMessageWithRecipient {
+            recipient: Recipient::Single(pid),
+            message: ServerMessage::Error { code, message },
         };
+        if let Err(e) = self.services.sender.send(msg) {
+            error!("Failed to send error: {e}");
+        }
+    }
+
+    pub fn new_player(&mut self, pid: PlayerId, name: Option<String>) -> Result<()> {
+        let result = self.services.world.new_player(pid, name)?;
+        self.spectators.lock().unwrap().remove(&pid);
         // Use turn 1 for new player messages
         self.handle_command_result(pid, 1, result)
     }
 
+    pub fn warp(&mut self, pid: PlayerId, stage_id: StageId) -> Result<()> {
+        let result = self.services.world.warp(pid, stage_id)?;
+        info!("Player #{pid} warped to stage {stage_id}.");
+        let turn = self
+            .services
+            .world
+            .players()
+            .get(&pid)
+            .map_or(1, |player| player.turn);
+        self.handle_command_result(pid, turn, result)
+    }
+
+    /// Plays the given maps instead of the current ones, the players in a stage being moved to
+    /// the first one
+    pub fn swap_maps(&mut self, maps: &[String]) -> Result<()> {
+        let maps: Vec<&str> = maps.iter().map(String::as_str).collect();
+        let templates = tiled::load_templates(&self.services.map_source, &maps)?;
+        let moved = self.services.world.swap_maps(templates)?;
+        info!("Maps swapped for {maps:?}, {} players moved.", moved.len());
+        for (pid, result) in moved {
+            let result = match result {
+                Ok(result) => result,
+                Err(e) => {
+                    error!("Player #{pid} could not enter the new maps: {e}");
+                    self.send_error(pid, &e);
+                    continue;
+                }
+            };
+            let turn = self
+                .services
+                .world
+                .players()
+                .get(&pid)
+                .map_or(1, |player| player.turn);
+            if let Err(e) = self.handle_command_result(pid, turn, result) {
+                error!("Error while moving player #{pid} to the new maps: {e}");
+            }
+        }
+        Ok(())
+    }
+
     pub fn player_command(
         &mut self,
         CommandMessage {
@@ -38,13 +295,76 @@ impl Game {
             senses,
         }: CommandMessage,
     ) -> Result<()> {
-        let result = {
-            let mut world = self.services.world.lock().unwrap();
-            world.add_command(player_id, action, senses)?
+        let commanding = self
+            .commanding
+            .lock()
+            .unwrap()
+            .entry(player_id)
+            .or_default()
+            .clone();
+        let _commanding = commanding.lock().unwrap();
+
+        self.spectators.lock().unwrap().remove(&player_id);
+        self.services.world.check_turn(player_id, turn, action)?;
+        let result = match self.services.world.add_command(player_id, action, senses) {
+            // The client already rewound or awaits new senses, it has to catch up with the server
+            Err(e) if matches!(action, ClientAction::Undo | ClientAction::Sense) => {
+                info!("{action:?} refused for player {player_id}: {e}");
+                return self.resync(player_id);
+            }
+            result => result?,
         };
         self.handle_command_result(player_id, turn, result)
     }
 
+    /// Makes idle players wait. They never asked for these turns, so they are resynced instead of
+    /// being sent turns they could not match with a command.
+    pub fn auto_wait(&mut self) {
+        for (pid, result) in self.services.world.auto_wait() {
+            let CommandResult {
+                timeline_updates,
+                limbos,
+                outcome,
+            } = result;
+            let sent = match outcome {
+                CommandResultOutcome::Turn {
+                    stage,
+                    stage_turn,
+                    events,
+                    ..
+                } => {
+                    if !events.is_empty() {
+                        let entry = GameLogEntry::Events {
+                            stage,
+                            stage_turn,
+                            events,
+                        };
+                        self.services.game_log.record(pid, entry);
+                    }
+                    self.resync(pid)
+                        .and_then(|_| self.send_updates(pid, timeline_updates, limbos))
+                }
+                outcome => {
+                    let turn = self
+                        .services
+                        .world
+                        .players()
+                        .get(&pid)
+                        .map_or(1, |player| player.turn);
+                    let result = CommandResult {
+                        timeline_updates,
+                        limbos,
+                        outcome,
+                    };
+                    self.handle_command_result(pid, turn, result)
+                }
+            };
+            if let Err(e) = sent {
+                error!("Error while making player #{pid} wait: {e}");
+            }
+        }
+    }
+
     fn handle_command_result(
         &mut self,
         player_id: PlayerId,
@@ -64,6 +384,15 @@ impl Game {
                 events,
                 timeline,
             } => {
+                if !events.is_empty() {
+                    let entry = GameLogEntry::Events {
+                        stage,
+                        stage_turn,
+                        events: events.clone(),
+                    };
+                    self.services.game_log.record(player_id, entry);
+                }
+
                 // Send turn result with senses info
                 let msg = TurnMessage {
                     player_id,
@@ -88,6 +417,12 @@ impl Game {
                 info,
                 timeline,
             } => {
+                let entry = GameLogEntry::Transition {
+                    stage: stage_id,
+                    stage_turn,
+                };
+                self.services.game_log.record(player_id, entry);
+
                 let msg = TransitionMessage {
                     player_id,
                     turn,
@@ -104,6 +439,9 @@ impl Game {
                 self.services.sender.send(msg).unwrap();
             }
             CommandResultOutcome::Gameover(gameover) => {
+                self.services
+                    .game_log
+                    .record(player_id, GameLogEntry::GameOver(gameover.clone()));
                 let msg = ServerMessageWithRecipient {
                     recipient: Recipient::Single(player_id),
                     message: ServerMessage::GameOver(gameover),
@@ -111,11 +449,20 @@ impl Game {
                 self.services.sender.send(msg).unwrap();
             }
         }
+        self.send_updates(player_id, timeline_updates, limbos)
+    }
+
+    /// Tells the others on the stages of the new timelines, and the players in limbo of their fate
+    fn send_updates(
+        &mut self,
+        player_id: PlayerId,
+        timeline_updates: Vec<(StageId, Timeline)>,
+        limbos: Vec<Limbo>,
+    ) -> Result<()> {
         for (stage_id, timeline) in timeline_updates {
-            let infos = {
-                let world = self.services.world.lock().unwrap();
-                world.get_all_infos_for_stage(stage_id)
-            };
+            self.update_spectators(stage_id)?;
+
+            let infos = self.services.world.get_all_infos_for_stage(stage_id);
             for (pid, stage_turn, senses_info) in infos {
                 if pid == player_id {
                     // Don't send timeline update to player
@@ -137,13 +484,23 @@ impl Game {
         for limbo in limbos {
             match limbo {
                 Limbo::Dead(player_id) | Limbo::TooFarBehind(player_id) => {
+                    // Dead players have their game over recorded by the world
+                    let gameover = self
+                        .services
+                        .world
+                        .players()
+                        .get(&player_id)
+                        .and_then(|player| player.gameover.clone())
+                        .unwrap_or_else(|| {
+                            GameOver::new(&Avatar::new(player_id), GameOverStatus::Dead, 1)
+                                .with_seed(self.services.world.seed)
+                        });
+                    self.services
+                        .game_log
+                        .record(player_id, GameLogEntry::GameOver(gameover.clone()));
                     let msg = ServerMessageWithRecipient {
                         recipient: Recipient::Single(player_id),
-                        message: ServerMessage::GameOver(GameOver::new(
-                            &Avatar::new(player_id),
-                            GameOverStatus::Dead,
-                            1,
-                        )),
+                        message: ServerMessage::GameOver(gameover),
                     };
                     self.services.sender.send(msg).unwrap();
                 }
@@ -157,6 +514,13 @@ impl Game {
                     };
                     self.services.sender.send(msg).unwrap();
                 }
+                Limbo::FallingBehind(pid, turns_left) => {
+                    let msg = ServerMessageWithRecipient {
+                        recipient: Recipient::Single(pid),
+                        message: ServerMessage::FallingBehind(turns_left),
+                    };
+                    self.services.sender.send(msg).unwrap();
+                }
                 Limbo::MaybeDead(pid) => {
                     let msg = ServerMessageWithRecipient {
                         recipient: Recipient::Single(pid),
@@ -173,3 +537,31 @@ impl Game {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use losig_core::leaderboard::Leaderboard;
+
+    use super::*;
+    use crate::tiled::{MapSource, load_arena};
+
+    #[test]
+    fn spectators_test() {
+        let world = load_arena(&MapSource::Embedded).unwrap();
+        let (sender, _receiver) = mpsc::channel();
+        let mut game = Game::new(Services::new(world, Leaderboard::new(), sender));
+        let spectating = |game: &Game, pid| game.spectators.lock().unwrap().contains_key(&pid);
+
+        game.spectate(1, 0).unwrap();
+        game.spectate(2, 1).unwrap();
+        assert!(spectating(&game, 1));
+
+        // Leaving the page or the server both stop the updates
+        game.stop_spectating(1);
+        game.disconnect(2);
+        assert!(!spectating(&game, 1));
+        assert!(!spectating(&game, 2));
+    }
+}
@@ -0,0 +1,93 @@
+//! Append-only log of what happened in the games, one JSON record per line. Unlike the human
+//! facing logs, it is meant to be analyzed after the fact, e.g. to check balance or abuse.
+
+use std::{
+    fs::OpenOptions,
+    io::{BufWriter, Write},
+    path::Path,
+    sync::mpsc::{Receiver, Sender, channel},
+    thread::spawn,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Result;
+use log::error;
+use losig_core::{
+    events::GEvent,
+    types::{GameOver, PlayerId, StageId, StageTurn},
+};
+use serde::Serialize;
+
+#[derive(Serialize, Debug, Clone)]
+pub enum GameLogEntry {
+    /// Events sensed by the player on its turn
+    Events {
+        stage: StageId,
+        stage_turn: StageTurn,
+        events: Vec<GEvent>,
+    },
+    Transition {
+        stage: StageId,
+        stage_turn: StageTurn,
+    },
+    GameOver(GameOver),
+}
+
+#[derive(Serialize, Debug)]
+struct GameLogRecord {
+    /// Milliseconds since the UNIX epoch
+    timestamp: u64,
+    player_id: PlayerId,
+    entry: GameLogEntry,
+}
+
+/// Handle to the log writer. The file is written on its own thread so that recording never
+/// blocks the game, and recording does nothing when no file was configured.
+#[derive(Clone, Default)]
+pub struct GameLog {
+    sender: Option<Sender<GameLogRecord>>,
+}
+
+impl GameLog {
+    /// Appends the records to the given file, creating it if needed
+    pub fn to_file(path: &Path) -> Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let (sender, receiver) = channel();
+        spawn(move || {
+            if let Err(e) = write_records(receiver, BufWriter::new(file)) {
+                error!("Game log stopped: {e}");
+            }
+        });
+        Ok(Self {
+            sender: Some(sender),
+        })
+    }
+
+    pub fn record(&self, player_id: PlayerId, entry: GameLogEntry) {
+        let Some(sender) = &self.sender else {
+            return;
+        };
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_millis() as u64);
+        let record = GameLogRecord {
+            timestamp,
+            player_id,
+            entry,
+        };
+        if sender.send(record).is_err() {
+            error!("Game log is down, record lost");
+        }
+    }
+}
+
+/// Writes the records until every handle is dropped, flushing whenever none is pending
+fn write_records(receiver: Receiver<GameLogRecord>, mut writer: impl Write) -> Result<()> {
+    while let Ok(record) = receiver.recv() {
+        for record in std::iter::once(record).chain(receiver.try_iter()) {
+            writeln!(writer, "{}", serde_json::to_string(&record)?)?;
+        }
+        writer.flush()?;
+    }
+    Ok(())
+}
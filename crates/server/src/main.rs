@@ -1,16 +1,35 @@
+#[cfg(not(feature = "tui"))]
+use std::sync::{Arc, atomic::AtomicBool};
+use std::{
+    sync::atomic::Ordering,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
 use losig_core::leaderboard::Leaderboard;
+#[cfg(not(feature = "tui"))]
+use signal_hook::consts::{SIGINT, SIGTERM};
 
-use crate::{dispatch::Dispatch, services::Services, ws_server::WsServer};
+use crate::{
+    dispatch::{DEFAULT_IDLE_TIMEOUT, Dispatch},
+    game_log::GameLog,
+    services::Services,
+    tiled::MapSource,
+    ws_server::WsServer,
+};
 
 #[cfg(feature = "tui")]
 use crate::tui::GameTui;
 
 mod action;
+mod codec;
 mod command;
 mod dispatch;
 mod events;
 mod foes;
 mod game;
+mod game_log;
+#[cfg(feature = "metrics")]
+mod metrics;
 mod sense;
 mod sense_bounds;
 mod services;
@@ -23,21 +42,95 @@ mod ws_server;
 mod tui;
 
 fn main() {
-    let (server, sm_tx, cm_rx) = WsServer::new();
-    server.run();
+    // Maps are embedded unless a directory is given with `--maps <dir>` or LOSIG_MAPS_DIR
+    let map_source = std::env::args()
+        .skip_while(|arg| arg != "--maps")
+        .nth(1)
+        .or_else(|| std::env::var("LOSIG_MAPS_DIR").ok())
+        .map(|dir| MapSource::Directory(dir.into()))
+        .unwrap_or_default();
+    let mut world = match tiled::load_default(&map_source) {
+        Ok(world) => world,
+        Err(e) => {
+            eprintln!("Could not load the maps: {e}");
+            std::process::exit(1);
+        }
+    };
+
+    // Stages share a seed given with `--seed <number|daily>` or LOSIG_SEED, "daily" changing
+    // every day so that everyone plays the same challenge
+    let seed = std::env::args()
+        .skip_while(|arg| arg != "--seed")
+        .nth(1)
+        .or_else(|| std::env::var("LOSIG_SEED").ok())
+        .map(|seed| match seed.as_str() {
+            "daily" => {
+                let since_epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+                since_epoch.as_secs() / (24 * 60 * 60)
+            }
+            _ => seed.parse().expect("Invalid seed"),
+        });
+    if let Some(seed) = seed {
+        world = world.with_seed(seed);
+    }
 
-    let world = tiled::load_default().unwrap();
+    // Rollbacks replaying at least `--log-rollbacks <turns>` or LOSIG_LOG_ROLLBACKS are logged
+    let log_rollbacks = std::env::args()
+        .skip_while(|arg| arg != "--log-rollbacks")
+        .nth(1)
+        .or_else(|| std::env::var("LOSIG_LOG_ROLLBACKS").ok())
+        .map(|turns| turns.parse().expect("Invalid rollback depth"));
+    if let Some(turns) = log_rollbacks {
+        world = world.with_rollback_log(turns);
+    }
+
+    let (server, sm_tx, cm_rx) = WsServer::new();
+    let server_stop = server.stop_flag();
+    let server_handle = server.run();
     let leaderboard = Leaderboard::default();
-    let services = Services::new(world, leaderboard, sm_tx);
+    let mut services = Services::new(world, leaderboard, sm_tx);
 
-    let dispatch = Dispatch::new(services.clone(), cm_rx);
+    // Game events are recorded as JSON lines with `--game-log <file>` or LOSIG_GAME_LOG
+    let game_log_path = std::env::args()
+        .skip_while(|arg| arg != "--game-log")
+        .nth(1)
+        .or_else(|| std::env::var("LOSIG_GAME_LOG").ok());
+    if let Some(path) = game_log_path {
+        services.game_log = GameLog::to_file(path.as_ref()).expect("Could not open game log");
+    }
+
+    // Tools can inspect the stages with the token given with `--admin-token` or LOSIG_ADMIN_TOKEN
+    services.admin_token = std::env::args()
+        .skip_while(|arg| arg != "--admin-token")
+        .nth(1)
+        .or_else(|| std::env::var("LOSIG_ADMIN_TOKEN").ok());
+
+    // Admins swap in maps from the same place as the ones loaded now
+    services.map_source = map_source;
+
+    // Players can warp to any stage with `--allow-warp` or LOSIG_ALLOW_WARP, for testing
+    services.allow_warp = std::env::args().any(|arg| arg == "--allow-warp")
+        || std::env::var("LOSIG_ALLOW_WARP").is_ok();
+
+    // Idle players are retired after `--idle-timeout <seconds>` or LOSIG_IDLE_TIMEOUT
+    let idle_timeout = std::env::args()
+        .skip_while(|arg| arg != "--idle-timeout")
+        .nth(1)
+        .or_else(|| std::env::var("LOSIG_IDLE_TIMEOUT").ok())
+        .map(|secs| secs.parse().expect("Invalid idle timeout"))
+        .map(Duration::from_secs)
+        .unwrap_or(DEFAULT_IDLE_TIMEOUT);
+    let dispatch = Dispatch::new(services.clone(), cm_rx, idle_timeout);
     dispatch.run();
 
+    #[cfg(feature = "metrics")]
+    metrics::serve(services.clone());
+
     #[cfg(feature = "tui")]
     {
         tui_logger::init_logger(log::LevelFilter::Debug).unwrap();
         tui_logger::set_default_level(log::LevelFilter::Debug);
-        let mut tui = GameTui::new(services);
+        let mut tui = GameTui::new(services.clone());
         tui.run().expect("Could not start TUI");
     }
 
@@ -49,7 +142,19 @@ fn main() {
             .init();
 
         log::info!("Server running in headless mode. Press Ctrl+C to stop.");
-        // Wait for Ctrl+C signal
-        let _ = std::sync::mpsc::channel::<()>().1.recv();
+        let signaled = Arc::new(AtomicBool::new(false));
+        for signal in [SIGINT, SIGTERM] {
+            signal_hook::flag::register(signal, signaled.clone())
+                .expect("Could not register signal handler");
+        }
+        while !signaled.load(Ordering::Relaxed) {
+            std::thread::sleep(Duration::from_millis(100));
+        }
+    }
+
+    services.shutdown();
+    server_stop.store(true, Ordering::Release);
+    if server_handle.join().is_err() {
+        log::error!("Websocket server panicked");
     }
 }
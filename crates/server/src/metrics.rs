@@ -0,0 +1,199 @@
+//! Prometheus metrics, served as plain text on a separate port.
+
+use std::{
+    fmt::Write as _,
+    io::{BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
+    sync::atomic::{AtomicU64, Ordering},
+    thread::spawn,
+    time::Duration,
+};
+
+use anyhow::Result;
+use log::{error, info};
+
+use crate::{
+    services::Services,
+    stage::{ROLLBACK_BUCKETS, RollbackStats},
+    world::World,
+};
+
+const ADDRESS: &str = "127.0.0.1:9091";
+
+/// Counters fed by the dispatch loop
+#[derive(Default)]
+pub struct Metrics {
+    commands: AtomicU64,
+    command_micros: AtomicU64,
+}
+
+impl Metrics {
+    pub fn record_command(&self, elapsed: Duration) {
+        self.commands.fetch_add(1, Ordering::Relaxed);
+        self.command_micros
+            .fetch_add(elapsed.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    /// Renders the metrics in the Prometheus text format
+    pub fn render(&self, world: &World) -> String {
+        let mut out = String::new();
+        let commands = self.commands.load(Ordering::Relaxed);
+        let micros = self.command_micros.load(Ordering::Relaxed);
+        let avg_seconds = if commands == 0 {
+            0.0
+        } else {
+            micros as f64 / commands as f64 / 1_000_000.0
+        };
+
+        let lobby = world.lobby();
+        // Writing to a String cannot fail
+        let _ = writeln!(
+            out,
+            "# HELP losig_players_online Players currently in a stage."
+        );
+        let _ = writeln!(out, "# TYPE losig_players_online gauge");
+        let _ = writeln!(out, "losig_players_online {}", lobby.online);
+
+        let _ = writeln!(out, "# HELP losig_commands_total Player commands handled.");
+        let _ = writeln!(out, "# TYPE losig_commands_total counter");
+        let _ = writeln!(out, "losig_commands_total {commands}");
+
+        let _ = writeln!(
+            out,
+            "# HELP losig_command_seconds_avg Average time spent handling a player command."
+        );
+        let _ = writeln!(out, "# TYPE losig_command_seconds_avg gauge");
+        let _ = writeln!(out, "losig_command_seconds_avg {avg_seconds}");
+
+        let _ = writeln!(out, "# HELP losig_stage_players Players in each stage.");
+        let _ = writeln!(out, "# TYPE losig_stage_players gauge");
+        let stages: Vec<_> = world
+            .stages
+            .iter()
+            .map(|stage| {
+                let stage = stage.lock().unwrap();
+                (
+                    stage.players.len(),
+                    stage.last_rollback_depth,
+                    stage.rollback_stats.clone(),
+                    stage.kept_states(),
+                )
+            })
+            .collect();
+        for (stage_id, (players, ..)) in stages.iter().enumerate() {
+            let _ = writeln!(out, "losig_stage_players{{stage=\"{stage_id}\"}} {players}");
+        }
+
+        let _ = writeln!(
+            out,
+            "# HELP losig_stage_rollback_depth Turns replayed by the last rollback of each stage."
+        );
+        let _ = writeln!(out, "# TYPE losig_stage_rollback_depth gauge");
+        for (stage_id, (_, depth, ..)) in stages.iter().enumerate() {
+            let _ = writeln!(
+                out,
+                "losig_stage_rollback_depth{{stage=\"{stage_id}\"}} {depth}"
+            );
+        }
+
+        let _ = writeln!(
+            out,
+            "# HELP losig_stage_rollback_turns Turns replayed by the rollbacks of each stage."
+        );
+        let _ = writeln!(out, "# TYPE losig_stage_rollback_turns histogram");
+        for (stage_id, (_, _, stats, _)) in stages.iter().enumerate() {
+            write_histogram(&mut out, stage_id, stats);
+        }
+
+        let _ = writeln!(
+            out,
+            "# HELP losig_stage_states States kept by each stage for its rollbacks."
+        );
+        let _ = writeln!(out, "# TYPE losig_stage_states gauge");
+        for (stage_id, (.., states)) in stages.iter().enumerate() {
+            let _ = writeln!(out, "losig_stage_states{{stage=\"{stage_id}\"}} {states}");
+        }
+
+        let _ = writeln!(
+            out,
+            "# HELP losig_stage_states_max Most states kept at once by each stage."
+        );
+        let _ = writeln!(out, "# TYPE losig_stage_states_max gauge");
+        for (stage_id, (_, _, stats, _)) in stages.iter().enumerate() {
+            let _ = writeln!(
+                out,
+                "losig_stage_states_max{{stage=\"{stage_id}\"}} {}",
+                stats.max_states
+            );
+        }
+
+        out
+    }
+}
+
+/// Writes the rollback buckets of a stage, cumulative as Prometheus expects
+fn write_histogram(out: &mut String, stage_id: usize, stats: &RollbackStats) {
+    let bounds = ROLLBACK_BUCKETS
+        .iter()
+        .map(|bound| bound.to_string())
+        .chain(["+Inf".to_string()]);
+    let mut cumulative = 0;
+    for (bound, count) in bounds.zip(stats.buckets) {
+        cumulative += count;
+        let _ = writeln!(
+            out,
+            "losig_stage_rollback_turns_bucket{{stage=\"{stage_id}\",le=\"{bound}\"}} {cumulative}"
+        );
+    }
+    let _ = writeln!(
+        out,
+        "losig_stage_rollback_turns_sum{{stage=\"{stage_id}\"}} {}",
+        stats.sum
+    );
+    let _ = writeln!(
+        out,
+        "losig_stage_rollback_turns_count{{stage=\"{stage_id}\"}} {}",
+        stats.count
+    );
+}
+
+/// Serves the metrics over HTTP, whatever the requested path
+pub fn serve(services: Services) {
+    let listener = match TcpListener::bind(ADDRESS) {
+        Ok(listener) => listener,
+        Err(e) => {
+            error!("Could not bind metrics endpoint: {e}");
+            return;
+        }
+    };
+    info!("Serving metrics on http://{ADDRESS}/metrics");
+
+    spawn(move || {
+        for stream in listener.incoming() {
+            let result = stream
+                .map_err(anyhow::Error::from)
+                .and_then(|stream| respond(stream, &services));
+            if let Err(e) = result {
+                error!("Metrics request failed: {e}");
+            }
+        }
+    });
+}
+
+fn respond(mut stream: TcpStream, services: &Services) -> Result<()> {
+    // Read the request head, its content is not needed
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut line = String::new();
+    while reader.read_line(&mut line)? > 2 {
+        line.clear();
+    }
+
+    let body = services.metrics.render(&services.world);
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )?;
+    stream.flush()?;
+    Ok(())
+}
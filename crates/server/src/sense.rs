@@ -1,16 +1,102 @@
+use std::collections::HashMap;
+
 use bounded_integer::BoundedU8;
 use losig_core::{
-    fov,
+    fov::{self, VisibilitySet},
     sense::{
-        HearingInfo, SelfInfo, SenseStrength, Senses, SensesInfo, SightInfo, SightedAlly,
-        SightedAllyStatus, SightedFoe, TouchInfo,
+        ChronoAlly, ChronoInfo, DangerInfo, HearingInfo, OrbInfo, SelfInfo, SenseStrength, Senses,
+        SensesInfo, SightInfo, SightedAlly, SightedAllyStatus, SightedFoe, SmellInfo, TouchInfo,
     },
-    types::{Avatar, PlayerId, ServerAction, Tile},
+    types::{Avatar, Direction, Offset, PlayerId, Position, ServerAction, StageTurn, Tile, Tiles},
+};
+
+use crate::{
+    stage::{Stage, StagePlayer, StageState},
+    world::Occlusion,
 };
 
-use crate::stage::{Stage, StagePlayer, StageState};
+/// How many turns apart allies can be and still share their senses
+const TEAM_VISION_TURNS: StageTurn = 3;
+
+/// Memoizes fov computations over the stage tiles. Meant to live for a single command so that
+/// senses and events share the same sight cone.
+#[derive(Default)]
+pub struct FovCache {
+    /// Keyed by the viewer, the radius and whether entities occluded the view
+    inner: HashMap<(Position, usize, bool), Tiles>,
+    /// Buffer of the fov computations, the tiles are only copied out of it once
+    visible: VisibilitySet,
+}
+
+impl FovCache {
+    pub fn fov(&mut self, viewer: Position, radius: usize, tiles: &Tiles) -> &Tiles {
+        self.inner
+            .entry((viewer, radius, false))
+            .or_insert_with(|| {
+                fov::fov_into(viewer, radius, tiles, &mut self.visible);
+                self.visible.to_tiles(viewer, tiles)
+            })
+    }
+
+    /// Sight cone in the given state. On stages where entities occlude the view, they are marked
+    /// as walls for the fov computation only, the cone shows the actual tiles.
+    pub fn sight(
+        &mut self,
+        viewer: Position,
+        radius: usize,
+        stage: &Stage,
+        state: &StageState,
+    ) -> &Tiles {
+        let tiles = &stage.template.tiles;
+        let occluders = occluders(stage, state);
+        if occluders.is_empty() {
+            return self.fov(viewer, radius, tiles);
+        }
+
+        self.inner.entry((viewer, radius, true)).or_insert_with(|| {
+            let mut marked = tiles.clone();
+            for position in occluders {
+                if let Some(tile) = marked.grid.get_mut(position.x, position.y) {
+                    *tile = Tile::Wall;
+                }
+            }
+            fov::fov_into(viewer, radius, &marked, &mut self.visible);
+            self.visible.to_tiles(viewer, tiles)
+        })
+    }
+
+    /// Number of fov actually computed
+    #[cfg(test)]
+    pub fn computed(&self) -> usize {
+        self.inner.len()
+    }
+}
+
+/// Positions of the entities blocking the sight on this stage
+fn occluders(stage: &Stage, state: &StageState) -> Vec<Position> {
+    let foes = state
+        .foes
+        .iter()
+        .filter(|foe| foe.alive() && !foe.is_trap())
+        .map(|foe| foe.position);
+    let avatars = state
+        .avatars
+        .values()
+        .filter(|avatar| !avatar.is_dead())
+        .map(|avatar| avatar.position);
+    match stage.template.occlusion {
+        Occlusion::None => vec![],
+        Occlusion::Foes => foes.collect(),
+        Occlusion::All => foes.chain(avatars).collect(),
+    }
+}
 
-pub fn gather(senses: &Senses, stage: &Stage, pid: PlayerId) -> SensesInfo {
+pub fn gather(
+    senses: &Senses,
+    stage: &Stage,
+    pid: PlayerId,
+    fov_cache: &mut FovCache,
+) -> SensesInfo {
     let player = &stage.players[&pid];
     let state = &stage.state_for(pid).unwrap();
     let tail_state = stage.tail_state();
@@ -18,47 +104,156 @@ pub fn gather(senses: &Senses, stage: &Stage, pid: PlayerId) -> SensesInfo {
 
     SensesInfo {
         selfi: try_gather(senses.selfs, |_| gather_self(player, avatar, tail_state)),
-        touch: try_gather(senses.touch, |_| gather_touch(avatar, stage, state)),
+        touch: try_gather(senses.touch, |strength| {
+            gather_touch(strength.get(), avatar, stage, state, fov_cache)
+        }),
         sight: try_gather(senses.sight, |strength| {
-            gather_sight(strength.get(), avatar, stage, state)
+            gather_sight(strength.get(), avatar, stage, state, fov_cache)
         }),
         hearing: try_gather(senses.hearing, |strength| {
             gather_hearing(strength.get(), avatar, stage, state)
         }),
+        smell: try_gather(senses.smell, |strength| {
+            gather_smell(strength.get(), avatar, stage, state)
+        }),
+        chrono: try_gather(senses.chrono, |_| gather_chrono(player, stage)),
+        orb: try_gather(senses.orb, |strength| {
+            gather_orb(strength.get(), avatar, stage, state)
+        }),
+        danger: try_gather(senses.danger, |strength| {
+            gather_danger(strength.get(), avatar, state)
+        }),
     }
 }
 
-fn gather_hearing(
-    strength: u8,
-    avatar: &Avatar,
-    _async_stage: &Stage,
-    state: &StageState,
-) -> HearingInfo {
-    let Some(ref orb) = state.orb else {
-        return HearingInfo { range: None };
+fn gather_hearing(strength: u8, avatar: &Avatar, stage: &Stage, state: &StageState) -> HearingInfo {
+    let Some(orb) = state.nearest_orb(avatar.position) else {
+        return HearingInfo::default();
     };
 
-    let dist = avatar.position.dist(&orb.position) as u8;
+    let dist = avatar.position.dist(&orb.position);
 
     for s in 1..(strength + 1) {
-        if let Some(range) = HearingInfo::dist(s)
-            && dist <= range
+        let range = if stage.template.scale_hearing {
+            HearingInfo::dist_for(s, stage.template.map_diagonal())
+        } else {
+            HearingInfo::dist(s).map(u16::from)
+        };
+        if let Some(range) = range
+            && dist <= range as usize
         {
+            let bearing = if strength >= HearingInfo::BEARING_STRENGTH {
+                Direction::from_offset(orb.position - avatar.position)
+            } else {
+                None
+            };
             return HearingInfo {
                 range: BoundedU8::new(s),
+                bearing,
             };
         }
     }
 
-    HearingInfo { range: None }
+    HearingInfo::default()
 }
 
-fn gather_sight(strength: u8, avatar: &Avatar, stage: &Stage, state: &StageState) -> SightInfo {
-    let tiles = fov::fov(avatar.position, strength.into(), &stage.template.tiles);
+fn gather_orb(strength: u8, avatar: &Avatar, _async_stage: &Stage, state: &StageState) -> OrbInfo {
+    let Some(orb) = state.nearest_orb(avatar.position) else {
+        return OrbInfo::default();
+    };
+
+    OrbInfo {
+        level: OrbInfo::level(strength, avatar.position.dist(&orb.position)),
+    }
+}
+
+fn gather_smell(
+    strength: u8,
+    avatar: &Avatar,
+    _async_stage: &Stage,
+    state: &StageState,
+) -> SmellInfo {
+    let radius = SmellInfo::radius(strength).unwrap_or_default() as usize;
+
+    let mut foe_count = 0;
+    let mut bearings: Vec<(Direction, u8)> = vec![];
+    for foe in &state.foes {
+        // Traps don't smell
+        if !foe.alive() || foe.is_trap() || foe.position.dist(&avatar.position) > radius {
+            continue;
+        }
+        foe_count += 1;
+
+        let Some(dir) = Direction::from_offset(foe.position - avatar.position) else {
+            continue;
+        };
+        match bearings.iter_mut().find(|(d, _)| *d == dir) {
+            Some((_, count)) => *count += 1,
+            None => bearings.push((dir, 1)),
+        }
+    }
+
+    SmellInfo {
+        foe_count,
+        strongest_bearing: bearings
+            .into_iter()
+            .max_by_key(|(_, count)| *count)
+            .map(|(dir, _)| dir),
+    }
+}
+
+/// Counts the living foes in range, walls or not. Their positions stay unknown.
+fn gather_danger(strength: u8, avatar: &Avatar, state: &StageState) -> DangerInfo {
+    let radius = DangerInfo::radius(strength).unwrap_or_default() as usize;
+    let count = state
+        .foes
+        .iter()
+        .filter(|foe| foe.alive() && foe.position.dist(&avatar.position) <= radius)
+        .count();
+    DangerInfo {
+        count: count.min(u8::MAX as usize) as u8,
+    }
+}
+
+/// Turns of the allies controlling a living avatar, left and dying ones are not reported
+fn gather_chrono(player: &StagePlayer, stage: &Stage) -> ChronoInfo {
+    let mut allies: Vec<ChronoAlly> = stage
+        .players
+        .values()
+        .filter(|ally| ally.id != player.id && !ally.limbo && ally.transition.is_none())
+        .filter(|ally| {
+            stage
+                .state_at(ally.turn)
+                .and_then(|state| state.avatars.get(&ally.id))
+                .is_some_and(|avatar| !avatar.is_dead())
+        })
+        .map(|ally| ChronoAlly {
+            name: ally.player_name.clone(),
+            turn_offset: ally.turn as i64 - player.turn as i64,
+        })
+        .collect();
+    allies.sort_by(|a, b| (a.turn_offset, &a.name).cmp(&(b.turn_offset, &b.name)));
+
+    ChronoInfo { allies }
+}
+
+fn gather_sight(
+    strength: u8,
+    avatar: &Avatar,
+    stage: &Stage,
+    state: &StageState,
+    fov_cache: &mut FovCache,
+) -> SightInfo {
+    let mut tiles = fov_cache
+        .sight(avatar.position, strength.into(), stage, state)
+        .clone();
+    if stage.template.team_vision {
+        tiles = share_allies_sight(tiles, avatar, stage, state, fov_cache);
+    }
     let mut foes = vec![];
 
     let center = tiles.center();
-    for (i, foe) in state.foes.iter().enumerate() {
+    for foe in state.foes.iter() {
         if foe.is_trap() {
             // Traps can't be seen
             continue;
@@ -68,7 +263,7 @@ fn gather_sight(strength: u8, avatar: &Avatar, stage: &Stage, state: &StageState
 
         if tiles.get(fov_position) != Tile::Unknown {
             foes.push(SightedFoe {
-                id: i,
+                id: foe.id,
                 offset,
                 foe_type: foe.foe_type,
                 alive: foe.alive(),
@@ -76,14 +271,19 @@ fn gather_sight(strength: u8, avatar: &Avatar, stage: &Stage, state: &StageState
         }
     }
 
-    let orb = state.orb.as_ref().and_then(|orb| {
-        let offset = orb.position - avatar.position;
-        let fov_position = center + offset;
-        match tiles.get(fov_position) {
-            Tile::Unknown => None,
-            _ => Some(offset),
-        }
-    });
+    let orb = state
+        .orbs
+        .iter()
+        .map(|orb| orb.position - avatar.position)
+        .filter(|offset| tiles.get(center + *offset) != Tile::Unknown)
+        .min_by_key(|offset| offset.x.abs().max(offset.y.abs()));
+
+    let shards = state
+        .shards
+        .iter()
+        .map(|shard| *shard - avatar.position)
+        .filter(|offset| tiles.get(center + *offset) != Tile::Unknown)
+        .collect();
 
     let mut allies = vec![];
     for ally in state.avatars.values() {
@@ -105,7 +305,7 @@ fn gather_sight(strength: u8, avatar: &Avatar, stage: &Stage, state: &StageState
                     ServerAction::Move(pos) => Some(pos),
                     _ => None,
                 })
-                .map(|pos| pos - avatar.position)
+                .map(|pos| pos - ally.position)
         } else {
             None
         };
@@ -121,7 +321,7 @@ fn gather_sight(strength: u8, avatar: &Avatar, stage: &Stage, state: &StageState
         allies.push(SightedAlly {
             name: avatar_tracker.map(|at| at.player_name.clone()),
             offset,
-            alive: !avatar.is_dead(),
+            alive: !ally.is_dead(),
             status,
             next_move: move_offset,
         });
@@ -132,16 +332,148 @@ fn gather_sight(strength: u8, avatar: &Avatar, stage: &Stage, state: &StageState
         foes,
         orb,
         allies,
+        shards,
+        delta: None,
+    }
+}
+
+/// Allies sharing their senses lend their sight cone. Their avatars are taken from the player's
+/// own state so that what is seen binds the player's timeline like its own sight does.
+fn share_allies_sight(
+    tiles: Tiles,
+    avatar: &Avatar,
+    stage: &Stage,
+    state: &StageState,
+    fov_cache: &mut FovCache,
+) -> Tiles {
+    let Some(player) = stage.players.get(&avatar.player_id) else {
+        return tiles;
+    };
+    if !player.share_senses {
+        return tiles;
+    }
+
+    let cones: Vec<(Offset, Tiles)> = stage
+        .players
+        .values()
+        .filter(|ally| {
+            ally.id != player.id
+                && ally.share_senses
+                && ally.turn.abs_diff(player.turn) <= TEAM_VISION_TURNS
+        })
+        .filter_map(|ally| {
+            let position = state
+                .avatars
+                .get(&ally.id)
+                .filter(|a| !a.is_dead())?
+                .position;
+            let radius = stage.last_senses(ally.id)?.sight.get() as usize;
+            if radius == 0 {
+                return None;
+            }
+            let cone = fov_cache.sight(position, radius, stage, state);
+            Some((position - avatar.position, cone.clone()))
+        })
+        .collect();
+    if cones.is_empty() {
+        return tiles;
+    }
+
+    // Large enough to hold every cone around the avatar
+    let radius = cones
+        .iter()
+        .map(|(offset, cone)| {
+            offset.x.unsigned_abs().max(offset.y.unsigned_abs()) + cone.width() / 2
+        })
+        .fold(tiles.width() / 2, usize::max);
+    let mut merged = Tiles::new(2 * radius + 1, 2 * radius + 1);
+    let center = merged.center();
+    let own = (Offset::default(), tiles);
+    for (offset, cone) in std::iter::once(&own).chain(cones.iter()) {
+        let cone_center = cone.center();
+        for ((x, y), tile) in cone.grid.indexed_iter() {
+            if *tile == Tile::Unknown {
+                continue;
+            }
+            let position = center + *offset + (Position { x, y } - cone_center);
+            if let Some(merged_tile) = merged.grid.get_mut(position.x, position.y) {
+                *merged_tile = *tile;
+            }
+        }
+    }
+    merged
+}
+
+/// Everything in the stage at its head, as seen from the center of the map
+pub fn gather_spectator(stage: &Stage) -> SightInfo {
+    let state = stage.head_state();
+    let tiles = stage.template.tiles.clone();
+    let center = tiles.center();
+
+    let foes = state
+        .foes
+        .iter()
+        .map(|foe| SightedFoe {
+            id: foe.id,
+            offset: foe.position - center,
+            foe_type: foe.foe_type,
+            alive: foe.alive(),
+        })
+        .collect();
+
+    let orb = state.nearest_orb(center).map(|orb| orb.position - center);
+    let shards = state.shards.iter().map(|shard| *shard - center).collect();
+
+    let allies = state
+        .avatars
+        .values()
+        .map(|ally| {
+            let tracker = stage.players.get(&ally.player_id);
+            let status = if let Some(tracker) = tracker {
+                SightedAllyStatus::Controlled {
+                    turn: tracker.turn,
+                    name: tracker.player_name.clone(),
+                }
+            } else {
+                SightedAllyStatus::Discarded
+            };
+
+            SightedAlly {
+                name: tracker.map(|t| t.player_name.clone()),
+                offset: ally.position - center,
+                alive: !ally.is_dead(),
+                status,
+                next_move: None,
+            }
+        })
+        .collect();
+
+    SightInfo {
+        tiles,
+        foes,
+        orb,
+        allies,
+        shards,
+        delta: None,
     }
 }
 
-fn gather_touch(avatar: &Avatar, async_stage: &Stage, state: &StageState) -> TouchInfo {
-    let tiles = fov::fov(avatar.position, 1, &async_stage.template.tiles);
+fn gather_touch(
+    strength: u8,
+    avatar: &Avatar,
+    async_stage: &Stage,
+    state: &StageState,
+    fov_cache: &mut FovCache,
+) -> TouchInfo {
+    let radius = strength as usize;
+    let tiles = fov_cache
+        .fov(avatar.position, radius, &async_stage.template.tiles)
+        .clone();
 
     let mut foes = vec![];
     let mut traps = 0;
     for foe in &state.foes {
-        if foe.alive() && foe.position.dist(&avatar.position) <= 1 {
+        if foe.alive() && foe.position.dist(&avatar.position) <= radius {
             if foe.is_trap() {
                 traps += 1;
             } else {
@@ -155,10 +487,9 @@ fn gather_touch(avatar: &Avatar, async_stage: &Stage, state: &StageState) -> Tou
         foes,
         traps,
         orb: state
-            .orb
-            .as_ref()
-            .map(|orb| orb.position.dist(&avatar.position) <= 1)
-            .unwrap_or(false),
+            .orbs
+            .iter()
+            .any(|orb| orb.position.dist(&avatar.position) <= radius),
     }
 }
 
@@ -173,6 +504,7 @@ fn gather_self(player: &StagePlayer, avatar: &Avatar, tail_state: &StageState) -
         focus: player.focus,
         hp: avatar.hp,
         hp_max,
+        attack: avatar.attack,
         turn: avatar.turns,
     }
 }
@@ -187,3 +519,102 @@ where
         Some(gather(strength))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use losig_core::{
+        sense::Senses,
+        types::{Foe, FoeType},
+    };
+
+    use super::*;
+    use crate::{
+        events::gather_events,
+        stage::tests::test_foe,
+        tiled::{MapSource, load_world},
+        world::{TransitionDestination, World},
+    };
+
+    /// A dense embedded battlefield with player 1 in it
+    fn battlefield() -> World {
+        let world = load_world(
+            &MapSource::Embedded,
+            &["battlefield_4"],
+            Box::new(|_, _, _| TransitionDestination::End),
+        )
+        .unwrap();
+        world.new_player(1, None).unwrap();
+        world
+    }
+
+    /// Counts the fov computed for a full sight + touch command on a dense battlefield, which
+    /// computed 3 fov (sight, touch and sight again for events) before the cache.
+    #[test]
+    fn fov_cache_test() {
+        let world = battlefield();
+
+        let stage = &world.stages[0].lock().unwrap();
+        let state = stage.state_for(1).unwrap();
+        let senses = Senses {
+            sight: BoundedU8::new(10).unwrap(),
+            touch: BoundedU8::new(1).unwrap(),
+            ..Default::default()
+        };
+
+        let mut fov_cache = FovCache::default();
+        let info = gather(&senses, stage, 1, &mut fov_cache);
+        gather_events(&senses, stage, &state, 1, &mut fov_cache);
+
+        assert!(info.sight.is_some() && info.touch.is_some());
+        assert_eq!(fov_cache.computed(), 2);
+    }
+
+    #[test]
+    fn danger_test() {
+        let world = battlefield();
+        let stage = &world.stages[0].lock().unwrap();
+        let mut state = stage.state_for(1).unwrap();
+        let avatar = state.avatars[&1].clone();
+
+        // Traps are threats too, the dead and the far away are not
+        let foe = |foe_type, dx, hp| Foe {
+            hp,
+            ..test_foe(foe_type, 0, avatar.position.x + dx, avatar.position.y)
+        };
+        state.foes = vec![
+            foe(FoeType::Dummy, 1, 3),
+            foe(FoeType::Trap, 2, 1),
+            foe(FoeType::Dummy, 2, 0),
+            foe(FoeType::Dummy, 3, 3),
+        ];
+        assert_eq!(gather_danger(1, &avatar, &state).count, 2);
+        assert_eq!(gather_danger(3, &avatar, &state).count, 3);
+    }
+
+    /// Allies are dead or alive from their own hp, whoever sees them
+    #[test]
+    fn dead_ally_test() {
+        let world = battlefield();
+        world.new_player(2, None).unwrap();
+        let stage = &world.stages[0].lock().unwrap();
+        let mut state = stage.state_for(2).unwrap();
+        let avatar = state.avatars[&1].clone();
+        let next_to = [(1, 0), (-1, 0), (0, 1), (0, -1)]
+            .map(|(x, y)| avatar.position + Offset { x, y })
+            .into_iter()
+            .find(|position| !stage.template.tiles.get(*position).opaque())
+            .unwrap();
+        let ally = state.avatars.get_mut(&2).unwrap();
+        ally.position = next_to;
+        ally.hp = 0;
+
+        let sight = gather_sight(3, &avatar, stage, &state, &mut FovCache::default());
+        let name = &stage.players[&2].player_name;
+        let ally = sight
+            .allies
+            .iter()
+            .find(|ally| ally.name.as_ref() == Some(name))
+            .unwrap();
+        assert!(!ally.alive);
+    }
+}
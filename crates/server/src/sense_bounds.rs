@@ -15,7 +15,8 @@ use crate::stage::StageState;
 
 #[derive(Clone, Default, Debug)]
 pub struct SenseBounds {
-    pub avatars: BTreeMap<PlayerId, MaxHpBound>,
+    /// Keyed by the witnessed avatar and the witness
+    pub avatars: BTreeMap<(PlayerId, PlayerId), MaxHpBound>,
 
     pub death_bounds: BTreeMap<FoeId, DeathBound>,
     pub position_bounds: BTreeMap<(FoeId, PlayerId), PositionBound>,
@@ -30,7 +31,7 @@ impl SenseBounds {
         selfi: &SelfInfo,
     ) {
         self.avatars.insert(
-            avatar_id,
+            (avatar_id, pid),
             MaxHpBound {
                 value: selfi.hp,
                 turn,
@@ -67,9 +68,62 @@ impl SenseBounds {
         }
     }
 
+    /// Allies seen alive must stay so. Only needed when avatars can hurt each other.
+    pub fn add_ally_bounds(
+        &mut self,
+        avatar: &Avatar,
+        turn: StageTurn,
+        sight: &SightInfo,
+        state: &StageState,
+    ) {
+        for ally in sight.allies.iter().filter(|ally| ally.alive) {
+            let position = avatar.position + ally.offset;
+            let Some(ally_avatar) = state
+                .avatars
+                .values()
+                .find(|a| a.player_id != avatar.player_id && a.position == position)
+            else {
+                continue;
+            };
+            self.avatars.insert(
+                (ally_avatar.player_id, avatar.player_id),
+                MaxHpBound {
+                    value: 1,
+                    turn,
+                    source: avatar.player_id,
+                },
+            );
+        }
+    }
+
+    /// Lowest health the avatar can be left with at this turn, for it has been witnessed with at
+    /// least that much at this turn or later
+    pub fn witnessed_hp(&self, avatar_id: PlayerId, turn: StageTurn) -> u8 {
+        self.avatars
+            .iter()
+            .filter(|((id, _), bound)| *id == avatar_id && bound.turn >= turn)
+            .map(|(_, bound)| bound.value)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Whether someone else than the player witnessed the avatar at this turn or later
+    pub fn witnessed_by_others(&self, avatar_id: PlayerId, pid: PlayerId, turn: StageTurn) -> bool {
+        self.avatars
+            .iter()
+            .any(|((id, _), bound)| *id == avatar_id && bound.source != pid && bound.turn >= turn)
+    }
+
+    /// Whether the foe has been seen alive at this turn or later
+    pub fn witnessed_alive(&self, foe_id: FoeId, turn: StageTurn) -> bool {
+        self.position_bounds
+            .iter()
+            .any(|((id, _), bound)| *id == foe_id && bound.turn >= turn)
+    }
+
     pub fn enforce(&self, state: &mut StageState) {
         // Enforce avatar HP bounds
-        for (avatar_id, hp_bound) in &self.avatars {
+        for ((avatar_id, _), hp_bound) in &self.avatars {
             if let Some(avatar) = state.avatars.get_mut(avatar_id) {
                 hp_bound.enforce(state.turn, avatar);
             }
@@ -77,7 +131,7 @@ impl SenseBounds {
 
         // Enforce foe death bounds
         for (foe_id, death_bound) in &self.death_bounds {
-            if let Some(foe) = state.foes.get_mut(*foe_id)
+            if let Some(foe) = state.foes.iter_mut().find(|foe| foe.id == *foe_id)
                 && death_bound.enforce(state.turn, foe) {
                     state.events.add(GameEventSource {
                         senses: EventSenses::All,
@@ -89,7 +143,7 @@ impl SenseBounds {
 
         // Enforce foe position bounds
         for ((foe_id, _player_id), position_bound) in &self.position_bounds {
-            if let Some(foe) = state.foes.get_mut(*foe_id)
+            if let Some(foe) = state.foes.iter_mut().find(|foe| foe.id == *foe_id)
                 && position_bound.enforce(state.turn, foe) {
                     state.events.add(GameEventSource {
                         senses: EventSenses::All,
@@ -105,6 +159,17 @@ impl SenseBounds {
         self.death_bounds.retain(|_, bound| bound.source != pid);
         self.position_bounds.retain(|_, bound| bound.source != pid);
     }
+
+    /// Forgets what the player witnessed on a turn it took back
+    pub fn release_turn(&mut self, pid: PlayerId, turn: StageTurn) {
+        let kept = |source: PlayerId, bound_turn: StageTurn| source != pid || bound_turn != turn;
+        self.avatars
+            .retain(|_, bound| kept(bound.source, bound.turn));
+        self.death_bounds
+            .retain(|_, bound| kept(bound.source, bound.turn));
+        self.position_bounds
+            .retain(|_, bound| kept(bound.source, bound.turn));
+    }
 }
 
 /// The target health has been witnessed
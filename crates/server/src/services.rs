@@ -1,19 +1,36 @@
+use crate::game_log::GameLog;
+#[cfg(feature = "metrics")]
+use crate::metrics::Metrics;
+use crate::tiled::MapSource;
 use crate::world::World;
-use crate::ws_server::ServerMessageWithRecipient;
+use crate::ws_server::{Recipient, ServerMessageWithRecipient};
 
 use std::sync::mpsc::Sender;
 
-use losig_core::leaderboard::Leaderboard;
+use log::{error, info};
+use losig_core::{leaderboard::Leaderboard, network::ServerMessage};
 
 use std::sync::Mutex;
 
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 
 #[derive(Clone)]
 pub struct Services {
-    pub world: Arc<Mutex<World>>,
+    pub world: Arc<World>,
     pub leaderboard: Arc<Mutex<Leaderboard>>,
     pub sender: Sender<ServerMessageWithRecipient>,
+    pub game_log: GameLog,
+    /// Commands are refused while paused, for maintenance
+    pub paused: Arc<AtomicBool>,
+    /// Secret allowing tools to inspect the stages, inspection is disabled without one
+    pub admin_token: Option<String>,
+    /// Players may warp to any stage, for testing
+    pub allow_warp: bool,
+    /// Where the maps swapped in by admins are read from
+    pub map_source: MapSource,
+    #[cfg(feature = "metrics")]
+    pub metrics: Arc<Metrics>,
 }
 
 impl Services {
@@ -23,9 +40,91 @@ impl Services {
         sender: Sender<ServerMessageWithRecipient>,
     ) -> Self {
         Services {
-            world: Arc::new(Mutex::new(world)),
+            world: Arc::new(world),
             leaderboard: Arc::new(Mutex::new(leaderboard)),
             sender,
+            game_log: Default::default(),
+            paused: Default::default(),
+            admin_token: None,
+            allow_warp: false,
+            map_source: MapSource::default(),
+            #[cfg(feature = "metrics")]
+            metrics: Default::default(),
         }
     }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Acquire)
+    }
+
+    pub fn is_admin(&self, token: &str) -> bool {
+        self.admin_token.as_deref() == Some(token)
+    }
+
+    /// Freezes or resumes the simulation and tells every client. Nobody could play during the
+    /// pause, so nobody counts as idle for it.
+    pub fn set_paused(&self, paused: bool) {
+        if self.paused.swap(paused, Ordering::AcqRel) == paused {
+            return;
+        }
+        if !paused {
+            self.world.refresh_activity();
+        }
+        info!("Simulation {}", if paused { "paused" } else { "resumed" });
+        let msg = ServerMessageWithRecipient {
+            recipient: Recipient::Broadcast,
+            message: ServerMessage::Paused(paused),
+        };
+        if let Err(e) = self.sender.send(msg) {
+            error!("Could not notify pause: {e}");
+        }
+    }
+
+    /// Tells the clients the server is stopping and gives a last chance to save state
+    pub fn shutdown(&self) {
+        let msg = ServerMessageWithRecipient {
+            recipient: Recipient::Broadcast,
+            message: ServerMessage::ServerShutdown,
+        };
+        if let Err(e) = self.sender.send(msg) {
+            error!("Could not notify shutdown: {e}");
+        }
+
+        // Nothing is persisted yet, the leaderboard is lost
+        let leaderboard = self.leaderboard.lock().unwrap();
+        info!(
+            "Shutting down with {} leaderboard entries",
+            leaderboard.top_entries(usize::MAX).len()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::mpsc,
+        time::{Duration, Instant},
+    };
+
+    use super::*;
+    use crate::tiled::{MapSource, load_arena};
+
+    #[test]
+    fn pause_test() {
+        let world = load_arena(&MapSource::Embedded).unwrap();
+        world.new_player(1, None).unwrap();
+        let (sender, _receiver) = mpsc::channel();
+        let services = Services::new(world, Leaderboard::new(), sender);
+        let timeout = Duration::from_secs(60);
+        let idle_since = |services: &Services, since| {
+            let mut stage = services.world.stages[0].lock().unwrap();
+            stage.players.get_mut(&1).unwrap().last_activity = since;
+        };
+
+        // The pause lasts longer than players may stay idle
+        services.set_paused(true);
+        idle_since(&services, Instant::now() - timeout * 2);
+        services.set_paused(false);
+        assert!(services.world.retire_idle(timeout).is_empty());
+    }
 }
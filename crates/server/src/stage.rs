@@ -1,15 +1,19 @@
-use std::collections::{BTreeMap, BTreeSet};
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, BTreeSet},
+    time::{Duration, Instant},
+};
 
 use anyhow::{Result, anyhow};
-use log::warn;
+use log::{info, warn};
 use losig_core::{
     events::{GEvent, GameEvent, Target},
-    fov,
-    sense::{Senses, SensesInfo},
+    fov::{self, VisibilitySet},
+    sense::{SenseType, Senses, SensesInfo, SightDelta},
     types::{
-        Avatar, AvatarId, ClientAction, FOCUS_MAX, Foe, HP_MAX, MAX_WITHOUT_PLAY, Offset, Orb,
-        PlayerId, Position, ServerAction, StageTurn, TURN_FOR_HP_REGEN, Tile, Timeline,
-        TimelineType, Transition, Turn,
+        Avatar, AvatarId, ClientAction, DISARM_COST, FOCUS_MAX, Foe, FoeId, HP_LOW, HP_MAX,
+        MAX_WITHOUT_PLAY, Offset, Orb, PlayerId, Position, REST_MAX, ServerAction, StageTurn, Tile,
+        Tiles, Timeline, TimelineType, Transition, Turn, UNDO_COST,
     },
 };
 
@@ -17,7 +21,7 @@ use crate::{
     action,
     events::{EventSenses, EventSource, GameEventSource, gather_events},
     foes,
-    sense::gather,
+    sense::{FovCache, gather},
     sense_bounds::SenseBounds,
     world::{Limbo, Player, StageTemplate},
 };
@@ -29,6 +33,8 @@ pub struct Stage {
      */
     pub template: StageTemplate,
     seed: u64,
+    /// Whether the seed survives a reset
+    fixed_seed: bool,
 
     /*
      * Rollback handling
@@ -38,28 +44,88 @@ pub struct Stage {
     states: BTreeMap<Turn, StageState>,
     pub diffs: Vec<TurnDiff>,
     pub bounds: SenseBounds,
+    /// Number of turns replayed by the last rollback
+    pub last_rollback_depth: Turn,
+    pub rollback_stats: RollbackStats,
+    /// Rollbacks replaying at least this many turns are logged
+    pub log_rollbacks: Option<Turn>,
+}
+
+/// Upper bounds of the rollback depth buckets, deeper rollbacks land in a last bucket
+pub const ROLLBACK_BUCKETS: [Turn; 8] = [0, 1, 2, 5, 10, 20, 50, 100];
+
+/// Distribution of the turns replayed by the rollbacks of a stage, one per command
+#[derive(Debug, Clone, Default)]
+pub struct RollbackStats {
+    /// Rollbacks by bucket, not cumulative
+    pub buckets: [u64; ROLLBACK_BUCKETS.len() + 1],
+    pub count: u64,
+    /// Turns replayed by all the rollbacks
+    pub sum: u64,
+    /// Most states kept at once after a rollback
+    pub max_states: usize,
+}
+
+impl RollbackStats {
+    fn record(&mut self, depth: Turn, states: usize) {
+        let bucket = ROLLBACK_BUCKETS
+            .iter()
+            .position(|bound| depth <= *bound)
+            .unwrap_or(ROLLBACK_BUCKETS.len());
+        self.buckets[bucket] += 1;
+        self.count += 1;
+        self.sum += depth;
+        self.max_states = self.max_states.max(states);
+    }
 }
 
 impl Stage {
     pub fn new(stage: StageTemplate) -> Self {
+        Self::build(stage, rand::random(), false)
+    }
+
+    /// Stage whose orbs always spawn the same way, even after a reset
+    pub fn new_with_seed(stage: StageTemplate, seed: u64) -> Self {
+        Self::build(stage, seed, true)
+    }
+
+    /// Stage with a fixed seed, so that tests are reproducible
+    #[cfg(test)]
+    pub fn from_template_for_test(stage: StageTemplate) -> Self {
+        Self::new_with_seed(stage, 0)
+    }
+
+    fn build(stage: StageTemplate, seed: u64, fixed_seed: bool) -> Self {
         let head_turn: Turn = 0;
         let avatars = Default::default();
-        let seed: u64 = rand::random();
 
         let mut new = Self {
             template: stage,
             seed,
+            fixed_seed,
             head_turn,
             players: Default::default(),
             states: Default::default(),
             diffs: vec![TurnDiff::default()],
             bounds: Default::default(),
+            last_rollback_depth: 0,
+            rollback_stats: Default::default(),
+            log_rollbacks: None,
         };
 
         let state = StageState {
             turn: head_turn,
             foes: new.template.foes.clone(),
-            orb: orb_spawn(&new, head_turn),
+            next_foe_id: new
+                .template
+                .foes
+                .iter()
+                .chain(new.template.waves.iter().flat_map(|wave| wave.foes.iter()))
+                .map(|foe| foe.id + 1)
+                .max()
+                .unwrap_or(0),
+            orbs: orb_spawns(&new, head_turn),
+            shards: new.template.shards.clone(),
             avatars,
             player: None,
             events: EventManager::default(),
@@ -72,20 +138,35 @@ impl Stage {
         self.states.last_key_value().unwrap().1
     }
 
+    /// States kept for the rollbacks
+    #[cfg(any(feature = "metrics", test))]
+    pub fn kept_states(&self) -> usize {
+        self.states.len()
+    }
+
     pub fn tail_state(&self) -> &StageState {
         self.states.first_key_value().unwrap().1
     }
 
+    pub fn state_at(&self, turn: Turn) -> Option<&StageState> {
+        self.states.get(&turn)
+    }
+
     pub fn state_for(&self, aid: PlayerId) -> Option<StageState> {
         let tracker = self.players.get(&aid)?;
         Some(self.states.get(&tracker.turn)?.clone())
     }
 
     pub fn add_player(&mut self, player: &Player, senses: Senses) -> Result<StageCommandResult> {
+        // Picked once on the head, the rollbacks to come must not move the avatar elsewhere
+        let spawn = self.pick_spawn(player.id, self.head_state());
         self.players
             .insert(player.id, StagePlayer::new(player, self.head_turn));
+        if let Some(focus) = self.template.start_focus {
+            self.players.get_mut(&player.id).unwrap().focus = focus.min(FOCUS_MAX);
+        }
 
-        self.player_turn(player.id, ServerAction::Enter, senses)
+        self.player_turn(player.id, ServerAction::Enter, senses, Some(spawn))
     }
 
     pub fn remove_player(&mut self, pid: PlayerId) -> Option<()> {
@@ -101,15 +182,264 @@ impl Stage {
         action: ClientAction,
         senses: Senses,
     ) -> Result<StageCommandResult> {
+        if let Some(player) = self.players.get_mut(&pid) {
+            player.auto_waited = 0;
+        }
+        match action {
+            ClientAction::Rest(turns) => return self.rest(pid, turns, senses),
+            ClientAction::Undo => return self.undo(pid),
+            ClientAction::Sense => return self.resense(pid, senses),
+            _ => {}
+        }
         let action = action::convert_client(action, self, pid);
-        self.player_turn(pid, action, senses)
+        self.player_turn(pid, action, senses, None)
+    }
+
+    /// Waits several turns, stopping as soon as a foe is in sight or the avatar is hurt. Returns
+    /// the last turn with the events of every turn.
+    fn rest(&mut self, pid: PlayerId, turns: u16, senses: Senses) -> Result<StageCommandResult> {
+        let hp = |stage: &Stage| {
+            stage
+                .state_for(pid)
+                .and_then(|state| state.avatars.get(&pid).map(|avatar| avatar.hp))
+        };
+
+        let mut events = vec![];
+        let mut limbos = vec![];
+        let mut focus_spent = 0;
+        let mut turns_played = 0;
+        let mut last_hp = hp(self);
+        let mut result;
+        let mut turns_left = turns.clamp(1, REST_MAX);
+        loop {
+            result = self.player_turn(pid, ServerAction::Wait, senses.clone(), None)?;
+            events.append(&mut result.events);
+            limbos.append(&mut result.limbos);
+            focus_spent += result.focus_spent;
+            turns_played += result.turns_played;
+            turns_left -= 1;
+
+            let current_hp = hp(self);
+            let hurt = current_hp.is_none() || current_hp < last_hp;
+            last_hp = current_hp;
+            let foe_in_sight = result
+                .senses_info
+                .as_ref()
+                .and_then(|info| info.sight.as_ref())
+                .is_some_and(|sight| sight.foes.iter().any(|foe| foe.alive));
+            let left = result.transition.is_some()
+                || self.players.get(&pid).is_none_or(|player| player.limbo);
+
+            if turns_left == 0 || hurt || foe_in_sight || left {
+                break;
+            }
+        }
+
+        result.events = events;
+        result.limbos = limbos;
+        result.focus_spent = focus_spent;
+        result.turns_played = turns_played;
+        Ok(result)
     }
 
+    /// Takes back the last turn of the player. The turn before must still be in the history,
+    /// and the turn can't be undone if it killed a foe an ally has seen dead since.
+    fn undo(&mut self, pid: PlayerId) -> Result<StageCommandResult> {
+        let mut player = self
+            .players
+            .get(&pid)
+            .ok_or_else(|| anyhow!("Could not find player"))?
+            .clone();
+        let turn = player.turn;
+
+        if self.template.timeline_type == TimelineType::Immediate {
+            return Err(anyhow!("Turns can't be undone on an immediate timeline"));
+        }
+        if turn <= self.tail_turn() {
+            return Err(anyhow!("Turn {turn} is too old to be undone"));
+        }
+        let index = self.diff_index(turn);
+        let cmd = self.diffs[index]
+            .get_avatar_diff(pid)
+            .ok_or_else(|| anyhow!("No command of {pid} at turn {turn}"))?;
+        if cmd.action == ServerAction::Enter || cmd.leaves {
+            return Err(anyhow!("Entering or leaving the stage can't be undone"));
+        }
+        if let ServerAction::Attack(foe_id) | ServerAction::Disarm(foe_id) = cmd.action
+            && self
+                .bounds
+                .death_bounds
+                .get(&foe_id)
+                .is_some_and(|bound| bound.source != pid)
+        {
+            return Err(anyhow!("Foe {foe_id} has been seen dead by an ally"));
+        }
+        if let ServerAction::AttackAvatar(target_id) = cmd.action
+            && self.bounds.witnessed_by_others(target_id, pid, turn)
+        {
+            return Err(anyhow!("Avatar {target_id} has been seen after the attack"));
+        }
+        if player.focus < UNDO_COST {
+            return Err(anyhow!("Not enough focus to undo"));
+        }
+
+        // Forget the command and what the player witnessed with it
+        self.diffs[index]
+            .cmd_by_avatar
+            .retain(|(aid, _)| *aid != pid);
+        self.bounds.release_turn(pid, turn);
+        player.cooldowns.retain(|(_, used)| *used != turn);
+        let others_on_turn = self.players.values().any(|p| p.id != pid && p.turn == turn);
+        if !others_on_turn {
+            self.states.remove(&turn);
+            if turn == self.head_turn {
+                self.diffs.pop();
+                self.head_turn -= 1;
+            }
+        }
+
+        player.turn -= 1;
+        player.focus -= UNDO_COST;
+        player.last_activity = Instant::now();
+        let stage_turn = player.turn;
+        if !self.states.contains_key(&stage_turn) {
+            let state = self.replay_to(stage_turn);
+            self.states.insert(stage_turn, state);
+        }
+        self.players.insert(pid, player);
+
+        // Rollback
+        self.rollback_from(stage_turn);
+
+        let senses = self.last_senses(pid).cloned().unwrap_or_default();
+        let action = self.diffs[self.diff_index(stage_turn)]
+            .get_avatar_diff(pid)
+            .map(|cmd| cmd.action)
+            .unwrap_or(ServerAction::Wait);
+        let info = self.gather_info(pid, &senses)?;
+        if let Some(avatar) = self.states[&stage_turn].avatars.get(&pid).cloned() {
+            self.bind_states(stage_turn, &avatar, &info);
+        }
+
+        let limbos = self.handle_limbo();
+        self.clean_history();
+
+        Ok(StageCommandResult {
+            stage_turn,
+            limbos,
+            events: vec![],
+            senses_info: Some(info),
+            action,
+            transition: None,
+            timeline: self.timeline(),
+            focus_spent: UNDO_COST as u64,
+            turns_played: 0,
+        })
+    }
+
+    /// Lowers the senses still cooling down from an earlier turn, returning them
+    fn lower_cooling_senses(
+        &self,
+        player: &StagePlayer,
+        turn: StageTurn,
+        senses: &mut Senses,
+    ) -> Vec<SenseType> {
+        let mut lowered = vec![];
+        for cooldown in &self.template.cooldowns {
+            let cooling = player.cooldowns.iter().any(|(sense, used)| {
+                *sense == cooldown.sense
+                    && *used < turn
+                    && turn <= used + cooldown.turns as StageTurn
+            });
+            if cooling && senses.strength(cooldown.sense) >= cooldown.strength {
+                senses.lower(cooldown.sense, cooldown.strength - 1);
+                lowered.push(cooldown.sense);
+            }
+        }
+        lowered
+    }
+
+    /// Senses used at their cooldown strength start cooling down from this turn
+    fn start_cooldowns(&self, player: &mut StagePlayer, turn: StageTurn, senses: &Senses) {
+        for cooldown in &self.template.cooldowns {
+            if senses.strength(cooldown.sense) >= cooldown.strength {
+                player
+                    .cooldowns
+                    .retain(|(sense, _)| *sense != cooldown.sense);
+                player.cooldowns.push((cooldown.sense, turn));
+            }
+        }
+    }
+
+    /// Senses the current turn of the player again. The senses are merged with the ones of its
+    /// command and only the senses it did not pay for yet cost focus.
+    fn resense(&mut self, pid: PlayerId, mut senses: Senses) -> Result<StageCommandResult> {
+        let mut player = self
+            .players
+            .get(&pid)
+            .ok_or_else(|| anyhow!("Could not find player"))?
+            .clone();
+        let stage_turn = player.turn;
+        let index = self.diff_index(stage_turn);
+        let cmd = self.diffs[index]
+            .get_avatar_diff(pid)
+            .ok_or_else(|| anyhow!("No command of {pid} at turn {stage_turn}"))?;
+        if cmd.leaves {
+            return Err(anyhow!("Player {pid} left the stage at turn {stage_turn}"));
+        }
+
+        senses.restrict_to(&self.template.senses);
+        senses.cap(self.template.max_sight, self.template.max_hearing);
+        let cooling = self.lower_cooling_senses(&player, stage_turn, &mut senses);
+        let merged = cmd.senses.clone().merge(senses);
+        // Exhausted players did not pay for the senses of their command
+        let paid = if player.exhausted {
+            0
+        } else {
+            cmd.senses.cost(&self.template.costs)
+        };
+        let focus_cost = merged.cost(&self.template.costs).saturating_sub(paid);
+        if !player.spend_focus(focus_cost) {
+            return Err(anyhow!("Not enough focus to sense again"));
+        }
+        let action = cmd.action;
+
+        player.exhausted = false;
+        player.last_activity = Instant::now();
+        self.start_cooldowns(&mut player, stage_turn, &merged);
+        self.players.insert(pid, player);
+        self.diffs[index]
+            .cmd_by_avatar
+            .iter_mut()
+            .filter(|(aid, _)| *aid == pid)
+            .for_each(|(_, cmd)| cmd.senses = merged.clone());
+
+        // Senses don't change the states, only what the player is bound to
+        let info = self.gather_info(pid, &merged)?;
+        if let Some(avatar) = self.states[&stage_turn].avatars.get(&pid).cloned() {
+            self.bind_states(stage_turn, &avatar, &info);
+        }
+
+        Ok(StageCommandResult {
+            stage_turn,
+            limbos: vec![],
+            events: cooldown_events(cooling),
+            senses_info: Some(info),
+            action,
+            transition: None,
+            timeline: self.timeline(),
+            focus_spent: focus_cost as u64,
+            turns_played: 0,
+        })
+    }
+
+    /// Plays the next turn of the player, entering avatars appearing on the given spawn
     pub fn player_turn(
         &mut self,
         pid: PlayerId,
-        action: ServerAction,
+        mut action: ServerAction,
         mut senses: Senses,
+        spawn: Option<Position>,
     ) -> Result<StageCommandResult> {
         let mut player = self
             .players
@@ -132,16 +462,30 @@ impl Stage {
 
         // Senses filtering
         senses.restrict_to(&self.template.senses);
+        senses.cap(self.template.max_sight, self.template.max_hearing);
+        let next_turn = player.turn + 1;
+        let cooling = self.lower_cooling_senses(&player, next_turn, &mut senses);
+
+        player.last_activity = Instant::now();
 
         // Focus handling
-        player.focus = (player.focus + self.template.fp_regen as u8).min(FOCUS_MAX);
-        let focus_cost = senses.cost();
-        let has_focus = focus_cost <= player.focus;
+        player.regen_focus(self.template.fp_regen);
+        let focus_cost = senses.cost(&self.template.costs);
+        let has_focus = player.spend_focus(focus_cost);
         if has_focus {
-            player.focus -= focus_cost;
+            self.start_cooldowns(&mut player, next_turn, &senses);
         } else {
             senses = Senses::default();
         }
+        // Only notify on the turn the player becomes exhausted
+        let newly_exhausted = !has_focus && !player.exhausted;
+        player.exhausted = !has_focus;
+        // Disarming needs the focus left once senses are paid for
+        if let ServerAction::Disarm(_) = action
+            && !player.spend_focus(DISARM_COST)
+        {
+            action = ServerAction::Wait;
+        }
 
         // Diff handling
         player.turn += 1;
@@ -150,6 +494,7 @@ impl Stage {
             action,
             senses: senses.clone(),
             leaves: false,
+            spawn,
         };
 
         // Add turns to head if player is at the head
@@ -170,6 +515,7 @@ impl Stage {
                 for (other_id, mut cmd) in previous_diff.cmd_by_avatar {
                     cmd.action = ServerAction::Wait;
                     cmd.leaves = false;
+                    cmd.spawn = None;
                     self.diffs[diff_index].cmd_by_avatar.push((other_id, cmd));
                 }
 
@@ -186,7 +532,7 @@ impl Stage {
 
         // Update state based on diff
         state.player = Some(player);
-        self.enact_turn(&mut state, turn_diff);
+        self.enact_turn(&mut state, turn_diff, &mut VisibilitySet::default());
         let mut player = state.player.take().unwrap();
         let transition = player.transition.take();
         let avatar;
@@ -200,6 +546,10 @@ impl Stage {
             avatar = state.avatars.remove(&pid).unwrap();
         } else {
             avatar = state.avatars[&pid].clone();
+            // Pylons recharge the avatar ending its turn next to them
+            if self.near_pylon(avatar.position) {
+                player.regen_focus(self.template.pylon_regen);
+            }
         }
 
         // Insert state back
@@ -209,16 +559,21 @@ impl Stage {
         self.players.insert(pid, player);
 
         // Gather info, update bounds
-        let (info, events) = if has_focus && transition.is_none() {
-            let info = gather(&senses, self, pid);
+        let (info, mut events) = if has_focus && transition.is_none() {
+            let mut fov_cache = FovCache::default();
+            let info = gather(&senses, self, pid, &mut fov_cache);
 
             let state = &self.states[&stage_turn];
-            let events = gather_events(&senses, self, state, pid);
+            let events = gather_events(&senses, self, state, pid, &mut fov_cache);
             self.bind_states(stage_turn, &avatar, &info);
             (Some(info), events)
+        } else if newly_exhausted {
+            let exhausted = GEvent::new(vec![SenseType::SelfSense], GameEvent::Exhausted);
+            (None, vec![exhausted])
         } else {
             (None, vec![])
         };
+        events.extend(cooldown_events(cooling));
 
         // Rollback
         self.rollback_from(stage_turn);
@@ -237,12 +592,15 @@ impl Stage {
             action,
             transition,
             timeline: self.timeline(),
+            focus_spent: if has_focus { focus_cost as u64 } else { 0 },
+            turns_played: 1,
         })
     }
 
     /// Recomputes the states from the given turn and forward
     fn rollback_from(&mut self, turn: StageTurn) -> Option<()> {
         let mut state = self.states[&turn].clone();
+        self.last_rollback_depth = self.head_turn - turn;
 
         let mut turns_to_save = self
             .players
@@ -252,23 +610,51 @@ impl Stage {
 
         turns_to_save.insert(self.head_turn);
 
+        let mut visible = VisibilitySet::default();
         for turn in (turn + 1)..(self.head_turn + 1) {
             let index = self.diff_index(turn);
             let diff = &self.diffs[index];
-            self.enact_turn(&mut state, diff);
+            self.enact_turn(&mut state, diff, &mut visible);
             if turns_to_save.contains(&turn) {
                 self.states.insert(turn, state.clone());
             }
         }
 
+        let depth = self.last_rollback_depth;
+        self.rollback_stats.record(depth, self.states.len());
+        if self.log_rollbacks.is_some_and(|min| depth >= min) {
+            info!(
+                "Stage {} replayed {depth} turns, keeping {} states",
+                self.template.id,
+                self.states.len()
+            );
+        }
+
         Some(())
     }
 
+    /// Computes the state of a turn that was not kept, from the closest kept one before it
+    fn replay_to(&self, turn: StageTurn) -> StageState {
+        let (&from, state) = self.states.range(..=turn).next_back().unwrap();
+        let mut state = state.clone();
+        let mut visible = VisibilitySet::default();
+        for turn in (from + 1)..(turn + 1) {
+            self.enact_turn(&mut state, &self.diffs[self.diff_index(turn)], &mut visible);
+        }
+        state
+    }
+
     pub fn diff_index(&self, turn: StageTurn) -> usize {
         let turn_diff = self.head_turn - turn;
         self.diffs.len() - 1 - turn_diff as usize
     }
 
+    fn near_pylon(&self, position: Position) -> bool {
+        (-1..2).any(|x| {
+            (-1..2).any(|y| self.template.tiles.get(position + Offset { x, y }) == Tile::Pylon)
+        })
+    }
+
     /// Remove old states that are no more used: e.g. turns older than the earliest avatar turn
     fn clean_history(&mut self) {
         if let Some(oldest_turn) = self.players.values().map(|tr| tr.turn).min() {
@@ -279,34 +665,148 @@ impl Stage {
         }
     }
 
+    /// Players who didn't play for at least `timeout`
+    pub fn idle_players(&self, timeout: Duration) -> Vec<PlayerId> {
+        self.players
+            .values()
+            .filter(|player| player.last_activity.elapsed() >= timeout)
+            .map(|player| player.id)
+            .collect()
+    }
+
+    /// Players idle for long enough to be made to wait a turn
+    pub fn auto_wait_players(&self) -> Vec<PlayerId> {
+        let Some(timeout) = self.template.auto_wait else {
+            return vec![];
+        };
+        self.players
+            .values()
+            .filter(|player| !player.limbo && player.auto_waited < self.template.auto_wait_turns)
+            .filter(|player| player.last_activity.elapsed() >= timeout * (player.auto_waited + 1))
+            .map(|player| player.id)
+            .collect()
+    }
+
+    /// Plays a Wait for an idle player with its last senses. It is still idle afterwards, so that it
+    /// can be retired.
+    pub fn auto_wait(&mut self, pid: PlayerId) -> Result<StageCommandResult> {
+        let last_activity = self
+            .players
+            .get(&pid)
+            .ok_or_else(|| anyhow!("Could not find player"))?
+            .last_activity;
+        let senses = self.last_senses(pid).cloned().unwrap_or_default();
+        let result = self.player_turn(pid, ServerAction::Wait, senses, None)?;
+        if let Some(player) = self.players.get_mut(&pid) {
+            player.last_activity = last_activity;
+            player.auto_waited += 1;
+        }
+        Ok(result)
+    }
+
     pub fn reset(&mut self) {
-        *self = Self::new(self.template.clone());
+        self.reset_with(self.template.clone());
+    }
+
+    /// Starts over on the given map. The seed, the rollback stats and their logging are kept.
+    pub fn reset_with(&mut self, template: StageTemplate) {
+        let mut stage = if self.fixed_seed {
+            Self::new_with_seed(template, self.seed)
+        } else {
+            Self::new(template)
+        };
+        stage.rollback_stats = std::mem::take(&mut self.rollback_stats);
+        stage.log_rollbacks = self.log_rollbacks;
+        *self = stage;
+    }
+
+    /// Replaces the sight tiles by their changes since the last turn sent to the player, when
+    /// that is lighter
+    pub fn compress_sight(&mut self, pid: PlayerId, stage_turn: StageTurn, info: &mut SensesInfo) {
+        let Some(sight) = info.sight.as_mut() else {
+            return;
+        };
+        let Some(player) = self.players.get_mut(&pid) else {
+            return;
+        };
+        let Some(position) = self
+            .states
+            .get(&player.turn)
+            .and_then(|state| state.avatars.get(&pid))
+            .map(|avatar| avatar.position)
+        else {
+            return;
+        };
+
+        let delta = player
+            .sent_sight
+            .as_ref()
+            .and_then(|(turn, base_position, base)| {
+                SightDelta::between(*turn, base, position - *base_position, &sight.tiles)
+            });
+        player.sent_sight = Some((stage_turn, position, sight.tiles.clone()));
+        if delta.is_some() {
+            sight.tiles = Tiles::default();
+            sight.delta = delta;
+        }
     }
 
     fn gather_info(&self, pid: PlayerId, senses: &Senses) -> Result<SensesInfo> {
-        Ok(gather(senses, self, pid))
+        Ok(gather(senses, self, pid, &mut FovCache::default()))
     }
 
     /// Update a state based on the diff
-    fn enact_turn(&self, state: &mut StageState, diff: &TurnDiff) {
+    /// Plays a turn over the state. The set is a buffer for the fov computations, shared by the
+    /// turns of a rollback.
+    fn enact_turn(&self, state: &mut StageState, diff: &TurnDiff, visible: &mut VisibilitySet) {
         state.turn += 1;
         for avatar in state.avatars.values_mut() {
             avatar.turns_not_played += 1;
         }
         state.events.clear();
         // Turn init
-        if let Some(ref mut orb) = state.orb
-            && orb.excited
-        {
-            state.orb = orb_spawn(self, state.turn);
+        for (i, orb) in state.orbs.iter_mut().enumerate() {
+            if !orb.excited {
+                continue;
+            }
+            if orb.countdown > 1 {
+                orb.countdown -= 1;
+            } else if let Some(respawn) = orb_spawn(self, state.turn, i) {
+                *orb = respawn;
+            }
         }
 
-        self.enact_avatars(state, diff);
-        self.enact_foes(state, &self.bounds);
+        let hps: Vec<_> = state
+            .avatars
+            .iter()
+            .map(|(aid, avatar)| (*aid, avatar.hp))
+            .collect();
+
+        self.enact_avatars(state, diff, visible);
+        self.enact_foes(state, &self.bounds, visible);
         self.welcome_avatar(state, diff);
+        self.spawn_waves(state);
 
         self.bounds.enforce(state);
 
+        // Warn avatars crossing the low hp threshold, dead ones already know
+        for (aid, hp_before) in hps {
+            if let Some(avatar) = state.avatars.get(&aid)
+                && hp_before >= HP_LOW
+                && avatar.hp < HP_LOW
+                && !avatar.is_dead()
+            {
+                state.events.add(GameEventSource {
+                    senses: EventSenses::SelfSense,
+                    source: EventSource::Position(avatar.position),
+                    event: GameEvent::Wounded {
+                        subject: Target::Avatar(avatar.player_id),
+                        hp: avatar.hp,
+                    },
+                });
+            }
+        }
+
         // Phase out avatars behind in time
         let mut to_phase_out: Vec<AvatarId> = vec![];
         for avatar in state.avatars.values() {
@@ -326,13 +826,14 @@ impl Stage {
     }
 
     /// Apply the turn of each avatar
-    fn enact_avatars(&self, state: &mut StageState, diff: &TurnDiff) {
+    fn enact_avatars(&self, state: &mut StageState, diff: &TurnDiff, visible: &mut VisibilitySet) {
         for (
             aid,
             AvatarCmd {
                 action: player_action,
                 senses,
                 leaves: transitions,
+                spawn: _,
             },
         ) in diff.cmd_by_avatar.iter()
         {
@@ -348,7 +849,7 @@ impl Stage {
             avatar.turns_not_played = 0;
 
             // Regen
-            if state.turn.is_multiple_of(TURN_FOR_HP_REGEN) {
+            if state.turn.is_multiple_of(self.template.hp_regen_interval) {
                 avatar.hp = (avatar.hp + 1).min(HP_MAX);
             }
 
@@ -365,10 +866,15 @@ impl Stage {
                 player.transition = Some(Transition::Stairs(avatar.position));
             }
 
-            // Orb on tile
-            if let Some(ref mut orb) = state.orb {
+            // Any orb on tile
+            for orb in state.orbs.iter_mut() {
                 if avatar.position == orb.position {
-                    orb.excited = true;
+                    orb.excite(self.template.orb_delay);
+                    state.events.add(GameEventSource {
+                        senses: EventSenses::All,
+                        source: EventSource::Position(orb.position),
+                        event: GameEvent::OrbTaken(Target::Avatar(avatar.player_id)),
+                    });
                     if let Some(ref mut player) = state.player
                         && avatar.player_id == player.id
                     {
@@ -382,9 +888,10 @@ impl Stage {
                     avatar.position,
                     orb.position,
                     senses.sight.get(),
+                    visible,
                 ) & !orb.excited
                 {
-                    orb.excited = true;
+                    orb.excite(self.template.orb_delay);
                     state.events.add(GameEventSource {
                         senses: EventSenses::All,
                         source: EventSource::Position(orb.position),
@@ -393,20 +900,6 @@ impl Stage {
                 }
             }
 
-            if let Some(ref mut player) = state.player {
-                // If pylon is adjacent, recharges focus
-                for x in -1..2 {
-                    for y in -1..2 {
-                        let offset = Offset { x, y };
-                        let position = avatar.position + offset;
-                        let tile = self.template.tiles.get(position);
-                        if matches!(tile, Tile::Pylon) {
-                            player.focus = FOCUS_MAX;
-                        }
-                    }
-                }
-            }
-
             avatar.turns += 1;
 
             if *transitions {
@@ -418,29 +911,72 @@ impl Stage {
     }
 
     /// Apply the turn of each foe
-    fn enact_foes(&self, state: &mut StageState, bindings: &SenseBounds) {
+    fn enact_foes(
+        &self,
+        state: &mut StageState,
+        bindings: &SenseBounds,
+        visible: &mut VisibilitySet,
+    ) {
         // Foes are static for now
         for i in 0..state.foes.len() {
             let foe = state.foes[i].clone();
-            let mutator = foes::act(&foe, self, state, bindings);
+            let mutator = foes::act(&foe, self, state, bindings, visible);
             mutator(&mut state.foes[i]);
         }
     }
 
+    /// Brings in the foes of the waves due this turn while the stage is under its cap. Foes whose
+    /// cell is taken don't spawn.
+    fn spawn_waves(&self, state: &mut StageState) {
+        let waves = self
+            .template
+            .waves
+            .iter()
+            .filter(|wave| wave.turn == state.turn);
+        for foe in waves.flat_map(|wave| wave.foes.iter()) {
+            let living = state
+                .foes
+                .iter()
+                .filter(|foe| foe.alive() && !foe.is_trap())
+                .count() as u32;
+            if self.template.foe_cap.is_some_and(|cap| living >= cap) {
+                return;
+            }
+            let taken = state.find_foe(foe.position).is_some()
+                || state.avatars.values().any(|a| a.position == foe.position);
+            if !taken {
+                state.foes.push(foe.clone());
+            }
+        }
+    }
+
     fn welcome_avatar(&self, state: &mut StageState, diff: &TurnDiff) {
         for (pid, cmd) in diff.cmd_by_avatar.iter() {
             let pid = *pid;
-            if cmd.action == ServerAction::Enter {
-                let spawn_position = self.find_spawns();
-                let position = spawn_position[pid as usize % spawn_position.len()];
-
+            if let Some(position) = cmd.spawn {
                 let mut avatar = Avatar::new(pid);
                 avatar.position = position;
+                if let Some(hp) = self.template.start_hp {
+                    avatar.hp = hp.clamp(1, HP_MAX);
+                }
                 state.avatars.insert(pid, avatar);
             }
         }
     }
 
+    /// Free spawn farthest from the avatars already on the stage, the player id choosing among
+    /// equals. Avatars are stacked on the spawn of their id once every spawn is taken.
+    fn pick_spawn(&self, pid: PlayerId, state: &StageState) -> Position {
+        let spawns = self.find_spawns();
+        let start = pid as usize % spawns.len();
+        let taken: Vec<Position> = state.avatars.values().map(|a| a.position).collect();
+        (0..spawns.len())
+            .map(|i| spawns[(start + i) % spawns.len()])
+            .filter(|spawn| !taken.contains(spawn))
+            .min_by_key(|spawn| Reverse(taken.iter().map(|p| p.dist(spawn)).min()))
+            .unwrap_or(spawns[start])
+    }
+
     pub fn find_spawns(&self) -> Vec<Position> {
         self.template
             .tiles
@@ -457,7 +993,7 @@ impl Stage {
     }
 
     pub fn handle_limbo(&mut self) -> Vec<Limbo> {
-        let statuses = self.limbo_check();
+        let mut statuses = self.limbo_check();
         for status in statuses.iter() {
             match status {
                 Limbo::Dead(pid) | Limbo::TooFarBehind(pid) => {
@@ -469,12 +1005,32 @@ impl Stage {
                 &Limbo::Averted(aid, _) => {
                     self.players.get_mut(&aid).unwrap().limbo = false;
                 }
+                Limbo::FallingBehind(..) => {}
             }
         }
 
+        statuses.extend(self.behind_check());
         statuses
     }
 
+    /// Warns once the players getting close to being too far behind the head
+    fn behind_check(&mut self) -> Vec<Limbo> {
+        let limit = self.template.timeline_length as StageTurn;
+        let warning = limit * self.template.behind_warning as StageTurn / 100;
+
+        let mut results = vec![];
+        for (&pid, tracker) in self.players.iter_mut() {
+            let behind = self.head_turn.saturating_sub(tracker.turn);
+            if behind <= warning {
+                tracker.behind_warned = false;
+            } else if !tracker.behind_warned {
+                tracker.behind_warned = true;
+                results.push(Limbo::FallingBehind(pid, limit.saturating_sub(behind)));
+            }
+        }
+        results
+    }
+
     /// Returns deaths, maybedeaths and reverted deaths
     fn limbo_check(&mut self) -> Vec<Limbo> {
         // Sort avatars by turn (earliest to latest)
@@ -533,7 +1089,7 @@ impl Stage {
         results
     }
 
-    fn timeline(&self) -> Timeline {
+    pub fn timeline(&self) -> Timeline {
         Timeline {
             head: self.head_turn,
             tail: self.tail_turn(),
@@ -559,27 +1115,41 @@ impl Stage {
 
         if let Some(sight) = &info.sight {
             self.bounds.add_sight_bounds(avatar, turn, sight);
+            if self.template.pvp
+                && let Some(state) = self.states.get(&turn)
+            {
+                self.bounds.add_ally_bounds(avatar, turn, sight, state);
+            }
         }
     }
 
     pub fn get_all_infos(&self) -> Vec<(PlayerId, StageTurn, SensesInfo)> {
-        let mut results = vec![];
+        self.players
+            .keys()
+            .filter_map(|&pid| {
+                let (turn, info) = self.player_info(pid)?;
+                Some((pid, turn, info))
+            })
+            .collect()
+    }
 
-        for (&pid, tracker) in &self.players {
-            let index = self.diff_index(tracker.turn);
-            let senses = self
-                .diffs
-                .get(index)
-                .and_then(|diff| diff.get_avatar_diff(pid))
-                .map(|avatar_diff| &avatar_diff.senses)
-                .cloned()
-                .unwrap_or_default();
+    /// Senses used by the last command of the player
+    pub fn last_senses(&self, pid: PlayerId) -> Option<&Senses> {
+        let tracker = self.players.get(&pid)?;
+        let index = self.diff_index(tracker.turn);
+        self.diffs
+            .get(index)
+            .and_then(|diff| diff.get_avatar_diff(pid))
+            .map(|avatar_diff| &avatar_diff.senses)
+    }
 
-            let info = self.gather_info(pid, &senses).unwrap_or_default();
-            results.push((pid, tracker.turn, info));
-        }
+    /// What the player currently senses, using the senses of its last command
+    pub fn player_info(&self, pid: PlayerId) -> Option<(StageTurn, SensesInfo)> {
+        let tracker = self.players.get(&pid)?;
+        let senses = self.last_senses(pid).cloned().unwrap_or_default();
 
-        results
+        let info = self.gather_info(pid, &senses).unwrap_or_default();
+        Some((tracker.turn, info))
     }
 }
 
@@ -591,13 +1161,44 @@ pub struct StagePlayer {
     /// Needed to have access to the player name in info gathering
     pub player_name: String,
     pub focus: u8,
+    /// Whether the last turn was played without enough focus
+    pub exhausted: bool,
     pub transition: Option<Transition>,
     /// Limbo means a message of MaybeDead has been sent to the player and is awaiting
     /// cancelation/confirmation
-    limbo: bool,
+    pub limbo: bool,
+    /// When the player last played, idle players pin the stage history
+    pub last_activity: Instant,
+    /// Stage turn, avatar position and sight tiles of the last turn sent to the player
+    pub sent_sight: Option<(StageTurn, Position, Tiles)>,
+    /// Pools its sight with the allies sharing theirs
+    pub share_senses: bool,
+    /// Senses cooling down, with the stage turn they were used on
+    pub cooldowns: Vec<(SenseType, StageTurn)>,
+    /// Whether the player was warned of falling behind, until it catches up
+    pub behind_warned: bool,
+    /// Turns waited for the player since its last command
+    pub auto_waited: u32,
 }
 
+/// Focus is only regained and spent on the commands of the player itself, never while enacting a
+/// turn: rollbacks replaying the same turns leave it untouched.
 impl StagePlayer {
+    fn regen_focus(&mut self, amount: u32) {
+        debug_assert!(self.focus <= FOCUS_MAX);
+        self.focus = (self.focus as u32 + amount).min(FOCUS_MAX as u32) as u8;
+    }
+
+    /// Spends the focus if there is enough of it
+    fn spend_focus(&mut self, cost: u8) -> bool {
+        debug_assert!(self.focus <= FOCUS_MAX);
+        if cost > self.focus {
+            return false;
+        }
+        self.focus -= cost;
+        true
+    }
+
     fn new(player: &Player, turn: Turn) -> Self {
         Self {
             id: player.id,
@@ -605,17 +1206,38 @@ impl StagePlayer {
             turn,
             limbo: false,
             focus: FOCUS_MAX,
+            exhausted: false,
             transition: None,
+            last_activity: Instant::now(),
+            sent_sight: None,
+            share_senses: player.share_senses,
+            cooldowns: vec![],
+            behind_warned: false,
+            auto_waited: 0,
         }
     }
 }
 
+/// Tells the player which senses were lowered by their cooldown
+fn cooldown_events(lowered: Vec<SenseType>) -> Vec<GEvent> {
+    lowered
+        .into_iter()
+        .map(|sense| GEvent::new(vec![SenseType::SelfSense], GameEvent::SenseCooldown(sense)))
+        .collect()
+}
+
 /// State of a stage for a given turn.
 #[derive(Clone)]
 pub struct StageState {
     pub turn: StageTurn,
+    /// Looked up by `Foe.id`, their index may change when foes are added
     pub foes: Vec<Foe>,
-    pub orb: Option<Orb>,
+    /// Id given to the next foe added, ids are never reused
+    pub next_foe_id: FoeId,
+    /// Usually a single one, chaos stages have several
+    pub orbs: Vec<Orb>,
+    /// Shards not picked yet
+    pub shards: Vec<Position>,
     pub avatars: BTreeMap<PlayerId, Avatar>,
 
     /// The player that is currently playing. only set on the current's player turn. Empty for
@@ -626,12 +1248,19 @@ pub struct StageState {
 }
 
 impl StageState {
-    pub fn find_foe(&self, position: Position) -> Option<(usize, &Foe)> {
+    pub fn find_foe(&self, position: Position) -> Option<(FoeId, &Foe)> {
         self.foes
             .iter()
-            .enumerate()
-            .filter(|f| f.1.alive())
-            .find(|(_, f)| f.position == position)
+            .find(|f| f.alive() && f.position == position)
+            .map(|f| (f.id, f))
+    }
+
+    /// Adds a foe under a new id
+    pub fn add_foe(&mut self, foe: Foe) -> FoeId {
+        let id = self.next_foe_id;
+        self.next_foe_id += 1;
+        self.foes.push(Foe { id, ..foe });
+        id
     }
 }
 
@@ -674,6 +1303,8 @@ pub struct AvatarCmd {
     pub action: ServerAction,
     pub senses: Senses,
     pub leaves: bool,
+    /// Where the avatar enters the stage, only set on its Enter turn
+    pub spawn: Option<Position>,
 }
 
 pub struct StageCommandResult {
@@ -684,32 +1315,906 @@ pub struct StageCommandResult {
     pub events: Vec<GEvent>,
     pub transition: Option<Transition>,
     pub timeline: Timeline,
+    /// Focus paid for senses during the command
+    pub focus_spent: u64,
+    pub turns_played: Turn,
 }
 
-fn orb_spawn(stage: &Stage, stage_turn: StageTurn) -> Option<Orb> {
-    let orb_spawns = stage.template.orb_spawns.as_ref()?;
+impl StageState {
+    /// The orb closest to this position, if any
+    pub fn nearest_orb(&self, position: Position) -> Option<&Orb> {
+        self.orbs
+            .iter()
+            .min_by_key(|orb| orb.position.dist(&position))
+    }
+}
+
+fn orb_spawns(stage: &Stage, stage_turn: StageTurn) -> Vec<Orb> {
+    (0..stage.template.orb_count as usize)
+        .map_while(|i| orb_spawn(stage, stage_turn, i))
+        .collect()
+}
 
-    let spawns: Vec<Position> = orb_spawns
-        .indexed_iter()
-        .filter(|(_, val)| **val)
-        .map(|(pos, _)| Position::from(pos))
-        .collect();
+fn orb_spawn(stage: &Stage, stage_turn: StageTurn, index: usize) -> Option<Orb> {
+    let spawns = &stage.template.orb_spawns;
+    let total: u64 = spawns.iter().map(|(_, weight)| *weight as u64).sum();
 
-    if spawns.is_empty() {
+    if total == 0 {
         warn!("Couldn't find a spawn point for lvl");
         return None;
     }
 
-    // Deterministic random selection based on seed and stage_turn
+    // Deterministic weighted selection based on seed, stage_turn and the orb index
     // Using a simple hash combination
     let hash = stage
         .seed
         .wrapping_add(stage_turn)
+        .wrapping_add((index as u64).wrapping_mul(0x9E3779B97F4A7C15))
         .wrapping_mul(6364136223846793005);
-    let i = (hash as usize) % spawns.len();
+    let mut roll = hash % total;
+
+    let position = spawns.iter().find_map(|(position, weight)| {
+        if roll < *weight as u64 {
+            Some(*position)
+        } else {
+            roll -= *weight as u64;
+            None
+        }
+    })?;
 
     Some(Orb {
-        position: spawns[i],
+        position,
         excited: false,
+        countdown: 0,
     })
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::path::PathBuf;
+
+    use bounded_integer::BoundedU8;
+    use losig_core::types::{Direction, FoeType};
+
+    use super::*;
+    use crate::{
+        sense_bounds::{DeathBound, PositionBound},
+        tiled::{MapSource, load_world},
+        world::{CommandResultOutcome, Occlusion, SpawnWave, TransitionDestination, World},
+    };
+
+    fn fixture_world(ids: &[&str]) -> World {
+        let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("fixtures");
+        load_world(
+            &MapSource::Directory(dir),
+            ids,
+            Box::new(|_, _, _| TransitionDestination::End),
+        )
+        .unwrap()
+    }
+
+    fn load_fixture(ids: &[&str]) -> World {
+        let world = fixture_world(ids);
+        world.new_player(1, None).unwrap();
+        world
+    }
+
+    #[test]
+    fn stairs_transition_test() {
+        let world = load_fixture(&["stairs"]);
+
+        let stage = &mut world.stages[0].lock().unwrap();
+        let result = stage
+            .add_command(
+                1,
+                ClientAction::MoveOrAttack(Direction::Right),
+                Senses::default(),
+            )
+            .unwrap();
+
+        let stairs = Position { x: 2, y: 1 };
+        assert!(matches!(result.transition, Some(Transition::Stairs(pos)) if pos == stairs));
+
+        // Stairs are routed independently from orb pickups
+        let transitions = &stage.template.transitions;
+        let tiles = &stage.template.tiles;
+        assert_eq!(
+            transitions.destination(tiles, Transition::Stairs(stairs)),
+            Some(TransitionDestination::End)
+        );
+        assert_eq!(transitions.destination(tiles, Transition::Orb), None);
+    }
+
+    #[test]
+    fn kill_test() {
+        let world = load_fixture(&["duel"]);
+        let senses = Senses {
+            selfs: true,
+            ..Default::default()
+        };
+        let right = ClientAction::MoveOrAttack(Direction::Right);
+        let result = world.add_command(1, right, senses.clone()).unwrap();
+
+        let CommandResultOutcome::Turn { action, events, .. } = result.outcome else {
+            panic!("Player should still be in the stage");
+        };
+        assert_eq!(action, ServerAction::Attack(0));
+        assert!(
+            events
+                .iter()
+                .any(|e| matches!(e.event(), GameEvent::Kill { .. }))
+        );
+        assert!(!world.stages[0].lock().unwrap().head_state().foes[0].alive());
+
+        // A foe seen alive later in the timeline survives the blow
+        let world = load_fixture(&["duel"]);
+        let mut stage = world.stages[0].lock().unwrap();
+        stage.bounds.position_bounds.insert(
+            (0, 2),
+            PositionBound {
+                value: Position { x: 2, y: 1 },
+                turn: 5,
+                source: 2,
+            },
+        );
+        let result = stage.add_command(1, right, senses).unwrap();
+        assert!(
+            result
+                .events
+                .iter()
+                .any(|e| matches!(e.event(), GameEvent::Attack { .. }))
+        );
+        assert!(stage.head_state().foes[0].alive());
+    }
+
+    #[test]
+    fn disarm_test() {
+        let world = load_fixture(&["trap"]);
+        let senses = Senses {
+            selfs: true,
+            touch: BoundedU8::new(1).unwrap(),
+            ..Default::default()
+        };
+
+        let focus = |info: Option<SensesInfo>| info.and_then(|i| i.selfi).unwrap().focus;
+        let result = world
+            .add_command(1, ClientAction::Wait, senses.clone())
+            .unwrap();
+        let CommandResultOutcome::Turn { info, .. } = result.outcome else {
+            panic!("Player should still be in the stage");
+        };
+        let waited = focus(info);
+
+        // Nothing to disarm there, the turn is spent waiting for free
+        let up = ClientAction::Disarm(Direction::Up);
+        let result = world.add_command(1, up, senses.clone()).unwrap();
+        let CommandResultOutcome::Turn { info, .. } = result.outcome else {
+            panic!("Player should still be in the stage");
+        };
+        assert_eq!(
+            info.as_ref()
+                .and_then(|i| i.touch.as_ref())
+                .map(|t| t.traps),
+            Some(1)
+        );
+        let turn_cost = waited - focus(info.clone());
+        let focus_before = focus(info);
+
+        let right = ClientAction::Disarm(Direction::Right);
+        let result = world.add_command(1, right, senses.clone()).unwrap();
+        let CommandResultOutcome::Turn { info, events, .. } = result.outcome else {
+            panic!("Player should still be in the stage");
+        };
+        assert!(
+            events
+                .iter()
+                .any(|e| matches!(e.event(), GameEvent::TrapDisarmed(Target::You)))
+        );
+        assert_eq!(
+            info.as_ref()
+                .and_then(|i| i.touch.as_ref())
+                .map(|t| t.traps),
+            Some(0)
+        );
+        assert_eq!(focus(info), focus_before - turn_cost - DISARM_COST);
+
+        // The tile is now safe to walk on
+        let stage = world.stages[0].lock().unwrap();
+        assert!(!stage.head_state().foes[0].alive());
+    }
+
+    #[test]
+    fn sight_delta_test() {
+        let world = load_fixture(&["hall"]);
+        let senses = Senses {
+            sight: BoundedU8::new(3).unwrap(),
+            ..Default::default()
+        };
+
+        // Walk along the hall, rebuilding the sight like the client does
+        let mut base: Option<(StageTurn, Tiles)> = None;
+        let (mut full_bytes, mut sent_bytes) = (0, 0);
+        for _ in 0..6 {
+            let right = ClientAction::MoveOrAttack(Direction::Right);
+            let result = world.add_command(1, right, senses.clone()).unwrap();
+            let CommandResultOutcome::Turn {
+                stage_turn, info, ..
+            } = result.outcome
+            else {
+                panic!("Player should still be in the stage");
+            };
+            let sight = info.and_then(|info| info.sight).unwrap();
+            let (_, expected) = world.stages[0].lock().unwrap().player_info(1).unwrap();
+            let expected = expected.sight.unwrap().tiles;
+
+            let tiles = match &sight.delta {
+                Some(delta) => {
+                    let (base_turn, base) = base.as_ref().unwrap();
+                    assert_eq!(delta.base_turn, *base_turn);
+                    sent_bytes += bincode::serialize(delta).unwrap().len();
+                    delta.apply(base)
+                }
+                None => {
+                    sent_bytes += bincode::serialize(&sight.tiles).unwrap().len();
+                    sight.tiles
+                }
+            };
+            full_bytes += bincode::serialize(&expected).unwrap().len();
+            assert_eq!(tiles, expected);
+            base = Some((stage_turn, tiles));
+        }
+
+        // Only the first turn sends the whole cone, a step then costs about half of it
+        assert!(
+            sent_bytes * 3 < full_bytes * 2,
+            "{sent_bytes} tile bytes sent instead of {full_bytes}"
+        );
+    }
+
+    fn test_template(map: &[&str], foes: Vec<Foe>) -> StageTemplate {
+        let mut tiles = Tiles::new(map[0].len(), map.len());
+        for (y, row) in map.iter().enumerate() {
+            for (x, c) in row.chars().enumerate() {
+                tiles.grid[(x, y)] = match c {
+                    '#' => Tile::Wall,
+                    'S' => Tile::Spawn,
+                    'P' => Tile::Pylon,
+                    _ => Tile::Empty,
+                };
+            }
+        }
+        StageTemplate::new(
+            "test".to_string(),
+            "Test".to_string(),
+            tiles,
+            vec![],
+            foes,
+            1,
+            0,
+            vec![SenseType::SelfSense, SenseType::Sight],
+            100,
+            TimelineType::Asynchronous,
+            true,
+        )
+    }
+
+    fn dummy(id: FoeId, x: usize, y: usize) -> Foe {
+        test_foe(FoeType::Dummy, id, x, y)
+    }
+
+    pub(crate) fn test_foe(foe_type: FoeType, id: FoeId, x: usize, y: usize) -> Foe {
+        Foe {
+            id,
+            foe_type,
+            position: Position { x, y },
+            hp: 3,
+            attack: 1,
+            last_seen: None,
+            summoned: false,
+        }
+    }
+
+    fn test_player(id: PlayerId) -> Player {
+        Player {
+            id,
+            name: format!("P{id}"),
+            stage: Some(0),
+            last_avatar: Avatar::new(id),
+            gameover: None,
+            focus_spent: 0,
+            turns_played: 0,
+            share_senses: false,
+            turn: 1,
+        }
+    }
+
+    /// Checks the relations between turns, diffs and states that rollbacks rely on
+    fn assert_history_invariants(stage: &Stage) {
+        let oldest = stage.players.values().map(|p| p.turn).min().unwrap();
+        assert_eq!(stage.tail_turn(), oldest);
+        assert_eq!(stage.diff_index(stage.tail_turn()), 0);
+        assert_eq!(stage.diff_index(stage.head_turn), stage.diffs.len() - 1);
+        assert!(stage.states.keys().all(|turn| *turn >= stage.tail_turn()));
+        assert!(stage.states.keys().all(|turn| *turn <= stage.head_turn));
+        for player in stage.players.values() {
+            assert!(stage.states.contains_key(&player.turn));
+        }
+    }
+
+    #[test]
+    fn rollback_bounds_test() {
+        let map = ["############", "#S.........#", "############"];
+        let mut stage = Stage::from_template_for_test(test_template(&map, vec![dummy(0, 7, 1)]));
+        let watching = Senses {
+            sight: BoundedU8::new(10).unwrap(),
+            ..Default::default()
+        };
+        let blind = Senses {
+            sight: BoundedU8::new(0).unwrap(),
+            ..Default::default()
+        };
+
+        stage.add_player(&test_player(1), blind.clone()).unwrap();
+        stage.add_player(&test_player(2), blind.clone()).unwrap();
+        assert_history_invariants(&stage);
+
+        // The first player runs ahead and sees the foe, too far to chase anyone
+        for _ in 0..4 {
+            stage
+                .add_command(1, ClientAction::Wait, watching.clone())
+                .unwrap();
+            assert_history_invariants(&stage);
+        }
+        let witnessed_turn = stage.players[&1].turn;
+        let witnessed = Position { x: 7, y: 1 };
+        assert_eq!(stage.states[&witnessed_turn].foes[0].position, witnessed);
+        assert!(stage.bounds.position_bounds.contains_key(&(0, 1)));
+
+        // The second one walks to the foe in the past, drawing it out
+        let right = ClientAction::MoveOrAttack(Direction::Right);
+        let mut deepest_rollback = 0;
+        while stage.players[&2].turn < witnessed_turn {
+            stage.add_command(2, right, blind.clone()).unwrap();
+            deepest_rollback = deepest_rollback.max(stage.last_rollback_depth);
+            assert_history_invariants(&stage);
+        }
+        assert!(deepest_rollback > 0);
+
+        // Recomputed states still match what was witnessed
+        assert_eq!(stage.states[&witnessed_turn].foes[0].position, witnessed);
+
+        // Once nobody is behind, the old turns are dropped
+        stage
+            .add_command(2, ClientAction::Wait, blind.clone())
+            .unwrap();
+        stage.add_command(1, ClientAction::Wait, blind).unwrap();
+        assert_history_invariants(&stage);
+        assert_eq!(stage.tail_turn(), witnessed_turn + 1);
+        assert_eq!(stage.diffs.len(), 1);
+    }
+
+    #[test]
+    fn foe_sight_test() {
+        let foe = dummy(0, 5, 1);
+        let walled = ["#######", "#S..#.#", "#######"];
+        let open = ["#######", "#S....#", "#######"];
+
+        for (map, chases) in [(walled, false), (open, true)] {
+            let mut stage = Stage::from_template_for_test(test_template(&map, vec![foe.clone()]));
+            stage
+                .add_player(&test_player(1), Senses::default())
+                .unwrap();
+            for _ in 0..3 {
+                stage
+                    .add_command(1, ClientAction::Wait, Senses::default())
+                    .unwrap();
+            }
+            let turn = stage.players[&1].turn;
+            let moved = stage.states[&turn].foes[0].position != foe.position;
+            assert_eq!(moved, chases);
+        }
+    }
+
+    #[test]
+    fn orb_taken_test() {
+        let map = ["######", "#SS..#", "######"];
+        let mut stage = Stage::from_template_for_test(test_template(&map, vec![]));
+        for pid in 1..=2 {
+            stage
+                .add_player(&test_player(pid), Senses::default())
+                .unwrap();
+        }
+        let position = stage.head_state().avatars[&1].position;
+        for state in stage.states.values_mut() {
+            state.orbs = vec![Orb {
+                position: position + Offset { x: 1, y: 0 },
+                ..Default::default()
+            }];
+        }
+
+        let right = ClientAction::MoveOrAttack(Direction::Right);
+        let result = stage.add_command(1, right, Senses::default()).unwrap();
+        assert!(matches!(result.transition, Some(Transition::Orb)));
+
+        // The ally next to it sees who took the orb
+        let turn = stage.players[&1].turn;
+        let senses = Senses {
+            sight: BoundedU8::new(3).unwrap(),
+            ..Default::default()
+        };
+        let events = gather_events(
+            &senses,
+            &stage,
+            &stage.states[&turn],
+            2,
+            &mut FovCache::default(),
+        );
+        assert!(
+            events
+                .iter()
+                .any(|e| matches!(e.event(), GameEvent::OrbTaken(Target::Player(1, _))))
+        );
+    }
+
+    #[test]
+    fn focus_rollback_test() {
+        let mut template = test_template(&["######", "#S.SP#", "######"], vec![]);
+        template.fp_regen = 0;
+        template.pylon_regen = 3;
+        let mut stage = Stage::from_template_for_test(template);
+        for pid in 1..=2 {
+            stage
+                .add_player(&test_player(pid), Senses::default())
+                .unwrap();
+        }
+        let avatars = &stage.head_state().avatars;
+        assert!(stage.near_pylon(avatars[&1].position));
+        assert!(!stage.near_pylon(avatars[&2].position));
+
+        // Only the avatar next to the pylon is recharged
+        let focus = |stage: &Stage| (stage.players[&1].focus, stage.players[&2].focus);
+        let (focus_1, focus_2) = focus(&stage);
+        stage
+            .add_command(2, ClientAction::Wait, Senses::default())
+            .unwrap();
+        let senses = Senses {
+            sight: BoundedU8::new(3).unwrap(),
+            ..Default::default()
+        };
+        stage.add_command(1, ClientAction::Wait, senses).unwrap();
+        let expected = (focus_1 - 6 + 3, focus_2 - 8);
+        assert_eq!(focus(&stage), expected);
+
+        // Replaying the turns does not regen nor spend again
+        for _ in 0..2 {
+            stage.rollback_from(stage.tail_turn());
+            assert_eq!(focus(&stage), expected);
+        }
+    }
+
+    #[test]
+    fn undo_test() {
+        let foe = Foe {
+            hp: 1,
+            ..dummy(0, 3, 1)
+        };
+        let map = ["#######", "#SS...#", "#######"];
+        let mut stage = Stage::from_template_for_test(test_template(&map, vec![foe]));
+        stage
+            .add_player(&test_player(1), Senses::default())
+            .unwrap();
+        stage
+            .add_player(&test_player(2), Senses::default())
+            .unwrap();
+
+        // The first player can't undo past the tail
+        assert!(
+            stage
+                .add_command(1, ClientAction::Undo, Senses::default())
+                .is_err()
+        );
+
+        // A step is taken back
+        let right = ClientAction::MoveOrAttack(Direction::Right);
+        let before = stage.add_command(2, ClientAction::Wait, Senses::default());
+        let head = stage.head_turn;
+        let position = stage.state_for(2).unwrap().avatars[&2].position;
+        stage.add_command(2, right, Senses::default()).unwrap();
+        let focus = stage.players[&2].focus;
+        let result = stage
+            .add_command(2, ClientAction::Undo, Senses::default())
+            .unwrap();
+        assert_eq!(result.stage_turn, before.unwrap().stage_turn);
+        assert_eq!(stage.head_turn, head);
+        assert_eq!(stage.players[&2].focus, focus - UNDO_COST);
+        let state = stage.state_for(2).unwrap();
+        assert_eq!(state.avatars[&2].position, position);
+        assert_history_invariants(&stage);
+
+        // The first player kills the foe where the second one had seen it alive before taking the
+        // step back, then the second one sees it dead
+        for _ in 0..2 {
+            stage
+                .add_command(1, ClientAction::Wait, Senses::default())
+                .unwrap();
+        }
+        let kill = stage.add_command(1, right, Senses::default()).unwrap();
+        assert_eq!(kill.action, ServerAction::Attack(0));
+        stage
+            .add_command(2, ClientAction::Wait, Senses::default())
+            .unwrap();
+        assert!(stage.bounds.death_bounds.contains_key(&0));
+        assert!(
+            stage
+                .add_command(1, ClientAction::Undo, Senses::default())
+                .is_err()
+        );
+        assert!(!stage.state_for(1).unwrap().foes[0].alive());
+        assert_history_invariants(&stage);
+    }
+
+    #[test]
+    fn check_turn_test() {
+        let world = load_fixture(&["stairs"]);
+
+        let play = |action| world.add_command(1, action, Senses::default());
+
+        // The first command is checked too, clients start at turn 1
+        assert!(world.check_turn(1, 7, ClientAction::Wait).is_err());
+        world.check_turn(1, 2, ClientAction::Wait).unwrap();
+        play(ClientAction::Wait).unwrap();
+        world.check_turn(1, 3, ClientAction::Wait).unwrap();
+        world.check_turn(1, 2, ClientAction::Sense).unwrap();
+        world.check_turn(1, 1, ClientAction::Undo).unwrap();
+
+        // A slight drift is tolerated, not a bogus turn
+        world.check_turn(1, 4, ClientAction::Wait).unwrap();
+        assert!(world.check_turn(1, 100, ClientAction::Wait).is_err());
+
+        // Claims don't move the count, only the commands played
+        for _ in 0..5 {
+            world.check_turn(1, 5, ClientAction::Wait).unwrap();
+        }
+        assert!(world.check_turn(1, 8, ClientAction::Wait).is_err());
+        play(ClientAction::Wait).unwrap();
+        world.check_turn(1, 4, ClientAction::Wait).unwrap();
+
+        // A refused command doesn't count
+        let before = world.players()[&1].turn;
+        assert!(play(ClientAction::Undo).is_err());
+        assert_eq!(world.players()[&1].turn, before);
+    }
+
+    #[test]
+    fn warp_test() {
+        let world = load_fixture(&["stairs", "orbs"]);
+        assert!(world.warp(1, 2).is_err());
+
+        let result = world.warp(1, 1).unwrap();
+        assert!(matches!(
+            result.outcome,
+            CommandResultOutcome::Transition { stage_id: 1, .. }
+        ));
+        assert_eq!(world.player_stage(1).unwrap(), 1);
+        assert!(world.stage(0).unwrap().players.is_empty());
+        assert!(world.stage(1).unwrap().players.contains_key(&1));
+    }
+
+    #[test]
+    fn fixed_seed_test() {
+        let seeded = || fixture_world(&["orbs"]).with_seed(42);
+        let positions = |stage: &Stage| -> Vec<Position> {
+            stage
+                .head_state()
+                .orbs
+                .iter()
+                .map(|orb| orb.position)
+                .collect()
+        };
+
+        // Same seed, same orbs, even after a reset
+        let (first, second) = (seeded(), seeded());
+        let mut stage = first.stages[0].lock().unwrap();
+        let expected = positions(&stage);
+        assert_eq!(positions(&second.stages[0].lock().unwrap()), expected);
+        stage.reset();
+        assert_eq!(positions(&stage), expected);
+        drop(stage);
+
+        // The seed ends up in the game over
+        first.new_player(1, None).unwrap();
+        let gameover = first.retire_player(1).unwrap();
+        assert_eq!(gameover.seed, Some(42));
+    }
+
+    #[test]
+    fn resense_test() {
+        let mut template = test_template(&["#####", "#S..#", "#####"], vec![]);
+        template.senses.push(SenseType::Touch);
+        let mut stage = Stage::from_template_for_test(template);
+        stage
+            .add_player(&test_player(1), Senses::default())
+            .unwrap();
+        stage
+            .add_command(1, ClientAction::Wait, Senses::default())
+            .unwrap();
+        let turn = stage.players[&1].turn;
+        let focus = stage.players[&1].focus;
+
+        // Only the touch is paid for, the sight was already used this turn
+        let senses = Senses {
+            selfs: false,
+            touch: BoundedU8::const_new::<2>(),
+            sight: BoundedU8::const_new::<3>(),
+            ..Default::default()
+        };
+        let result = stage
+            .add_command(1, ClientAction::Sense, senses.clone())
+            .unwrap();
+        assert_eq!(result.stage_turn, turn);
+        assert_eq!(result.turns_played, 0);
+        assert_eq!(result.focus_spent, 2);
+        assert!(result.senses_info.unwrap().touch.is_some());
+        assert_eq!(stage.players[&1].turn, turn);
+        assert_eq!(stage.players[&1].focus, focus - 2);
+        let merged = stage.last_senses(1).unwrap();
+        assert_eq!(merged.sight, 5);
+        assert_eq!(merged.touch, 2);
+        assert!(merged.selfs);
+
+        // Sensing the same again is free
+        let result = stage.add_command(1, ClientAction::Sense, senses).unwrap();
+        assert_eq!(result.focus_spent, 0);
+        assert_history_invariants(&stage);
+    }
+
+    #[test]
+    fn king_dummy_test() {
+        let king = Foe {
+            hp: 6,
+            attack: 4,
+            ..test_foe(FoeType::KingDummy, 0, 2, 1)
+        };
+        let map = ["######", "#S...#", "######"];
+        let mut stage = Stage::from_template_for_test(test_template(&map, vec![king.clone()]));
+        let result = stage
+            .add_player(&test_player(1), Senses::default())
+            .unwrap();
+
+        // The king winds up, then strikes on its cadence only
+        let mut turn = result.stage_turn;
+        let mut hp = stage.state_for(1).unwrap().avatars[&1].hp;
+        while turn < 8 {
+            let result = stage
+                .add_command(1, ClientAction::Wait, Senses::default())
+                .unwrap();
+            turn = result.stage_turn;
+            let current_hp = stage.state_for(1).unwrap().avatars[&1].hp;
+            assert_eq!(current_hp < hp, turn.is_multiple_of(3), "turn {turn}");
+            let wind_up = result
+                .events
+                .iter()
+                .any(|e| matches!(e.event(), GameEvent::WindUp(_)));
+            assert_eq!(wind_up, turn % 3 == 2, "turn {turn}");
+            hp = current_hp;
+        }
+
+        // Once badly hurt, it calls a dummy to its side
+        let mut stage = Stage::from_template_for_test(test_template(&map, vec![king]));
+        stage.states.get_mut(&0).unwrap().foes[0].hp = 3;
+        stage
+            .add_player(&test_player(1), Senses::default())
+            .unwrap();
+        let foes = &stage.head_state().foes;
+        assert_eq!(foes.len(), 2);
+        assert_eq!(foes[1].id, 1);
+        assert_eq!(foes[1].foe_type, FoeType::Dummy);
+        assert!(foes[0].summoned);
+        stage
+            .add_command(1, ClientAction::Wait, Senses::default())
+            .unwrap();
+        assert_eq!(stage.head_state().foes.len(), 2);
+        assert_history_invariants(&stage);
+    }
+
+    #[test]
+    fn occlusion_test() {
+        let map = ["#########", "#S......#", "#########"];
+        let watching = Senses {
+            sight: BoundedU8::new(7).unwrap(),
+            ..Default::default()
+        };
+        let seen_foes = |occlusion| {
+            let mut template = test_template(&map, vec![dummy(0, 4, 1), dummy(1, 6, 1)]);
+            template.occlusion = occlusion;
+            let mut stage = Stage::from_template_for_test(template);
+            let result = stage.add_player(&test_player(1), watching.clone()).unwrap();
+            let sight = result.senses_info.and_then(|info| info.sight).unwrap();
+            sight.foes.iter().map(|foe| foe.id).collect::<Vec<_>>()
+        };
+
+        assert_eq!(seen_foes(Occlusion::None), vec![0, 1]);
+        // The nearest foe hides the other one, but is seen itself
+        assert_eq!(seen_foes(Occlusion::Foes), vec![0]);
+    }
+
+    #[test]
+    fn foe_id_test() {
+        let map = ["######", "#S...#", "######"];
+        let mut stage = Stage::from_template_for_test(test_template(
+            &map,
+            vec![dummy(0, 3, 1), dummy(1, 4, 1)],
+        ));
+        stage
+            .bounds
+            .death_bounds
+            .insert(1, DeathBound { turn: 0, source: 1 });
+
+        // A foe added mid-run gets a new id, wherever it ends up in the list
+        let mut state = stage.head_state().clone();
+        let id = state.add_foe(dummy(0, 2, 1));
+        assert_eq!(id, 2);
+        state.foes.rotate_right(1);
+
+        stage.bounds.enforce(&mut state);
+        let alive = |id| state.foes.iter().find(|foe| foe.id == id).unwrap().alive();
+        assert!(alive(0));
+        assert!(!alive(1));
+        assert!(alive(2));
+        assert_eq!(state.find_foe(Position { x: 2, y: 1 }).unwrap().0, 2);
+    }
+
+    #[test]
+    fn waves_test() {
+        let map = ["###########", "#S........#", "###########"];
+        let mut template = test_template(&map, vec![dummy(0, 9, 1)]);
+        template.waves = vec![SpawnWave {
+            turn: 3,
+            foes: vec![dummy(1, 7, 1), dummy(2, 8, 1)],
+        }];
+        template.foe_cap = Some(2);
+        let mut stage = Stage::from_template_for_test(template);
+        stage
+            .add_player(&test_player(1), Senses::default())
+            .unwrap();
+        stage
+            .add_player(&test_player(2), Senses::default())
+            .unwrap();
+        let ids = |stage: &Stage, pid| -> (StageTurn, Vec<FoeId>) {
+            let state = stage.state_for(pid).unwrap();
+            (state.turn, state.foes.iter().map(|foe| foe.id).collect())
+        };
+
+        // The wave comes on its turn, the cap leaving out its second foe
+        let mut seen = vec![ids(&stage, 1)];
+        while stage.players[&1].turn < 5 {
+            stage
+                .add_command(1, ClientAction::Wait, Senses::default())
+                .unwrap();
+            seen.push(ids(&stage, 1));
+        }
+        assert_eq!(
+            seen,
+            vec![
+                (1, vec![0]),
+                (2, vec![0]),
+                (3, vec![0, 1]),
+                (4, vec![0, 1]),
+                (5, vec![0, 1]),
+            ]
+        );
+
+        // Recomputing the turns spawns it again, exactly the same way
+        let mut replayed = vec![ids(&stage, 2)];
+        let mut deepest_rollback = 0;
+        while stage.players[&2].turn < 5 {
+            stage
+                .add_command(2, ClientAction::Wait, Senses::default())
+                .unwrap();
+            deepest_rollback = deepest_rollback.max(stage.last_rollback_depth);
+            replayed.push(ids(&stage, 2));
+        }
+        assert!(deepest_rollback > 0);
+        assert_eq!(replayed, seen[1..]);
+        assert_eq!(ids(&stage, 1), (5, vec![0, 1]));
+        assert_eq!(stage.head_state().foes[1].position, Position { x: 7, y: 1 });
+    }
+
+    #[test]
+    fn spawn_test() {
+        let map = ["#########", "#S.S...S#", "#########"];
+        let mut stage = Stage::from_template_for_test(test_template(&map, vec![]));
+        let position = |stage: &Stage, pid| stage.head_state().avatars[&pid].position;
+        let spawn = |x| Position { x, y: 1 };
+
+        // Players spread over the free spawns, away from the others
+        stage
+            .add_player(&test_player(1), Senses::default())
+            .unwrap();
+        assert_eq!(position(&stage, 1), spawn(3));
+        stage
+            .add_player(&test_player(2), Senses::default())
+            .unwrap();
+        assert_eq!(position(&stage, 2), spawn(7));
+        stage
+            .add_player(&test_player(3), Senses::default())
+            .unwrap();
+        assert_eq!(position(&stage, 3), spawn(1));
+
+        // Once they are all taken, the id picks the spawn
+        stage
+            .add_player(&test_player(4), Senses::default())
+            .unwrap();
+        assert_eq!(position(&stage, 4), spawn(3));
+    }
+
+    #[test]
+    fn spawn_replay_test() {
+        let map = ["#########", "#S.S...S#", "#########"];
+        let mut stage = Stage::from_template_for_test(test_template(&map, vec![]));
+        let position = |stage: &Stage, pid| stage.head_state().avatars[&pid].position;
+        let spawn = |x| Position { x, y: 1 };
+        let left = ClientAction::MoveOrAttack(Direction::Left);
+
+        stage
+            .add_player(&test_player(1), Senses::default())
+            .unwrap();
+        stage
+            .add_player(&test_player(2), Senses::default())
+            .unwrap();
+        for _ in 0..3 {
+            stage
+                .add_command(2, ClientAction::Wait, Senses::default())
+                .unwrap();
+        }
+        stage
+            .add_player(&test_player(3), Senses::default())
+            .unwrap();
+        assert_eq!(position(&stage, 3), spawn(1));
+
+        // The lagging player reaches the spawn before the turn the newcomer entered on
+        stage.add_command(1, left, Senses::default()).unwrap();
+        stage.add_command(1, left, Senses::default()).unwrap();
+        assert!(stage.players[&1].turn < stage.players[&3].turn);
+        assert_eq!(position(&stage, 1), spawn(1));
+        assert_eq!(position(&stage, 3), spawn(1));
+        assert_history_invariants(&stage);
+    }
+
+    #[test]
+    fn rollback_stats_test() {
+        let map = ["#####", "#S..#", "#####"];
+        let mut stage = Stage::from_template_for_test(test_template(&map, vec![]));
+        stage
+            .add_player(&test_player(1), Senses::default())
+            .unwrap();
+        stage
+            .add_player(&test_player(2), Senses::default())
+            .unwrap();
+        for _ in 0..4 {
+            stage
+                .add_command(1, ClientAction::Wait, Senses::default())
+                .unwrap();
+        }
+
+        // The player left behind replays the turns up to the head, commands at the head replay none
+        stage
+            .add_command(2, ClientAction::Wait, Senses::default())
+            .unwrap();
+        let depth = stage.head_turn - stage.players[&2].turn;
+        assert_eq!(depth, 2);
+        let stats = &stage.rollback_stats;
+        assert_eq!(stats.count, 7);
+        assert_eq!(stats.buckets[0], 6);
+        assert_eq!(stats.buckets[2], 1);
+        assert_eq!(stats.sum, depth);
+        assert_eq!(stats.max_states, stage.kept_states());
+
+        // Stats and their logging outlive the runs of the stage
+        stage.log_rollbacks = Some(3);
+        stage.reset();
+        assert_eq!(stage.head_turn, 0);
+        assert_eq!(stage.rollback_stats.count, 7);
+        assert_eq!(stage.log_rollbacks, Some(3));
+    }
+}
@@ -1,20 +1,48 @@
 //! Tiled related code
 
+use std::collections::HashMap;
 use std::io::Cursor;
+use std::path::PathBuf;
 use std::str::FromStr;
+use std::time::Duration;
 
 use anyhow::{Result, anyhow};
-use grid::Grid;
-use losig_core::sense::SenseType;
-use losig_core::types::{Foe, FoeType, Position, Tile, Tiles, TimelineType};
-use tiled::{Layer, Loader};
-
-use crate::world::{StageTemplate, TransitionDestination, TransitionResolver, World};
-use losig_core::types::Transition;
+use losig_core::network::DIFFICULTY_MAX;
+use losig_core::sense::{CostTable, SenseCost, SenseType, Senses};
+use losig_core::types::{
+    FOCUS_MAX, Foe, FoeId, FoeType, HP_MAX, Position, StageId, TURN_FOR_HP_REGEN, Tile, Tiles,
+    TimelineType,
+};
+use tiled::{DefaultResourceCache, Layer, Loader, ResourceReader};
+
+use crate::world::{
+    AUTO_WAIT_TURNS, BEHIND_WARNING, Occlusion, SenseCooldown, SpawnWave, StageTemplate,
+    StageTransitions, TransitionDestination, TransitionResolver, World,
+};
 
 struct AssetsReader {}
 
-const TILESET: &[u8] = include_bytes!("../../../maps/tileset/editor.tsx");
+/// Where the stage maps are read from
+#[derive(Debug, Clone, Default)]
+pub enum MapSource {
+    /// Maps embedded in the binary
+    #[default]
+    Embedded,
+    /// `<id>.tmx` files read at runtime from the given directory, tileset included
+    Directory(PathBuf),
+}
+
+/// Embedded tilesets, by the path the maps reference them with
+const TILESETS: &[(&str, &[u8])] = &[
+    (
+        "tileset/editor.tsx",
+        include_bytes!("../../../maps/tileset/editor.tsx"),
+    ),
+    (
+        "tileset/decor.tsx",
+        include_bytes!("../../../maps/tileset/decor.tsx"),
+    ),
+];
 
 macro_rules! include_stages {
       ($($name:literal),* $(,)?) => {
@@ -44,15 +72,57 @@ const STAGES: &[(&str, &[u8])] = include_stages![
     "hub"
 ];
 
-const MINDSNARE_ID: u32 = 1;
-const SIMPLE_FOE_ID: u32 = 6;
-const KING_DUMMY_ID: u32 = 9;
-const SPAWN_ID: u32 = 2;
-const ORB_ID: u32 = 3;
-const WALL_ID: u32 = 4;
-const PYLON_ID: u32 = 5;
-const STAIR_UP_ID: u32 = 7;
-const STAIR_DOWN_ID: u32 = 8;
+/// What the local ids of a tileset stand for
+struct TilesetIds {
+    name: &'static str,
+    /// Ids behaving as each logical tile, unlisted ids are empty
+    tiles: &'static [(Tile, &'static [u32])],
+    foes: &'static [(FoeType, u32)],
+    orb: Option<u32>,
+}
+
+const TILESET_IDS: &[TilesetIds] = &[
+    TilesetIds {
+        name: "editor",
+        tiles: &[
+            (Tile::Spawn, &[2]),
+            (Tile::Wall, &[4]),
+            (Tile::Pylon, &[5]),
+            (Tile::StairUp, &[7]),
+            (Tile::StairDown, &[8]),
+        ],
+        foes: &[
+            (FoeType::Trap, 1),
+            (FoeType::Dummy, 6),
+            (FoeType::KingDummy, 9),
+        ],
+        orb: Some(3),
+    },
+    TilesetIds {
+        name: "decor",
+        tiles: &[(Tile::Wall, &[0]), (Tile::Pylon, &[2])],
+        foes: &[],
+        orb: None,
+    },
+];
+
+/// Ids of the tileset the tile comes from, validated maps only use known tilesets
+fn tileset_ids(tile: &tiled::LayerTile) -> Option<&'static TilesetIds> {
+    let name = &tile.get_tileset().name;
+    TILESET_IDS.iter().find(|ids| ids.name == name)
+}
+
+fn foe_type(tile: &tiled::LayerTile) -> Option<FoeType> {
+    tileset_ids(tile)?
+        .foes
+        .iter()
+        .find(|(_, id)| *id == tile.id())
+        .map(|(foe_type, _)| *foe_type)
+}
+
+fn is_orb(tile: &tiled::LayerTile) -> bool {
+    tileset_ids(tile).is_some_and(|ids| ids.orb == Some(tile.id()))
+}
 
 impl tiled::ResourceReader for AssetsReader {
     type Resource = Cursor<&'static [u8]>;
@@ -63,7 +133,15 @@ impl tiled::ResourceReader for AssetsReader {
         path: &std::path::Path,
     ) -> std::result::Result<Self::Resource, Self::Error> {
         match path.to_str() {
-            Some("tileset/editor.tsx") => Ok(Cursor::new(TILESET)),
+            Some(tileset) if tileset.ends_with(".tsx") => {
+                match TILESETS.iter().find(|(path, _)| *path == tileset) {
+                    Some((_, bytes)) => Ok(Cursor::new(bytes)),
+                    None => Err(std::io::Error::new(
+                        std::io::ErrorKind::NotFound,
+                        format!("No embedded tileset {tileset}"),
+                    )),
+                }
+            }
             Some(lvl) => {
                 match STAGES
                     .iter()
@@ -98,63 +176,84 @@ fn convert_tiled(value: &tiled::TileLayer) -> Result<Tiles> {
             let Some(tiled_tile) = value.get_tile(x as i32, y as i32) else {
                 continue;
             };
-            let tile = match tiled_tile.id() {
-                SPAWN_ID => Tile::Spawn,
-                WALL_ID => Tile::Wall,
-                PYLON_ID => Tile::Pylon,
-                STAIR_UP_ID => Tile::StairUp,
-                STAIR_DOWN_ID => Tile::StairDown,
-                _ => Tile::Empty,
-            };
-            result.grid[(x, y)] = tile;
+            result.grid[(x, y)] = logical_tile(&tiled_tile);
         }
     }
     Ok(result)
 }
 
+/// Tile ids are only meaningful in the known tilesets, other tiles would load as empty
+fn validate_tilesets(map: &tiled::Map) -> Result<()> {
+    if map.tilesets().is_empty() {
+        return Err(anyhow!("no tileset"));
+    }
+    for tileset in map.tilesets() {
+        if !TILESET_IDS.iter().any(|ids| ids.name == tileset.name) {
+            return Err(anyhow!("unknown {} tileset", tileset.name));
+        }
+    }
+    Ok(())
+}
+
+/// A tileset tile can declare the logical tile it behaves as with its class (e.g. "Wall"), so that
+/// decorative variants don't need their own id. Otherwise the ids of its tileset are used.
+fn logical_tile(tiled_tile: &tiled::LayerTile) -> Tile {
+    let class = tiled_tile.get_tile().and_then(|t| t.user_type.clone());
+    let from_class = class.and_then(|class| match class.as_str() {
+        "Empty" => Some(Tile::Empty),
+        "Spawn" => Some(Tile::Spawn),
+        "Wall" => Some(Tile::Wall),
+        "Pylon" => Some(Tile::Pylon),
+        "StairUp" => Some(Tile::StairUp),
+        "StairDown" => Some(Tile::StairDown),
+        _ => None,
+    });
+
+    from_class.unwrap_or_else(|| {
+        tileset_ids(tiled_tile)
+            .and_then(|tileset| {
+                tileset
+                    .tiles
+                    .iter()
+                    .find(|(_, ids)| ids.contains(&tiled_tile.id()))
+            })
+            .map(|(tile, _)| *tile)
+            .unwrap_or(Tile::Empty)
+    })
+}
+
 fn convert_map(id: String, value: &tiled::Map) -> Result<StageTemplate> {
+    validate_tilesets(value)?;
     let terrain_layer = value
         .layers()
         .find(|l| l.name == "Terrain")
         .and_then(Layer::as_tile_layer)
-        .ok_or(anyhow!("No terrain layer"))?;
+        .ok_or(anyhow!("no Terrain tile layer"))?;
     let foes_layer = value
         .layers()
         .find(|l| l.name == "Foes")
         .and_then(Layer::as_tile_layer)
-        .ok_or(anyhow!("No foes layer"))?;
-    let orb_layer = value
-        .layers()
-        .find(|l| l.name == "Orb")
-        .and_then(Layer::as_tile_layer);
+        .ok_or(anyhow!("no Foes tile layer"))?;
+    let foe_stats = get_foe_stats(value)?;
+    let foes = get_foes(&foes_layer, &foe_stats, 0)?;
+    let waves = get_waves(value, &foe_stats, foes.len())?;
 
     // Read custom properties
-    let name = value
-        .properties
-        .get("name")
-        .and_then(|p| match p {
-            tiled::PropertyValue::StringValue(s) => Some(s.as_str()),
-            _ => None,
-        })
-        .unwrap_or(&id)
+    let props = &value.properties;
+    let name = string_prop(props, "name").unwrap_or(&id).to_string();
+    let author = string_prop(props, "author")
+        .unwrap_or_default()
+        .trim()
+        .to_string();
+    let description = string_prop(props, "description")
+        .unwrap_or_default()
+        .trim()
         .to_string();
 
-    let fp_regen = value
-        .properties
-        .get("fp_regen")
-        .and_then(|p| match p {
-            tiled::PropertyValue::IntValue(v) => Some(*v as u32),
-            _ => None,
-        })
-        .unwrap_or(4);
-
-    let senses = value
-        .properties
-        .get("senses")
-        .and_then(|p| match p {
-            tiled::PropertyValue::StringValue(s) => Some(s.as_str()),
-            _ => None,
-        })
+    let fp_regen = int_prop(props, "fp_regen").map_or(4, |v| v as u32);
+    let pylon_regen = int_prop(props, "pylon_regen").map_or(20, |v| v as u32);
+
+    let senses = string_prop(props, "senses")
         .map(|s| {
             s.split(';')
                 .filter_map(|sense| SenseType::from_str(sense.trim()).ok())
@@ -166,66 +265,334 @@ fn convert_map(id: String, value: &tiled::Map) -> Result<StageTemplate> {
                 SenseType::Sight,
                 SenseType::Touch,
                 SenseType::Hearing,
+                SenseType::Smell,
+                SenseType::Danger,
+                SenseType::Chronosense,
+                SenseType::OrbSense,
             ]
         });
 
-    let timeline_length = value
-        .properties
-        .get("timeline_length")
-        .and_then(|p| match p {
-            tiled::PropertyValue::IntValue(v) => Some(*v as u32),
-            _ => None,
-        })
-        .unwrap_or(100);
-
-    let timeline_type = value
-        .properties
-        .get("timeline_type")
-        .and_then(|p| match p {
-            tiled::PropertyValue::StringValue(s) => Some(s.as_str()),
-            _ => None,
-        })
+    let timeline_length = int_prop(props, "timeline_length").map_or(100, |v| v as u32);
+
+    let timeline_type = string_prop(props, "timeline_type")
         .and_then(|s| TimelineType::from_str(s).ok())
         .unwrap_or(TimelineType::Asynchronous);
 
-    Ok(StageTemplate::new(
+    let allow_diagonal = bool_prop(props, "allow_diagonal").unwrap_or(true);
+
+    let mut template = StageTemplate::new(
         id,
         name,
         convert_tiled(&terrain_layer)?,
-        orb_layer.map(|layer| get_orb_spawns(&layer)).transpose()?,
-        get_foes(&foes_layer)?,
+        get_orb_spawns(value)?,
+        foes,
         fp_regen,
+        pylon_regen,
         senses,
         timeline_length,
         timeline_type,
-    ))
+        allow_diagonal,
+    );
+    template.shards = get_shards(value)?;
+    template.waves = waves;
+    template.foe_cap = int_prop(props, "foe_cap")
+        .filter(|v| *v > 0)
+        .map(|v| v as u32);
+    template.team_vision = bool_prop(props, "team_vision").unwrap_or(false);
+    template.scale_hearing = bool_prop(props, "scale_hearing").unwrap_or(true);
+    template.pvp = bool_prop(props, "pvp").unwrap_or(false);
+    template.occlusion = match string_prop(props, "occlusion") {
+        Some("foes") => Occlusion::Foes,
+        Some("all") => Occlusion::All,
+        _ => Occlusion::default(),
+    };
+    template.cooldowns = string_prop(props, "cooldowns").map_or(Ok(vec![]), parse_cooldowns)?;
+    template.costs =
+        string_prop(props, "sense_costs").map_or(Ok(CostTable::default()), parse_sense_costs)?;
+    template.orb_delay = int_prop(props, "orb_delay").map_or(1, |v| v.max(1) as u32);
+    template.orb_count = int_prop(props, "orb_count").map_or(1, |v| v.max(1) as u32);
+    template.behind_warning =
+        int_prop(props, "behind_warning").map_or(BEHIND_WARNING, |v| v.clamp(1, 100) as u32);
+    template.hp_regen_interval =
+        int_prop(props, "hp_regen_interval").map_or(TURN_FOR_HP_REGEN, |v| v.max(1) as u64);
+    template.start_hp = int_prop(props, "start_hp").map(|v| v.clamp(1, HP_MAX as i32) as u8);
+    template.start_focus =
+        int_prop(props, "start_focus").map(|v| v.clamp(0, FOCUS_MAX as i32) as u8);
+    template.author = author;
+    template.description = description;
+    template.difficulty =
+        int_prop(props, "difficulty").map(|v| v.clamp(1, DIFFICULTY_MAX as i32) as u8);
+    template.auto_wait = int_prop(props, "auto_wait")
+        .filter(|v| *v > 0)
+        .map(|v| Duration::from_secs(v as u64));
+    template.auto_wait_turns =
+        int_prop(props, "auto_wait_turns").map_or(AUTO_WAIT_TURNS, |v| v.max(0) as u32);
+    template.max_sight = int_prop(props, "max_sight").map_or(Senses::MAX_SIGHT, |v| {
+        v.clamp(0, Senses::MAX_SIGHT as i32) as u8
+    });
+    template.max_hearing = int_prop(props, "max_hearing").map_or(Senses::MAX_HEARING, |v| {
+        v.clamp(0, Senses::MAX_HEARING as i32) as u8
+    });
+    Ok(template)
+}
+
+/// Value of a string property, `None` when missing or of another type
+fn string_prop<'a>(props: &'a tiled::Properties, key: &str) -> Option<&'a str> {
+    match props.get(key) {
+        Some(tiled::PropertyValue::StringValue(s)) => Some(s.as_str()),
+        _ => None,
+    }
+}
+
+/// Value of an int property, `None` when missing or of another type
+fn int_prop(props: &tiled::Properties, key: &str) -> Option<i32> {
+    match props.get(key) {
+        Some(tiled::PropertyValue::IntValue(v)) => Some(*v),
+        _ => None,
+    }
+}
+
+/// Value of a bool property, `None` when missing or of another type
+fn bool_prop(props: &tiled::Properties, key: &str) -> Option<bool> {
+    match props.get(key) {
+        Some(tiled::PropertyValue::BoolValue(v)) => Some(*v),
+        _ => None,
+    }
 }
 
-fn get_orb_spawns(layer: &tiled::TileLayer) -> Result<Grid<bool>> {
-    let width = layer.width().ok_or(anyhow!("no width"))? as usize;
-    let height = layer.height().ok_or(anyhow!("no height"))? as usize;
+/// Cooldowns are written `Sense:strength:turns` and separated by `;`, e.g. `Sight:8:1`
+fn parse_cooldowns(value: &str) -> Result<Vec<SenseCooldown>> {
+    value
+        .split(';')
+        .map(str::trim)
+        .filter(|cooldown| !cooldown.is_empty())
+        .map(|cooldown| {
+            let invalid = || anyhow!("Invalid cooldown '{cooldown}'");
+            let mut parts = cooldown.split(':').map(str::trim);
+            let (Some(sense), Some(strength), Some(turns), None) =
+                (parts.next(), parts.next(), parts.next(), parts.next())
+            else {
+                return Err(invalid());
+            };
+            Ok(SenseCooldown {
+                sense: SenseType::from_str(sense).map_err(|_| invalid())?,
+                strength: strength
+                    .parse()
+                    .ok()
+                    .filter(|strength| *strength > 0)
+                    .ok_or_else(invalid)?,
+                turns: turns.parse().map_err(|_| invalid())?,
+            })
+        })
+        .collect()
+}
+
+/// Overrides the default costs with `Sense:base:per_level` entries separated by `;`
+fn parse_sense_costs(value: &str) -> Result<CostTable> {
+    let mut costs = CostTable::default();
+    for cost in value
+        .split(';')
+        .map(str::trim)
+        .filter(|cost| !cost.is_empty())
+    {
+        let invalid = || anyhow!("Invalid sense cost '{cost}'");
+        let mut parts = cost.split(':').map(str::trim);
+        let (Some(sense), Some(base), Some(per_level), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err(invalid());
+        };
+        let sense = SenseType::from_str(sense).map_err(|_| invalid())?;
+        *costs.get_mut(sense) = SenseCost::new(
+            base.parse().map_err(|_| invalid())?,
+            per_level.parse().map_err(|_| invalid())?,
+        );
+    }
+    Ok(costs)
+}
+
+/// Shards are optional: any tile of a "Shards" layer is one
+fn get_shards(map: &tiled::Map) -> Result<Vec<Position>> {
+    let Some(layer) = map
+        .layers()
+        .find(|l| l.name == "Shards")
+        .and_then(Layer::as_tile_layer)
+    else {
+        return Ok(vec![]);
+    };
 
-    let mut grid = Grid::<bool>::new(width as usize, height as usize);
+    let width = layer.width().ok_or(anyhow!("no width"))?;
+    let height = layer.height().ok_or(anyhow!("no height"))?;
+    let mut results = vec![];
     for x in 0..width {
         for y in 0..height {
-            let Some(tile) = layer.get_tile(x as i32, y as i32) else {
-                continue;
-            };
-            if tile.id() == ORB_ID {
-                grid[(x, y)] = true;
+            if layer.get_tile(x as i32, y as i32).is_some() {
+                results.push(Position {
+                    x: x as usize,
+                    y: y as usize,
+                });
             }
         }
     }
-    Ok(grid)
+    Ok(results)
+}
+
+/// Orb spawns are read from every tile layer whose name starts with "Orb". Each layer is a region
+/// weighted by its optional int `weight` property, 1 by default.
+fn get_orb_spawns(map: &tiled::Map) -> Result<Vec<(Position, u32)>> {
+    let mut results = vec![];
+    for layer in map.layers().filter(|l| l.name.starts_with("Orb")) {
+        let weight = match layer.properties.get("weight") {
+            Some(tiled::PropertyValue::IntValue(v)) => {
+                u32::try_from(*v).map_err(|_| anyhow!("Invalid orb weight {v}"))?
+            }
+            Some(_) => return Err(anyhow!("Orb weight should be an int")),
+            None => 1,
+        };
+        let Some(layer) = layer.as_tile_layer() else {
+            continue;
+        };
+
+        let width = layer.width().ok_or(anyhow!("no width"))?;
+        let height = layer.height().ok_or(anyhow!("no height"))?;
+        for x in 0..width {
+            for y in 0..height {
+                let Some(tile) = layer.get_tile(x as i32, y as i32) else {
+                    continue;
+                };
+                if is_orb(&tile) {
+                    let position = Position {
+                        x: x as usize,
+                        y: y as usize,
+                    };
+                    results.push((position, weight));
+                }
+            }
+        }
+    }
+    Ok(results)
+}
+
+/// Stats overriding the defaults of a single placed foe
+#[derive(Debug, Default)]
+struct FoeStats {
+    hp: Option<u8>,
+    attack: Option<u8>,
+}
+
+/// Tile layers can't hold per-instance properties, so foe stats are read from the objects of the
+/// optional "FoeStats" object layer. An object applies to the foe on the cell containing its origin.
+fn get_foe_stats(map: &tiled::Map) -> Result<HashMap<Position, FoeStats>> {
+    let mut results = HashMap::new();
+    let Some(layer) = map
+        .layers()
+        .find(|l| l.name == "FoeStats")
+        .and_then(Layer::as_object_layer)
+    else {
+        return Ok(results);
+    };
+
+    let stat = |object: &tiled::Object, name: &str| -> Result<Option<u8>> {
+        match object.properties.get(name) {
+            Some(tiled::PropertyValue::IntValue(v)) => {
+                let v = u8::try_from(*v).map_err(|_| anyhow!("Invalid {name} {v}"))?;
+                Ok(Some(v))
+            }
+            Some(_) => Err(anyhow!("{name} should be an int")),
+            None => Ok(None),
+        }
+    };
+
+    for object in layer.objects() {
+        let position = object_cell(map, &object);
+        let stats = FoeStats {
+            hp: stat(&object, "hp")?,
+            attack: stat(&object, "attack")?,
+        };
+        results.insert(position, stats);
+    }
+
+    Ok(results)
+}
+
+/// Cell containing the object's origin
+fn object_cell(map: &tiled::Map, object: &tiled::Object) -> Position {
+    Position {
+        x: (object.x / map.tile_width as f32) as usize,
+        y: (object.y / map.tile_height as f32) as usize,
+    }
+}
+
+/// Transition destinations as named in the map, resolved once every stage is loaded
+#[derive(Debug, Default)]
+struct TransitionNames {
+    orb: Option<String>,
+    stairs_up: Option<String>,
+    stairs_down: Option<String>,
+    stairs: Vec<(Position, String)>,
+}
+
+impl TransitionNames {
+    /// Stage ids are looked up in the loaded stages, "end" ends the game
+    fn resolve(&self, stage_ids: &HashMap<String, StageId>) -> Result<StageTransitions> {
+        let resolve = |name: &String| match name.as_str() {
+            "end" => Ok(TransitionDestination::End),
+            id => stage_ids
+                .get(id)
+                .map(|stage_id| TransitionDestination::Stage(*stage_id))
+                .ok_or_else(|| anyhow!("Unknown transition destination {id}")),
+        };
+
+        Ok(StageTransitions {
+            orb: self.orb.as_ref().map(resolve).transpose()?,
+            stairs_up: self.stairs_up.as_ref().map(resolve).transpose()?,
+            stairs_down: self.stairs_down.as_ref().map(resolve).transpose()?,
+            stairs: self
+                .stairs
+                .iter()
+                .map(|(pos, name)| Ok((*pos, resolve(name)?)))
+                .collect::<Result<_>>()?,
+        })
+    }
+}
+
+/// Reads the transition_orb, stairs_up and stairs_down properties, and the destination of the
+/// objects of the optional "Stairs" object layer.
+fn get_transition_names(map: &tiled::Map) -> Result<TransitionNames> {
+    let mut stairs = vec![];
+    if let Some(layer) = map
+        .layers()
+        .find(|l| l.name == "Stairs")
+        .and_then(Layer::as_object_layer)
+    {
+        for object in layer.objects() {
+            let destination = string_prop(&object.properties, "destination")
+                .map(str::to_string)
+                .ok_or_else(|| anyhow!("Stairs object without destination"))?;
+            stairs.push((object_cell(map, &object), destination));
+        }
+    }
+
+    Ok(TransitionNames {
+        orb: string_prop(&map.properties, "transition_orb").map(str::to_string),
+        stairs_up: string_prop(&map.properties, "stairs_up").map(str::to_string),
+        stairs_down: string_prop(&map.properties, "stairs_down").map(str::to_string),
+        stairs,
+    })
 }
 
 /// TODO: get foe templates instead of foes
-fn get_foes(layer: &tiled::TileLayer) -> Result<Vec<Foe>> {
+/// Foes of the layer, numbered from `first_id`
+fn get_foes(
+    layer: &tiled::TileLayer,
+    stats: &HashMap<Position, FoeStats>,
+    first_id: FoeId,
+) -> Result<Vec<Foe>> {
     let mut results = vec![];
     let width = layer.width().ok_or(anyhow!("no width"))?;
     let height = layer.height().ok_or(anyhow!("no height"))?;
 
-    let mut id = 0;
+    let mut id = first_id;
     for x in 0..width {
         for y in 0..height {
             let Some(tile) = layer.get_tile(x as i32, y as i32) else {
@@ -236,34 +603,42 @@ fn get_foes(layer: &tiled::TileLayer) -> Result<Vec<Foe>> {
                 y: y as usize,
             };
 
-            let foe = if tile.id() == MINDSNARE_ID {
-                Foe {
+            let mut foe = match foe_type(&tile) {
+                Some(FoeType::Trap) => Foe {
                     id,
                     foe_type: FoeType::Trap,
                     position,
                     hp: 1,
                     attack: 3,
-                }
-            } else if tile.id() == SIMPLE_FOE_ID {
-                Foe {
+                    last_seen: None,
+                    summoned: false,
+                },
+                Some(FoeType::Dummy) => Foe {
                     id,
                     foe_type: FoeType::Dummy,
                     position,
                     hp: 3,
                     attack: 2,
-                }
-            } else if tile.id() == KING_DUMMY_ID {
-                Foe {
+                    last_seen: None,
+                    summoned: false,
+                },
+                Some(FoeType::KingDummy) => Foe {
                     id,
                     foe_type: FoeType::KingDummy,
                     position,
                     hp: 6,
                     attack: 4,
-                }
-            } else {
-                continue;
+                    last_seen: None,
+                    summoned: false,
+                },
+                None => continue,
             };
 
+            if let Some(stats) = stats.get(&position) {
+                foe.hp = stats.hp.unwrap_or(foe.hp);
+                foe.attack = stats.attack.unwrap_or(foe.attack);
+            }
+
             results.push(foe);
             id += 1;
         }
@@ -272,20 +647,50 @@ fn get_foes(layer: &tiled::TileLayer) -> Result<Vec<Foe>> {
     Ok(results)
 }
 
+/// Waves are read from every tile layer named "Wave <turn>", holding foe tiles like the "Foes"
+/// layer. Their foes are numbered after the ones of the map, in the order of the layers.
+fn get_waves(
+    map: &tiled::Map,
+    stats: &HashMap<Position, FoeStats>,
+    first_id: FoeId,
+) -> Result<Vec<SpawnWave>> {
+    let mut waves = vec![];
+    let mut next_id = first_id;
+    for layer in map.layers() {
+        let Some(turn) = layer.name.strip_prefix("Wave") else {
+            continue;
+        };
+        let invalid = || anyhow!("Invalid wave layer '{}'", layer.name);
+        let turn = turn
+            .trim()
+            .parse()
+            .ok()
+            .filter(|turn| *turn > 0)
+            .ok_or_else(invalid)?;
+        let tiles = layer.as_tile_layer().ok_or_else(invalid)?;
+        let foes = get_foes(&tiles, stats, next_id)?;
+        next_id += foes.len();
+        waves.push(SpawnWave { turn, foes });
+    }
+    waves.sort_by_key(|wave| wave.turn);
+    Ok(waves)
+}
+
 #[allow(unused)]
-pub fn load_tutorial() -> Result<World> {
+pub fn load_tutorial(source: &MapSource) -> Result<World> {
     let tutos: Vec<&str> = STAGES
         .iter()
         .map(|stage| stage.0)
         .filter(|id| id.starts_with("tuto"))
         .collect();
 
-    load_world(&tutos, default_transition_resolver())
+    load_world(source, &tutos, default_transition_resolver())
 }
 
 #[allow(unused)]
-pub fn load_arena() -> Result<World> {
+pub fn load_arena(source: &MapSource) -> Result<World> {
     load_world(
+        source,
         &[
             "battlefield_1",
             "battlefield_2",
@@ -297,8 +702,9 @@ pub fn load_arena() -> Result<World> {
 }
 
 #[allow(unused)]
-pub fn load_default() -> Result<World> {
+pub fn load_default(source: &MapSource) -> Result<World> {
     load_world(
+        source,
         &[
             "hub",
             "tuto_self",
@@ -316,67 +722,201 @@ pub fn load_default() -> Result<World> {
             "timelab_blind_kill",
             "timelab_infinity",
         ],
-        Box::new(|world, stage_id, transition| {
-            let id = world.stages[stage_id].template.id.as_str();
-            match (id, transition) {
-                ("hub", Transition::Stairs(pos)) => {
-                    if pos.y > 20 {
-                        TransitionDestination::Stage(5)
-                    } else if pos.x > 20 {
-                        TransitionDestination::Stage(1)
-                    } else {
-                        TransitionDestination::Stage(10)
-                    }
-                }
-                ("battlefield_end" | "tuto_sight" | "timelab_infinity", _) => {
-                    TransitionDestination::End
-                }
-                _ => TransitionDestination::Stage(stage_id + 1),
-            }
-        }),
+        default_transition_resolver(),
     )
 }
 
-/// Creates a default transition resolver that advances linearly through stages
+/// Creates a default transition resolver that follows the destinations declared by the maps, and
+/// advances linearly through stages otherwise
 fn default_transition_resolver() -> TransitionResolver {
-    Box::new(|world, previous_stage, transition| match transition {
-        Transition::Orb | Transition::Stairs(_) => {
-            let max_stage = world.stages.len() - 1;
-            let next_stage = previous_stage + 1;
-            if next_stage > max_stage {
-                TransitionDestination::End
-            } else {
-                TransitionDestination::Stage(next_stage)
-            }
+    Box::new(|world, previous_stage, transition| {
+        let stage = world.stages[previous_stage].lock().unwrap();
+        let template = &stage.template;
+        if let Some(destination) = template
+            .transitions
+            .destination(&template.tiles, transition)
+        {
+            return destination;
+        }
+
+        let max_stage = world.stages.len() - 1;
+        let next_stage = previous_stage + 1;
+        if next_stage > max_stage {
+            TransitionDestination::End
+        } else {
+            TransitionDestination::Stage(next_stage)
         }
     })
 }
 
-pub fn load_world(stage_ids: &[&str], transition_resolver: TransitionResolver) -> Result<World> {
-    let mut stages = vec![];
-    let mut loader = Loader::with_reader(AssetsReader {});
+pub fn load_world(
+    source: &MapSource,
+    stage_ids: &[&str],
+    transition_resolver: TransitionResolver,
+) -> Result<World> {
+    Ok(World::new(
+        load_templates(source, stage_ids)?,
+        transition_resolver,
+    ))
+}
 
-    for id in stage_ids {
-        let map = loader.load_tmx_map(id)?;
-        let stage = convert_map(id.to_string(), &map)?;
+/// Templates of the given maps, in order, their transitions resolved between them
+pub fn load_templates(source: &MapSource, stage_ids: &[&str]) -> Result<Vec<StageTemplate>> {
+    let loaded = match source {
+        MapSource::Embedded => {
+            load_stages(Loader::with_reader(AssetsReader {}), stage_ids, |id| {
+                id.into()
+            })?
+        }
+        MapSource::Directory(dir) => {
+            load_stages(Loader::new(), stage_ids, |id| dir.join(format!("{id}.tmx")))?
+        }
+    };
+
+    // Resolve transitions now so that typos fail at load time
+    let stage_ids: HashMap<String, StageId> = loaded
+        .iter()
+        .enumerate()
+        .map(|(i, (stage, _))| (stage.id.clone(), i))
+        .collect();
+    let mut stages = vec![];
+    for (mut stage, names) in loaded {
+        stage.transitions = names
+            .resolve(&stage_ids)
+            .map_err(|e| anyhow!("{}: {e}", stage.id))?;
         stages.push(stage);
     }
+    Ok(stages)
+}
 
-    Ok(World::new(stages, transition_resolver))
+fn load_stages<R: ResourceReader>(
+    mut loader: Loader<DefaultResourceCache, R>,
+    stage_ids: &[&str],
+    path_for: impl Fn(&str) -> PathBuf,
+) -> Result<Vec<(StageTemplate, TransitionNames)>> {
+    let mut stages = vec![];
+    for id in stage_ids {
+        let path = path_for(id);
+        let map = loader
+            .load_tmx_map(&path)
+            .map_err(|e| anyhow!("Could not load map {id} from {}: {e}", path.display()))?;
+        let invalid = |e: anyhow::Error| anyhow!("Invalid map {id}: {e}");
+        let stage = convert_map(id.to_string(), &map).map_err(invalid)?;
+        stage.validate().map_err(invalid)?;
+        let transitions = get_transition_names(&map).map_err(invalid)?;
+        stages.push((stage, transitions));
+    }
+    Ok(stages)
 }
 
 #[cfg(test)]
 mod tests {
 
+    use std::path::Path;
+
     use super::*;
 
     #[test]
     fn load_world_test() {
-        let world = load_arena();
+        let world = load_arena(&MapSource::Embedded);
         assert!(world.is_ok());
 
         let world = world.unwrap();
-        assert!(world.stages.len() > 0);
+        assert!(!world.stages.is_empty());
+    }
+
+    #[test]
+    fn foe_stats_test() {
+        let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("fixtures");
+        let world = load_world(
+            &MapSource::Directory(dir),
+            &["foe_stats"],
+            default_transition_resolver(),
+        )
+        .unwrap();
+        let foes = &world.stages[0].lock().unwrap().template.foes;
+
+        // Elite foe on (1, 1), regular one on (3, 1)
+        let elite = foes.iter().find(|f| f.position == Position { x: 1, y: 1 });
+        let regular = foes.iter().find(|f| f.position == Position { x: 3, y: 1 });
+        assert_eq!(elite.map(|f| (f.hp, f.attack)), Some((5, 2)));
+        assert_eq!(regular.map(|f| (f.hp, f.attack)), Some((3, 2)));
+    }
+
+    #[test]
+    fn waves_test() {
+        let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("fixtures");
+        let world = load_world(
+            &MapSource::Directory(dir),
+            &["waves"],
+            default_transition_resolver(),
+        )
+        .unwrap();
+        let template = &world.stages[0].lock().unwrap().template;
+
+        // Wave foes are numbered after the one placed on the map
+        assert_eq!(template.foe_cap, Some(2));
+        assert_eq!(template.foes.len(), 1);
+        assert_eq!(template.waves.len(), 1);
+        assert_eq!(template.waves[0].turn, 3);
+        let ids: Vec<_> = template.waves[0].foes.iter().map(|f| f.id).collect();
+        assert_eq!(ids, vec![1, 2]);
+    }
+
+    #[test]
+    fn disconnected_map_test() {
+        let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("fixtures");
+        let world = load_world(
+            &MapSource::Directory(dir),
+            &["disconnected"],
+            default_transition_resolver(),
+        );
+        let error = world.err().map(|e| e.to_string()).unwrap_or_default();
+        assert!(error.contains("disconnected"), "{error}");
+    }
+
+    #[test]
+    fn second_tileset_test() {
+        let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("fixtures");
+        let world = load_world(
+            &MapSource::Directory(dir),
+            &["decor"],
+            default_transition_resolver(),
+        )
+        .unwrap();
+        let template = &world.stages[0].lock().unwrap().template;
+
+        // The same local ids stand for different tiles in each tileset
+        let tile = |x, y| template.tiles.get(Position { x, y });
+        assert_eq!(tile(0, 0), Tile::Wall);
+        assert_eq!(tile(2, 0), Tile::Pylon);
+        assert_eq!(tile(3, 0), Tile::Wall);
+        assert_eq!(tile(1, 1), Tile::Spawn);
+        assert_eq!(tile(2, 1), Tile::Empty);
+        assert_eq!(template.foes.len(), 1);
+        assert_eq!(template.foes[0].foe_type, FoeType::Dummy);
+
+        assert!(
+            AssetsReader {}
+                .read_from(Path::new("tileset/decor.tsx"))
+                .is_ok()
+        );
+        assert!(
+            AssetsReader {}
+                .read_from(Path::new("tileset/other.tsx"))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn parse_sense_costs_test() {
+        let costs = parse_sense_costs("Sight:0:2; Chrono:1:0;").unwrap();
+        assert_eq!(costs.sight, SenseCost::new(0, 2));
+        assert_eq!(costs.chrono, SenseCost::new(1, 0));
+        assert_eq!(costs.touch, CostTable::default().touch);
+        assert!(parse_sense_costs("Sight:1").is_err());
+        assert!(parse_sense_costs("Sight:-1:1").is_err());
+        assert!(parse_sense_costs("Sixth:1:1").is_err());
     }
 
     #[test]
@@ -384,11 +924,11 @@ mod tests {
         use losig_core::sense::SenseType;
         use losig_core::types::TimelineType;
 
-        let world = load_default();
+        let world = load_default(&MapSource::Embedded);
         assert!(world.is_ok());
 
         let world = world.unwrap();
-        let template = &world.stages[0].template;
+        let template = &world.stages[0].lock().unwrap().template;
 
         assert_eq!(template.name, "The hub");
         assert_eq!(template.fp_regen, 100);
@@ -403,5 +943,34 @@ mod tests {
                 SenseType::Hearing
             ]
         );
+        assert_eq!(template.hp_regen_interval, TURN_FOR_HP_REGEN);
+        assert_eq!(template.max_sight, Senses::MAX_SIGHT);
+        assert_eq!(template.author, "");
+        assert_eq!(template.difficulty, None);
+    }
+
+    #[test]
+    fn fp_regen_test() {
+        use bounded_integer::BoundedU8;
+        use losig_core::sense::Senses;
+        use losig_core::types::{ClientAction, FOCUS_MAX};
+
+        let world = load_default(&MapSource::Embedded).unwrap();
+        world.new_player(1, None).unwrap();
+        let stage = &mut world.stages[0].lock().unwrap();
+        let senses = Senses {
+            sight: BoundedU8::new(10).unwrap(),
+            ..Default::default()
+        };
+        let cost = senses.cost(&stage.template.costs);
+        assert!(cost > 0);
+
+        // The hub gives back all the focus spent every turn
+        for _ in 0..3 {
+            stage
+                .add_command(1, ClientAction::Wait, senses.clone())
+                .unwrap();
+            assert_eq!(stage.players[&1].focus, FOCUS_MAX - cost);
+        }
     }
 }
@@ -3,7 +3,7 @@ use crossterm::{
     execute,
     terminal::{EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode, enable_raw_mode},
 };
-use losig_core::types::{Offset, Position, Tile};
+use losig_core::types::{Offset, PlayerId, Position, StageId, Tile};
 use ratatui::{
     Frame, Terminal,
     backend::CrosstermBackend,
@@ -17,11 +17,19 @@ use crate::{services::Services, world::World};
 
 pub struct GameTui {
     services: Services,
+    /// Stage shown in the game view
+    stage_id: StageId,
+    /// Avatar the camera is locked onto, the map center otherwise
+    follow: Option<PlayerId>,
 }
 
 impl GameTui {
     pub fn new(services: Services) -> Self {
-        Self { services }
+        Self {
+            services,
+            stage_id: 0,
+            follow: None,
+        }
     }
 
     pub fn run(&mut self) -> Result<(), Box<dyn std::error::Error>> {
@@ -55,13 +63,41 @@ impl GameTui {
 
             if event::poll(std::time::Duration::from_millis(50))?
                 && let Event::Key(key) = event::read()?
-                && let KeyCode::Char('q') = key.code
             {
-                return Ok(());
+                match key.code {
+                    KeyCode::Char('q') => return Ok(()),
+                    KeyCode::Tab => self.cycle_stage(1),
+                    KeyCode::BackTab => self.cycle_stage(-1),
+                    KeyCode::Char('f') => self.cycle_follow(),
+                    KeyCode::Char('p') => self.services.set_paused(!self.services.is_paused()),
+                    _ => {}
+                }
             }
         }
     }
 
+    fn cycle_stage(&mut self, step: isize) {
+        let count = self.services.world.stages.len() as isize;
+        if count == 0 {
+            return;
+        }
+        self.stage_id = (self.stage_id as isize + step).rem_euclid(count) as StageId;
+        self.follow = None;
+    }
+
+    /// Locks the camera onto the next avatar of the stage, then back to the map center
+    fn cycle_follow(&mut self) {
+        let Some(stage) = self.services.world.stages.get(self.stage_id) else {
+            return;
+        };
+        let stage = stage.lock().unwrap();
+        let mut avatars = stage.head_state().avatars.keys().copied();
+        self.follow = match self.follow {
+            None => avatars.next(),
+            Some(current) => avatars.find(|pid| *pid > current),
+        };
+    }
+
     fn ui(&self, f: &mut Frame) {
         let chunks = Layout::default()
             .direction(Direction::Horizontal)
@@ -77,8 +113,15 @@ impl GameTui {
         self.render_game_view(left_chunks[0], f.buffer_mut());
 
         // Status bar
-        let status = Paragraph::new("Press 'q' to quit")
-            .block(Block::default().borders(Borders::ALL).title("Status"));
+        let status_title = if self.services.is_paused() {
+            "Status - PAUSED"
+        } else {
+            "Status"
+        };
+        let status = Paragraph::new(
+            "q: quit | Tab/Shift+Tab: change stage | f: follow an avatar | p: pause/resume",
+        )
+        .block(Block::default().borders(Borders::ALL).title(status_title));
         f.render_widget(status, left_chunks[1]);
 
         // Logs panel - using tui-logger
@@ -91,14 +134,22 @@ impl GameTui {
     }
 
     fn render_game_view(&self, area: Rect, buf: &mut Buffer) {
-        let world = self.services.world.lock().unwrap();
-        let borders = Block::default().borders(Borders::all()).title("Game View");
+        let world = &self.services.world;
+        let Some((stage_id, viewer)) = self.get_view_center(world) else {
+            return;
+        };
+
+        let stage = world.stages[stage_id].lock().unwrap();
+        let mut title = format!("Game View - {} ({stage_id})", stage.template.name);
+        if let Some(pid) = self.follow {
+            title.push_str(&format!(" following {pid}"));
+        }
+        let borders = Block::default().borders(Borders::all()).title(title);
 
         let inner = borders.inner(area);
         borders.render(area, buf);
         let area = inner;
 
-        let (stage_id, viewer) = self.get_view_center(&world);
         let area_offset = Offset {
             x: (area.width / 2) as isize,
             y: (area.height / 2) as isize,
@@ -106,7 +157,6 @@ impl GameTui {
 
         let offset = viewer.as_offset() - area_offset;
 
-        let stage = world.stages.get(stage_id).unwrap();
         let tiles = &stage.template.tiles;
         for x in 0..area.width as usize {
             for y in 0..area.height as usize {
@@ -137,18 +187,18 @@ impl GameTui {
         // Convert from world ref to view ref
         let offset = -offset;
         for avatar in state.avatars.values() {
-            let position = avatar.position;
-
-            let Position { x, y } = position + offset;
-            buf.set_string(
-                area.x + x as u16,
-                area.y + y as u16,
-                "@",
-                Style::default().green(),
-            );
+            let Position { x, y } = avatar.position + offset;
+            if (0..area.width).contains(&(x as u16)) && (0..area.height).contains(&(y as u16)) {
+                let style = if self.follow == Some(avatar.player_id) {
+                    Style::default().green().reversed()
+                } else {
+                    Style::default().green()
+                };
+                buf.set_string(area.x + x as u16, area.y + y as u16, "@", style);
+            }
         }
 
-        if let Some(ref orb) = state.orb {
+        for orb in state.orbs.iter() {
             let Position { x, y } = orb.position + offset;
 
             if (0..area.width).contains(&(x as u16)) && (0..area.height).contains(&(y as u16)) {
@@ -174,8 +224,17 @@ impl GameTui {
         }
     }
 
-    fn get_view_center(&self, world: &World) -> (usize, Position) {
-        let stage = world.stages.first().unwrap();
-        (0, stage.template.tiles.center())
+    /// The followed avatar if it is still there, the map center otherwise
+    fn get_view_center(&self, world: &World) -> Option<(StageId, Position)> {
+        let stage_id = self.stage_id.min(world.stages.len().checked_sub(1)?);
+        let stage = world.stages[stage_id].lock().unwrap();
+        let followed = self
+            .follow
+            .and_then(|pid| stage.head_state().avatars.get(&pid))
+            .map(|avatar| avatar.position);
+        Some((
+            stage_id,
+            followed.unwrap_or_else(|| stage.template.tiles.center()),
+        ))
     }
 }
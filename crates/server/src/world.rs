@@ -1,56 +1,280 @@
-use std::collections::BTreeMap;
+use std::{
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
+    sync::{
+        Mutex, MutexGuard,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::{Duration, Instant},
+};
 
 use anyhow::{Result, anyhow};
-use grid::Grid;
 use log::{info, warn};
 use losig_core::{
     events::GEvent,
-    network::StageInfo,
-    sense::{SenseType, Senses, SensesInfo},
+    leaderboard::sanitize_name,
+    network::{
+        AvatarSnapshot, FoeSnapshot, LobbyMessage, ResyncMessage, SpectateMessage, StageInfo,
+        StageSnapshot,
+    },
+    sense::{CostTable, SenseType, Senses, SensesInfo},
     types::{
-        Avatar, ClientAction, Foe, GameOver, GameOverStatus, PlayerId, ServerAction, StageId,
-        StageTurn, Tiles, Timeline, TimelineType, Transition,
+        Avatar, ClientAction, Foe, FoeId, GameOver, GameOverStatus, Offset, PlayerId, Position,
+        ServerAction, StageId, StageTurn, TURN_FOR_HP_REGEN, Tile, Tiles, Timeline, TimelineType,
+        Transition, Turn,
     },
 };
 
-use crate::stage::Stage;
+use crate::{
+    sense,
+    stage::{Stage, StageCommandResult},
+};
+
+/// How far a claimed turn may be from the expected one, a command lost shifts the count of an
+/// honest client until it resyncs
+const TURN_TOLERANCE: Turn = 2;
+
+/// Default percentage of the timeline length past which lagging players are warned
+pub const BEHIND_WARNING: u32 = 80;
+/// Default number of turns idle players are made to wait before being left alone
+pub const AUTO_WAIT_TURNS: u32 = 10;
 
 /// Data of a stage that can not change with time or action players
 #[derive(Debug, Clone)]
 pub struct StageTemplate {
     pub id: String,
     pub name: String,
+    /// Who made the map, empty if unknown
+    pub author: String,
+    /// From 1 to DIFFICULTY_MAX, None if unknown
+    pub difficulty: Option<u8>,
+    /// One line presenting the stage to the players entering it
+    pub description: String,
     pub tiles: Tiles,
-    pub orb_spawns: Option<Grid<bool>>,
+    /// Cells where the orb can spawn, with their weight
+    pub orb_spawns: Vec<(Position, u32)>,
     pub foes: Vec<Foe>,
+    /// Focus regained every turn
     pub fp_regen: u32,
+    /// Turns between two HP regained by avatars
+    pub hp_regen_interval: u64,
+    /// Focus regained per turn when next to a pylon
+    pub pylon_regen: u32,
     pub senses: Vec<SenseType>,
+    /// Sight and hearing can't be raised past these, whatever the focus
+    pub max_sight: u8,
+    pub max_hearing: u8,
+    /// Focus spent by the senses every turn
+    pub costs: CostTable,
     pub timeline_length: u32,
+    /// Percentage of the timeline length past which players lagging behind the head are warned
+    pub behind_warning: u32,
     pub timeline_type: TimelineType,
+    pub allow_diagonal: bool,
+    pub transitions: StageTransitions,
+    /// Pickups raising the attack of the avatar stepping on them
+    pub shards: Vec<Position>,
+    /// Allies sharing their senses see what the others see
+    pub team_vision: bool,
+    /// Entities blocking the sight of the avatars
+    pub occlusion: Occlusion,
+    /// Senses that can't be used strongly several turns in a row
+    pub cooldowns: Vec<SenseCooldown>,
+    /// Avatars moving into each other attack instead of being blocked
+    pub pvp: bool,
+    /// Turns the orb stays in place once excited
+    pub orb_delay: u32,
+    /// Orbs on the stage at the same time, picking any of them triggers the transition
+    pub orb_count: u32,
+    /// Players who don't act for this long wait a turn, once per period, so that they don't
+    /// pin their allies
+    pub auto_wait: Option<Duration>,
+    /// Turns an idle player can be made to wait in a row
+    pub auto_wait_turns: u32,
+    /// Stretches the hearing distances on large maps
+    pub scale_hearing: bool,
+    /// Foes joining the stage during the run, by turn
+    pub waves: Vec<SpawnWave>,
+    /// Living foes, traps aside, past which the waves spawn nobody
+    pub foe_cap: Option<u32>,
+    /// HP and focus of the avatars entering the stage instead of the maxima, which they can't
+    /// exceed
+    pub start_hp: Option<u8>,
+    pub start_focus: Option<u8>,
+}
+
+/// Foes spawning once the stage reaches a turn. Their ids are set by the template, after the
+/// ones of the foes placed on the map, so that replays spawn them the same way.
+#[derive(Debug, Clone)]
+pub struct SpawnWave {
+    /// At least 1, the foes of the first turn are placed on the map
+    pub turn: StageTurn,
+    pub foes: Vec<Foe>,
+}
+
+/// Once used at this strength or more, a sense is lowered under it for the next turns
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SenseCooldown {
+    pub sense: SenseType,
+    /// At least 1
+    pub strength: u8,
+    pub turns: u32,
+}
+
+/// What blocks the sight besides walls and pylons
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Occlusion {
+    #[default]
+    None,
+    /// Living foes
+    Foes,
+    /// Living foes and avatars
+    All,
 }
 
 impl StageTemplate {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         id: String,
         name: String,
         tiles: Tiles,
-        orb_spawns: Option<Grid<bool>>,
+        orb_spawns: Vec<(Position, u32)>,
         foes: Vec<Foe>,
         fp_regen: u32,
+        pylon_regen: u32,
         senses: Vec<SenseType>,
         timeline_length: u32,
         timeline_type: TimelineType,
+        allow_diagonal: bool,
     ) -> Self {
         Self {
             id,
             name,
+            author: String::new(),
+            difficulty: None,
+            description: String::new(),
             tiles,
             foes,
             orb_spawns,
             fp_regen,
+            pylon_regen,
             senses,
             timeline_length,
+            behind_warning: BEHIND_WARNING,
             timeline_type,
+            allow_diagonal,
+            transitions: Default::default(),
+            shards: vec![],
+            team_vision: false,
+            pvp: false,
+            occlusion: Occlusion::None,
+            cooldowns: vec![],
+            orb_delay: 1,
+            orb_count: 1,
+            auto_wait: None,
+            auto_wait_turns: AUTO_WAIT_TURNS,
+            scale_hearing: false,
+            waves: vec![],
+            foe_cap: None,
+            start_hp: None,
+            start_focus: None,
+            hp_regen_interval: TURN_FOR_HP_REGEN,
+            max_sight: Senses::MAX_SIGHT,
+            max_hearing: Senses::MAX_HEARING,
+            costs: CostTable::default(),
+        }
+    }
+
+    /// Foe as placed on the map or in its wave, before the stage starts
+    pub fn initial_foe(&self, id: FoeId) -> Option<&Foe> {
+        self.foes
+            .iter()
+            .chain(self.waves.iter().flat_map(|wave| wave.foes.iter()))
+            .find(|foe| foe.id == id)
+    }
+
+    /// Longest distance between two cells, distances being the number of moves with diagonals
+    pub fn map_diagonal(&self) -> u16 {
+        self.tiles.width().max(self.tiles.height()) as u16
+    }
+
+    /// Checks that the stage is playable: there is a spawn, a way out (orb spawn or stairs) and
+    /// every spawn can walk to one of them.
+    pub fn validate(&self) -> Result<()> {
+        let positions = (0..self.tiles.width())
+            .flat_map(|x| (0..self.tiles.height()).map(move |y| Position { x, y }));
+        let spawns: Vec<Position> = positions
+            .clone()
+            .filter(|pos| self.tiles.get(*pos) == Tile::Spawn)
+            .collect();
+        let exits: HashSet<Position> = positions
+            .filter(|pos| matches!(self.tiles.get(*pos), Tile::StairUp | Tile::StairDown))
+            .chain(self.orb_spawns.iter().map(|(pos, _)| *pos))
+            .collect();
+
+        if spawns.is_empty() {
+            return Err(anyhow!("No spawn"));
+        }
+        if exits.is_empty() {
+            return Err(anyhow!("No orb spawn nor stairs"));
+        }
+
+        for spawn in spawns {
+            if !self.reaches(spawn, &exits) {
+                return Err(anyhow!("Spawn {spawn} can't reach any orb spawn or stairs"));
+            }
+        }
+        Ok(())
+    }
+
+    /// BFS over travelable tiles
+    fn reaches(&self, from: Position, targets: &HashSet<Position>) -> bool {
+        let mut visited = HashSet::from([from]);
+        let mut queue = VecDeque::from([from]);
+
+        while let Some(pos) = queue.pop_front() {
+            if targets.contains(&pos) {
+                return true;
+            }
+            for x in -1..2 {
+                for y in -1..2 {
+                    let next = pos + Offset { x, y };
+                    if next.x < self.tiles.width()
+                        && next.y < self.tiles.height()
+                        && self.tiles.get(next).can_travel()
+                        && visited.insert(next)
+                    {
+                        queue.push_back(next);
+                    }
+                }
+            }
+        }
+        false
+    }
+}
+
+/// Destinations declared by a stage map. Undeclared transitions are left to the resolver.
+#[derive(Debug, Clone, Default)]
+pub struct StageTransitions {
+    pub orb: Option<TransitionDestination>,
+    pub stairs_up: Option<TransitionDestination>,
+    pub stairs_down: Option<TransitionDestination>,
+    /// Destinations of specific stairs, taking precedence over the stairs kind
+    pub stairs: HashMap<Position, TransitionDestination>,
+}
+
+impl StageTransitions {
+    pub fn destination(
+        &self,
+        tiles: &Tiles,
+        transition: Transition,
+    ) -> Option<TransitionDestination> {
+        match transition {
+            Transition::Orb => self.orb,
+            Transition::Stairs(pos) => self.stairs.get(&pos).copied().or(match tiles.get(pos) {
+                Tile::StairUp => self.stairs_up,
+                Tile::StairDown => self.stairs_down,
+                _ => None,
+            }),
         }
     }
 }
@@ -62,15 +286,27 @@ impl From<&StageTemplate> for StageInfo {
             timeline_length: value.timeline_length,
             timeline_type: value.timeline_type,
             senses: value.senses.clone(),
+            allow_diagonal: value.allow_diagonal,
+            team_vision: value.team_vision,
+            max_sight: value.max_sight,
+            max_hearing: value.max_hearing,
+            costs: value.costs.clone(),
+            author: value.author.clone(),
+            difficulty: value.difficulty,
+            description: value.description.clone(),
+            hearing_diagonal: value.scale_hearing.then(|| value.map_diagonal()),
         }
     }
 }
 
+#[allow(clippy::large_enum_variant)]
 pub enum Limbo {
     Dead(PlayerId),
     MaybeDead(PlayerId),
     Averted(PlayerId, SensesInfo),
     TooFarBehind(PlayerId),
+    /// The player is close to being too far behind, with the turns left before it happens
+    FallingBehind(PlayerId, StageTurn),
 }
 
 /// Info returned by add_command. Game over data might concern other players as they can be saved
@@ -100,11 +336,19 @@ pub enum CommandResultOutcome {
     Gameover(GameOver),
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum TransitionDestination {
     Stage(StageId),
     End,
 }
 
+#[allow(clippy::large_enum_variant)]
+pub enum ResyncOutcome {
+    Playing(ResyncMessage),
+    Gameover(GameOver),
+}
+
+#[derive(Clone)]
 pub struct Player {
     pub id: PlayerId,
     pub name: String,
@@ -112,121 +356,311 @@ pub struct Player {
     /// Copy of the last avatar sent to a stage
     pub last_avatar: Avatar,
     pub gameover: Option<GameOver>,
+    /// Focus paid for senses over the whole game
+    pub focus_spent: u64,
+    pub turns_played: Turn,
+    /// Kept from one stage to the next
+    pub share_senses: bool,
+    /// Turn the client should be at, counted as it does: one per command, going back one on undo
+    pub turn: Turn,
 }
 
+impl Player {
+    /// Turn of the client once it sent the action
+    fn next_turn(&self, action: ClientAction) -> Turn {
+        match action {
+            ClientAction::Undo => self.turn.saturating_sub(1),
+            ClientAction::Sense => self.turn,
+            _ => self.turn + 1,
+        }
+    }
+
+    fn record_focus(&mut self, scr: &StageCommandResult) {
+        self.focus_spent += scr.focus_spent;
+        self.turns_played += scr.turns_played;
+    }
+
+    fn to_gameover(
+        &self,
+        status: GameOverStatus,
+        stage_id: StageId,
+        seed: Option<u64>,
+    ) -> GameOver {
+        GameOver::new(&self.last_avatar, status, stage_id)
+            .with_focus(self.focus_spent, self.turns_played)
+            .with_seed(seed)
+    }
+}
+
+/// Stages are locked independently so that a long rollback in one stage doesn't block the others.
+/// To avoid deadlocks, several stages are only held when taken in index order, and the stages are
+/// always taken before `player_by_id`, never the other way around.
 pub struct World {
-    pub player_by_id: BTreeMap<PlayerId, Player>,
-    pub stages: Vec<Stage>,
-    pub name_gen: usize,
+    pub player_by_id: Mutex<BTreeMap<PlayerId, Player>>,
+    pub stages: Vec<Mutex<Stage>>,
+    name_gen: AtomicUsize,
     pub transition_resolver: TransitionResolver,
+    /// Seed fixed for every stage, e.g. for a daily challenge
+    pub seed: Option<u64>,
 }
 
+/// Called with no lock held
 pub type TransitionResolver =
-    Box<dyn Fn(&World, usize, Transition) -> TransitionDestination + Send + 'static>;
+    Box<dyn Fn(&World, usize, Transition) -> TransitionDestination + Send + Sync + 'static>;
 
 impl World {
     pub fn new(stages: Vec<StageTemplate>, transition_resolver: TransitionResolver) -> Self {
         World {
-            stages: stages.into_iter().map(Stage::new).collect(),
+            stages: stages
+                .into_iter()
+                .map(|template| Mutex::new(Stage::new(template)))
+                .collect(),
             player_by_id: Default::default(),
-            name_gen: 0,
+            name_gen: AtomicUsize::new(0),
             transition_resolver,
+            seed: None,
         }
     }
 
-    pub fn new_player(&mut self, pid: PlayerId, name: Option<String>) -> Result<CommandResult> {
+    /// Rebuilds every stage on the given seed, so that all games spawn orbs the same way
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        for stage in &mut self.stages {
+            let stage = stage.get_mut().unwrap();
+            *stage = Stage::new_with_seed(stage.template.clone(), seed);
+        }
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Logs the rollbacks replaying at least this many turns
+    pub fn with_rollback_log(mut self, min_depth: Turn) -> Self {
+        for stage in &mut self.stages {
+            stage.get_mut().unwrap().log_rollbacks = Some(min_depth);
+        }
+        self
+    }
+
+    pub fn players(&self) -> MutexGuard<'_, BTreeMap<PlayerId, Player>> {
+        self.player_by_id.lock().unwrap()
+    }
+
+    pub fn stage(&self, stage_id: StageId) -> Result<MutexGuard<'_, Stage>> {
+        self.stages
+            .get(stage_id)
+            .map(|stage| stage.lock().unwrap())
+            .ok_or_else(|| anyhow!("Stage not found"))
+    }
+
+    /// Stage the player is currently in
+    pub fn player_stage(&self, pid: PlayerId) -> Result<StageId> {
+        self.players()
+            .get(&pid)
+            .ok_or_else(|| anyhow!("No player #{pid} found."))?
+            .stage
+            .ok_or_else(|| anyhow!("Player #{pid} is not in a stage"))
+    }
+
+    pub fn new_player(&self, pid: PlayerId, name: Option<String>) -> Result<CommandResult> {
         // Retire player if present
         self.retire_player(pid);
 
-        let name = match name {
+        let name = match name.as_deref().and_then(sanitize_name) {
             Some(name) => name,
-            None => {
-                self.name_gen += 1;
-                format!("P{}", self.name_gen)
-            }
+            None => format!("P{}", self.name_gen.fetch_add(1, Ordering::Relaxed) + 1),
         };
         info!("New player #{pid} created.");
-        let new_player = Player {
+        let mut new_player = Player {
             id: pid,
             name,
             stage: Some(0),
             last_avatar: Avatar::new(pid),
             gameover: None,
+            focus_spent: 0,
+            turns_played: 0,
+            share_senses: false,
+            turn: 1,
         };
 
-        let stage = &mut self.stages[0];
+        let mut stage = self.stage(0)?;
         let scr = stage.add_player(&new_player, Senses::default())?;
+        new_player.record_focus(&scr);
+        let result = Self::entered(0, &stage, scr);
+        drop(stage);
 
-        self.player_by_id.insert(pid, new_player);
+        self.players().insert(pid, new_player);
+        Ok(result)
+    }
 
-        Ok(CommandResult {
-            limbos: scr.limbos,
-            timeline_updates: vec![(0, scr.timeline)],
-            outcome: CommandResultOutcome::Transition {
-                stage_id: 0,
-                stage_info: (&stage.template).into(),
-                stage_turn: scr.stage_turn,
-                info: scr.senses_info,
-                timeline: scr.timeline,
-            },
-        })
+    pub fn lobby(&self) -> LobbyMessage {
+        let online = self
+            .players()
+            .values()
+            .filter(|player| player.stage.is_some())
+            .count() as u32;
+        let per_stage = self
+            .stages
+            .iter()
+            .enumerate()
+            .map(|(stage_id, stage)| (stage_id, stage.lock().unwrap().players.len() as u32))
+            .filter(|(_, count)| *count > 0)
+            .collect();
+
+        LobbyMessage {
+            online,
+            per_stage,
+            stages: self.stages.len(),
+        }
     }
 
-    pub fn retire_player(&mut self, pid: PlayerId) -> Option<GameOver> {
-        let player = self.player_by_id.remove(&pid)?;
+    pub fn retire_player(&self, pid: PlayerId) -> Option<GameOver> {
+        let player = self.players().remove(&pid)?;
 
         if let Some(stage_id) = player.stage {
-            self.stages.get_mut(stage_id)?.remove_player(pid)?;
-            Some(GameOver::new(
-                &player.last_avatar,
-                GameOverStatus::Dead,
-                stage_id,
-            ))
+            self.stage(stage_id).ok()?.remove_player(pid)?;
+            Some(player.to_gameover(GameOverStatus::Dead, stage_id, self.seed))
         } else {
             player.gameover
         }
     }
 
+    /// Counts every player in a stage as active now
+    pub fn refresh_activity(&self) {
+        let now = Instant::now();
+        for stage in self.stages.iter() {
+            for player in stage.lock().unwrap().players.values_mut() {
+                player.last_activity = now;
+            }
+        }
+    }
+
+    /// Retires the players who didn't play for `timeout` so that their stage history can be
+    /// freed, and resets the stages they left empty
+    pub fn retire_idle(&self, timeout: Duration) -> Vec<(PlayerId, GameOver)> {
+        let idle: Vec<PlayerId> = self
+            .stages
+            .iter()
+            .flat_map(|stage| stage.lock().unwrap().idle_players(timeout))
+            .collect();
+
+        let retired = idle
+            .into_iter()
+            .filter_map(|pid| {
+                info!("Retiring idle player #{pid}.");
+                Some((pid, self.retire_player(pid)?))
+            })
+            .collect();
+
+        for stage in self.stages.iter() {
+            let mut stage = stage.lock().unwrap();
+            if stage.players.is_empty() && stage.head_turn > 0 {
+                stage.reset();
+            }
+        }
+        retired
+    }
+
+    /// Checks the turn claimed by a client against the one the server counted for it
+    pub fn check_turn(&self, pid: PlayerId, turn: Turn, action: ClientAction) -> Result<()> {
+        let players = self.players();
+        let player = players
+            .get(&pid)
+            .ok_or_else(|| anyhow!("No player #{pid} found."))?;
+
+        let expected = player.next_turn(action);
+        if turn.abs_diff(expected) > TURN_TOLERANCE {
+            return Err(anyhow!(
+                "Player #{pid} claimed turn {turn} instead of {expected}"
+            ));
+        }
+        Ok(())
+    }
+
     pub fn add_command(
-        &mut self,
+        &self,
         pid: PlayerId,
         action: ClientAction,
         senses: Senses,
     ) -> Result<CommandResult> {
-        let player = self
-            .player_by_id
-            .get(&pid)
-            .ok_or_else(|| anyhow!("No player #{pid} found."))?;
-        let stage_id = player
-            .stage
-            .ok_or_else(|| anyhow!("Trying to transition when not in a stage"))?;
-        let stage = self
+        let result = self.play(pid, senses.clone(), |stage| {
+            stage.add_command(pid, action, senses)
+        })?;
+        if let Some(player) = self.players().get_mut(&pid) {
+            player.turn = player.next_turn(action);
+        }
+        Ok(result)
+    }
+
+    /// Makes the players idle for longer than their stage allows wait a turn
+    pub fn auto_wait(&self) -> Vec<(PlayerId, CommandResult)> {
+        let idle: Vec<PlayerId> = self
             .stages
-            .get_mut(stage_id)
-            .ok_or_else(|| anyhow!("Stage not found"))?;
+            .iter()
+            .flat_map(|stage| stage.lock().unwrap().auto_wait_players())
+            .collect();
+
+        idle.into_iter()
+            .filter_map(|pid| {
+                // Used to enter the next stage if the wait is the one leaving this one
+                let senses = self
+                    .stage(self.player_stage(pid).ok()?)
+                    .ok()?
+                    .last_senses(pid)
+                    .cloned()
+                    .unwrap_or_default();
+                match self.play(pid, senses, |stage| stage.auto_wait(pid)) {
+                    Ok(result) => Some((pid, result)),
+                    Err(e) => {
+                        warn!("Could not make player #{pid} wait: {e}");
+                        None
+                    }
+                }
+            })
+            .collect()
+    }
+
+    /// Plays a turn of the player in its stage, moving it to the next one if the turn made it
+    /// leave
+    fn play(
+        &self,
+        pid: PlayerId,
+        senses: Senses,
+        turn: impl FnOnce(&mut Stage) -> Result<StageCommandResult>,
+    ) -> Result<CommandResult> {
+        let stage_id = self.player_stage(pid)?;
+        let mut stage = self.stage(stage_id)?;
 
-        let scr = stage.add_command(pid, action, senses.clone())?;
+        let scr = turn(&mut stage)?;
+        if let Some(player) = self.players().get_mut(&pid) {
+            player.record_focus(&scr);
+        }
         let timeline_updates = vec![(stage_id, scr.timeline)];
 
         let result = if let Some(transition) = &scr.transition {
-            match self.handle_transition(pid, stage_id, *transition, senses) {
-                Ok(mut tr_scr) => {
-                    tr_scr.limbos.extend(scr.limbos);
-                    tr_scr.timeline_updates.extend(timeline_updates);
-                    tr_scr
-                }
-                Err(e) => {
-                    return Err(e);
-                }
-            }
+            stage
+                .remove_player(pid)
+                .ok_or_else(|| anyhow!("Couldn't find avatar {pid} in stage for transition"))?;
+            let limbos_from_leave = stage.handle_limbo();
+            // The next stage is locked by the transition
+            drop(stage);
+
+            let mut tr_scr = self.handle_transition(pid, stage_id, *transition, senses)?;
+            tr_scr.limbos.extend(limbos_from_leave);
+            tr_scr.limbos.extend(scr.limbos);
+            tr_scr.timeline_updates.extend(timeline_updates);
+            tr_scr
         } else {
+            let mut info = scr.senses_info;
+            if let Some(info) = info.as_mut() {
+                stage.compress_sight(pid, scr.stage_turn, info);
+            }
+            drop(stage);
             CommandResult {
                 limbos: scr.limbos,
                 timeline_updates,
                 outcome: CommandResultOutcome::Turn {
                     stage: stage_id,
                     stage_turn: scr.stage_turn,
-                    info: scr.senses_info,
+                    info,
                     action: scr.action,
                     events: scr.events,
                     timeline: scr.timeline,
@@ -236,33 +670,32 @@ impl World {
 
         self.handle_limbos(&result.limbos, stage_id);
 
-        let stage = &mut self.stages[stage_id];
+        let mut stage = self.stage(stage_id)?;
         if stage.players.is_empty() {
             stage.reset();
         }
         Ok(result)
     }
 
-    fn handle_limbos(&mut self, limbos: &[Limbo], stage_id: StageId) {
+    fn handle_limbos(&self, limbos: &[Limbo], stage_id: StageId) {
+        let mut players = self.players();
         for status in limbos {
             if let Limbo::Dead(player_id) = status {
-                let Some(player) = self.player_by_id.get_mut(player_id) else {
+                let Some(player) = players.get_mut(player_id) else {
                     warn!("Could not find player {player_id} for handling limbo");
                     continue;
                 };
 
-                player.gameover = Some(GameOver::new(
-                    &player.last_avatar,
-                    GameOverStatus::Dead,
-                    stage_id,
-                ));
+                player.gameover =
+                    Some(player.to_gameover(GameOverStatus::Dead, stage_id, self.seed));
                 player.stage = None;
             }
         }
     }
 
+    /// Moves a player that already left its stage to the next one
     fn handle_transition(
-        &mut self,
+        &self,
         pid: PlayerId,
         stage_id: StageId,
         transition: Transition,
@@ -270,29 +703,15 @@ impl World {
     ) -> Result<CommandResult> {
         let destination = (self.transition_resolver)(self, stage_id, transition);
 
-        let player = self
-            .player_by_id
+        let mut players = self.players();
+        let player = players
             .get_mut(&pid)
             .ok_or_else(|| anyhow!("Player not found."))?;
 
-        let stage_id = player
-            .stage
-            .ok_or_else(|| anyhow!("Trying to transition when not in a stage"))?;
-        let stage = self
-            .stages
-            .get_mut(stage_id)
-            .ok_or_else(|| anyhow!("Stage not found"))?;
-
-        stage
-            .remove_player(pid)
-            .ok_or_else(|| anyhow!("Couldn't find avatar {pid} in stage for transition"))?;
-
-        let limbos_from_leave = stage.handle_limbo();
-
         match destination {
             TransitionDestination::End => {
                 player.stage = None;
-                let gameover = GameOver::new(&player.last_avatar, GameOverStatus::Win, stage_id);
+                let gameover = player.to_gameover(GameOverStatus::Win, stage_id, self.seed);
                 player.gameover = Some(gameover.clone());
                 Ok(CommandResult {
                     limbos: vec![],
@@ -301,32 +720,233 @@ impl World {
                 })
             }
             TransitionDestination::Stage(stage_id) => {
-                player.stage = Some(stage_id);
-                let next_stage = &mut self.stages[stage_id];
+                drop(players);
+                self.enter_stage(pid, stage_id, senses)
+            }
+        }
+    }
 
-                let mut scr = next_stage.add_player(player, senses.clone())?;
-                scr.limbos.extend(limbos_from_leave);
+    /// Adds a player that is in no stage anymore to this one
+    fn enter_stage(
+        &self,
+        pid: PlayerId,
+        stage_id: StageId,
+        senses: Senses,
+    ) -> Result<CommandResult> {
+        let player = {
+            let mut players = self.players();
+            let player = players
+                .get_mut(&pid)
+                .ok_or_else(|| anyhow!("Player not found."))?;
+            player.stage = Some(stage_id);
+            player.clone()
+        };
 
-                Ok(CommandResult {
-                    limbos: scr.limbos,
-                    timeline_updates: vec![(stage_id, scr.timeline)],
-                    outcome: CommandResultOutcome::Transition {
-                        stage_id,
-                        stage_info: (&next_stage.template).into(),
-                        stage_turn: scr.stage_turn,
-                        info: scr.senses_info,
-                        timeline: scr.timeline,
-                    },
-                })
-            }
+        let mut next_stage = self.stage(stage_id)?;
+        let scr = next_stage.add_player(&player, senses)?;
+        if let Some(player) = self.players().get_mut(&pid) {
+            player.record_focus(&scr);
+        }
+
+        Ok(Self::entered(stage_id, &next_stage, scr))
+    }
+
+    /// What is sent to a player that just entered the stage
+    fn entered(stage_id: StageId, stage: &Stage, scr: StageCommandResult) -> CommandResult {
+        CommandResult {
+            limbos: scr.limbos,
+            timeline_updates: vec![(stage_id, scr.timeline)],
+            outcome: CommandResultOutcome::Transition {
+                stage_id,
+                stage_info: (&stage.template).into(),
+                stage_turn: scr.stage_turn,
+                info: scr.senses_info,
+                timeline: scr.timeline,
+            },
         }
     }
+
+    /// Ids of the maps played on each stage
+    pub fn map_set(&self) -> Vec<String> {
+        self.stages
+            .iter()
+            .map(|stage| stage.lock().unwrap().template.id.clone())
+            .collect()
+    }
+
+    /// Replaces the maps of every stage, e.g. to rotate them without losing the players and the
+    /// leaderboard. There must be as many maps as stages, each stage having its own worker. The
+    /// players in a stage start over in the first new one, with the result of their transition.
+    /// Players who could not enter it are left out of any stage.
+    pub fn swap_maps(
+        &self,
+        templates: Vec<StageTemplate>,
+    ) -> Result<Vec<(PlayerId, Result<CommandResult>)>> {
+        if templates.len() != self.stages.len() {
+            return Err(anyhow!(
+                "Expected {} maps, got {}",
+                self.stages.len(),
+                templates.len()
+            ));
+        }
+
+        // Stages are locked before the players, as the turns do. Players are held until
+        // they are all moved, so none joins or leaves the stages being swapped.
+        let mut stages: Vec<MutexGuard<'_, Stage>> = self
+            .stages
+            .iter()
+            .map(|stage| stage.lock().unwrap())
+            .collect();
+        let mut players = self.players();
+        for (stage, template) in stages.iter_mut().zip(templates) {
+            stage.reset_with(template);
+        }
+
+        let first = &mut stages[0];
+        let mut moved = vec![];
+        for player in players.values_mut().filter(|player| player.stage.is_some()) {
+            player.stage = Some(0);
+            let result = match first.add_player(player, Senses::default()) {
+                Ok(scr) => {
+                    player.record_focus(&scr);
+                    Ok(Self::entered(0, first, scr))
+                }
+                Err(e) => {
+                    player.stage = None;
+                    Err(e)
+                }
+            };
+            moved.push((player.id, result));
+        }
+        Ok(moved)
+    }
+
+    /// Moves the player straight to another stage, as if it had picked the orb. For testing.
+    pub fn warp(&self, pid: PlayerId, stage_id: StageId) -> Result<CommandResult> {
+        if stage_id >= self.stages.len() {
+            return Err(anyhow!("Stage {stage_id} not found"));
+        }
+        let from = self.player_stage(pid)?;
+
+        let mut stage = self.stage(from)?;
+        stage
+            .remove_player(pid)
+            .ok_or_else(|| anyhow!("Couldn't find avatar {pid} in stage {from}"))?;
+        let limbos = stage.handle_limbo();
+        let timeline = stage.timeline();
+        if stage.players.is_empty() {
+            stage.reset();
+        }
+        drop(stage);
+
+        let mut result = self.enter_stage(pid, stage_id, Senses::default())?;
+        result.limbos.extend(limbos);
+        result.timeline_updates.push((from, timeline));
+        self.handle_limbos(&result.limbos, from);
+        Ok(result)
+    }
+
+    pub fn share_senses(&self, pid: PlayerId, share: bool) -> Result<()> {
+        let stage_id = self.player_stage(pid)?;
+        let mut stage = self.stage(stage_id)?;
+        if let Some(player) = stage.players.get_mut(&pid) {
+            player.share_senses = share;
+        }
+        if let Some(player) = self.players().get_mut(&pid) {
+            player.share_senses = share;
+        }
+        Ok(())
+    }
+
+    /// Current state of the player, or its gameover if it is not in a stage anymore
+    pub fn resync(&self, pid: PlayerId) -> Result<ResyncOutcome> {
+        let (stage_id, gameover, turn) = {
+            let players = self.players();
+            let player = players
+                .get(&pid)
+                .ok_or_else(|| anyhow!("No player #{pid} found."))?;
+            (player.stage, player.gameover.clone(), player.turn)
+        };
+        let Some(stage_id) = stage_id else {
+            let gameover = gameover.ok_or_else(|| anyhow!("Player #{pid} is nowhere"))?;
+            return Ok(ResyncOutcome::Gameover(gameover));
+        };
+
+        let mut stage = self.stage(stage_id)?;
+        // The client may have missed the sight the next delta would be based on
+        if let Some(player) = stage.players.get_mut(&pid) {
+            player.sent_sight = None;
+        }
+        let (stage_turn, info) = stage
+            .player_info(pid)
+            .ok_or_else(|| anyhow!("Player #{pid} not found in stage {stage_id}"))?;
+        let limbo = stage.players.get(&pid).is_some_and(|p| p.limbo);
+
+        Ok(ResyncOutcome::Playing(ResyncMessage {
+            turn,
+            stage_id,
+            stage_info: (&stage.template).into(),
+            stage_turn,
+            info: Some(info),
+            timeline: stage.timeline(),
+            limbo,
+        }))
+    }
+
+    pub fn spectate(&self, stage_id: StageId) -> Result<SpectateMessage> {
+        let stage = self.stage(stage_id)?;
+
+        Ok(SpectateMessage {
+            stage_id,
+            stage_info: (&stage.template).into(),
+            stage_turn: stage.head_turn,
+            timeline: stage.timeline(),
+            sight: sense::gather_spectator(&stage),
+        })
+    }
+
+    /// Everything on the stage head, without fog. Only meant for admins.
+    pub fn inspect(&self, stage_id: StageId) -> Result<StageSnapshot> {
+        let stage = self.stage(stage_id)?;
+        let state = stage.head_state();
+
+        Ok(StageSnapshot {
+            stage_id,
+            stage_turn: stage.head_turn,
+            tiles: stage.template.tiles.clone(),
+            foes: state
+                .foes
+                .iter()
+                .map(|foe| FoeSnapshot {
+                    id: foe.id,
+                    foe_type: foe.foe_type,
+                    position: foe.position,
+                    hp: foe.hp,
+                })
+                .collect(),
+            orbs: state.orbs.iter().map(|orb| orb.position).collect(),
+            shards: state.shards.clone(),
+            avatars: state
+                .avatars
+                .values()
+                .map(|avatar| AvatarSnapshot {
+                    player_id: avatar.player_id,
+                    position: avatar.position,
+                    hp: avatar.hp,
+                    turn: stage
+                        .players
+                        .get(&avatar.player_id)
+                        .map_or(stage.head_turn, |player| player.turn),
+                })
+                .collect(),
+        })
+    }
+
     pub fn get_all_infos_for_stage(
         &self,
         stage: StageId,
     ) -> Vec<(PlayerId, StageTurn, SensesInfo)> {
-        self.stages
-            .get(stage)
+        self.stage(stage)
             .map(|st| st.get_all_infos())
             .unwrap_or_default()
     }
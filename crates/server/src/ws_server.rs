@@ -1,25 +1,105 @@
 use std::{
+    cell::Cell,
     collections::HashMap,
-    net::{SocketAddr, TcpListener, TcpStream},
-    sync::mpsc::{Receiver, Sender, channel},
-    thread::{sleep, spawn},
+    io::ErrorKind,
+    net::SocketAddr,
+    rc::Rc,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+        mpsc::{Receiver, Sender, channel},
+    },
+    thread::{JoinHandle, spawn},
     time::Duration,
 };
 
-use anyhow::{Result, bail};
+use anyhow::Result;
 use log::{error, info, warn};
 use losig_core::{
-    network::{ClientMessage, ServerMessage},
+    network::{ClientMessage, ClientMessageContent, ServerMessage},
     types::PlayerId,
 };
-use tungstenite::{Bytes, Message, WebSocket};
+use mio::{
+    Events, Interest, Poll, Token, Waker,
+    net::{TcpListener, TcpStream},
+};
+use tungstenite::{
+    HandshakeError, Message, WebSocket,
+    handshake::{
+        MidHandshake,
+        server::{Callback, ErrorResponse, Request, Response, ServerHandshake},
+    },
+    http::{HeaderValue, header::SEC_WEBSOCKET_PROTOCOL},
+};
+
+use crate::codec::{self, Codec};
 
 type Ws = WebSocket<TcpStream>;
+type HandshakeResult =
+    std::result::Result<Ws, HandshakeError<ServerHandshake<TcpStream, Negotiation>>>;
+
+const LISTENER: Token = Token(0);
+const WAKER: Token = Token(1);
+/// Sockets and outgoing messages wake the loop up, this only bounds how late the stop flag is seen
+const POLL_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// A client socket and the encoding it asked for
+struct Connection {
+    ws: Ws,
+    codec: Box<dyn Codec>,
+    addr: SocketAddr,
+}
+
+/// A client socket waiting for the rest of its handshake
+struct Pending {
+    handshake: MidHandshake<ServerHandshake<TcpStream, Negotiation>>,
+    addr: SocketAddr,
+    codec: Rc<Cell<Option<Box<dyn Codec>>>>,
+}
+
+/// Where a handshake stands after reading what the client sent so far
+enum Handshaking {
+    Connected(Connection),
+    Pending(Pending),
+    Failed,
+}
+
+/// Picks the codec from the subprotocols requested in the handshake
+struct Negotiation(Rc<Cell<Option<Box<dyn Codec>>>>);
+
+impl Callback for Negotiation {
+    fn on_request(
+        self,
+        request: &Request,
+        mut response: Response,
+    ) -> std::result::Result<Response, ErrorResponse> {
+        let protocols = request
+            .headers()
+            .get(SEC_WEBSOCKET_PROTOCOL)
+            .and_then(|p| p.to_str().ok());
+        let chosen = codec::negotiate(protocols);
+        if let Some(protocol) = chosen.protocol() {
+            response
+                .headers_mut()
+                .insert(SEC_WEBSOCKET_PROTOCOL, HeaderValue::from_static(protocol));
+        }
+        self.0.set(Some(chosen));
+        Ok(response)
+    }
+}
+
+/// What was read from a client socket
+enum Read {
+    Message(ClientMessage),
+    /// The client sent a close frame, tungstenite answers it
+    Closed,
+    /// Ping and pong frames, tungstenite answers the pings
+    Control,
+}
 
 pub enum Recipient {
     Broadcast,
     Single(PlayerId),
-    #[allow(unused)]
     Multi(Vec<PlayerId>),
 }
 
@@ -32,6 +112,8 @@ pub struct ServerMessageWithRecipient {
 pub struct WsServer {
     cm_tx: Sender<ClientMessage>,
     sm_rx: Receiver<ServerMessageWithRecipient>,
+    /// Once set, pending messages are sent and connections closed
+    stop: Arc<AtomicBool>,
 }
 
 impl WsServer {
@@ -42,112 +124,270 @@ impl WsServer {
     ) {
         let (cm_tx, cm_rx) = channel();
         let (sm_tx, sm_rx) = channel();
-        (WsServer { cm_tx, sm_rx }, sm_tx, cm_rx)
+        let stop = Arc::new(AtomicBool::new(false));
+        (WsServer { cm_tx, sm_rx, stop }, sm_tx, cm_rx)
     }
 
-    pub fn run(self) {
+    /// Flag stopping the server when set
+    pub fn stop_flag(&self) -> Arc<AtomicBool> {
+        self.stop.clone()
+    }
+
+    pub fn run(self) -> JoinHandle<()> {
         spawn(move || {
-            let e = self.do_run().unwrap_err();
-            error!("{e}");
-        });
+            if let Err(e) = self.do_run() {
+                error!("{e}");
+            }
+        })
     }
 
     fn do_run(self) -> Result<()> {
-        let Self { cm_tx, sm_rx } = self;
+        let Self { cm_tx, sm_rx, stop } = self;
+
+        let mut poll = Poll::new()?;
+        let mut server = TcpListener::bind("127.0.0.1:9001".parse()?)?;
+        poll.registry()
+            .register(&mut server, LISTENER, Interest::READABLE)?;
 
-        let server = TcpListener::bind("127.0.0.1:9001")?;
-        server.set_nonblocking(true)?;
+        // Outgoing messages are relayed so that the poll wakes up as soon as one is sent
+        let waker = Waker::new(poll.registry(), WAKER)?;
+        let (relay_tx, relay_rx) = channel();
+        spawn(move || {
+            for server_message in sm_rx {
+                if relay_tx.send(server_message).is_err() || waker.wake().is_err() {
+                    break;
+                }
+            }
+        });
 
-        let mut ws_by_addr = HashMap::<SocketAddr, Ws>::new();
-        let mut addr_by_player_id = HashMap::<PlayerId, SocketAddr>::new();
+        let mut events = Events::with_capacity(128);
+        let mut next_token = WAKER.0 + 1;
+        let mut handshakes = HashMap::<Token, Pending>::new();
+        let mut ws_by_token = HashMap::<Token, Connection>::new();
+        let mut token_by_player_id = HashMap::<PlayerId, Token>::new();
 
         info!("Launching server on 127.0.0.1:9001");
 
         loop {
-            match handle_incoming(&server) {
-                Ok((stream, addr)) => {
-                    ws_by_addr.insert(addr, stream);
-                }
-                Err(e) => {
-                    if !is_would_block(&e) {
-                        warn!("Could not establish connection: {:?}", e);
+            if let Err(e) = poll.poll(&mut events, Some(POLL_TIMEOUT))
+                && e.kind() != ErrorKind::Interrupted
+            {
+                return Err(e.into());
+            }
+
+            let mut started = vec![];
+            let mut readable = vec![];
+            for event in events.iter() {
+                match event.token() {
+                    LISTENER => loop {
+                        let (mut stream, addr) = match server.accept() {
+                            Ok(accepted) => accepted,
+                            Err(e) => {
+                                if e.kind() != ErrorKind::WouldBlock {
+                                    warn!("Could not establish connection: {e}");
+                                }
+                                break;
+                            }
+                        };
+                        let token = Token(next_token);
+                        next_token += 1;
+                        poll.registry().register(
+                            &mut stream,
+                            token,
+                            Interest::READABLE | Interest::WRITABLE,
+                        )?;
+                        let codec = Rc::new(Cell::new(None));
+                        let result = tungstenite::accept_hdr(stream, Negotiation(codec.clone()));
+                        started.push((token, handshake(result, addr, codec)));
+                    },
+                    WAKER => {}
+                    token => {
+                        if let Some(Pending {
+                            handshake: mid,
+                            addr,
+                            codec,
+                        }) = handshakes.remove(&token)
+                        {
+                            started.push((token, handshake(mid.handshake(), addr, codec)));
+                        } else if let Some(connection) = ws_by_token.get_mut(&token) {
+                            // Messages that did not fit in the socket are sent once it drains
+                            if event.is_writable() {
+                                let _ = connection.ws.flush();
+                            }
+                            readable.push(token);
+                        }
                     }
                 }
             }
-            if let Ok((stream, addr)) = handle_incoming(&server) {
-                ws_by_addr.insert(addr, stream);
-            }
 
-            for (addr, stream) in ws_by_addr.iter_mut() {
-                match handle_read(stream) {
-                    Ok(client_message) => {
-                        if let Some(player_id) = client_message.player_id {
-                            addr_by_player_id.insert(player_id, *addr);
-                        }
-                        cm_tx.send(client_message)?;
+            for (token, step) in started {
+                match step {
+                    Handshaking::Connected(connection) => {
+                        // What the client sent right after the handshake raises no new event
+                        ws_by_token.insert(token, connection);
+                        readable.push(token);
                     }
-                    Err(e) => {
-                        if !is_would_block(&e) {
-                            warn!("Couldn't read: {e}");
-                        }
+                    Handshaking::Pending(pending) => {
+                        handshakes.insert(token, pending);
                     }
+                    Handshaking::Failed => {}
                 }
             }
 
-            for server_message in sm_rx.try_iter() {
-                match server_message.recipient {
-                    Recipient::Single(id) => {
-                        if let Some(addr) = addr_by_player_id.get(&id)
-                            && let Some(ws) = ws_by_addr.get_mut(addr)
-                        {
-                            let _ = handle_write(ws, &server_message.message);
+            let mut closed = vec![];
+            for token in readable {
+                let Some(connection) = ws_by_token.get_mut(&token) else {
+                    continue;
+                };
+                // Readiness is only signaled once, the socket is read until it would block
+                loop {
+                    match handle_read(connection) {
+                        Ok(Read::Message(client_message)) => {
+                            if let Some(player_id) = client_message.player_id {
+                                token_by_player_id.insert(player_id, token);
+                            }
+                            cm_tx.send(client_message)?;
                         }
-                    }
-                    Recipient::Broadcast => {
-                        for ws in ws_by_addr.values_mut() {
-                            let _ = handle_write(ws, &server_message.message);
+                        Ok(Read::Closed) => {
+                            closed.push(token);
+                            break;
                         }
-                    }
-                    Recipient::Multi(pids) => {
-                        for pid in pids {
-                            if let Some(addr) = addr_by_player_id.get(&pid)
-                                && let Some(ws) = ws_by_addr.get_mut(addr)
-                            {
-                                let _ = handle_write(ws, &server_message.message);
+                        Ok(Read::Control) => {}
+                        Err(e) => {
+                            if is_would_block(&e) {
+                                break;
+                            }
+                            warn!("Couldn't read: {e}");
+                            // Messages that could not be decoded do not stop the reading
+                            if e.is::<tungstenite::Error>() {
+                                break;
                             }
                         }
                     }
                 }
             }
-            ws_by_addr.retain(|_, v| v.can_read());
 
-            sleep(Duration::from_millis(10));
+            // Players leaving cleanly are retired right away instead of waiting to be idle
+            for token in closed {
+                if let Some(mut connection) = ws_by_token.remove(&token) {
+                    info!("Connection from {} closed", connection.addr);
+                    let _ = connection.ws.flush();
+                }
+                let player_ids: Vec<PlayerId> = token_by_player_id
+                    .iter()
+                    .filter(|&(_, player_token)| *player_token == token)
+                    .map(|(&player_id, _)| player_id)
+                    .collect();
+                for player_id in player_ids {
+                    token_by_player_id.remove(&player_id);
+                    cm_tx.send(ClientMessage {
+                        player_id: Some(player_id),
+                        content: ClientMessageContent::Disconnect,
+                    })?;
+                }
+            }
+
+            // Read the flag before draining so that messages sent before the stop are delivered
+            let stopping = stop.load(Ordering::Acquire);
+            let mut pending = HashMap::<Token, Vec<ServerMessage>>::new();
+            for server_message in relay_rx.try_iter() {
+                queue_message(
+                    server_message,
+                    &ws_by_token,
+                    &token_by_player_id,
+                    &mut pending,
+                );
+            }
+            for (token, messages) in pending {
+                if let Some(connection) = ws_by_token.get_mut(&token) {
+                    let _ = handle_write(connection, &batch(messages));
+                }
+            }
+
+            if stopping {
+                info!("Closing {} connections", ws_by_token.len());
+                for Connection { ws, .. } in ws_by_token.values_mut() {
+                    let _ = ws.close(None);
+                    let _ = ws.flush();
+                }
+                return Ok(());
+            }
+
+            ws_by_token.retain(|_, v| v.ws.can_read());
         }
     }
 }
 
-fn handle_incoming(server: &TcpListener) -> Result<(Ws, SocketAddr)> {
-    let (stream, addr) = server.accept()?;
-    let mut stream = tungstenite::accept(stream)?;
-    stream.get_mut().set_nonblocking(true)?;
-    info!("Incoming connection from {addr}");
-    Ok((stream, addr))
+/// Adds the message to the ones to send to each of its recipients during this drain pass
+fn queue_message(
+    server_message: ServerMessageWithRecipient,
+    ws_by_token: &HashMap<Token, Connection>,
+    token_by_player_id: &HashMap<PlayerId, Token>,
+    pending: &mut HashMap<Token, Vec<ServerMessage>>,
+) {
+    let tokens: Vec<Token> = match server_message.recipient {
+        Recipient::Single(id) => token_by_player_id.get(&id).copied().into_iter().collect(),
+        Recipient::Broadcast => ws_by_token.keys().copied().collect(),
+        Recipient::Multi(pids) => pids
+            .iter()
+            .filter_map(|pid| token_by_player_id.get(pid))
+            .copied()
+            .collect(),
+    };
+
+    for token in tokens {
+        if ws_by_token.contains_key(&token) {
+            pending
+                .entry(token)
+                .or_default()
+                .push(server_message.message.clone());
+        }
+    }
 }
 
-fn handle_read(stream: &mut Ws) -> Result<ClientMessage> {
-    let msg = stream.read()?;
-    let Message::Binary(msg) = msg else {
-        bail!("Not a binary");
-    };
+/// Lone messages are sent as is, batches are only used when there is more than one
+fn batch(mut messages: Vec<ServerMessage>) -> ServerMessage {
+    if messages.len() == 1 {
+        messages.remove(0)
+    } else {
+        ServerMessage::Batch(messages)
+    }
+}
+
+fn handshake(
+    result: HandshakeResult,
+    addr: SocketAddr,
+    codec: Rc<Cell<Option<Box<dyn Codec>>>>,
+) -> Handshaking {
+    match result {
+        Ok(ws) => {
+            let codec = codec.take().unwrap_or_else(|| codec::negotiate(None));
+            info!("Incoming connection from {addr}");
+            Handshaking::Connected(Connection { ws, codec, addr })
+        }
+        Err(HandshakeError::Interrupted(handshake)) => Handshaking::Pending(Pending {
+            handshake,
+            addr,
+            codec,
+        }),
+        Err(HandshakeError::Failure(e)) => {
+            warn!("Could not establish connection: {e:?}");
+            Handshaking::Failed
+        }
+    }
+}
 
-    let command = bincode::deserialize::<ClientMessage>(&msg)?;
-    Ok(command)
+fn handle_read(connection: &mut Connection) -> Result<Read> {
+    match connection.ws.read()? {
+        Message::Close(_) => Ok(Read::Closed),
+        Message::Ping(_) | Message::Pong(_) => Ok(Read::Control),
+        msg => Ok(Read::Message(connection.codec.decode(msg)?)),
+    }
 }
 
-fn handle_write(ws: &mut Ws, msg: &ServerMessage) -> Result<()> {
-    let msg = bincode::serialize(msg)?;
-    let msg = Bytes::from_owner(msg);
-    ws.send(Message::Binary(msg))?;
+fn handle_write(connection: &mut Connection, msg: &ServerMessage) -> Result<()> {
+    let msg = connection.codec.encode(msg)?;
+    connection.ws.send(msg)?;
     Ok(())
 }
 
@@ -0,0 +1,13 @@
+<?xml version="1.0" encoding="UTF-8"?>
+<tileset version="1.10" tiledversion="1.11.2" name="decor" tilewidth="16" tileheight="32" tilecount="3" columns="0">
+ <grid orientation="orthogonal" width="1" height="1"/>
+ <tile id="0">
+  <image source="wall.png" width="16" height="32"/>
+ </tile>
+ <tile id="1">
+  <image source="empty.png" width="16" height="32"/>
+ </tile>
+ <tile id="2">
+  <image source="pylon.png" width="16" height="32"/>
+ </tile>
+</tileset>